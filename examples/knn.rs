@@ -1,3 +1,5 @@
+#![allow(clippy::ptr_arg, clippy::assign_op_pattern)]
+
 use vpsearch::{BestCandidate, MetricSpace};
 
 use std::collections::HashSet;
@@ -43,7 +45,7 @@ struct CountBasedNeighborhood<Item: MetricSpace<Impl>, Impl> {
 impl<Item: MetricSpace<Impl>, Impl> CountBasedNeighborhood<Item, Impl> {
     /// Helper function for creating the CountBasedNeighborhood struct.
     /// Here `item_count` is the amount of items returned, the k in knn.
-    fn new(item_count: usize) -> Self {
+    fn new(item_count: usize) -> Self where Item::Distance: Bounded {
         CountBasedNeighborhood {
             max_item_count: item_count,
             max_observed_distance: <Item::Distance as Bounded>::min_value(),
@@ -76,7 +78,7 @@ impl<Item: MetricSpace<Impl>, Impl> CountBasedNeighborhood<Item, Impl> {
 
 /// Best candidate definitions that tracks of the index all the points
 /// within the radius of `distance` as specified in the `RadiusBasedNeighborhood`.
-impl<Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<Item, Impl>
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<'tree, Item, Impl>
     for CountBasedNeighborhood<Item, Impl>
 {
     type Output = HashSet<usize>;
@@ -1,3 +1,5 @@
+#![allow(clippy::useless_vec)]
+
 /// Newtype
 #[derive(Clone)]
 struct LotsaDimensions<'a>(&'a [u8; 64]);
@@ -1,3 +1,5 @@
+#![allow(clippy::extra_unused_lifetimes)]
+
 struct WorkAroundRustOrphanRules;
 
 impl<'a> vpsearch::MetricSpace<WorkAroundRustOrphanRules> for Vec<u8> {
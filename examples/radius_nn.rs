@@ -1,3 +1,5 @@
+#![allow(clippy::ptr_arg)]
+
 use vpsearch::{BestCandidate, MetricSpace};
 
 use std::collections::HashSet;
@@ -50,7 +52,7 @@ impl<Item: MetricSpace<Impl>, Impl> RadiusBasedNeighborhood<Item, Impl> {
 
 /// Best candidate definitions that tracks of the index all the points
 /// within the radius of `distance` as specified in the `RadiusBasedNeighborhood`.
-impl<Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<Item, Impl>
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<'tree, Item, Impl>
     for RadiusBasedNeighborhood<Item, Impl>
 {
     type Output = HashSet<usize>;
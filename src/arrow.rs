@@ -0,0 +1,109 @@
+//! Building trees from Apache Arrow and Parquet data (`arrow` and `parquet` features).
+//!
+//! Vectors are read from a fixed-size-list column of `Float32` or `Float64` values.
+//! Each row becomes one item, converted with `From<Vec<f32>>` (or `From<Vec<f64>>`).
+//!
+//! ```rust,ignore
+//! #[derive(Clone)]
+//! struct Embedding(Vec<f32>);
+//! impl From<Vec<f32>> for Embedding { /*…*/ }
+//! impl vpsearch::MetricSpace for Embedding { /*…*/ }
+//!
+//! let tree = vpsearch::Tree::<Embedding>::from_record_batch(&batch, "embedding")?;
+//! ```
+
+use crate::{MetricSpace, Owned, Tree};
+use arrow_array::types::{ArrowPrimitiveType, Float32Type, Float64Type};
+use arrow_array::{Array, ArrowNativeTypeOp, FixedSizeListArray, PrimitiveArray, RecordBatch};
+use arrow_schema::ArrowError;
+#[cfg(feature = "parquet")]
+use std::path::Path;
+
+/// Element types of vector columns that can be read: `f32` and `f64`.
+pub trait ArrowFloat: ArrowNativeTypeOp + private::Sealed {
+    #[doc(hidden)]
+    type ArrowType: ArrowPrimitiveType<Native = Self>;
+}
+
+impl ArrowFloat for f32 {
+    type ArrowType = Float32Type;
+}
+
+impl ArrowFloat for f64 {
+    type ArrowType = Float64Type;
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for f32 {}
+    impl Sealed for f64 {}
+}
+
+/// Reads every row of a fixed-size-list column as a vector.
+///
+/// Fails if the column doesn't exist, has a different type, or contains nulls.
+pub fn vectors_from_record_batch<T: ArrowFloat>(batch: &RecordBatch, column: &str) -> Result<Vec<Vec<T>>, ArrowError> {
+    let array = batch.column_by_name(column)
+        .ok_or_else(|| ArrowError::SchemaError(format!("no column named '{}'", column)))?;
+    let mut vectors = Vec::with_capacity(array.len());
+    append_vectors(array.as_ref(), column, &mut vectors)?;
+    Ok(vectors)
+}
+
+fn append_vectors<T: ArrowFloat>(array: &dyn Array, column: &str, vectors: &mut Vec<Vec<T>>) -> Result<(), ArrowError> {
+    let list = array.as_any().downcast_ref::<FixedSizeListArray>()
+        .ok_or_else(|| ArrowError::SchemaError(format!("column '{}' must be a fixed-size list, but it's {}", column, array.data_type())))?;
+    if list.null_count() > 0 {
+        return Err(ArrowError::InvalidArgumentError(format!("column '{}' contains null vectors", column)));
+    }
+    for row in list.iter().flatten() {
+        let row = row.as_any().downcast_ref::<PrimitiveArray<T::ArrowType>>()
+            .ok_or_else(|| ArrowError::SchemaError(format!("column '{}' must contain {} values, but it has {}", column, T::ArrowType::DATA_TYPE, row.data_type())))?;
+        if row.null_count() > 0 {
+            return Err(ArrowError::InvalidArgumentError(format!("column '{}' contains null values", column)));
+        }
+        vectors.push(row.values().to_vec());
+    }
+    Ok(())
+}
+
+/// Reads all rows of a fixed-size-list column from a Parquet file. Only that one column is decoded.
+#[cfg(feature = "parquet")]
+pub fn vectors_from_parquet<T: ArrowFloat>(path: impl AsRef<Path>, column: &str) -> Result<Vec<Vec<T>>, ArrowError> {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::arrow::ProjectionMask;
+
+    let file = std::fs::File::open(path).map_err(|e| ArrowError::IoError(e.to_string(), e))?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file).map_err(|e| ArrowError::ParquetError(e.to_string()))?;
+    let root = builder.schema().index_of(column)?;
+    let mask = ProjectionMask::roots(builder.parquet_schema(), [root]);
+    let reader = builder.with_projection(mask).build().map_err(|e| ArrowError::ParquetError(e.to_string()))?;
+
+    let mut vectors = Vec::new();
+    for batch in reader {
+        let batch = batch?;
+        append_vectors(batch.column(0).as_ref(), column, &mut vectors)?;
+    }
+    Ok(vectors)
+}
+
+impl<Item: MetricSpace<Impl, UserData = ()> + Clone, Impl> Tree<Item, Impl, Owned<()>> {
+    /// Creates a tree from a fixed-size-list column of `Float32` or `Float64` values. Each row is converted to an `Item`.
+    ///
+    /// Item indices returned by searches are row numbers in the batch.
+    /// Errors of `Tree::try_new`, e.g. NaN distances, are returned as `ArrowError::ComputeError`.
+    pub fn from_record_batch<T: ArrowFloat>(batch: &RecordBatch, column: &str) -> Result<Self, ArrowError> where Item: From<Vec<T>> {
+        let items: Vec<Item> = vectors_from_record_batch(batch, column)?.into_iter().map(Item::from).collect();
+        Self::try_new(&items).map_err(|err| ArrowError::ComputeError(err.to_string()))
+    }
+
+    /// Creates a tree from a fixed-size-list column in a Parquet file. Each row is converted to an `Item`.
+    ///
+    /// Item indices returned by searches are row numbers in the file.
+    /// Errors of `Tree::try_new` are returned as `ArrowError::ComputeError`.
+    #[cfg(feature = "parquet")]
+    pub fn from_parquet<T: ArrowFloat>(path: impl AsRef<Path>, column: &str) -> Result<Self, ArrowError> where Item: From<Vec<T>> {
+        let items: Vec<Item> = vectors_from_parquet(path, column)?.into_iter().map(Item::from).collect();
+        Self::try_new(&items).map_err(|err| ArrowError::ComputeError(err.to_string()))
+    }
+}
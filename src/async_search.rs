@@ -0,0 +1,138 @@
+//! Searching with metrics that compute distances asynchronously, e.g. on a GPU queue or by a remote service.
+//!
+//! The search keeps a stack of nodes to visit, like the regular search. Instead of visiting one node at a time,
+//! it takes several nodes off the stack, and waits for distances to all of their items at once.
+//! Nodes are checked again before they're taken, because distances found in the meantime may have ruled them out.
+
+use crate::{BestCandidate, DistanceAdd, HoldsUserData, KNearest, MetricSpace, Tree, NO_NODE};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::Poll;
+use num_traits::Bounded;
+
+/// Distance that is computed asynchronously. Searched with `Tree::find_k_nearest_async` and `Tree::find_nearest_custom_async`.
+///
+/// The tree is built using `MetricSpace::distance()`, so the items need both. Only searches use `distance_async()`.
+/// It must return the same distances as `distance()`.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Embedding(Vec<f32>);
+/// # impl vpsearch::MetricSpace for Embedding {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0[0] - other.0[0]).abs() }
+/// # }
+/// # async fn ask_the_server(a: &[f32], b: &[f32]) -> f32 { (a[0] - b[0]).abs() }
+/// impl vpsearch::AsyncMetricSpace for Embedding {
+///     async fn distance_async(&self, other: &Self, _: &()) -> f32 {
+///         ask_the_server(&self.0, &other.0).await
+///     }
+/// }
+///
+/// async fn search(tree: &vpsearch::Tree<Embedding>, needle: &Embedding) -> usize {
+///     // Up to 16 distances are computed at the same time
+///     tree.find_k_nearest_async(needle, 1, 16).await[0].0
+/// }
+/// ```
+pub trait AsyncMetricSpace<UserImplementationType = ()>: MetricSpace<UserImplementationType> {
+    fn distance_async(&self, other: &Self, user_data: &Self::UserData) -> impl Future<Output = Self::Distance>;
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Like `find_k_nearest`, but the distances are computed with `AsyncMetricSpace`, with up to `max_in_flight` computations at a time.
+    ///
+    /// With `max_in_flight` of 1 it computes as few distances as the regular search. Larger values make the search finish sooner,
+    /// but it computes more distances in total, because it doesn't wait for each distance before deciding which nodes to skip.
+    pub async fn find_k_nearest_async<Q>(&self, needle: &Q, k: usize, max_in_flight: usize) -> Vec<(usize, Item::Distance)> where Q: AsyncMetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.find_nearest_custom_async(needle, self.user_data.user_data(), KNearest::new(k), max_in_flight).await
+    }
+}
+
+/// A node to visit, if it's still needed when it's taken off the stack
+struct Visit<D> {
+    node: u32,
+    /// Distance from the needle to the parent, the parent's radius, and whether it's the far child. `None` for the root.
+    parent: Option<(D, D, bool)>,
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Ownership, Impl> Tree<Item, Impl, Ownership> {
+    /// Like `find_nearest_custom`, but the distances are computed with `AsyncMetricSpace`, with up to `max_in_flight` computations at a time.
+    ///
+    /// It's exact, like the other searches. See `find_k_nearest_async`.
+    pub async fn find_nearest_custom_async<'tree, ReturnBy: BestCandidate<'tree, Item, Impl>, Q>(&'tree self, needle: &Q, user_data: &Item::UserData, mut best_candidate: ReturnBy, max_in_flight: usize) -> ReturnBy::Output where Q: AsyncMetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        let max_in_flight = max_in_flight.max(1);
+        let mut stack: Vec<Visit<Item::Distance>> = Vec::new();
+        if (self.root as usize) < self.nodes.len() {
+            stack.push(Visit { node: self.root, parent: None });
+        }
+        let mut batch = Vec::new();
+        while !stack.is_empty() {
+            // Items of a node are computed together, so a batch can be a bit larger than `max_in_flight`
+            batch.clear();
+            let mut in_flight = 0;
+            while in_flight < max_in_flight {
+                let Visit { node, parent } = match stack.pop() {
+                    Some(visit) => visit,
+                    None => break,
+                };
+                let bound = best_candidate.distance();
+                let needed = match parent {
+                    None => true,
+                    // Same conditions as in the synchronous search
+                    Some((distance, radius, true)) => distance.saturating_add(bound) >= radius,
+                    Some((distance, radius, false)) => distance <= radius.saturating_add(bound),
+                };
+                if needed {
+                    let node = &self.nodes[node as usize];
+                    in_flight += 1 + node.bucket().map_or(0, |bucket| bucket.len());
+                    batch.push(node);
+                }
+            }
+
+            let items = batch.iter().flat_map(|node| {
+                let bucket = node.bucket().map(|bucket| &self.buckets.items[bucket]).unwrap_or(&[]);
+                core::iter::once(&node.vantage_point).chain(bucket)
+            });
+            let mut distances = join_all(items.map(|item| needle.distance_async(item.borrow(), user_data)).collect()).await.into_iter();
+
+            for node in &batch {
+                let distance = distances.next().unwrap();
+                best_candidate.consider(&node.vantage_point, distance, node.idx as usize, user_data);
+                if let Some(bucket) = node.bucket() {
+                    for (item, &idx) in self.buckets.items[bucket.clone()].iter().zip(&self.buckets.idx[bucket]) {
+                        best_candidate.consider(item, distances.next().unwrap(), idx as usize, user_data);
+                    }
+                    continue;
+                }
+                // The more likely child is pushed last, so that it's visited first
+                let children = if distance < node.radius { [(node.far, true), (node.near, false)] } else { [(node.near, false), (node.far, true)] };
+                stack.extend(children.iter()
+                    .filter(|&&(child, _)| child != NO_NODE)
+                    .map(|&(child, far)| Visit { node: child, parent: Some((distance, node.radius, far)) }));
+            }
+        }
+        best_candidate.result(user_data)
+    }
+}
+
+/// Waits for all of the futures, which run concurrently. Returns their outputs in the same order.
+async fn join_all<F: Future>(futures: Vec<F>) -> Vec<F::Output> {
+    let mut futures: Vec<Pin<Box<F>>> = futures.into_iter().map(Box::pin).collect();
+    let mut outputs: Vec<Option<F::Output>> = futures.iter().map(|_| None).collect();
+    core::future::poll_fn(|cx| {
+        let mut pending = false;
+        for (future, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+            // Completed futures must not be polled again
+            if output.is_none() {
+                match future.as_mut().poll(cx) {
+                    Poll::Ready(distance) => *output = Some(distance),
+                    Poll::Pending => pending = true,
+                }
+            }
+        }
+        if pending { Poll::Pending } else { Poll::Ready(()) }
+    }).await;
+    outputs.into_iter().map(|output| output.unwrap()).collect()
+}
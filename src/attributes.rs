@@ -0,0 +1,135 @@
+//! Searches limited to items with a numeric attribute in a range, e.g. a timestamp or a price.
+//!
+//! Every node knows the smallest and largest attribute in its subtree, so subtrees that are entirely out of the range are skipped.
+
+use crate::side_data::PerItem;
+#[cfg(feature = "serde")]
+use crate::Error;
+use crate::{BestCandidate, Filter, HoldsUserData, KNearest, MetricSpace, Needle, NoExtraPruning, Owned, ReturnByIndex, Tree, WithinRadius};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+#[cfg(feature = "serde")]
+use core::convert::TryFrom;
+use core::ops::{Bound, RangeBounds};
+use num_traits::Bounded;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A tree that has a numeric attribute for each item, and can search only items with the attribute in a range. Created with `Tree::with_attribute`.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+/// let prices = vec![10, 250, 30];
+/// let tree = vpsearch::Tree::new(&[Point(1.), Point(2.), Point(5.)]).with_attribute(prices);
+/// let (index, _) = tree.find_nearest_in_range(&Point(2.), ..100).unwrap();
+/// assert_eq!(index, 0);
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "AttributeTreeData<Item, A, Impl, Ownership>"))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "Item: Serialize, Item::Distance: Serialize, Ownership: Serialize, A: Serialize",
+    deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>, Ownership: Deserialize<'de>, A: Deserialize<'de>",
+)))]
+pub struct AttributeTree<Item: MetricSpace<Impl> + Clone, A, Impl = (), Ownership = Owned<()>> {
+    tree: Tree<Item, Impl, Ownership>,
+    attributes: PerItem<A>,
+    /// Smallest and largest attribute in the subtree of each node
+    node_ranges: Vec<(A, A)>,
+}
+
+/// Same fields as `AttributeTree`, which is checked after they're deserialized
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "AttributeTree")]
+#[serde(bound(deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>, Ownership: Deserialize<'de>, A: Deserialize<'de>"))]
+struct AttributeTreeData<Item: MetricSpace<Impl> + Clone, A, Impl, Ownership> {
+    tree: Tree<Item, Impl, Ownership>,
+    attributes: PerItem<A>,
+    node_ranges: Vec<(A, A)>,
+}
+
+#[cfg(feature = "serde")]
+impl<Item: MetricSpace<Impl> + Clone, A, Impl, Ownership> TryFrom<AttributeTreeData<Item, A, Impl, Ownership>> for AttributeTree<Item, A, Impl, Ownership> {
+    type Error = Error;
+
+    fn try_from(AttributeTreeData { tree, attributes, node_ranges }: AttributeTreeData<Item, A, Impl, Ownership>) -> Result<Self, Error> {
+        attributes.check_len(&tree)?;
+        tree.check_per_node_len(&node_ranges)?;
+        Ok(Self { tree, attributes, node_ranges })
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// Adds an attribute for each item, in the same order as the items the tree has been created from.
+    ///
+    /// Panics if the number of attributes is different than the number of items, or if an attribute isn't comparable (NaN).
+    pub fn with_attribute<A: PartialOrd + Copy>(self, attributes: Vec<A>) -> AttributeTree<Item, A, Impl, Ownership> {
+        let attributes = PerItem::new(&self, attributes, "attribute");
+        assert!(attributes.iter().all(|a| a.partial_cmp(a).is_some()), "attributes must be comparable");
+
+        let node_ranges = self.aggregate_nodes(|idx| (attributes[idx], attributes[idx]), |(min, max), (other_min, other_max)| {
+            (if other_min < min { other_min } else { min }, if other_max > max { other_max } else { max })
+        });
+        AttributeTree { tree: self, attributes, node_ranges }
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, A: PartialOrd + Copy, Impl, Ownership> AttributeTree<Item, A, Impl, Ownership> {
+    /// The tree for searches of all items
+    #[inline]
+    pub fn tree(&self) -> &Tree<Item, Impl, Ownership> {
+        &self.tree
+    }
+
+    /// Attribute of the item at `index`
+    #[inline]
+    pub fn attribute(&self, index: usize) -> A {
+        self.attributes[index]
+    }
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, A: PartialOrd + Copy, Ownership: HoldsUserData<UserData = Item::UserData>> AttributeTree<Item, A, Impl, Ownership> {
+    /// Finds the item closest to the `needle`, out of the items with the attribute in the `range`. Returns `None` if there are none.
+    pub fn find_nearest_in_range<Q, R: RangeBounds<A>>(&self, needle: &Q, range: R) -> Option<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.search_in_range(needle, &range, ReturnByIndex::new(<Item::Distance as Bounded>::max_value()))
+    }
+
+    /// Finds up to `k` items closest to the `needle`, out of the items with the attribute in the `range`
+    pub fn find_k_nearest_in_range<Q, R: RangeBounds<A>>(&self, needle: &Q, k: usize, range: R) -> Vec<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.search_in_range(needle, &range, KNearest::new(k))
+    }
+
+    /// Finds all items at most `radius` away from the `needle`, out of the items with the attribute in the `range`
+    pub fn find_within_radius_in_range<Q, R: RangeBounds<A>>(&self, needle: &Q, radius: Item::Distance, range: R) -> Vec<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.search_in_range(needle, &range, WithinRadius::new(radius))
+    }
+
+    pub(crate) fn search_in_range<'tree, B: BestCandidate<'tree, Item, Impl>, Q, R: RangeBounds<A>>(&'tree self, needle: &Q, range: &R, best_candidate: B) -> B::Output where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        let user_data = self.tree.user_data();
+        let mut best_candidate = Filter::new(best_candidate, |idx| range.contains(&self.attributes[idx]));
+        self.tree.search_subtrees(self.tree.root, &mut Needle { needle, user_data }, &mut best_candidate, user_data, |node| {
+            let (min, max) = self.node_ranges[node as usize];
+            overlaps(range, min, max)
+        }, &mut NoExtraPruning);
+        best_candidate.result(user_data)
+    }
+}
+
+/// `true` if any value between `min` and `max` (inclusive) is in the `range`
+fn overlaps<A: PartialOrd, R: RangeBounds<A>>(range: &R, min: A, max: A) -> bool {
+    let after_start = match range.start_bound() {
+        Bound::Included(start) => max >= *start,
+        Bound::Excluded(start) => max > *start,
+        Bound::Unbounded => true,
+    };
+    let before_end = match range.end_bound() {
+        Bound::Included(end) => min <= *end,
+        Bound::Excluded(end) => min < *end,
+        Bound::Unbounded => true,
+    };
+    after_start && before_end
+}
@@ -0,0 +1,96 @@
+//! Searching for many needles at once.
+//!
+//! All needles go through the tree together, so every node is loaded from memory once per batch rather than once per needle.
+//! Needles that can't have a better match in a subtree are dropped from the batch before it goes there.
+
+use crate::{BestCandidate, DistanceAdd, HoldsUserData, KNearest, MetricSpace, Tree, LEAF_SIZE, MAX_DEPTH};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use num_traits::Bounded;
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Finds up to `k` nearest items for each of the `needles`. Returns a list of results for every needle, in the same order.
+    ///
+    /// It's faster than searching for each needle separately when the needles are close to each other (e.g. the same
+    /// point in consecutive video frames), because then they visit mostly the same nodes.
+    pub fn find_k_nearest_batch<Q>(&self, needles: &[Q], k: usize) -> Vec<Vec<(usize, Item::Distance)>> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance>, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.find_nearest_custom_batch(needles, self.user_data.user_data(), needles.iter().map(|_| KNearest::new(k)).collect())
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Ownership, Impl> Tree<Item, Impl, Ownership> {
+    /// Like `find_nearest_custom`, but searches for all `needles` in one pass over the tree. There must be one `best_candidates` element for each needle.
+    ///
+    /// Returns the results of the `best_candidates`, in the same order.
+    pub fn find_nearest_custom_batch<'tree, ReturnBy: BestCandidate<'tree, Item, Impl>, Q>(&'tree self, needles: &[Q], user_data: &Item::UserData, mut best_candidates: Vec<ReturnBy>) -> Vec<ReturnBy::Output> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance>, Item: Borrow<Q> {
+        assert_eq!(needles.len(), best_candidates.len(), "each needle needs its own best candidate");
+        assert!(needles.len() <= u32::MAX as usize);
+
+        if !needles.is_empty() {
+            let batch: Vec<u32> = (0..needles.len() as u32).collect();
+            // Empty `Vec`s don't allocate, and each depth reuses its buffers for all of its nodes
+            let mut scratch: Vec<Scratch<Item::Distance>> = (0..MAX_DEPTH).map(|_| Scratch { distances: Vec::new(), child_batch: Vec::new() }).collect();
+            self.search_batch(self.root, needles, &batch, &mut best_candidates, user_data, &mut scratch);
+        }
+        best_candidates.into_iter().map(|best| best.result(user_data)).collect()
+    }
+
+    /// `batch` has indexes of the needles (and their best candidates) that need to search this node. It's never empty.
+    /// `scratch` starts with the buffers for the depth of this node.
+    fn search_batch<'tree, B: BestCandidate<'tree, Item, Impl>, Q>(&'tree self, node_idx: u32, needles: &[Q], batch: &[u32], best_candidates: &mut [B], user_data: &Item::UserData, scratch: &mut [Scratch<Item::Distance>]) where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance>, Item: Borrow<Q> {
+        // No-node case uses out-of-bounds index, so this reuses a safe bounds check as the "null" check
+        let node = match self.nodes.get(node_idx as usize) {
+            Some(node) => node,
+            None => return,
+        };
+
+        let (Scratch { distances, child_batch }, deeper) = scratch.split_first_mut().expect("tree too deep");
+        distances.clear();
+        distances.extend(batch.iter().map(|&n| {
+            let distance = needles[n as usize].distance(node.vantage_point.borrow(), user_data);
+            best_candidates[n as usize].consider(&node.vantage_point, distance, node.idx as usize, user_data);
+            distance
+        }));
+
+        if let Some(bucket) = node.bucket() {
+            let items = &self.buckets.items[bucket.clone()];
+            let indexes = &self.buckets.idx[bucket];
+            let mut bucket_distances = [distances[0]; LEAF_SIZE];
+            let bucket_distances = &mut bucket_distances[..items.len()];
+            for &n in batch {
+                needles[n as usize].distance_to_many(items, bucket_distances, user_data);
+                for ((item, &idx), &distance) in items.iter().zip(indexes).zip(bucket_distances.iter()) {
+                    best_candidates[n as usize].consider(item, distance, idx as usize, user_data);
+                }
+            }
+            return;
+        }
+
+        // Needles disagree which child is more likely to have the nearest items, so it goes with the majority
+        let near_first = distances.iter().filter(|&&distance| distance < node.radius).count() * 2 >= batch.len();
+        let children = if near_first { [(node.near, false), (node.far, true)] } else { [(node.far, true), (node.near, false)] };
+        for &(child, is_far) in &children {
+            // Same conditions as in the search for a single needle
+            child_batch.clear();
+            child_batch.extend(batch.iter().zip(distances.iter()).filter(|&(&n, &distance)| {
+                let best = best_candidates[n as usize].distance();
+                if is_far {
+                    distance.saturating_add(best) >= node.radius
+                } else {
+                    distance <= node.radius.saturating_add(best)
+                }
+            }).map(|(&n, _)| n));
+            if !child_batch.is_empty() {
+                self.search_batch(child, needles, child_batch, best_candidates, user_data, deeper);
+            }
+        }
+    }
+}
+
+/// Buffers of `search_batch` for one depth of the tree
+struct Scratch<D> {
+    /// Distances from the needles in the batch to the node's vantage point
+    distances: Vec<D>,
+    /// Needles that go to a child
+    child_batch: Vec<u32>,
+}
@@ -0,0 +1,160 @@
+//! Builds a VP-tree from a file of vectors, and answers nearest-neighbor queries read from stdin.
+//!
+//! Vectors are one per line, either comma-separated numbers (CSV) or JSON arrays (JSONL).
+//! Results refer to the vectors by their 0-based position in the input file.
+
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::process;
+use vpsearch::{MetricSpace, Tree};
+
+const USAGE: &str = "\
+Usage:
+    vpsearch build <vectors.csv|vectors.jsonl> <snapshot>
+    vpsearch query <snapshot> [-k <count> | -r <radius>]
+
+`build` reads one vector per line, as comma-separated numbers or a JSON array,
+and saves the tree to the snapshot file.
+
+`query` reads needles from stdin in the same format, and for each needle prints
+a line of space-separated `index:distance` pairs, closest first.
+By default it finds 1 nearest item.";
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Vector(Vec<f32>);
+
+impl MetricSpace for Vector {
+    type UserData = ();
+    type Distance = f32;
+
+    fn distance(&self, other: &Self, _: &Self::UserData) -> Self::Distance {
+        self.0.iter().zip(other.0.iter())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f32>()
+            .sqrt()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    dimensions: usize,
+    tree: Tree<Vector>,
+}
+
+enum Query {
+    Nearest(usize),
+    Radius(f32),
+}
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let res = match args.first().map(|s| s.as_str()) {
+        Some("build") if args.len() == 3 => build(&args[1], &args[2]),
+        Some("query") if args.len() >= 2 => parse_query(&args[2..]).and_then(|q| query(&args[1], q)),
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            return;
+        },
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        },
+    };
+    if let Err(err) = res {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn parse_query(args: &[String]) -> Result<Query, BoxError> {
+    match args {
+        [] => Ok(Query::Nearest(1)),
+        [flag, value] if flag == "-k" => Ok(Query::Nearest(value.parse().map_err(|_| format!("invalid count: {}", value))?)),
+        [flag, value] if flag == "-r" => Ok(Query::Radius(value.parse().map_err(|_| format!("invalid radius: {}", value))?)),
+        _ => Err(USAGE.into()),
+    }
+}
+
+/// A CSV row of column names: none of its fields are numbers
+fn is_header(line: &str) -> bool {
+    !line.trim_start().starts_with('[') && line.split(',').all(|field| field.trim().parse::<f32>().is_err())
+}
+
+/// Returns `None` for blank lines
+fn parse_vector(line: &str) -> Result<Option<Vector>, BoxError> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let values = if line.starts_with('[') {
+        serde_json::from_str(line)?
+    } else {
+        line.split(',').map(|v| v.trim().parse::<f32>()).collect::<Result<Vec<_>, _>>()?
+    };
+    if values.iter().any(|v: &f32| !v.is_finite()) {
+        return Err("vectors must not contain NaN or infinite values".into());
+    }
+    Ok(Some(Vector(values)))
+}
+
+fn build(input_path: &str, snapshot_path: &str) -> Result<(), BoxError> {
+    let input = BufReader::new(File::open(input_path).map_err(|e| format!("can't open {}: {}", input_path, e))?);
+
+    let mut vectors = Vec::new();
+    for (line_no, line) in input.lines().enumerate() {
+        let line = line?;
+        let vector = match parse_vector(&line) {
+            Ok(Some(v)) => v,
+            Ok(None) => continue,
+            // CSV files may start with a header row, which has no numbers. A malformed first row of numbers is still an error.
+            Err(_) if line_no == 0 && is_header(&line) => continue,
+            Err(err) => return Err(format!("{}:{}: {}", input_path, line_no + 1, err).into()),
+        };
+        if let Some(Vector(first)) = vectors.first() {
+            if first.len() != vector.0.len() {
+                return Err(format!("{}:{}: expected {} dimensions, got {}", input_path, line_no + 1, first.len(), vector.0.len()).into());
+            }
+        }
+        vectors.push(vector);
+    }
+
+    let snapshot = Snapshot {
+        dimensions: vectors.first().map_or(0, |v| v.0.len()),
+        tree: Tree::new(&vectors),
+    };
+    let mut out = BufWriter::new(File::create(snapshot_path).map_err(|e| format!("can't create {}: {}", snapshot_path, e))?);
+    bincode::serialize_into(&mut out, &snapshot)?;
+    out.flush()?;
+    eprintln!("Indexed {} vectors with {} dimensions", vectors.len(), snapshot.dimensions);
+    Ok(())
+}
+
+fn query(snapshot_path: &str, query: Query) -> Result<(), BoxError> {
+    let file = BufReader::new(File::open(snapshot_path).map_err(|e| format!("can't open {}: {}", snapshot_path, e))?);
+    let snapshot: Snapshot = bincode::deserialize_from(file).map_err(|e| format!("{} is not a valid snapshot: {}", snapshot_path, e))?;
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let stdin = io::stdin();
+    for (line_no, line) in stdin.lock().lines().enumerate() {
+        let needle = match parse_vector(&line?).map_err(|e| format!("stdin:{}: {}", line_no + 1, e))? {
+            Some(v) => v,
+            None => continue,
+        };
+        if needle.0.len() != snapshot.dimensions {
+            return Err(format!("stdin:{}: expected {} dimensions, got {}", line_no + 1, snapshot.dimensions, needle.0.len()).into());
+        }
+        let results = match query {
+            Query::Nearest(k) => snapshot.tree.find_k_nearest(&needle, k),
+            Query::Radius(r) => snapshot.tree.find_within_radius(&needle, r),
+        };
+        let line = results.iter().map(|(idx, dist)| format!("{}:{}", idx, dist)).collect::<Vec<_>>().join(" ");
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,117 @@
+//! Searches limited to a number of distance computations, for metrics that are expensive to compute.
+//!
+//! Before a node is visited, its cost (the vantage point and the bucket items) is taken out of the budget.
+//! Nodes that don't fit in the remaining budget are skipped, so the budget is never exceeded.
+
+use crate::{BestCandidate, HoldsUserData, KNearest, MetricSpace, Needle, NoExtraPruning, Tree};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cell::Cell;
+use num_traits::{Bounded, ToPrimitive};
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Like `find_k_nearest`, but computes at most `max_distance_evaluations` distances.
+    ///
+    /// Returns the nearest items found within the budget, and `true` if the search has finished, so they're exactly the `k` nearest items.
+    /// If it's `false`, parts of the tree haven't been searched, and closer items may exist.
+    pub fn find_k_nearest_budgeted<Q>(&self, needle: &Q, k: usize, max_distance_evaluations: usize) -> (Vec<(usize, Item::Distance)>, bool) where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.find_nearest_custom_budgeted(needle, self.user_data.user_data(), KNearest::new(k), max_distance_evaluations)
+    }
+
+    /// Like `find_k_nearest_budgeted`, but instead of `exact` returns an estimated probability that none of the skipped items are nearer
+    /// than the returned ones. For `k = 1` it's how likely the returned item is the true nearest.
+    ///
+    /// It's `1.0` if the search has finished. Otherwise it's lowered by the fraction of the items that couldn't be pruned, but haven't been visited.
+    /// Each skipped subtree is weighted by how much of the distance to the `k`-th item is left after the closest its items could be to the needle,
+    /// so subtrees that only just failed to be pruned barely count. It's a heuristic, not a bound, but it's cheap to compute,
+    /// and can be used to decide when to repeat the search with a larger budget or without a limit.
+    ///
+    /// ```rust
+    /// # #[derive(Clone)] struct Point(f32);
+    /// # impl vpsearch::MetricSpace for Point {
+    /// #     type UserData = (); type Distance = f32;
+    /// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+    /// # }
+    /// let points: Vec<_> = (0..1000).map(|i| Point(i as f32)).collect();
+    /// let tree = vpsearch::Tree::new(&points);
+    /// let (mut nearest, confidence) = tree.find_k_nearest_with_confidence(&Point(123.4), 1, 50);
+    /// if confidence < 0.99 {
+    ///     nearest = tree.find_k_nearest(&Point(123.4), 1);
+    /// }
+    /// assert_eq!(nearest[0].0, 123);
+    /// ```
+    pub fn find_k_nearest_with_confidence<Q>(&self, needle: &Q, k: usize, max_distance_evaluations: usize) -> (Vec<(usize, Item::Distance)>, f64) where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded + ToPrimitive {
+        let user_data = self.user_data.user_data();
+        let mut best_candidate = KNearest::new(k);
+        let mut remaining = max_distance_evaluations;
+        // The pruning rule is called just before a child is visited, so this is the lower bound of the next visited subtree
+        let lower_bound = Cell::new(0.);
+        // `(node, lower bound of the distances from the needle to its items)`
+        let mut skipped = Vec::new();
+        self.search_subtrees(self.root, &mut Needle { needle, user_data }, &mut best_candidate, user_data, |node_idx| {
+            let node = &self.nodes[node_idx as usize];
+            let cost = 1 + node.bucket().map_or(0, |bucket| bucket.len());
+            if cost > remaining {
+                skipped.push((node_idx, lower_bound.get()));
+                return false;
+            }
+            remaining -= cost;
+            true
+        }, &mut |needle_distance: Item::Distance, radius: Item::Distance, _, far| {
+            let (needle_distance, radius) = (needle_distance.to_f64().unwrap_or(0.), radius.to_f64().unwrap_or(0.));
+            // Triangle inequality: far items are at least `radius` from the vantage point, near ones at most
+            lower_bound.set(if far { radius - needle_distance } else { needle_distance - radius }.max(0.));
+            true
+        });
+
+        // Only the items that haven't been pruned could be the nearest, and they've been either visited or skipped
+        let items_per_node = (self.nodes.len() + self.buckets.items.len()) as f64 / self.nodes.len() as f64;
+        let visited = (max_distance_evaluations - remaining) as f64;
+        let bound = BestCandidate::<Item, Impl>::distance(&best_candidate).to_f64().unwrap_or(f64::INFINITY);
+        let (mut skipped_items, mut missed) = (0., 0.);
+        for &(node, lower_bound) in &skipped {
+            let items = self.subtree_node_count(node) as f64 * items_per_node;
+            skipped_items += items;
+            missed += items * if bound > 0. { (1. - lower_bound / bound).max(0.) } else { 0. };
+        }
+        let missed = if missed > 0. { missed / (visited + skipped_items) } else { 0. };
+        (best_candidate.result(user_data), (1. - missed).clamp(0., 1.))
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Ownership, Impl> Tree<Item, Impl, Ownership> {
+    /// Like `find_nearest_custom`, but computes at most `max_distance_evaluations` distances between the needle and the items.
+    ///
+    /// Returns the result of the `best_candidate`, and `true` if the whole search fit in the budget, so the result is the same as without the limit.
+    pub fn find_nearest_custom_budgeted<'tree, ReturnBy: BestCandidate<'tree, Item, Impl>, Q>(&'tree self, needle: &Q, user_data: &Item::UserData, mut best_candidate: ReturnBy, max_distance_evaluations: usize) -> (ReturnBy::Output, bool) where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        let mut remaining = max_distance_evaluations;
+        let mut exact = true;
+        self.search_subtrees(self.root, &mut Needle { needle, user_data }, &mut best_candidate, user_data, |node| {
+            let node = &self.nodes[node as usize];
+            let cost = 1 + node.bucket().map_or(0, |bucket| bucket.len());
+            if cost > remaining {
+                exact = false;
+                return false;
+            }
+            remaining -= cost;
+            true
+        }, &mut NoExtraPruning);
+        (best_candidate.result(user_data), exact)
+    }
+
+    /// Number of nodes in the subtree. Nodes are in depth-first order, so the last node of the subtree is found by following the last children.
+    fn subtree_node_count(&self, root: u32) -> usize {
+        let mut last = root;
+        while let Some(node) = self.nodes.get(last as usize) {
+            if node.bucket().is_some() {
+                break;
+            }
+            let child = if (node.far as usize) < self.nodes.len() { node.far } else { node.near };
+            if child as usize >= self.nodes.len() {
+                break;
+            }
+            last = child;
+        }
+        (last - root) as usize + 1
+    }
+}
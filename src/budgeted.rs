@@ -0,0 +1,118 @@
+//! Building a tree of `f32` vectors that fits in a given amount of memory, e.g. on devices with little RAM.
+//!
+//! Vectors take most of the tree's memory, so when they don't fit as `f32`, they're stored quantized to `u8`,
+//! which is 4 times smaller, at the cost of the distances being approximate.
+
+use crate::metrics::{Euclidean, Quantized, QuantizedElement, Quantizer};
+use crate::{Error, Owned, Tree};
+use alloc::vec::Vec;
+use core::mem;
+
+/// How the vectors of a `BudgetedTree` are stored
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StorageLayout {
+    /// As they are, so the distances are exact
+    F32,
+    /// Quantized to `u8`, using a `Quantizer` fitted to all vectors
+    U8,
+}
+
+/// The layout chosen by `BudgetedTree::new`, and how much memory it takes
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct BudgetReport {
+    pub layout: StorageLayout,
+    /// Bytes used by the tree, including the vectors' elements. See `Tree::memory_size`.
+    pub bytes: usize,
+    /// The budget the tree has been built for
+    pub max_bytes: usize,
+}
+
+/// A tree of `f32` vectors compared with the `Euclidean` distance, stored in the most precise layout that fits in a memory budget.
+///
+/// ```rust
+/// let vectors: Vec<Vec<f32>> = (0..1000).map(|i| vec![(i % 10) as f32, (i / 10) as f32, 0.5]).collect();
+/// let (tree, report) = vpsearch::BudgetedTree::new(&vectors, 64 * 1024).unwrap();
+/// assert!(report.bytes <= 64 * 1024);
+/// eprintln!("stored as {:?}", report.layout);
+///
+/// let nearest = tree.find_k_nearest(&[3.1, 4.9, 0.5], 1);
+/// assert_eq!(nearest[0].0, 3 + 5 * 10);
+/// ```
+pub struct BudgetedTree {
+    tree: LayoutTree,
+}
+
+/// Tree of vectors with elements `S`
+type VectorTree<S> = Tree<Quantized<S>, Euclidean, Owned<Quantizer>>;
+
+enum LayoutTree {
+    F32(VectorTree<f32>),
+    U8(VectorTree<u8>),
+}
+
+impl BudgetedTree {
+    /// Builds a tree of the `vectors` that uses at most `max_bytes` of memory, with the vectors stored as `f32` if they fit, or as `u8` otherwise.
+    /// All vectors must have the same number of elements.
+    ///
+    /// Fails with `Error::OverBudget` if even the smallest layout doesn't fit, and otherwise in the same cases as `Tree::try_new`.
+    /// The tree may be built twice, if the smaller layout is only needed because of the memory taken by the nodes.
+    pub fn new<V: AsRef<[f32]>>(vectors: &[V], max_bytes: usize) -> Result<(Self, BudgetReport), Error> {
+        let quantizer = Quantizer::fit(vectors);
+        let dimensions = vectors.first().map_or(0, |v| v.as_ref().len());
+
+        if let Some((tree, bytes)) = build::<f32, V>(vectors, quantizer, dimensions, max_bytes)? {
+            return Ok((Self { tree: LayoutTree::F32(tree) }, BudgetReport { layout: StorageLayout::F32, bytes, max_bytes }));
+        }
+        if let Some((tree, bytes)) = build::<u8, V>(vectors, quantizer, dimensions, max_bytes)? {
+            return Ok((Self { tree: LayoutTree::U8(tree) }, BudgetReport { layout: StorageLayout::U8, bytes, max_bytes }));
+        }
+        Err(Error::OverBudget)
+    }
+
+    /// The layout the vectors are stored in
+    pub fn layout(&self) -> StorageLayout {
+        match self.tree {
+            LayoutTree::F32(_) => StorageLayout::F32,
+            LayoutTree::U8(_) => StorageLayout::U8,
+        }
+    }
+
+    /// Number of vectors in the tree
+    #[inline]
+    pub fn len(&self) -> usize {
+        match &self.tree {
+            LayoutTree::F32(tree) => tree.len(),
+            LayoutTree::U8(tree) => tree.len(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Finds up to `k` vectors closest to the `needle`. Returns `(index, distance)` sorted by distance, closest first.
+    ///
+    /// With the `U8` layout, the needle is quantized too, and the distances are between the quantized vectors.
+    pub fn find_k_nearest(&self, needle: &[f32], k: usize) -> Vec<(usize, f32)> {
+        match &self.tree {
+            LayoutTree::F32(tree) => tree.find_k_nearest(&tree.user_data().quantize(needle), k),
+            LayoutTree::U8(tree) => tree.find_k_nearest(&tree.user_data().quantize(needle), k),
+        }
+    }
+}
+
+/// Builds the tree with elements `S`, unless it's certain to be over the budget. Returns the tree if it fits, and its size.
+fn build<S: QuantizedElement, V: AsRef<[f32]>>(vectors: &[V], quantizer: Quantizer, dimensions: usize, max_bytes: usize) -> Result<Option<(VectorTree<S>, usize)>, Error> {
+    let elements_bytes = vectors.len() * dimensions * mem::size_of::<S>();
+    // Every vector is at least in a bucket, with its index
+    let min_bytes = elements_bytes + vectors.len() * (mem::size_of::<Quantized<S>>() + mem::size_of::<u32>());
+    if min_bytes > max_bytes {
+        return Ok(None);
+    }
+    let items: Vec<Quantized<S>> = vectors.iter().map(|v| quantizer.quantize(v.as_ref())).collect();
+    let tree = Tree::try_new_with_user_data_owned(&items, quantizer)?;
+    let bytes = tree.memory_size() + elements_bytes;
+    Ok(if bytes <= max_bytes { Some((tree, bytes)) } else { None })
+}
@@ -0,0 +1,53 @@
+//! Searches that can be stopped part way, e.g. when the client that asked for the results has disconnected.
+//!
+//! Cancellation is checked before every node is visited, so a search stops after at most one more node and its bucket.
+
+use crate::{BestCandidate, Error, HoldsUserData, KNearest, MetricSpace, Needle, NoExtraPruning, Tree};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::ops::ControlFlow;
+use core::sync::atomic::{AtomicBool, Ordering};
+use num_traits::Bounded;
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Like `find_k_nearest`, but stops and returns `Error::Cancelled` when `cancelled` is set, e.g. by another thread.
+    ///
+    /// ```rust
+    /// # #[derive(Clone)] struct Point(f32);
+    /// # impl vpsearch::MetricSpace for Point {
+    /// #     type UserData = (); type Distance = f32;
+    /// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+    /// # }
+    /// use std::sync::atomic::AtomicBool;
+    ///
+    /// let tree = vpsearch::Tree::new(&[Point(1.), Point(2.)]);
+    /// let client_disconnected = AtomicBool::new(false);
+    /// assert!(tree.find_k_nearest_cancellable(&Point(1.5), 1, &client_disconnected).is_ok());
+    /// client_disconnected.store(true, std::sync::atomic::Ordering::Relaxed);
+    /// assert_eq!(tree.find_k_nearest_cancellable(&Point(1.5), 1, &client_disconnected), Err(vpsearch::Error::Cancelled));
+    /// ```
+    pub fn find_k_nearest_cancellable<Q>(&self, needle: &Q, k: usize, cancelled: &AtomicBool) -> Result<Vec<(usize, Item::Distance)>, Error> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.find_nearest_custom_cancellable(needle, self.user_data.user_data(), KNearest::new(k), || {
+            if cancelled.load(Ordering::Relaxed) { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+        })
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Ownership, Impl> Tree<Item, Impl, Ownership> {
+    /// Like `find_nearest_custom`, but calls `check` before visiting every node, and if it returns `Break`, stops and returns `Error::Cancelled`.
+    ///
+    /// `check` can e.g. look at a deadline, or ask a server whether the client is still waiting.
+    /// It's not called again after it has returned `Break`.
+    pub fn find_nearest_custom_cancellable<'tree, ReturnBy: BestCandidate<'tree, Item, Impl>, Q>(&'tree self, needle: &Q, user_data: &Item::UserData, mut best_candidate: ReturnBy, mut check: impl FnMut() -> ControlFlow<()>) -> Result<ReturnBy::Output, Error> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        let mut cancelled = false;
+        self.search_subtrees(self.root, &mut Needle { needle, user_data }, &mut best_candidate, user_data, |_| {
+            // Skipping every remaining node ends the search right away
+            cancelled = cancelled || check().is_break();
+            !cancelled
+        }, &mut NoExtraPruning);
+        if cancelled {
+            return Err(Error::Cancelled);
+        }
+        Ok(best_candidate.result(user_data))
+    }
+}
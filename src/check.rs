@@ -0,0 +1,91 @@
+//! Checking whether a distance function is a metric, because the tree silently misses results when it isn't.
+//!
+//! The most common mistake is a distance that breaks the triangle inequality, like squared Euclidean distance.
+//! Every ordered triple of sampled items is checked, so it takes `sample_size³` comparisons.
+
+use crate::{shuffle, DistanceAdd, MetricSpace};
+use alloc::vec::Vec;
+use num_traits::ToPrimitive;
+
+/// How well a distance function follows the triangle inequality on a sample of items. Returned by `check_metric()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MetricCheck {
+    /// Number of `(a, b, c)` triples checked
+    pub triples: usize,
+    /// Number of triples where `distance(a, c) > distance(a, b) + distance(b, c)`
+    pub violations: usize,
+    /// Indexes of the `(a, b, c)` items that break the triangle inequality by the largest factor. `None` if there are no violations.
+    pub worst: Option<(usize, usize, usize)>,
+    /// `distance(a, c) / (distance(a, b) + distance(b, c))` of the worst triple. It's more than `1` when there's a violation.
+    pub worst_ratio: f64,
+}
+
+impl MetricCheck {
+    /// Fraction of triples that follow the triangle inequality, from `0` to `1`.
+    ///
+    /// Anything less than `1` means that searches may miss some of the nearest items.
+    #[must_use]
+    pub fn score(&self) -> f64 {
+        if self.triples == 0 {
+            return 1.;
+        }
+        1. - self.violations as f64 / self.triples as f64
+    }
+
+    /// `true` if no violations have been found in the sample. It doesn't prove that the distance is a metric.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.violations == 0
+    }
+}
+
+/// Checks the triangle inequality on `sample_size` randomly chosen items, to catch distance functions that won't work with the tree.
+///
+/// The same `seed` picks the same sample. Use all items when `sample_size >= items.len()`.
+/// A few hundred items are usually enough to find a problem, and take a few seconds to check.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32);
+/// impl vpsearch::MetricSpace for Point {
+///     type UserData = (); type Distance = f32;
+///     // Wrong! Squared distances aren't a metric
+///     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).powi(2) }
+/// }
+///
+/// let points: Vec<_> = (0..50).map(|i| Point(i as f32)).collect();
+/// let check = vpsearch::check_metric(&points, &(), 50, 0);
+/// assert!(!check.is_ok());
+/// assert!(check.score() < 1.);
+/// ```
+pub fn check_metric<Item: MetricSpace<Impl>, Impl>(items: &[Item], user_data: &Item::UserData, sample_size: usize, seed: u64) -> MetricCheck where Item::Distance: ToPrimitive {
+    let mut sample: Vec<usize> = (0..items.len()).collect();
+    shuffle(&mut sample, seed);
+    sample.truncate(sample_size);
+    let len = sample.len();
+
+    // Distances aren't assumed to be symmetric, so both directions are computed
+    let distances: Vec<Item::Distance> = sample.iter()
+        .flat_map(|&a| sample.iter().map(move |&b| items[a].distance(&items[b], user_data)))
+        .collect();
+    let distance = |a: usize, b: usize| distances[a * len + b];
+
+    let mut check = MetricCheck { triples: 0, violations: 0, worst: None, worst_ratio: 1. };
+    for a in 0..len {
+        for c in (0..len).filter(|&c| c != a) {
+            let direct = distance(a, c);
+            for b in (0..len).filter(|&b| b != a && b != c) {
+                check.triples += 1;
+                let detour = distance(a, b).saturating_add(distance(b, c));
+                if direct > detour {
+                    check.violations += 1;
+                    let ratio = direct.to_f64().unwrap_or(f64::NAN) / detour.to_f64().unwrap_or(f64::NAN);
+                    if check.worst.is_none() || ratio > check.worst_ratio {
+                        check.worst = Some((sample[a], sample[b], sample[c]));
+                        check.worst_ratio = ratio;
+                    }
+                }
+            }
+        }
+    }
+    check
+}
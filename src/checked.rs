@@ -0,0 +1,151 @@
+//! Searches that verify the tree's pruning, for finding distance functions that aren't metrics while debugging.
+//!
+//! The search is the same as `find_k_nearest`, but whenever a child is skipped, small subtrees are searched exhaustively
+//! to check that they really had no better items. A distance that breaks the triangle inequality makes the tree skip
+//! subtrees that it shouldn't, and the search then silently returns items that aren't the nearest.
+
+use crate::{BestCandidate, DistanceAdd, HoldsUserData, KNearest, MetricSpace, Tree, NO_NODE};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::fmt;
+use num_traits::Bounded;
+
+/// `(index, distance)` of the nearest items
+type Neighbors<D> = Vec<(usize, D)>;
+
+/// A subtree that `Tree::find_k_nearest_checked` has skipped, but which has an item nearer than the items found so far
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct PruningMistake<Distance> {
+    /// Index of the item that is the vantage point of the parent of the skipped subtree
+    pub vantage_point: usize,
+    /// `true` if the far subtree has been skipped, `false` if the near one
+    pub far: bool,
+    /// Distance from the needle to the vantage point
+    pub needle_distance: Distance,
+    /// Radius of the vantage point
+    pub radius: Distance,
+    /// Distance of the farthest of the `k` nearest items found before the subtree was skipped
+    pub bound: Distance,
+    /// Index of the item in the skipped subtree that is nearer than the `bound`
+    pub missed: usize,
+    /// Distance from the needle to the `missed` item
+    pub missed_distance: Distance,
+    /// Distance from the vantage point to the `missed` item. With a metric, it's impossible for the other distances to be what they are.
+    pub vantage_distance: Distance,
+}
+
+impl<Distance: fmt::Debug> fmt::Display for PruningMistake<Distance> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "item {} is {:?} from the needle, which is nearer than {:?}, but it has been skipped, because it's {:?} from item {} in the {} subtree of radius {:?}, and the needle is {:?} from it",
+            self.missed, self.missed_distance, self.bound, self.vantage_distance, self.vantage_point, if self.far { "far" } else { "near" }, self.radius, self.needle_distance)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<Distance: fmt::Debug> std::error::Error for PruningMistake<Distance> {}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Like `find_k_nearest`, but checks every skipped subtree of up to `max_checked` items, and returns the first subtree that had a nearer item.
+    ///
+    /// This is much slower than `find_k_nearest`, and only meant for tests and debugging. A mistake means that the distance isn't a metric,
+    /// most likely because it breaks the triangle inequality. Larger subtrees aren't checked, so `Ok` doesn't prove that the result is exact.
+    ///
+    /// ```rust
+    /// # #[derive(Clone)] struct Point(f32);
+    /// impl vpsearch::MetricSpace for Point {
+    ///     type UserData = (); type Distance = f32;
+    ///     // Wrong! Squared distances aren't a metric
+    ///     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).powi(2) }
+    /// }
+    ///
+    /// let points: Vec<_> = (0..1000).map(|i| Point(i as f32)).collect();
+    /// let tree = vpsearch::Tree::new(&points);
+    /// let mistakes = (0..100).filter_map(|i| tree.find_k_nearest_checked(&Point(i as f32 * 10.3), 1, 100).err());
+    /// assert!(mistakes.count() > 0);
+    /// ```
+    pub fn find_k_nearest_checked<Q>(&self, needle: &Q, k: usize, max_checked: usize) -> Result<Neighbors<Item::Distance>, PruningMistake<Item::Distance>>
+    where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        let user_data = self.user_data.user_data();
+        let mut best_candidate = KNearest::new(k);
+        // Visits the nodes in the same order as `search_subtrees`, so that the bounds are the same
+        let mut pending = Vec::new();
+        let mut next = self.root;
+        loop {
+            if let Some(node) = self.nodes.get(next as usize) {
+                let distance = needle.distance(node.vantage_point.borrow(), user_data);
+                BestCandidate::<Item, Impl>::consider(&mut best_candidate, &node.vantage_point, distance, node.idx as usize, user_data);
+                if let Some(bucket) = node.bucket() {
+                    for (item, &idx) in self.buckets.items[bucket.clone()].iter().zip(&self.buckets.idx[bucket]) {
+                        let distance = needle.distance(item.borrow(), user_data);
+                        BestCandidate::<Item, Impl>::consider(&mut best_candidate, item, distance, idx as usize, user_data);
+                    }
+                    next = NO_NODE;
+                    continue;
+                }
+                let (first, second_is_far) = if distance < node.radius { (node.near, true) } else { (node.far, false) };
+                pending.push((next, distance, second_is_far));
+                next = first;
+                continue;
+            }
+
+            loop {
+                let (parent_idx, distance, far) = match pending.pop() {
+                    Some(pending) => pending,
+                    None => return Ok(best_candidate.result(user_data)),
+                };
+                let parent = &self.nodes[parent_idx as usize];
+                let bound = BestCandidate::<Item, Impl>::distance(&best_candidate);
+                let (child, visit) = if far {
+                    (parent.far, distance.saturating_add(bound) >= parent.radius)
+                } else {
+                    (parent.near, distance <= parent.radius.saturating_add(bound))
+                };
+                if visit {
+                    next = child;
+                    break;
+                }
+                let items = match self.subtree_items_at_most(child, max_checked) {
+                    Some(items) => items,
+                    None => continue,
+                };
+                for (idx, item) in items {
+                    let missed_distance = needle.distance(item.borrow(), user_data);
+                    if missed_distance < bound {
+                        return Err(PruningMistake {
+                            vantage_point: parent.idx as usize,
+                            far,
+                            needle_distance: distance,
+                            radius: parent.radius,
+                            bound,
+                            missed: idx as usize,
+                            missed_distance,
+                            vantage_distance: parent.vantage_point.distance(item, user_data),
+                        });
+                    }
+                }
+            }
+        }
+    }
+
+    /// All items of the subtree, or `None` if there are more than `max` of them
+    fn subtree_items_at_most(&self, root: u32, max: usize) -> Option<Vec<(u32, &Item)>> {
+        let mut items = Vec::new();
+        let mut stack = vec![root];
+        while let Some(next) = stack.pop() {
+            let node = match self.nodes.get(next as usize) {
+                Some(node) => node,
+                None => continue,
+            };
+            items.push((node.idx, &node.vantage_point));
+            match node.bucket() {
+                Some(bucket) => items.extend(self.buckets.idx[bucket.clone()].iter().copied().zip(&self.buckets.items[bucket])),
+                None => stack.extend([node.near, node.far].iter()),
+            }
+            if items.len() > max {
+                return None;
+            }
+        }
+        Some(items)
+    }
+}
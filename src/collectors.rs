@@ -0,0 +1,400 @@
+//! Ready-made `BestCandidate` implementations for the most common kinds of queries.
+//!
+//! Use them with `Tree::find_nearest_custom()`, or via `find_k_nearest()`/`find_within_radius()` shortcuts.
+
+use crate::{BestCandidate, MetricSpace};
+use num_traits::Bounded;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// Collects up to `k` items nearest to the needle.
+///
+/// The output is a list of `(index, distance)` sorted by distance, closest first.
+pub struct KNearest<Item: MetricSpace<Impl>, Impl> {
+    k: usize,
+    max_distance: Item::Distance,
+    found: Vec<(usize, Item::Distance)>,
+}
+
+impl<Item: MetricSpace<Impl>, Impl> KNearest<Item, Impl> where Item::Distance: Bounded {
+    /// `k` is the maximum number of items to return
+    pub fn new(k: usize) -> Self {
+        Self::with_max_distance(k, <Item::Distance as Bounded>::max_value())
+    }
+}
+
+impl<Item: MetricSpace<Impl>, Impl> KNearest<Item, Impl> {
+    /// Returns up to `k` items, but only those at most `max_distance` away from the needle
+    pub fn with_max_distance(k: usize, max_distance: Item::Distance) -> Self {
+        KNearest {
+            k,
+            max_distance,
+            found: Vec::with_capacity(k.min(1024) + 1),
+        }
+    }
+
+    /// Like `with_max_distance`, but collects the results in the `buffer`, so that a buffer from a previous search can be reused without allocating.
+    ///
+    /// The buffer is cleared first.
+    pub fn with_buffer(k: usize, max_distance: Item::Distance, mut buffer: Vec<(usize, Item::Distance)>) -> Self {
+        buffer.clear();
+        KNearest { k, max_distance, found: buffer }
+    }
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<'tree, Item, Impl> for KNearest<Item, Impl> {
+    type Output = Vec<(usize, Item::Distance)>;
+
+    #[inline]
+    fn consider(&mut self, _: &Item, distance: Item::Distance, candidate_index: usize, _: &Item::UserData) {
+        insert_nearest(&mut self.found, self.k, self.max_distance, candidate_index, distance);
+    }
+
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        kth_distance(&self.found, self.k, self.max_distance)
+    }
+
+    fn result(self, _: &Item::UserData) -> Self::Output {
+        self.found
+    }
+}
+
+/// Like `KNearest`, but returns references to the items in the tree instead of their indexes.
+///
+/// The output is a list of `(item, distance)` sorted by distance, closest first.
+pub struct KNearestItems<'tree, Item: MetricSpace<Impl>, Impl> {
+    k: usize,
+    max_distance: Item::Distance,
+    found: Vec<(&'tree Item, Item::Distance)>,
+}
+
+impl<Item: MetricSpace<Impl>, Impl> KNearestItems<'_, Item, Impl> where Item::Distance: Bounded {
+    /// `k` is the maximum number of items to return
+    pub fn new(k: usize) -> Self {
+        Self::with_max_distance(k, <Item::Distance as Bounded>::max_value())
+    }
+}
+
+impl<Item: MetricSpace<Impl>, Impl> KNearestItems<'_, Item, Impl> {
+    /// Returns up to `k` items, but only those at most `max_distance` away from the needle
+    pub fn with_max_distance(k: usize, max_distance: Item::Distance) -> Self {
+        KNearestItems {
+            k,
+            max_distance,
+            found: Vec::with_capacity(k.min(1024) + 1),
+        }
+    }
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<'tree, Item, Impl> for KNearestItems<'tree, Item, Impl> {
+    type Output = Vec<(&'tree Item, Item::Distance)>;
+
+    #[inline]
+    fn consider(&mut self, item: &'tree Item, distance: Item::Distance, _: usize, _: &Item::UserData) {
+        insert_nearest(&mut self.found, self.k, self.max_distance, item, distance);
+    }
+
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        kth_distance(&self.found, self.k, self.max_distance)
+    }
+
+    fn result(self, _: &Item::UserData) -> Self::Output {
+        self.found
+    }
+}
+
+/// Adds the item to the `found` list sorted by distance, if it's one of the `k` nearest
+#[inline]
+pub(crate) fn insert_nearest<T, D: PartialOrd + Copy>(found: &mut Vec<(T, D)>, k: usize, max_distance: D, item: T, distance: D) {
+    // NaN isn't within any distance
+    if k == 0 || !matches!(distance.partial_cmp(&max_distance), Some(Ordering::Less | Ordering::Equal)) {
+        return;
+    }
+    if found.len() >= k {
+        match found.last() {
+            Some(&(_, worst)) if distance < worst => {},
+            _ => return,
+        }
+        found.pop();
+    }
+    // Items with equal distance keep the order in which they've been found
+    let pos = found.iter().position(|&(_, d)| distance < d).unwrap_or(found.len());
+    found.insert(pos, (item, distance));
+}
+
+/// Distance of the `k`th nearest item, or `max_distance` if fewer have been found
+#[inline]
+pub(crate) fn kth_distance<T, D: Copy>(found: &[(T, D)], k: usize, max_distance: D) -> D {
+    if found.len() < k {
+        return max_distance;
+    }
+    found.last().map_or(max_distance, |&(_, d)| d)
+}
+
+/// Like `KNearest`, but returns at most one item for each key, e.g. one nearest chunk of each document.
+///
+/// The `key` function gets the index of an item. The search goes on until it finds `k` items with distinct keys,
+/// so it doesn't return fewer results like removing duplicates from `KNearest`'s output would.
+///
+/// The output is a list of `(index, distance)` of the nearest item of each key, sorted by distance, closest first.
+pub struct KNearestDistinct<Item: MetricSpace<Impl>, Impl, Key, F> {
+    k: usize,
+    max_distance: Item::Distance,
+    key: F,
+    found: Vec<((Key, usize), Item::Distance)>,
+}
+
+impl<Item: MetricSpace<Impl>, Impl, Key: PartialEq, F: FnMut(usize) -> Key> KNearestDistinct<Item, Impl, Key, F> where Item::Distance: Bounded {
+    /// `k` is the maximum number of items to return
+    pub fn new(k: usize, key: F) -> Self {
+        Self::with_max_distance(k, <Item::Distance as Bounded>::max_value(), key)
+    }
+}
+
+impl<Item: MetricSpace<Impl>, Impl, Key: PartialEq, F: FnMut(usize) -> Key> KNearestDistinct<Item, Impl, Key, F> {
+    /// Returns up to `k` items, but only those at most `max_distance` away from the needle
+    pub fn with_max_distance(k: usize, max_distance: Item::Distance, key: F) -> Self {
+        KNearestDistinct {
+            k,
+            max_distance,
+            key,
+            found: Vec::with_capacity(k.min(1024) + 1),
+        }
+    }
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl, Key: PartialEq, F: FnMut(usize) -> Key> BestCandidate<'tree, Item, Impl> for KNearestDistinct<Item, Impl, Key, F> {
+    type Output = Vec<(usize, Item::Distance)>;
+
+    #[inline]
+    fn consider(&mut self, _: &Item, distance: Item::Distance, candidate_index: usize, _: &Item::UserData) {
+        if distance > self.distance() {
+            return;
+        }
+        let key = (self.key)(candidate_index);
+        if let Some(pos) = self.found.iter().position(|((k, _), _)| *k == key) {
+            // Only the nearest item of each key is kept
+            if distance.partial_cmp(&self.found[pos].1) != Some(Ordering::Less) {
+                return;
+            }
+            self.found.remove(pos);
+        }
+        insert_nearest(&mut self.found, self.k, self.max_distance, (key, candidate_index), distance);
+    }
+
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        kth_distance(&self.found, self.k, self.max_distance)
+    }
+
+    fn result(self, _: &Item::UserData) -> Self::Output {
+        self.found.into_iter().map(|((_, idx), distance)| (idx, distance)).collect()
+    }
+}
+
+/// Like `KNearest`, but ranks items by a score computed from their distance, e.g. distance penalized by the age of the item.
+///
+/// The `score` function gets the index of an item, the item, and its distance from the needle. The score must not be smaller
+/// than the distance (e.g. it can add a penalty, or multiply by a factor ≥ 1), because the search skips items
+/// that are farther than the `k`th best score. Otherwise some items with better scores may not be found.
+///
+/// The output is a list of `(index, score)` sorted by score, best (lowest) first.
+pub struct KNearestScored<Item: MetricSpace<Impl>, Impl, F> {
+    k: usize,
+    max_score: Item::Distance,
+    score: F,
+    found: Vec<(usize, Item::Distance)>,
+}
+
+impl<Item: MetricSpace<Impl>, Impl, F: FnMut(usize, &Item, Item::Distance) -> Item::Distance> KNearestScored<Item, Impl, F> where Item::Distance: Bounded {
+    /// `k` is the maximum number of items to return
+    pub fn new(k: usize, score: F) -> Self {
+        Self::with_max_score(k, <Item::Distance as Bounded>::max_value(), score)
+    }
+}
+
+impl<Item: MetricSpace<Impl>, Impl, F: FnMut(usize, &Item, Item::Distance) -> Item::Distance> KNearestScored<Item, Impl, F> {
+    /// Returns up to `k` items, but only those with a score of at most `max_score`
+    pub fn with_max_score(k: usize, max_score: Item::Distance, score: F) -> Self {
+        KNearestScored {
+            k,
+            max_score,
+            score,
+            found: Vec::with_capacity(k.min(1024) + 1),
+        }
+    }
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl, F: FnMut(usize, &Item, Item::Distance) -> Item::Distance> BestCandidate<'tree, Item, Impl> for KNearestScored<Item, Impl, F> {
+    type Output = Vec<(usize, Item::Distance)>;
+
+    #[inline]
+    fn consider(&mut self, item: &Item, distance: Item::Distance, candidate_index: usize, _: &Item::UserData) {
+        // The score can't be better than the distance
+        if distance > self.distance() {
+            return;
+        }
+        let score = (self.score)(candidate_index, item, distance);
+        insert_nearest(&mut self.found, self.k, self.max_score, candidate_index, score);
+    }
+
+    /// The `k`th best score is also the largest distance of an item that could have a better score
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        kth_distance(&self.found, self.k, self.max_score)
+    }
+
+    fn result(self, _: &Item::UserData) -> Self::Output {
+        self.found
+    }
+}
+
+/// Collects all items that are at most `radius` away from the needle (inclusive).
+///
+/// The output is a list of `(index, distance)` sorted by distance, closest first.
+pub struct WithinRadius<Item: MetricSpace<Impl>, Impl> {
+    radius: Item::Distance,
+    found: Vec<(usize, Item::Distance)>,
+}
+
+impl<Item: MetricSpace<Impl>, Impl> WithinRadius<Item, Impl> {
+    pub fn new(radius: Item::Distance) -> Self {
+        WithinRadius {
+            radius,
+            found: Vec::new(),
+        }
+    }
+
+    /// Like `new`, but collects the results in the `buffer`, so that a buffer from a previous search can be reused without allocating.
+    ///
+    /// The buffer is cleared first.
+    pub fn with_buffer(radius: Item::Distance, mut buffer: Vec<(usize, Item::Distance)>) -> Self {
+        buffer.clear();
+        WithinRadius { radius, found: buffer }
+    }
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<'tree, Item, Impl> for WithinRadius<Item, Impl> {
+    type Output = Vec<(usize, Item::Distance)>;
+
+    #[inline]
+    fn consider(&mut self, _: &Item, distance: Item::Distance, candidate_index: usize, _: &Item::UserData) {
+        if distance <= self.radius {
+            self.found.push((candidate_index, distance));
+        }
+    }
+
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        self.radius
+    }
+
+    fn result(mut self, _: &Item::UserData) -> Self::Output {
+        self.found.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal).then(a.0.cmp(&b.0)));
+        self.found
+    }
+}
+
+/// Like `KNearest`, but keeps the results in an array of `K` elements, so it doesn't need any heap memory.
+///
+/// The output is an array of `Some((index, distance))` sorted by distance, closest first, followed by `None`s if fewer than `K` items were found.
+pub struct KNearestArray<Item: MetricSpace<Impl>, Impl, const K: usize> {
+    max_distance: Item::Distance,
+    len: usize,
+    found: [Option<(usize, Item::Distance)>; K],
+}
+
+impl<Item: MetricSpace<Impl>, Impl, const K: usize> KNearestArray<Item, Impl, K> where Item::Distance: Bounded {
+    pub fn new() -> Self {
+        Self::with_max_distance(<Item::Distance as Bounded>::max_value())
+    }
+}
+
+impl<Item: MetricSpace<Impl>, Impl, const K: usize> Default for KNearestArray<Item, Impl, K> where Item::Distance: Bounded {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<Item: MetricSpace<Impl>, Impl, const K: usize> KNearestArray<Item, Impl, K> {
+    /// Returns up to `K` items, but only those at most `max_distance` away from the needle
+    pub fn with_max_distance(max_distance: Item::Distance) -> Self {
+        KNearestArray {
+            max_distance,
+            len: 0,
+            found: [None; K],
+        }
+    }
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl, const K: usize> BestCandidate<'tree, Item, Impl> for KNearestArray<Item, Impl, K> {
+    type Output = [Option<(usize, Item::Distance)>; K];
+
+    #[inline]
+    fn consider(&mut self, _: &Item, distance: Item::Distance, candidate_index: usize, _: &Item::UserData) {
+        if K == 0 || !matches!(distance.partial_cmp(&self.max_distance), Some(Ordering::Less | Ordering::Equal)) {
+            return;
+        }
+        if self.len >= K {
+            match self.found[K - 1] {
+                Some((_, worst)) if distance < worst => {},
+                _ => return,
+            }
+            self.len = K - 1;
+        }
+        // Items with equal distance keep the order in which they've been found
+        let found = &mut self.found[..=self.len];
+        let pos = found.iter().position(|f| f.map_or(true, |(_, d)| distance < d)).unwrap_or(found.len() - 1);
+        found[pos..].rotate_right(1);
+        found[pos] = Some((candidate_index, distance));
+        self.len += 1;
+    }
+
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        // The last slot is empty until `K` items are found
+        self.found.last().copied().flatten().map_or(self.max_distance, |(_, d)| d)
+    }
+
+    fn result(self, _: &Item::UserData) -> Self::Output {
+        self.found
+    }
+}
+
+/// Wraps another collector, and hides from it items for which the `predicate` returns `false`.
+///
+/// The predicate gets the index of the item. Because it's checked during the search, the search still prunes the tree
+/// as well as it would without the filter, unlike filtering results afterwards, which could return fewer than `k` items.
+pub struct Filter<B, F> {
+    inner: B,
+    predicate: F,
+}
+
+impl<B, F: FnMut(usize) -> bool> Filter<B, F> {
+    /// `inner` is any `BestCandidate`, e.g. `KNearest`
+    pub fn new(inner: B, predicate: F) -> Self {
+        Filter { inner, predicate }
+    }
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl, B: BestCandidate<'tree, Item, Impl>, F: FnMut(usize) -> bool> BestCandidate<'tree, Item, Impl> for Filter<B, F> {
+    type Output = B::Output;
+
+    #[inline]
+    fn consider(&mut self, item: &'tree Item, distance: Item::Distance, candidate_index: usize, user_data: &Item::UserData) {
+        if (self.predicate)(candidate_index) {
+            self.inner.consider(item, distance, candidate_index, user_data);
+        }
+    }
+
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        self.inner.distance()
+    }
+
+    fn result(self, user_data: &Item::UserData) -> Self::Output {
+        self.inner.result(user_data)
+    }
+}
@@ -0,0 +1,83 @@
+//! Memory reused by many searches, for servers that search from many threads and don't want to allocate for every query.
+//!
+//! Searches keep the nodes they have yet to visit in a fixed-size array on the stack, so the only memory they allocate is the list of results.
+//! A `QueryContext` keeps that list from one search to the next.
+
+use crate::{HoldsUserData, MetricSpace, Tree};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use num_traits::Bounded;
+
+/// Memory for the results of searches, reused by every search made with it. Created with `Tree::query_context`.
+///
+/// It isn't tied to a tree, so it can be kept in a `thread_local!` for a pool of one context per thread, and used with any tree
+/// that has the same `Distance` type.
+///
+/// ```rust
+/// use std::cell::RefCell;
+/// use vpsearch::QueryContext;
+/// # #[derive(Clone)] struct Point(f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+///
+/// thread_local! {
+///     static CONTEXT: RefCell<QueryContext<f32>> = RefCell::new(QueryContext::new());
+/// }
+///
+/// let tree = vpsearch::Tree::new(&[Point(1.), Point(2.), Point(5.)]);
+/// let nearest = CONTEXT.with(|context| {
+///     let mut context = context.borrow_mut();
+///     let found = context.find_k_nearest(&tree, &Point(4.), 2);
+///     found.iter().map(|&(index, _)| index).collect::<Vec<_>>()
+/// });
+/// assert_eq!(nearest, [2, 1]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct QueryContext<D> {
+    results: Vec<(usize, D)>,
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// A context for searches of this tree, or other trees with the same `Distance` type, that reuse its memory
+    pub fn query_context(&self) -> QueryContext<Item::Distance> {
+        QueryContext::new()
+    }
+}
+
+impl<D> QueryContext<D> {
+    /// A context that hasn't allocated anything yet. It grows to fit the largest results.
+    pub fn new() -> Self {
+        Self { results: Vec::new() }
+    }
+
+    /// Like `Tree::find_k_nearest`, but the results are in the context's memory, and are valid until the next search.
+    pub fn find_k_nearest<Item, Impl, Ownership, Q>(&mut self, tree: &Tree<Item, Impl, Ownership>, needle: &Q, k: usize) -> &[(usize, D)]
+    where
+        Item: MetricSpace<Impl, Distance = D> + Clone + Borrow<Q>,
+        Ownership: HoldsUserData<UserData = Item::UserData>,
+        Q: MetricSpace<Impl, UserData = Item::UserData, Distance = D> + ?Sized,
+        D: Bounded,
+    {
+        tree.find_k_nearest_into(needle, k, &mut self.results);
+        &self.results
+    }
+
+    /// Like `Tree::find_within_radius`, but the results are in the context's memory, and are valid until the next search.
+    pub fn find_within_radius<Item, Impl, Ownership, Q>(&mut self, tree: &Tree<Item, Impl, Ownership>, needle: &Q, radius: D) -> &[(usize, D)]
+    where
+        Item: MetricSpace<Impl, Distance = D> + Clone + Borrow<Q>,
+        Ownership: HoldsUserData<UserData = Item::UserData>,
+        Q: MetricSpace<Impl, UserData = Item::UserData, Distance = D> + ?Sized,
+    {
+        tree.find_within_radius_into(needle, radius, &mut self.results);
+        &self.results
+    }
+}
+
+impl<D> Default for QueryContext<D> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
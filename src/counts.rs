@@ -0,0 +1,206 @@
+//! Counting and sampling items within a radius, without listing all of them.
+//!
+//! Every node knows how many items its subtree has. All items in the near subtree of a node are at most the node's radius
+//! away from its vantage point, so when that subtree fits entirely in the search radius, it's counted without visiting it.
+
+use crate::{DistanceAdd, HoldsUserData, MetricSpace, Owned, SplitMix64, Tree, LEAF_SIZE, NO_NODE};
+use alloc::collections::BTreeSet;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+#[cfg(feature = "serde")]
+use crate::Error;
+#[cfg(feature = "serde")]
+use core::convert::TryFrom;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A tree that can count items within a radius, and pick a random sample of them. Created with `Tree::with_counts`.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+/// let points: Vec<_> = (0..1000).map(|i| Point(i as f32)).collect();
+/// let tree = vpsearch::Tree::new(&points).with_counts();
+/// assert_eq!(tree.count_within_radius(&Point(500.), 100.), 201);
+///
+/// let sample = tree.sample_within_radius(&Point(500.), 100., 10, 1234);
+/// assert_eq!(sample.len(), 10);
+/// assert!(sample.iter().all(|&idx| (400..=600).contains(&idx)));
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "CountedTreeData<Item, Impl, Ownership>"))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "Item: Serialize, Item::Distance: Serialize, Ownership: Serialize",
+    deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>, Ownership: Deserialize<'de>",
+)))]
+pub struct CountedTree<Item: MetricSpace<Impl> + Clone, Impl = (), Ownership = Owned<()>> {
+    tree: Tree<Item, Impl, Ownership>,
+    /// Number of items in the subtree of each node, including the node
+    counts: Vec<u32>,
+}
+
+/// Same fields as `CountedTree`, which is checked after they're deserialized
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "CountedTree")]
+#[serde(bound(deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>, Ownership: Deserialize<'de>"))]
+struct CountedTreeData<Item: MetricSpace<Impl> + Clone, Impl, Ownership> {
+    tree: Tree<Item, Impl, Ownership>,
+    counts: Vec<u32>,
+}
+
+#[cfg(feature = "serde")]
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> TryFrom<CountedTreeData<Item, Impl, Ownership>> for CountedTree<Item, Impl, Ownership> {
+    type Error = Error;
+
+    /// Sampling walks down the subtrees by their counts, so they have to be exact, not only one for each node
+    fn try_from(CountedTreeData { tree, counts }: CountedTreeData<Item, Impl, Ownership>) -> Result<Self, Error> {
+        let counted = tree.with_counts();
+        if counted.counts != counts {
+            return Err(Error::InvalidTree);
+        }
+        Ok(counted)
+    }
+}
+
+/// Items within the radius, found either one by one, or as whole subtrees
+pub(crate) enum Found {
+    Item(usize),
+    Subtree(u32),
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// Adds the number of items in every subtree to the tree, for `count_within_radius` and `sample_within_radius`
+    pub fn with_counts(self) -> CountedTree<Item, Impl, Ownership> {
+        let counts = self.aggregate_nodes(|_| 1, |a, b| a + b);
+        CountedTree { tree: self, counts }
+    }
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Gives all items within the radius to `on_found`, with whole subtrees in place of their items when they're entirely within the radius
+    pub(crate) fn visit_within_radius<Q>(&self, needle: &Q, radius: Item::Distance, mut on_found: impl FnMut(Found)) where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        let user_data = self.user_data.user_data();
+        let mut stack = vec![self.root];
+        while let Some(next) = stack.pop() {
+            let node = match self.nodes.get(next as usize) {
+                Some(node) => node,
+                None => continue,
+            };
+            let distance = needle.distance(node.vantage_point.borrow(), user_data);
+            if distance <= radius {
+                on_found(Found::Item(node.idx as usize));
+            }
+
+            if let Some(bucket) = node.bucket() {
+                let items = &self.buckets.items[bucket.clone()];
+                let mut distances = [distance; LEAF_SIZE];
+                let distances = &mut distances[..items.len()];
+                needle.distance_to_many(items, distances, user_data);
+                for (&idx, &distance) in self.buckets.idx[bucket].iter().zip(distances.iter()) {
+                    if distance <= radius {
+                        on_found(Found::Item(idx as usize));
+                    }
+                }
+                continue;
+            }
+
+            if node.near != NO_NODE && distance.saturating_add(node.radius) <= radius {
+                on_found(Found::Subtree(node.near));
+            } else if distance <= node.radius.saturating_add(radius) {
+                stack.push(node.near);
+            }
+            if distance.saturating_add(radius) >= node.radius {
+                stack.push(node.far);
+            }
+        }
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> CountedTree<Item, Impl, Ownership> {
+    /// The tree for other searches
+    #[inline]
+    pub fn tree(&self) -> &Tree<Item, Impl, Ownership> {
+        &self.tree
+    }
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> CountedTree<Item, Impl, Ownership> {
+    /// Number of items at most `radius` away from the `needle`
+    pub fn count_within_radius<Q>(&self, needle: &Q, radius: Item::Distance) -> usize where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.find_within_radius(needle, radius).iter().map(|found| self.len_of(found)).sum()
+    }
+
+    /// Picks `size` random items out of all items at most `radius` away from the `needle`. Every item within the radius is equally likely to be picked.
+    ///
+    /// Returns indexes of the items, in no particular order. If there are fewer than `size` items within the radius, returns all of them.
+    /// The same `seed` gives the same sample.
+    pub fn sample_within_radius<Q>(&self, needle: &Q, radius: Item::Distance, size: usize, seed: u64) -> Vec<usize> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        let found = self.find_within_radius(needle, radius);
+        let total: usize = found.iter().map(|found| self.len_of(found)).sum();
+
+        // Floyd's algorithm picks distinct positions without shuffling all of them
+        let mut rng = SplitMix64(seed);
+        let mut positions = BTreeSet::new();
+        for end in total - size.min(total) + 1..=total {
+            let position = rng.below(end);
+            if !positions.insert(position) {
+                positions.insert(end - 1);
+            }
+        }
+
+        let mut sample = Vec::with_capacity(positions.len());
+        let mut positions = positions.into_iter().peekable();
+        let mut start = 0;
+        for found in &found {
+            let len = self.len_of(found);
+            while let Some(position) = positions.next_if(|&position| position < start + len) {
+                sample.push(match *found {
+                    Found::Item(idx) => idx,
+                    Found::Subtree(node) => self.nth_item(node, position - start),
+                });
+            }
+            start += len;
+        }
+        sample
+    }
+
+    /// Items within the radius, with whole subtrees in place of their items when they're entirely within the radius
+    fn find_within_radius<Q>(&self, needle: &Q, radius: Item::Distance) -> Vec<Found> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        let mut found = Vec::new();
+        self.tree.visit_within_radius(needle, radius, |item| found.push(item));
+        found
+    }
+
+    fn len_of(&self, found: &Found) -> usize {
+        match *found {
+            Found::Item(_) => 1,
+            Found::Subtree(node) => self.counts[node as usize] as usize,
+        }
+    }
+
+    /// Index of the `n`th item in the subtree, in the order of nodes
+    fn nth_item(&self, mut node: u32, mut n: usize) -> usize {
+        loop {
+            let current = &self.tree.nodes[node as usize];
+            if n == 0 {
+                return current.idx as usize;
+            }
+            n -= 1;
+            if let Some(bucket) = current.bucket() {
+                return self.tree.buckets.idx[bucket][n] as usize;
+            }
+            let near_len = self.counts.get(current.near as usize).map_or(0, |&count| count as usize);
+            if n < near_len {
+                node = current.near;
+            } else {
+                n -= near_len;
+                node = current.far;
+            }
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use super::*;
 
-use std::fmt::{Debug,Formatter,Error};
+use core::fmt::{Debug,Formatter,Error};
 impl<Item: Debug + Clone + MetricSpace<UserImpl>, UserImpl, Ownership> Debug for Tree<Item, UserImpl, Ownership> {
     fn fmt(&self, f:&mut Formatter<'_>) -> Result<(),Error> {
         write!(f, "digraph \"vp tree.dot\" {{\n{:?}}}", self.root)
@@ -9,6 +9,10 @@ impl<Item: Debug + Clone + MetricSpace<UserImpl>, UserImpl, Ownership> Debug for
 
 impl<Item: Debug + Clone + MetricSpace<UserImpl>, UserImpl> Debug for Node<Item, UserImpl> {
     fn fmt(&self, f:&mut Formatter<'_>) -> Result<(),Error> {
+        // Buckets aren't nodes
+        if self.bucket().is_some() {
+            return Ok(());
+        }
         if self.near != NO_NODE {
             write!(f, "\"{:?}\" -> \"{:?}\"\n", self.vantage_point, self.near)?;
         }
@@ -0,0 +1,59 @@
+//! Comparing two trees, e.g. an old and a new snapshot of the same index.
+
+use crate::{MetricSpace, Tree};
+use alloc::vec::Vec;
+
+/// Differences between two trees, returned by `Tree::diff()`. Items are compared by their index.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TreeDiff {
+    /// Indexes of items that only the other tree has
+    pub added: Vec<usize>,
+    /// Indexes of items that only this tree has
+    pub removed: Vec<usize>,
+    /// Indexes of items that are different in the other tree
+    pub changed: Vec<usize>,
+    /// Fraction of nodes that have a different vantage point, radius or children in the other tree, from `0` to `1`.
+    ///
+    /// Trees built from the same items the same way have `0`. When items are the same, but the structure isn't,
+    /// the trees have been built differently, e.g. by a different version of the crate.
+    pub structure_drift: f64,
+}
+
+impl TreeDiff {
+    /// `true` if the trees have the same items and the same structure
+    pub fn is_identical(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty() && self.structure_drift == 0.
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// Compares items and structure of this tree with the `other` tree, e.g. to check that a loaded snapshot is the expected one.
+    ///
+    /// Items are matched by their index, so the trees should have been created from the same list of items,
+    /// possibly with some items changed, or added at the end.
+    pub fn diff<OtherOwnership>(&self, other: &Tree<Item, Impl, OtherOwnership>) -> TreeDiff where Item: PartialEq {
+        let items = self.items_by_index();
+        let other_items = other.items_by_index();
+
+        let changed = items.iter().zip(&other_items).enumerate()
+            .filter(|(_, (item, other_item))| item != other_item)
+            .map(|(idx, _)| idx)
+            .collect();
+
+        let different_nodes = self.nodes.iter().zip(&other.nodes)
+            .filter(|(node, other_node)| {
+                node.idx != other_node.idx || node.near != other_node.near || node.far != other_node.far || node.radius != other_node.radius
+            })
+            .count();
+        let max_nodes = self.nodes.len().max(other.nodes.len());
+        // Nodes that only one of the trees has are different too
+        let different_nodes = different_nodes + max_nodes - self.nodes.len().min(other.nodes.len());
+
+        TreeDiff {
+            added: (items.len()..other_items.len()).collect(),
+            removed: (other_items.len()..items.len()).collect(),
+            changed,
+            structure_drift: if max_nodes > 0 { different_nodes as f64 / max_nodes as f64 } else { 0. },
+        }
+    }
+}
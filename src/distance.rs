@@ -0,0 +1,99 @@
+//! Arithmetic the search needs from `MetricSpace::Distance`.
+
+/// Addition of distances that can't overflow.
+///
+/// The search adds distances together to decide which parts of the tree to skip, and one of them is often `max_value()`.
+/// Integer and fixed-point sums must saturate rather than wrap around (which would skip the wrong parts of the tree) or panic.
+/// For floats it's the regular addition, which goes to infinity instead of overflowing.
+///
+/// It's implemented for all primitive numbers, pairs and triples of distances (compared lexicographically), and for `fixed`, `half` and `ordered-float` types when their features are enabled.
+/// If your distance is a custom type, implement it like this:
+///
+/// ```rust
+/// # #[derive(Copy, Clone, PartialEq, PartialOrd)] struct MyDistance(u16);
+/// impl vpsearch::DistanceAdd for MyDistance {
+///     fn saturating_add(self, other: Self) -> Self {
+///         MyDistance(self.0.saturating_add(other.0))
+///     }
+/// }
+/// ```
+pub trait DistanceAdd: Sized {
+    /// `self + other`, but if the sum is too large to represent, it's the largest value instead
+    fn saturating_add(self, other: Self) -> Self;
+}
+
+macro_rules! impl_saturating_int {
+    ($($t:ty),*) => {$(
+        impl DistanceAdd for $t {
+            #[inline(always)]
+            fn saturating_add(self, other: Self) -> Self {
+                <$t>::saturating_add(self, other)
+            }
+        }
+    )*};
+}
+
+impl_saturating_int!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+macro_rules! impl_float {
+    ($($t:ty),*) => {$(
+        impl DistanceAdd for $t {
+            #[inline(always)]
+            fn saturating_add(self, other: Self) -> Self {
+                self + other
+            }
+        }
+    )*};
+}
+
+impl_float!(f32, f64);
+
+/// Tuples are compared lexicographically, so the first element is the main distance, and the others only break ties.
+/// They're added element-wise, which keeps the triangle inequality as long as every element is a metric.
+macro_rules! impl_tuple {
+    ($($t:ident $n:tt),+) => {
+        impl<$($t: DistanceAdd),+> DistanceAdd for ($($t,)+) {
+            #[inline(always)]
+            fn saturating_add(self, other: Self) -> Self {
+                ($(self.$n.saturating_add(other.$n),)+)
+            }
+        }
+    };
+}
+
+impl_tuple!(A 0, B 1);
+impl_tuple!(A 0, B 1, C 2);
+#[cfg(feature = "half")]
+impl_float!(half::f16, half::bf16);
+
+#[cfg(feature = "ordered-float")]
+impl<T: ordered_float::FloatCore> DistanceAdd for ordered_float::NotNan<T> {
+    #[inline(always)]
+    fn saturating_add(self, other: Self) -> Self {
+        // infinity + infinity isn't NaN, and distances aren't negative, so this never panics
+        self + other
+    }
+}
+
+#[cfg(feature = "ordered-float")]
+impl<T: ordered_float::FloatCore> DistanceAdd for ordered_float::OrderedFloat<T> {
+    #[inline(always)]
+    fn saturating_add(self, other: Self) -> Self {
+        self + other
+    }
+}
+
+#[cfg(feature = "fixed")]
+macro_rules! impl_fixed {
+    ($($t:ident),*) => {$(
+        impl<Frac> DistanceAdd for fixed::$t<Frac> where Self: num_traits::SaturatingAdd {
+            #[inline(always)]
+            fn saturating_add(self, other: Self) -> Self {
+                num_traits::SaturatingAdd::saturating_add(&self, &other)
+            }
+        }
+    )*};
+}
+
+#[cfg(feature = "fixed")]
+impl_fixed!(FixedU8, FixedU16, FixedU32, FixedU64, FixedU128, FixedI8, FixedI16, FixedI32, FixedI64, FixedI128);
@@ -0,0 +1,41 @@
+use core::fmt;
+
+/// Reasons why a tree can't be created or searched. See `Tree::try_new`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// There are no items to search
+    Empty,
+    /// The tree can hold at most 2^31 items
+    TooManyItems,
+    /// `MetricSpace::distance()` returned a value that can't be compared, such as NaN
+    InvalidDistance,
+    /// `MetricSpace::distance()` has panicked. See `Tree::catch`.
+    MetricPanicked,
+    /// The search has been stopped before it finished. See `Tree::find_k_nearest_cancellable`.
+    Cancelled,
+    /// Distances computed with new user data are different than with the tree's user data. See `Tree::replace_user_data_checked`.
+    DistancesChanged,
+    /// Deserialized data has indexes out of bounds, is too deep, or doesn't have the data of every item or node, so it's not a tree that can be searched
+    InvalidTree,
+    /// Even the smallest layout of the items doesn't fit in the memory budget. See `BudgetedTree::new`.
+    OverBudget,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Error::Empty => "no items to create the tree from",
+            Error::TooManyItems => "too many items for the tree (the limit is 2^31)",
+            Error::InvalidDistance => "distance between items is not comparable (NaN)",
+            Error::MetricPanicked => "distance computation has panicked",
+            Error::Cancelled => "search has been cancelled",
+            Error::DistancesChanged => "distances are different with the new user data",
+            Error::InvalidTree => "the data is not a valid tree (indexes out of bounds, too deep, or missing data of items)",
+            Error::OverBudget => "the tree doesn't fit in the memory budget",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
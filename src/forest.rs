@@ -0,0 +1,146 @@
+//! Searching several trees of the same items at once, e.g. trees built with `Tree::new_shuffled` with different seeds.
+//!
+//! Trees with different vantage points skip different items, so when the number of distance computations is limited,
+//! several trees find the nearest items more often than one. It helps with items of many dimensions, where a single tree prunes poorly.
+//!
+//! Instead of searching the trees one after another, the nodes of all trees are visited in one queue,
+//! starting from the nodes whose items could be the nearest to the needle, and a distance computed in one tree isn't computed again in the others.
+
+use crate::{BestCandidate, DistanceAdd, HoldsUserData, KNearest, MetricSpace, Tree};
+use alloc::collections::btree_map::Entry;
+use alloc::collections::{BTreeMap, BinaryHeap};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use num_traits::{Bounded, ToPrimitive};
+
+/// Finds the `k` nearest items in trees that have been created from the same items, computing at most `max_distance_evaluations` distances.
+///
+/// Returns `(index, distance)` of the nearest items found, and `true` if the search has finished, so they're exactly the `k` nearest items.
+/// The budget is shared by all trees: the search always continues in the node, of any tree, that may have the nearest items,
+/// judging by the lower bound of their distance to the needle. Every item's distance is computed at most once.
+///
+/// With one tree, it's like `Tree::find_k_nearest_budgeted`, but visits the nodes in a different order.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32, f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).hypot(self.1 - other.1) }
+/// # }
+/// let points: Vec<_> = (0..1000).map(|i| Point((i % 40) as f32, (i / 40) as f32)).collect();
+/// let forest: Vec<_> = (0..4).map(|seed| vpsearch::Tree::new_shuffled(&points, seed)).collect();
+/// let (nearest, _exact) = vpsearch::find_k_nearest_in_forest(&forest, &Point(3.1, 4.9), 1, 100);
+/// assert_eq!(nearest[0].0, 3 + 5 * 40);
+/// ```
+pub fn find_k_nearest_in_forest<Item, Impl, Ownership, Q>(trees: &[Tree<Item, Impl, Ownership>], needle: &Q, k: usize, max_distance_evaluations: usize) -> (Vec<(usize, Item::Distance)>, bool)
+where
+    Item: MetricSpace<Impl> + Clone + Borrow<Q>,
+    Ownership: HoldsUserData<UserData = Item::UserData>,
+    Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized,
+    Item::Distance: Bounded + ToPrimitive,
+{
+    let first = match trees.first() {
+        Some(first) => first,
+        None => return (Vec::new(), true),
+    };
+    let mut best_candidate = KNearest::<Item, Impl>::new(k);
+    let mut distances = BTreeMap::new();
+    let mut remaining = max_distance_evaluations;
+    let mut exact = true;
+
+    let mut probes: BinaryHeap<Probe<Item::Distance>> = trees.iter().enumerate()
+        .filter(|(_, tree)| (tree.root as usize) < tree.nodes.len())
+        .map(|(tree, t)| Probe { lower_bound: 0., depth: 0, tree, node: t.root, parent: None })
+        .collect();
+
+    while let Some(probe) = probes.pop() {
+        let tree = &trees[probe.tree];
+        let user_data = tree.user_data.user_data();
+        // The bound may have shrunk since the probe has been queued
+        if let Some((parent, distance, far)) = probe.parent {
+            let parent = &tree.nodes[parent as usize];
+            let bound = BestCandidate::<Item, Impl>::distance(&best_candidate);
+            let visit = if far {
+                distance.saturating_add(bound) >= parent.radius
+            } else {
+                distance <= parent.radius.saturating_add(bound)
+            };
+            if !visit {
+                continue;
+            }
+        }
+
+        let node = &tree.nodes[probe.node as usize];
+        let bucket = node.bucket();
+        let bucket_idx = bucket.clone().map_or(&[][..], |bucket| &tree.buckets.idx[bucket]);
+        let cost = usize::from(!distances.contains_key(&node.idx)) + bucket_idx.iter().filter(|idx| !distances.contains_key(*idx)).count();
+        if cost > remaining {
+            exact = false;
+            continue;
+        }
+        remaining -= cost;
+
+        let distance = match distances.entry(node.idx) {
+            Entry::Occupied(known) => *known.get(),
+            Entry::Vacant(entry) => {
+                let distance = needle.distance(node.vantage_point.borrow(), user_data);
+                entry.insert(distance);
+                best_candidate.consider(&node.vantage_point, distance, node.idx as usize, user_data);
+                distance
+            },
+        };
+
+        if let Some(bucket) = bucket {
+            for (item, &idx) in tree.buckets.items[bucket].iter().zip(bucket_idx) {
+                if let Entry::Vacant(entry) = distances.entry(idx) {
+                    let distance = needle.distance(item.borrow(), user_data);
+                    entry.insert(distance);
+                    best_candidate.consider(item, distance, idx as usize, user_data);
+                }
+            }
+            continue;
+        }
+
+        // Triangle inequality: near items are at most `radius` from the vantage point, far ones at least
+        let (needle_distance, radius) = (distance.to_f64().unwrap_or(0.), node.radius.to_f64().unwrap_or(0.));
+        for &(child, far, lower_bound) in &[(node.near, false, needle_distance - radius), (node.far, true, radius - needle_distance)] {
+            if (child as usize) < tree.nodes.len() {
+                probes.push(Probe { lower_bound: lower_bound.max(probe.lower_bound), depth: probe.depth + 1, tree: probe.tree, node: child, parent: Some((probe.node, distance, far)) });
+            }
+        }
+    }
+    (best_candidate.result(first.user_data.user_data()), exact)
+}
+
+/// A node to visit, ordered so that the `BinaryHeap` gives the lowest `lower_bound` first
+struct Probe<D> {
+    /// The closest the node's items could be to the needle
+    lower_bound: f64,
+    /// Nodes on the path to the needle all have the same `lower_bound`, and going down first finds close items sooner
+    depth: u8,
+    tree: usize,
+    node: u32,
+    /// `(parent node, distance from the needle to the parent, whether the node is the far child)`
+    parent: Option<(u32, D, bool)>,
+}
+
+impl<D> PartialEq for Probe<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<D> Eq for Probe<D> {}
+
+impl<D> PartialOrd for Probe<D> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<D> Ord for Probe<D> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.lower_bound.partial_cmp(&self.lower_bound).unwrap_or(Ordering::Equal).then(self.depth.cmp(&other.depth))
+    }
+}
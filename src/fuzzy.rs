@@ -0,0 +1,80 @@
+//! Spelling suggestions: finding words within a few edits of a query.
+
+use crate::metrics::Levenshtein;
+use crate::{AttributeTree, BestCandidate, KNearest, KNearestItems, Tree};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::iter::FromIterator;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Index of words for spell-checking and fuzzy autocomplete, using the `Levenshtein` edit distance.
+///
+/// Words that differ in length by more than the allowed number of edits can't match, so the index knows
+/// the range of word lengths in every subtree, and skips subtrees with only too short or too long words.
+///
+/// ```rust
+/// let index: vpsearch::FuzzyStringIndex = vec!["apple", "apply", "maple", "banana"].into_iter().collect();
+/// let suggestions = index.suggest("appel", 2, 10);
+/// assert_eq!(suggestions, [("apple", 2), ("apply", 2)]);
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FuzzyStringIndex {
+    /// The attribute is the length of the word in `char`s
+    tree: AttributeTree<String, u32, Levenshtein>,
+}
+
+impl FuzzyStringIndex {
+    /// Indexes the words. Their indexes are in the order of the iterator.
+    ///
+    /// Panics if there are more than 2 billion words.
+    pub fn new<I: IntoIterator<Item = S>, S: Into<String>>(words: I) -> Self {
+        let words: Vec<String> = words.into_iter().map(Into::into).collect();
+        let lengths = words.iter().map(|word| word.chars().count() as u32).collect();
+        Self { tree: Tree::new(&words).with_attribute(lengths) }
+    }
+
+    /// Finds up to `limit` words at most `max_edits` edits away from the `query`.
+    ///
+    /// Returns `(word, edits)` sorted by the number of edits, closest first. Ties are in an arbitrary order.
+    pub fn suggest(&self, query: &str, max_edits: u32, limit: usize) -> Vec<(&str, u32)> {
+        self.search(query, max_edits, KNearestItems::with_max_distance(limit, max_edits))
+            .into_iter()
+            .map(|(word, edits)| (word.as_str(), edits))
+            .collect()
+    }
+
+    /// Like `suggest`, but returns `(index, edits)`, with indexes of the words in the order they've been indexed
+    pub fn suggest_indexes(&self, query: &str, max_edits: u32, limit: usize) -> Vec<(usize, u32)> {
+        self.search(query, max_edits, KNearest::with_max_distance(limit, max_edits))
+    }
+
+    fn search<'tree, B: BestCandidate<'tree, String, Levenshtein>>(&'tree self, query: &str, max_edits: u32, best_candidate: B) -> B::Output {
+        let length = query.chars().count() as u32;
+        let lengths = length.saturating_sub(max_edits)..=length.saturating_add(max_edits);
+        self.tree.search_in_range(query, &lengths, best_candidate)
+    }
+
+    /// The tree of all words, e.g. for searches with custom collectors
+    #[inline]
+    pub fn tree(&self) -> &Tree<String, Levenshtein> {
+        self.tree.tree()
+    }
+
+    /// Number of indexed words
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tree.tree().len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tree.tree().is_empty()
+    }
+}
+
+impl<S: Into<String>> FromIterator<S> for FuzzyStringIndex {
+    fn from_iter<I: IntoIterator<Item = S>>(words: I) -> Self {
+        Self::new(words)
+    }
+}
@@ -0,0 +1,82 @@
+//! `MetricSpace` for `geo-types` points and coordinates (`geo` feature).
+//!
+//! `Point` and `Coord` with `f32` or `f64` coordinates can be put in the tree directly.
+//! With the default `Impl` (`Tree<Point>`) the distance is planar Euclidean, for projected coordinates.
+//! With the `metrics::Haversine` marker the distance is in meters on the Earth's surface, for longitude/latitude in degrees.
+//! Like in `geo`, `x` is the longitude and `y` is the latitude.
+//!
+//! ```rust
+//! use geo_types::Point;
+//! use vpsearch::{Tree, metrics::Haversine};
+//!
+//! let cities = vec![Point::new(2.35, 48.86), Point::new(-0.13, 51.51), Point::new(13.40, 52.52)];
+//! let tree: Tree<Point, Haversine> = Tree::new(&cities);
+//! let (index, meters) = tree.find_nearest(&Point::new(4.90, 52.37));
+//! assert_eq!(index, 1);
+//! assert!(meters > 350_000. && meters < 370_000.);
+//! ```
+
+use crate::metrics::{haversine, Haversine};
+use crate::MetricSpace;
+use geo_types::{Coord, Point};
+
+/// Converts a `geo` coordinate (`x` = longitude, `y` = latitude) to the `(latitude, longitude)` tuple used by `metrics::haversine()`.
+#[inline]
+pub fn lat_lon<T: Into<f64> + geo_types::CoordNum>(coord: impl Into<Coord<T>>) -> (f64, f64) {
+    let coord = coord.into();
+    (coord.y.into(), coord.x.into())
+}
+
+/// Converts a `(latitude, longitude)` tuple to a `geo` point (`x` = longitude, `y` = latitude).
+#[inline]
+pub fn point_from_lat_lon((lat, lon): (f64, f64)) -> Point<f64> {
+    Point::new(lon, lat)
+}
+
+macro_rules! impl_geo {
+    ($t:ty) => {
+        impl MetricSpace for Coord<$t> {
+            type UserData = ();
+            type Distance = $t;
+
+            #[inline]
+            fn distance(&self, other: &Self, _: &()) -> $t {
+                (self.x - other.x).hypot(self.y - other.y)
+            }
+        }
+
+        impl MetricSpace for Point<$t> {
+            type UserData = ();
+            type Distance = $t;
+
+            #[inline]
+            fn distance(&self, other: &Self, user_data: &()) -> $t {
+                MetricSpace::<()>::distance(&self.0, &other.0, user_data)
+            }
+        }
+
+        impl MetricSpace<Haversine> for Coord<$t> {
+            type UserData = ();
+            type Distance = $t;
+
+            /// Computed in `f64`, because `f32` isn't precise enough for short distances
+            #[inline]
+            fn distance(&self, other: &Self, _: &()) -> $t {
+                haversine(lat_lon(*self), lat_lon(*other)) as $t
+            }
+        }
+
+        impl MetricSpace<Haversine> for Point<$t> {
+            type UserData = ();
+            type Distance = $t;
+
+            #[inline]
+            fn distance(&self, other: &Self, user_data: &()) -> $t {
+                MetricSpace::<Haversine>::distance(&self.0, &other.0, user_data)
+            }
+        }
+    };
+}
+
+impl_geo!(f32);
+impl_geo!(f64);
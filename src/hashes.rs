@@ -0,0 +1,140 @@
+//! Finding near-duplicate images (or other data) by their perceptual hashes.
+
+use crate::metrics::{Hamming, Hash64};
+use crate::{MetricSpace, Tree};
+use alloc::vec::Vec;
+use core::iter::FromIterator;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Hashes added since the tree has been built are compared one by one, until there are this many of them
+const MIN_PENDING: usize = 32;
+
+/// Index of perceptual hashes compared by the number of differing bits (`metrics::Hamming`).
+///
+/// The hashes can be `Hash64`, `Hash128` or byte arrays, e.g. `[u8; 32]` for 256-bit hashes.
+/// Hashes get indexes in the order they've been added.
+///
+/// ```rust
+/// use vpsearch::Hash64;
+///
+/// let mut index = vpsearch::HashIndex::new();
+/// index.add(Hash64(0xFF00_FF00_FF00_FF00));
+/// index.add(Hash64(0x1234_5678_9ABC_DEF0));
+/// index.add(Hash64(0xFF00_FF00_FF00_FF01));
+///
+/// assert_eq!(index.find_duplicates(2), [(0, 2, 1)]);
+/// assert_eq!(index.nearest(&Hash64(0x1234_5678_9ABC_DEF1)), Some((1, 1)));
+/// ```
+///
+/// Trees can't be modified, so `add()` only collects the hashes, and the tree is rebuilt when enough of them have been added.
+/// Searches check the recently added hashes one by one. If you have all hashes up front, `collect()` them instead.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(serialize = "H: Serialize", deserialize = "H: Deserialize<'de>")))]
+pub struct HashIndex<H: MetricSpace<Hamming, UserData = (), Distance = u32> + Clone = Hash64> {
+    tree: Tree<H, Hamming>,
+    /// Added after the tree has been built. Their indexes start at `tree.len()`.
+    pending: Vec<H>,
+}
+
+impl<H: MetricSpace<Hamming, UserData = (), Distance = u32> + Clone> HashIndex<H> {
+    /// An empty index
+    pub fn new() -> Self {
+        Self { tree: Tree::new(&[]), pending: Vec::new() }
+    }
+
+    /// Adds the hash, and returns its index
+    pub fn add(&mut self, hash: H) -> usize {
+        let index = self.len();
+        self.pending.push(hash);
+        if self.pending.len() >= MIN_PENDING.max(self.tree.len() / 8) {
+            let mut hashes: Vec<H> = self.tree.items_by_index().into_iter().cloned().collect();
+            hashes.append(&mut self.pending);
+            self.tree = Tree::new(&hashes);
+        }
+        index
+    }
+
+    /// Number of hashes added
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tree.len() + self.pending.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Finds the most similar hash. Returns its index and the number of differing bits, or `None` if the index is empty.
+    ///
+    /// If there are several equally similar hashes, returns any of them.
+    pub fn nearest(&self, hash: &H) -> Option<(usize, u32)> {
+        let nearest = self.tree.find_k_nearest(hash, 1).into_iter().next();
+        self.pending_distances(hash).fold(nearest, |nearest, (idx, distance)| match nearest {
+            Some((_, min)) if min <= distance => nearest,
+            _ => Some((idx, distance)),
+        })
+    }
+
+    /// Finds all hashes that differ by at most `threshold` bits. Returns their indexes and distances, sorted by distance.
+    pub fn find_similar(&self, hash: &H, threshold: u32) -> Vec<(usize, u32)> {
+        let mut found = self.tree.find_within_radius(hash, threshold);
+        found.extend(self.pending_distances(hash).filter(|&(_, distance)| distance <= threshold));
+        found.sort_by_key(|&(_, distance)| distance);
+        found
+    }
+
+    /// Finds all pairs of hashes that differ by at most `threshold` bits, e.g. copies of the same image.
+    ///
+    /// Returns `(index, other index, distance)`, with the first index smaller than the other, sorted by the indexes.
+    pub fn find_duplicates(&self, threshold: u32) -> Vec<(usize, usize, u32)> {
+        let mut pairs = Vec::new();
+        for (idx, hash) in self.tree.items() {
+            pairs.extend(self.tree.find_within_radius(hash, threshold).into_iter().filter(|&(other, _)| other > idx).map(|(other, distance)| (idx, other, distance)));
+        }
+        let tree_len = self.tree.len();
+        for (pending_idx, hash) in self.pending.iter().enumerate() {
+            let idx = tree_len + pending_idx;
+            // Pairs of a pending hash with a hash in the tree, and with the hashes pending after it
+            pairs.extend(self.tree.find_within_radius(hash, threshold).into_iter().map(|(other, distance)| (other, idx, distance)));
+            pairs.extend(self.pending_distances(hash).skip(pending_idx + 1)
+                .filter(|&(_, distance)| distance <= threshold)
+                .map(|(other, distance)| (idx, other, distance)));
+        }
+        pairs.sort_unstable_by_key(|&(idx, other, _)| (idx, other));
+        pairs
+    }
+
+    /// The tree, without the most recently added hashes
+    #[inline]
+    pub fn tree(&self) -> &Tree<H, Hamming> {
+        &self.tree
+    }
+
+    fn pending_distances<'a>(&'a self, hash: &'a H) -> impl Iterator<Item = (usize, u32)> + 'a {
+        let tree_len = self.tree.len();
+        self.pending.iter().enumerate().map(move |(idx, pending)| (tree_len + idx, hash.distance(pending, &())))
+    }
+}
+
+impl<H: MetricSpace<Hamming, UserData = (), Distance = u32> + Clone> Default for HashIndex<H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<H: MetricSpace<Hamming, UserData = (), Distance = u32> + Clone> Extend<H> for HashIndex<H> {
+    fn extend<I: IntoIterator<Item = H>>(&mut self, hashes: I) {
+        for hash in hashes {
+            self.add(hash);
+        }
+    }
+}
+
+impl<H: MetricSpace<Hamming, UserData = (), Distance = u32> + Clone> FromIterator<H> for HashIndex<H> {
+    fn from_iter<I: IntoIterator<Item = H>>(hashes: I) -> Self {
+        let hashes: Vec<H> = hashes.into_iter().collect();
+        Self { tree: Tree::new(&hashes), pending: Vec::new() }
+    }
+}
@@ -0,0 +1,155 @@
+//! Building a tree a bit at a time, for programs that can't block their main thread for the whole build, like GUIs and games.
+//!
+//! The nodes that still need to be built are kept on a stack, instead of being built recursively.
+//! The near child is taken off the stack first, so the nodes end up in the same order, and the tree is the same as one made by `Tree::new`.
+
+use crate::{Buckets, Error, MetricSpace, Node, Owned, Tmp, Tree, NO_NODE};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+/// A tree that is being built. Created with `Tree::build_incrementally`.
+///
+/// Call `step()` until it returns `true`, then `finish()`. Or use `build_async()` in an async program.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+/// let points: Vec<_> = (0..10000).map(|i| Point(i as f32)).collect();
+/// let mut build = vpsearch::Tree::build_incrementally(&points);
+/// // Each frame of the game computes up to 1000 distances
+/// while !build.step(1000).unwrap() {
+///     println!("{:.0}%", build.progress() * 100.);
+/// }
+/// let tree = build.finish().unwrap();
+/// assert_eq!(tree.find_nearest(&Point(12.3)).0, 12);
+/// ```
+pub struct TreeBuild<'items, Item: MetricSpace<Impl> + Clone, Impl = ()> {
+    items: &'items [Item],
+    user_data: Item::UserData,
+    indexes: Vec<Tmp<Item, Impl>>,
+    /// Ranges of `indexes` that still need a node
+    pending: Vec<Pending>,
+    nodes: Vec<Node<Item, Impl>>,
+    buckets: Buckets<Item>,
+    /// Number of items that are in the nodes already
+    done: usize,
+}
+
+#[derive(Copy, Clone)]
+struct Pending {
+    start: usize,
+    end: usize,
+    /// The node that is the parent, and whether it's the far child. `None` for the root.
+    parent: Option<(u32, bool)>,
+}
+
+impl<Item: MetricSpace<Impl, UserData = ()> + Clone, Impl> Tree<Item, Impl, Owned<()>> {
+    /// Starts building a tree, which is then built by calling `TreeBuild::step()`. The tree will be the same as from `new`.
+    pub fn build_incrementally(items: &[Item]) -> TreeBuild<'_, Item, Impl> {
+        TreeBuild::new_with_user_data_owned(items, ())
+    }
+}
+
+impl<'items, Item: MetricSpace<Impl> + Clone, Impl> TreeBuild<'items, Item, Impl> {
+    /// Like `Tree::build_incrementally`, for a tree like `Tree::new_with_user_data_owned`
+    pub fn new_with_user_data_owned(items: &'items [Item], user_data: Item::UserData) -> Self {
+        // Too many items are reported by `step()`, so that every error comes from the same place
+        let indexes = Tree::<Item, Impl, ()>::initial_indexes(items, &user_data, None).unwrap_or_default();
+        let pending = if indexes.is_empty() { vec![] } else { vec![Pending { start: 0, end: indexes.len(), parent: None }] };
+        TreeBuild {
+            items,
+            user_data,
+            indexes,
+            pending,
+            nodes: Vec::new(),
+            buckets: Buckets { items: Vec::new(), idx: Vec::new() },
+            done: 0,
+        }
+    }
+
+    /// Builds more nodes, until about `max_distance_evaluations` distances have been computed. Returns `true` when the whole tree is built.
+    ///
+    /// Every step builds at least one node. If the metric panics, the panic can be caught, and the build continued with the next step.
+    /// The time a step takes is proportional to the number of distances. A single node near the root may need more distances than the limit,
+    /// because it needs a distance to every item in its subtree, so the first steps can take longer.
+    pub fn step(&mut self, max_distance_evaluations: usize) -> Result<bool, Error> {
+        if self.items.len() != self.indexes.len() {
+            return Err(Error::TooManyItems);
+        }
+        let mut evaluations = 0;
+        while let Some(&Pending { start, end, parent }) = self.pending.last() {
+            let node_idx = self.nodes.len() as u32;
+            let indexes = &mut self.indexes[start..end];
+            let split = Tree::<Item, Impl, ()>::push_node(indexes, &mut self.nodes, &mut self.buckets, self.items, &self.user_data)?;
+            // Taken off only after the node has been added, so that if the metric panics, the next step can build it again
+            self.pending.pop();
+            match parent {
+                Some((parent, false)) => self.nodes[parent as usize].near = node_idx,
+                Some((parent, true)) => self.nodes[parent as usize].far = node_idx,
+                None => {},
+            }
+            match split {
+                Some(half_idx) => {
+                    self.done += 1;
+                    evaluations += end - start - 1;
+                    let half_idx = start + half_idx;
+                    // The near child is pushed last, so that it's built first, like in `Tree::new`
+                    let children = [(half_idx, end - 1, true), (start, half_idx, false)];
+                    self.pending.extend(children.iter()
+                        .filter(|&&(start, end, _)| start < end)
+                        .map(|&(start, end, far)| Pending { start, end, parent: Some((node_idx, far)) }));
+                },
+                None => self.done += end - start,
+            }
+            if evaluations >= max_distance_evaluations {
+                break;
+            }
+        }
+        Ok(self.pending.is_empty())
+    }
+
+    /// Fraction of items that are in the tree already, from `0` to `1`
+    pub fn progress(&self) -> f32 {
+        if self.items.is_empty() {
+            return 1.;
+        }
+        self.done as f32 / self.items.len() as f32
+    }
+
+    /// Builds the rest of the tree, and returns it
+    pub fn finish(mut self) -> Result<Tree<Item, Impl, Owned<Item::UserData>>, Error> {
+        while !self.step(usize::MAX)? {}
+        let root = if self.nodes.is_empty() { NO_NODE } else { 0 };
+        Ok(Tree { nodes: self.nodes, buckets: self.buckets, root, user_data: Owned(self.user_data) })
+    }
+
+    /// Builds the tree in steps of `max_distance_evaluations`, and lets other tasks run between the steps
+    pub async fn build_async(mut self, max_distance_evaluations: usize) -> Result<Tree<Item, Impl, Owned<Item::UserData>>, Error> {
+        while !self.step(max_distance_evaluations)? {
+            YieldNow(false).await;
+        }
+        self.finish()
+    }
+}
+
+/// Returns `Pending` once, so that the executor can run other tasks
+struct YieldNow(bool);
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.0 {
+            return Poll::Ready(());
+        }
+        self.0 = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
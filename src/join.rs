@@ -0,0 +1,159 @@
+//! Finding all pairs of nearby items in a fixed amount of memory, even when there are more pairs than fit in memory,
+//! and finding the distance between the `n`th closest pair.
+//!
+//! The pairs are collected in a single buffer, which is given to the caller, e.g. to be written to a file, and reused whenever it's full.
+
+use crate::{BestCandidate, HoldsUserData, MetricSpace, Needle, Tree};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use num_traits::Bounded;
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Finds all pairs of items at most `radius` apart, and gives them to `on_block` in blocks of up to `block_size` pairs.
+    ///
+    /// Pairs are `(index, index, distance)`, each given once, with the smaller index first. The blocks are in the same order every time.
+    /// Only one block is kept in memory, so the memory used doesn't depend on the number of pairs.
+    /// `on_block` can return `false` to stop the work, and then this returns `false`.
+    ///
+    /// It's single-threaded. `self_join_each` is faster, but it keeps all pairs of a few thousand items in memory at a time.
+    ///
+    /// Panics if `block_size` is `0`.
+    ///
+    /// ```rust
+    /// # #[derive(Clone)] struct Point(f32);
+    /// # impl vpsearch::MetricSpace for Point {
+    /// #     type UserData = (); type Distance = f32;
+    /// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+    /// # }
+    /// let points: Vec<_> = (0..1000).map(|i| Point(i as f32)).collect();
+    /// let tree = vpsearch::Tree::new(&points);
+    /// let mut pairs = 0;
+    /// tree.self_join_blocks(2., 100, |block| {
+    ///     assert!(block.len() <= 100);
+    ///     pairs += block.len(); // or write them to a file
+    ///     true
+    /// });
+    /// assert_eq!(pairs, 999 + 998);
+    /// ```
+    pub fn self_join_blocks<F>(&self, radius: Item::Distance, block_size: usize, mut on_block: F) -> bool where F: FnMut(&[(usize, usize, Item::Distance)]) -> bool {
+        assert!(block_size > 0, "blocks must have room for at least one pair");
+        let user_data = self.user_data.user_data();
+        let mut writer = BlockWriter { idx: 0, radius, block: Vec::with_capacity(block_size.min(1 << 16)), block_size, on_block: &mut on_block, stopped: false };
+        for (idx, item) in self.items() {
+            writer.idx = idx;
+            self.search(self.root, &mut Needle { needle: item, user_data }, &mut writer, user_data);
+            if writer.stopped {
+                return false;
+            }
+        }
+        writer.block.is_empty() || (writer.on_block)(&writer.block)
+    }
+
+    /// Finds the `n`th smallest distance between all pairs of items, counting from `0`, e.g. to choose the radius for clustering
+    /// from the distribution of distances. Returns `(index, index, distance)` of one of the pairs at that distance, with the smaller index first,
+    /// or `None` if there are fewer than `n + 1` pairs.
+    ///
+    /// It's exact, but the pairs aren't all compared: searches for each item only look for pairs closer than the `n + 1` closest found so far.
+    /// It keeps up to `2n + 2` pairs in memory.
+    ///
+    /// ```rust
+    /// # #[derive(Clone)] struct Point(f32);
+    /// # impl vpsearch::MetricSpace for Point {
+    /// #     type UserData = (); type Distance = f32;
+    /// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+    /// # }
+    /// let points: Vec<_> = (0..1000).map(|i| Point((i * i) as f32)).collect();
+    /// let tree = vpsearch::Tree::new(&points);
+    /// // Points 0, 1, 4, 9… are 1, 3, 4, 5… apart
+    /// assert_eq!(tree.nth_pair_distance(0), Some((0, 1, 1.)));
+    /// assert_eq!(tree.nth_pair_distance(3), Some((2, 3, 5.)));
+    /// ```
+    pub fn nth_pair_distance(&self, n: usize) -> Option<(usize, usize, Item::Distance)> where Item::Distance: Bounded {
+        let user_data = self.user_data.user_data();
+        let mut collector = NthPair { idx: 0, keep: n.checked_add(1)?, bound: <Item::Distance as Bounded>::max_value(), found: Vec::new() };
+        for (idx, item) in self.items() {
+            collector.idx = idx;
+            self.search(self.root, &mut Needle { needle: item, user_data }, &mut collector, user_data);
+        }
+        if collector.found.len() <= n {
+            return None;
+        }
+        collector.found.select_nth_unstable_by(n, compare_pairs);
+        Some(collector.found[n])
+    }
+}
+
+/// Sorts pairs by distance. NaN isn't collected, so the order is total.
+#[inline]
+fn compare_pairs<D: PartialOrd>(a: &(usize, usize, D), b: &(usize, usize, D)) -> Ordering {
+    a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal)
+}
+
+/// Collects pairs of the item `idx` and later items, which may be among the `keep` closest pairs.
+///
+/// Pairs are added without sorting, and when there are twice as many as needed, the farther half is removed, and the `bound` lowered.
+struct NthPair<D> {
+    idx: usize,
+    keep: usize,
+    bound: D,
+    found: Vec<(usize, usize, D)>,
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<'tree, Item, Impl> for NthPair<Item::Distance> {
+    type Output = ();
+
+    #[inline]
+    fn consider(&mut self, _: &Item, distance: Item::Distance, candidate_index: usize, _: &Item::UserData) {
+        // NaN isn't within any distance
+        if candidate_index <= self.idx || !matches!(distance.partial_cmp(&self.bound), Some(Ordering::Less | Ordering::Equal)) {
+            return;
+        }
+        self.found.push((self.idx, candidate_index, distance));
+        if self.found.len() >= self.keep.saturating_mul(2) {
+            let last = self.keep - 1;
+            self.found.select_nth_unstable_by(last, compare_pairs);
+            self.found.truncate(self.keep);
+            self.bound = self.found[last].2;
+        }
+    }
+
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        self.bound
+    }
+
+    fn result(self, _: &Item::UserData) {}
+}
+
+/// Adds pairs of the item `idx` and later items to the `block`, and gives the block to `on_block` whenever it's full
+struct BlockWriter<'a, D, F> {
+    idx: usize,
+    radius: D,
+    block: Vec<(usize, usize, D)>,
+    block_size: usize,
+    on_block: &'a mut F,
+    stopped: bool,
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl, F> BestCandidate<'tree, Item, Impl> for BlockWriter<'_, Item::Distance, F> where F: FnMut(&[(usize, usize, Item::Distance)]) -> bool {
+    type Output = ();
+
+    #[inline]
+    fn consider(&mut self, _: &Item, distance: Item::Distance, candidate_index: usize, _: &Item::UserData) {
+        if self.stopped || candidate_index <= self.idx || !matches!(distance.partial_cmp(&self.radius), Some(Ordering::Less | Ordering::Equal)) {
+            return;
+        }
+        self.block.push((self.idx, candidate_index, distance));
+        if self.block.len() >= self.block_size {
+            self.stopped = !(self.on_block)(&self.block);
+            self.block.clear();
+        }
+    }
+
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        self.radius
+    }
+
+    fn result(self, _: &Item::UserData) {}
+}
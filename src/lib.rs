@@ -57,21 +57,224 @@
 //!     println!("The {}th element is the nearest", index);
 //! }
 //! ```
+//!
+//! Without the default `std` feature the crate is `no_std`, and only needs the `alloc` crate.
+//! The built-in `metrics` then need the `libm` feature.
+//!
+//! Stack usage is small and bounded, so the tree works with small stacks, e.g. in WASM or on embedded systems.
+//! Items are split at the median distance, so the tree is at most 32 levels deep. Building recurses at most that deep,
+//! and searches don't recurse at all: they keep the pending nodes in a fixed array of 32 entries.
+//!
+//! Searches only read the tree, so if the metric panics, the tree is still usable. `Tree::catch` turns such panics into errors.
 
+#![cfg_attr(not(feature = "std"), no_std)]
 
+extern crate alloc;
 
-use std::cmp::Ordering;
-use std::ops::Add;
-use std::marker::Sized;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::marker::Sized;
 use num_traits::Bounded;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-#[cfg(test)]
+#[cfg(all(test, feature = "std"))]
 mod test;
+mod async_search;
+mod attributes;
+mod batch;
+mod budget;
+#[cfg(any(feature = "std", feature = "libm"))]
+mod budgeted;
+mod cancel;
+mod check;
+mod checked;
+mod context;
+mod counts;
+#[allow(clippy::needless_return, clippy::write_with_newline)]
 mod debug;
+mod diff;
+mod distance;
+mod error;
+mod forest;
+#[cfg(any(feature = "std", feature = "libm"))]
+mod fuzzy;
+#[cfg(any(feature = "std", feature = "libm"))]
+mod hashes;
+mod incremental;
+mod join;
+pub mod collectors;
+#[cfg(any(feature = "std", feature = "libm"))]
+pub mod metrics;
+pub mod prelude;
+#[cfg(feature = "arrow")]
+pub mod arrow;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "ndarray")]
+pub mod ndarray;
+#[cfg(feature = "nalgebra")]
+mod nalgebra;
+#[cfg(feature = "geo")]
+pub mod geo;
+#[cfg(feature = "std")]
+mod parallel;
+#[cfg(feature = "std")]
+mod query_cache;
+#[cfg(feature = "std")]
+mod query_log;
+#[cfg(feature = "rand_core")]
+mod rng;
+mod map;
+mod mst;
+mod nodes;
+mod pages;
+#[cfg(feature = "std")]
+mod panic;
+#[cfg(feature = "std")]
+mod report;
+mod sample;
+mod session;
+mod side_data;
+#[cfg(any(feature = "std", feature = "libm"))]
+mod stats;
+mod streamed;
+mod superset;
+mod tags;
+mod tracking;
+mod user_data;
+#[cfg(feature = "serde")]
+mod validate;
+mod values;
+mod warm;
+mod weights;
+
+pub use crate::async_search::AsyncMetricSpace;
+pub use crate::attributes::AttributeTree;
+#[cfg(any(feature = "std", feature = "libm"))]
+pub use crate::budgeted::{BudgetReport, BudgetedTree, StorageLayout};
+pub use crate::check::{check_metric, MetricCheck};
+pub use crate::checked::PruningMistake;
+pub use crate::context::QueryContext;
+pub use crate::counts::CountedTree;
+pub use crate::collectors::{Filter, KNearest, KNearestArray, KNearestDistinct, KNearestItems, KNearestScored, WithinRadius};
+pub use crate::diff::TreeDiff;
+pub use crate::forest::find_k_nearest_in_forest;
+pub use crate::distance::DistanceAdd;
+pub use crate::error::Error;
+#[cfg(any(feature = "std", feature = "libm"))]
+pub use crate::fuzzy::FuzzyStringIndex;
+#[cfg(any(feature = "std", feature = "libm"))]
+pub use crate::hashes::HashIndex;
+pub use crate::incremental::TreeBuild;
+pub use crate::map::{VpMap, VpMapBuilder};
+#[cfg(feature = "std")]
+pub use crate::query_cache::{CacheStats, QueryCache};
+#[cfg(feature = "std")]
+pub use crate::query_log::{LoggedQuery, QueryLog, ReplayReport};
+#[cfg(feature = "std")]
+pub use crate::report::{BuildReport, BuildWarning};
+#[cfg(feature = "rand_core")]
+pub use crate::rng::check_metric_with_rng;
+// Marker types of the built-in metrics, also available in the `metrics` module
+#[cfg(any(feature = "std", feature = "libm"))]
+pub use crate::metrics::{Chebyshev, Euclidean, Hamming, Hash128, Hash64, Haversine, Levenshtein, Manhattan, Xor};
+pub use crate::nodes::NodeInfo;
+pub use crate::pages::{Page, PageCursor};
+pub use crate::session::QuerySession;
+#[cfg(any(feature = "std", feature = "libm"))]
+pub use crate::stats::NeighborStats;
+pub use crate::tags::TaggedTree;
+pub use crate::tracking::Tracker;
+pub use crate::values::{RemappedTree, TreeWithValues};
+pub use crate::weights::WeightedTree;
+
+/// Implements `MetricSpace` as the Euclidean distance over the struct's numeric fields (`derive` feature).
+///
+/// ```rust
+/// #[derive(vpsearch::MetricSpace)]
+/// struct Point {
+///     x: f32,
+///     #[vpsearch(weight = 4.0)]
+///     y: f32,
+///     #[vpsearch(skip)]
+///     label: &'static str,
+/// }
+/// ```
+///
+/// The distance is `sqrt(Σ weight·(a-b)²)`. Use `#[vpsearch(distance = f64)]` on the struct to choose the `Distance` type.
+#[cfg(feature = "derive")]
+pub use vpsearch_derive::MetricSpace;
+
+/// Implements `DistanceAdd`, `num_traits::Bounded` and `num_traits::ToPrimitive` for a newtype of a number (`derive` feature).
+///
+/// It's for distances with units, which can't be mixed up with other numbers. `Copy`, `PartialEq` and `PartialOrd` need to be derived too.
+///
+/// ```rust
+/// #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, vpsearch::Distance)]
+/// struct Meters(f64);
+///
+/// #[derive(Clone)]
+/// struct Station { x: f64, y: f64 }
+/// impl vpsearch::MetricSpace for Station {
+///     type UserData = ();
+///     type Distance = Meters;
+///     fn distance(&self, other: &Self, _: &()) -> Meters {
+///         Meters((self.x - other.x).hypot(self.y - other.y))
+///     }
+/// }
+///
+/// let tree = vpsearch::Tree::new(&[Station { x: 0., y: 0. }, Station { x: 30., y: 40. }]);
+/// assert_eq!(tree.find_nearest(&Station { x: 20., y: 30. }), (1, Meters(10f64.hypot(10.))));
+/// ```
+#[cfg(feature = "derive")]
+pub use vpsearch_derive::Distance;
 
+// For the `Distance` derive, so that users don't need to depend on `num-traits` themselves
 #[doc(hidden)]
+pub use num_traits as __num_traits;
+
+// Lets the derive's `::vpsearch::` paths work in this crate's own tests
+#[cfg(all(test, feature = "derive"))]
+extern crate self as vpsearch;
+
+#[doc(hidden)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Owned<T>(T);
 
+/// Ownership of user data shared with other trees or the rest of the program. See `Tree::new_with_user_data_shared`.
+#[doc(hidden)]
+pub struct Shared<T>(Arc<T>);
+
+/// Tree ownership variants that keep the user data inside the tree, so it doesn't need to be passed to searches
+#[doc(hidden)]
+pub trait HoldsUserData {
+    type UserData;
+    fn user_data(&self) -> &Self::UserData;
+}
+
+impl<T> HoldsUserData for Owned<T> {
+    type UserData = T;
+
+    #[inline(always)]
+    fn user_data(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> HoldsUserData for Shared<T> {
+    type UserData = T;
+
+    #[inline(always)]
+    fn user_data(&self) -> &T {
+        &self.0
+    }
+}
+
 /// Elements you're searching for must be comparable using this trait.
 ///
 /// You can ignore `UserImplementationType` if you're implementing `MetricSpace` for your custom type.
@@ -86,10 +289,23 @@ pub struct Owned<T>(T);
 /// impl MetricSpace<MyXYCoordinates> for (f32,f32) {/*…*/}
 pub trait MetricSpace<UserImplementationType = ()> {
     /// This is used as a context for comparisons. Use `()` if the elements already contain all the data you need.
+    ///
+    /// Searches only get a shared reference to it, so to collect statistics or count calls, use atomics or `Cell`s inside it.
+    /// A tree can be searched from many threads at once, as long as the user data is `Sync` (so atomics, not `Cell`s).
     type UserData;
 
     /// This is a fancy way of saying it should be `f32` or `u32`
-    type Distance: Copy + PartialOrd + Bounded + Add<Output = Self::Distance>;
+    ///
+    /// Integers are fine, because distances are added with `DistanceAdd`, which saturates instead of overflowing.
+    ///
+    /// If it implements `num_traits::Bounded`, searches can start from `max_value()`. Otherwise use the `_bounded` variants
+    /// of the search methods, which take the largest distance to consider instead.
+    ///
+    /// Types with a total order, like `ordered_float::NotNan<f32>` or `OrderedFloat<f64>` (`ordered-float` feature), work too.
+    /// With them, the tree never has to deal with NaN. Otherwise NaN distances are rejected when building the tree,
+    /// and a needle with a NaN distance panics in debug builds (in release builds it just doesn't match anything).
+    /// Fixed-point numbers from the `fixed` crate are supported with the `fixed` feature.
+    type Distance: Copy + PartialOrd + DistanceAdd;
 
     /**
      * This function must return distance between two items that meets triangle inequality.
@@ -98,10 +314,68 @@ pub trait MetricSpace<UserImplementationType = ()> {
      * * `user_data` —Whatever you want. Passed from `new_with_user_data_*()`
      */
     fn distance(&self, other: &Self, user_data: &Self::UserData) -> Self::Distance;
+
+    /// Distances from `self` to each of `others`, written to `out`, which has the same length as `others`.
+    ///
+    /// Searches compare the needle with small buckets of items at the bottom of the tree using this method.
+    /// By default it calls `distance()` for each item, but you can override it to compute them together, e.g. with SIMD.
+    #[inline]
+    fn distance_to_many<O: Borrow<Self>>(&self, others: &[O], out: &mut [Self::Distance], user_data: &Self::UserData) {
+        for (other, out) in others.iter().zip(out) {
+            *out = self.distance(other.borrow(), user_data);
+        }
+    }
+}
+
+/// Distance that can also use a per-query context, e.g. a cache or statistics for a single request.
+///
+/// The tree is built using `MetricSpace::distance()`, and searched using `distance_with_context()` (via `*_with_context` search methods).
+/// Unlike `UserData`, the context isn't stored in the tree, and each query can use a different one.
+///
+/// The context is mutable, so it can also be a scratch space for metrics that need temporary buffers (like `metrics::LevenshteinScratch`).
+/// It's created once per search, rather than allocated on every call to `distance()`.
+///
+/// The context must not change the distances. If it does (e.g. per-query weights), the tree's structure no longer matches
+/// the metric, and the results are only approximate.
+pub trait MetricSpaceWithContext<Context, UserImplementationType = ()>: MetricSpace<UserImplementationType> {
+    fn distance_with_context(&self, other: &Self, user_data: &Self::UserData, context: &mut Context) -> Self::Distance;
 }
 
+macro_rules! impl_metric_space_for_pointer {
+    ($($ptr:ty),*) => {$(
+        /// Forwards to the impl of the pointed-to type, so that trees can store pointers without a newtype wrapper
+        impl<T: MetricSpace<Impl> + ?Sized, Impl> MetricSpace<Impl> for $ptr {
+            type UserData = T::UserData;
+            type Distance = T::Distance;
+
+            #[inline(always)]
+            fn distance(&self, other: &Self, user_data: &Self::UserData) -> Self::Distance {
+                T::distance(self, other, user_data)
+            }
+
+            #[inline(always)]
+            fn distance_to_many<O: Borrow<Self>>(&self, others: &[O], out: &mut [Self::Distance], user_data: &Self::UserData) {
+                for (other, out) in others.iter().zip(out) {
+                    *out = T::distance(self, other.borrow(), user_data);
+                }
+            }
+        }
+
+        impl<T: MetricSpaceWithContext<C, Impl> + ?Sized, C, Impl> MetricSpaceWithContext<C, Impl> for $ptr {
+            #[inline(always)]
+            fn distance_with_context(&self, other: &Self, user_data: &Self::UserData, context: &mut C) -> Self::Distance {
+                T::distance_with_context(self, other, user_data, context)
+            }
+        }
+    )*};
+}
+
+impl_metric_space_for_pointer!(&T, Box<T>, Rc<T>, Arc<T>);
+
 /// You can implement this if you want to peek at all visited elements
 ///
+/// `'tree` is the lifetime of the tree being searched, so the `Output` can borrow the items from the tree, like `collectors::KNearestItems` does.
+///
 /// ```rust
 /// # use vpsearch::*;
 /// struct Impl;
@@ -110,7 +384,7 @@ pub trait MetricSpace<UserImplementationType = ()> {
 ///    idx: usize,
 /// }
 ///
-/// impl<Item: MetricSpace<Impl> + Clone> BestCandidate<Item, Impl> for ReturnByIndex<Item> {
+/// impl<'tree, Item: MetricSpace<Impl> + Clone> BestCandidate<'tree, Item, Impl> for ReturnByIndex<Item> {
 ///     type Output = (usize, Item::Distance);
 ///
 ///     fn consider(&mut self, _: &Item, distance: Item::Distance, candidate_index: usize, _: &Item::UserData) {
@@ -127,13 +401,13 @@ pub trait MetricSpace<UserImplementationType = ()> {
 ///     }
 /// }
 /// ```
-pub trait BestCandidate<Item: MetricSpace<Impl> + Clone, Impl> where Self: Sized {
+pub trait BestCandidate<'tree, Item: MetricSpace<Impl> + Clone, Impl> where Self: Sized {
     /// `find_nearest()` will return this type
     type Output;
 
     /// This is a visitor method. If the given distance is smaller than previously seen, keep the item (or its index).
     /// `UserData` is the same as for `MetricSpace<Impl>`, and it's `()` by default.
-    fn consider(&mut self, item: &Item, distance: Item::Distance, candidate_index: usize, user_data: &Item::UserData);
+    fn consider(&mut self, item: &'tree Item, distance: Item::Distance, candidate_index: usize, user_data: &Item::UserData);
 
     /// Minimum distance seen so far
     fn distance(&self) -> Item::Distance;
@@ -142,14 +416,49 @@ pub trait BestCandidate<Item: MetricSpace<Impl> + Clone, Impl> where Self: Sized
     fn result(self, user_data: &Item::UserData) -> Self::Output;
 }
 
-impl<Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<Item, Impl> for ReturnByIndex<Item, Impl> {
-    type Output = (usize, Item::Distance);
+/// An additional rule for skipping parts of the tree during a search, for experimenting with search algorithms,
+/// e.g. probabilistic pruning. Use it with `Tree::find_nearest_custom_pruned`.
+///
+/// It's also implemented for closures taking the same arguments as `visit_child`.
+pub trait PruningRule<Distance> {
+    /// Called before searching a child of a node, after the tree's own checks have decided that the child may have better items.
+    ///
+    /// * `needle_distance` — distance from the needle to the node's vantage point
+    /// * `radius` — the node's radius. Items of the near child are at most `radius` away from the vantage point, and items of the far child at least `radius` away.
+    /// * `bound` — `BestCandidate::distance()` at this point of the search
+    /// * `far` — whether it's the far child
+    ///
+    /// Return `false` to skip the child and all of its items. The search stays exact only if skipped children can't have items closer than the `bound`.
+    fn visit_child(&mut self, needle_distance: Distance, radius: Distance, bound: Distance, far: bool) -> bool;
+}
+
+impl<Distance, F: FnMut(Distance, Distance, Distance, bool) -> bool> PruningRule<Distance> for F {
+    #[inline]
+    fn visit_child(&mut self, needle_distance: Distance, radius: Distance, bound: Distance, far: bool) -> bool {
+        self(needle_distance, radius, bound, far)
+    }
+}
+
+/// Only the tree's own pruning
+struct NoExtraPruning;
+
+impl<Distance> PruningRule<Distance> for NoExtraPruning {
+    #[inline(always)]
+    fn visit_child(&mut self, _: Distance, _: Distance, _: Distance, _: bool) -> bool {
+        true
+    }
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<'tree, Item, Impl> for ReturnByIndex<Item, Impl> {
+    type Output = Option<(usize, Item::Distance)>;
 
     #[inline]
     fn consider(&mut self, _: &Item, distance: Item::Distance, candidate_index: usize, _: &Item::UserData) {
-        if distance < self.distance {
+        // The max distance is inclusive
+        let closer = if self.idx.is_some() { distance < self.distance } else { distance <= self.distance };
+        if closer {
             self.distance = distance;
-            self.idx = candidate_index;
+            self.idx = Some(candidate_index);
         }
     }
 
@@ -158,13 +467,74 @@ impl<Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<Item, Impl> for Return
         self.distance
     }
 
-    fn result(self, _: &Item::UserData) -> (usize, Item::Distance) {
-        (self.idx, self.distance)
+    fn result(self, _: &Item::UserData) -> Self::Output {
+        self.idx.map(|idx| (idx, self.distance))
+    }
+}
+
+const NO_NODE: u32 = u32::MAX;
+
+/// Hints the CPU to start loading memory that the search is going to read soon.
+/// Trees larger than the cache are searched at the speed of memory, not the CPU.
+#[inline(always)]
+fn prefetch<T>(data: &T) {
+    #[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
+    #[allow(unused_unsafe)]
+    // It's only a hint, and can't fault
+    unsafe {
+        use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        _mm_prefetch::<_MM_HINT_T0>((data as *const T).cast());
+    }
+    #[cfg(not(all(target_arch = "x86_64", target_feature = "sse")))]
+    let _ = data;
+}
+
+/// SplitMix64 random numbers, so that the results are the same on all platforms and versions
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
     }
 }
 
-const NO_NODE: u32 = u32::max_value();
+/// Fisher–Yates shuffle
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut rng = SplitMix64(seed);
+    for i in (1..items.len()).rev() {
+        items.swap(i, rng.below(i + 1));
+    }
+}
 
+/// NaN is the only distance that isn't comparable with itself, and it breaks sorting and pruning of the tree
+#[inline(always)]
+fn is_comparable<D: PartialOrd>(distance: &D) -> bool {
+    distance.partial_cmp(distance).is_some()
+}
+
+/// Set in `Node::far` of leaves that have a bucket of items. The remaining bits are the length of the bucket.
+const BUCKET: u32 = 1 << 31;
+
+/// Nodes are split in half, so with at most 2^31 items the tree can't be deeper than this
+const MAX_DEPTH: usize = 32;
+
+/// Groups of up to this many items are stored in a single leaf, and compared with the needle all at once
+const LEAF_SIZE: usize = 8;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "Item: Serialize, Item::Distance: Serialize",
+    deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>",
+)))]
 struct Node<Item: MetricSpace<Impl> + Clone, Impl> {
     near: u32,
     far: u32,
@@ -173,13 +543,120 @@ struct Node<Item: MetricSpace<Impl> + Clone, Impl> {
     idx: u32,             // Index of the `vantage_point` in the original items array
 }
 
+impl<Item: MetricSpace<Impl> + Clone, Impl> Node<Item, Impl> {
+    /// Leaves can have more items than the vantage point. Then `near` is the start of their range in `Buckets`.
+    #[inline]
+    fn bucket(&self) -> Option<core::ops::Range<usize>> {
+        if self.far != NO_NODE && self.far & BUCKET != 0 {
+            let start = self.near as usize;
+            Some(start..start + (self.far & !BUCKET) as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// Child of the `parent` node that the search may visit later. `distance` is from the needle to the `parent`.
+#[derive(Copy, Clone)]
+struct Pending<D> {
+    parent: u32,
+    distance: D,
+    far: bool,
+}
+
+/// Items of all leaf buckets. They're kept together, so that a bucket is a slice for `MetricSpace::distance_to_many()`.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct Buckets<Item> {
+    items: Vec<Item>,
+    idx: Vec<u32>, // Indexes of the `items` in the original items array
+}
+
+/// Measures distance from the needle to items of the tree
+trait NeedleDistance<Item: MetricSpace<Impl>, Impl> {
+    fn distance_to(&mut self, item: &Item) -> Item::Distance;
+
+    #[inline]
+    fn distances_to(&mut self, items: &[Item], out: &mut [Item::Distance]) {
+        for (item, out) in items.iter().zip(out) {
+            *out = self.distance_to(item);
+        }
+    }
+
+    /// Like `distance_to`, with the index of the item, for needles that remember distances
+    #[inline]
+    fn distance_to_indexed(&mut self, item: &Item, _idx: u32) -> Item::Distance {
+        self.distance_to(item)
+    }
+
+    /// Like `distances_to`, with the indexes of the items
+    #[inline]
+    fn distances_to_indexed(&mut self, items: &[Item], _idx: &[u32], out: &mut [Item::Distance]) {
+        self.distances_to(items, out)
+    }
+}
+
+struct Needle<'a, Q: ?Sized, U> {
+    needle: &'a Q,
+    user_data: &'a U,
+}
+
+impl<'a, Q, Item, Impl> NeedleDistance<Item, Impl> for Needle<'a, Q, Item::UserData> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: MetricSpace<Impl> + Borrow<Q> {
+    #[inline]
+    fn distance_to(&mut self, item: &Item) -> Item::Distance {
+        self.needle.distance(item.borrow(), self.user_data)
+    }
+
+    #[inline]
+    fn distances_to(&mut self, items: &[Item], out: &mut [Item::Distance]) {
+        self.needle.distance_to_many(items, out, self.user_data)
+    }
+}
+
+struct NeedleWithContext<'a, Q: ?Sized, U, C> {
+    needle: &'a Q,
+    user_data: &'a U,
+    context: &'a mut C,
+}
+
+impl<'a, Q, C, Item, Impl> NeedleDistance<Item, Impl> for NeedleWithContext<'a, Q, Item::UserData, C> where Q: MetricSpaceWithContext<C, Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: MetricSpace<Impl> + Borrow<Q> {
+    #[inline]
+    fn distance_to(&mut self, item: &Item) -> Item::Distance {
+        self.needle.distance_with_context(item.borrow(), self.user_data, self.context)
+    }
+}
+
 /// The VP-Tree.
+///
+/// `Impl` is the `UserImplementationType` of the `MetricSpace` impl, and `Ownership` says where the `UserData` lives.
+/// You usually don't need to spell them out. See the `SimpleTree`, `TreeWithData`, `TreeWithSharedData` and `TreeWithDataRef` aliases.
+///
+/// With the `serde` feature enabled the tree can be serialized and loaded back without rebuilding it.
+/// Loading fails with `Error::InvalidTree` if the data isn't a tree that can be searched, e.g. because it's corrupted.
+///
+/// The tree is `Send` and `Sync` if the items, their `Distance` and the `UserData` are. Searches only need `&self`,
+/// so once built, the tree can be searched from many threads at once, e.g. shared via `Arc` or scoped threads.
+// Deserialized in the `validate` module, which checks the structure
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(feature = "serde", serde(bound(serialize = "Item: Serialize, Item::Distance: Serialize, Ownership: Serialize")))]
 pub struct Tree<Item: MetricSpace<Impl> + Clone, Impl=(), Ownership=Owned<()>> {
     nodes: Vec<Node<Item, Impl>>,
+    buckets: Buckets<Item>,
     root: u32,
     user_data: Ownership,
 }
 
+/// A tree of items that don't need `UserData`. Created with `Tree::new`.
+pub type SimpleTree<Item, Impl = ()> = Tree<Item, Impl, Owned<()>>;
+
+/// A tree that owns the `UserData` of type `U`. Created with `Tree::new_with_user_data_owned`.
+pub type TreeWithData<Item, U, Impl = ()> = Tree<Item, Impl, Owned<U>>;
+
+/// A tree that shares the `UserData` of type `U` via `Arc`. Created with `Tree::new_with_user_data_shared`.
+pub type TreeWithSharedData<Item, U, Impl = ()> = Tree<Item, Impl, Shared<U>>;
+
+/// A tree that doesn't keep the `UserData`, so it has to be passed to every search. Created with `Tree::new_with_user_data_ref`.
+pub type TreeWithDataRef<Item, Impl = ()> = Tree<Item, Impl, ()>;
+
 /* Temporary object used to reorder/track distance between items without modifying the orignial items array
    (also used during search to hold the two properties).
 */
@@ -190,14 +667,14 @@ struct Tmp<Item: MetricSpace<Impl>, Impl> {
 
 struct ReturnByIndex<Item: MetricSpace<Impl>, Impl> {
     distance: Item::Distance,
-    idx: usize,
+    idx: Option<usize>,
 }
 
 impl<Item: MetricSpace<Impl>, Impl> ReturnByIndex<Item, Impl> {
-    fn new() -> Self {
+    fn new(max_distance: Item::Distance) -> Self {
         ReturnByIndex {
-            distance: <Item::Distance as Bounded>::max_value(),
-            idx: 0,
+            distance: max_distance,
+            idx: None,
         }
     }
 }
@@ -207,47 +684,158 @@ impl<Item: MetricSpace<Impl, UserData = ()> + Clone, Impl> Tree<Item, Impl, Owne
     /**
      * Creates a new tree from items. Maximum number of items is 2^31.
      *
-     * See `Tree::new_with_user_data_owned`.
+     * Panics if there are too many items, or the distance between them is NaN. See `Tree::try_new` and `Tree::new_with_user_data_owned`.
+     *
+     * The items can be empty. Such tree has `len() == 0`, and searches don't find anything.
      */
     pub fn new(items: &[Item]) -> Self {
         Self::new_with_user_data_owned(items, ())
     }
+
+    /// Like `new`, but returns an error instead of panicking. It also doesn't accept an empty slice of items.
+    pub fn try_new(items: &[Item]) -> Result<Self, Error> {
+        Self::try_new_with_user_data_owned(items, ())
+    }
+
+    /// Like `new`, but builds the tree from the items in a random order, chosen by the `seed`.
+    ///
+    /// Vantage points are chosen depending on the order of the items, so items sorted by something correlated with
+    /// their distances (e.g. by time or by an ID) can make a tree that needs more distance computations to search.
+    /// The same items and the same seed always make the same tree. Indexes of the items are the same as with `new`.
+    pub fn new_shuffled(items: &[Item], seed: u64) -> Self {
+        Self::create_root_node(items, &(), Some(seed)).unwrap_or_else(|err| panic!("{}", err)).with_ownership(Owned(()))
+    }
 }
 
-impl<U, Impl, Item: MetricSpace<Impl, UserData = U> + Clone> Tree<Item, Impl, Owned<U>> {
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// The user data the tree has been created with, e.g. to read statistics collected by `distance()`
+    #[inline]
+    pub fn user_data(&self) -> &Item::UserData {
+        self.user_data.user_data()
+    }
+
     /**
      * Finds item closest to the given `needle` (that can be any item) and returns *index* of the item in items array from `new()`.
      *
      * Returns the index of the nearest item (index from the items slice passed to `new()`) found and the distance from the nearest item.
+     *
+     * Like `HashMap::get`, the needle can be of any type the items can be borrowed as, e.g. `&str` for a tree of `String`s,
+     * or `&[f32]` for a tree of `Vec<f32>`, as long as it implements `MetricSpace` with the same `Distance`. This applies to all search methods.
+     *
+     * An empty tree has nothing to find, so it returns index `0` with the `max_value()` distance. Check `is_empty()` first,
+     * or use `find_nearest_bounded()`, which returns `None` then. Other search methods return no results.
      */
     #[inline]
-    pub fn find_nearest(&self, needle: &Item) -> (usize, Item::Distance) {
-        self.find_nearest_with_user_data(needle, &self.user_data.0)
+    pub fn find_nearest<Q>(&self, needle: &Q) -> (usize, Item::Distance) where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.find_nearest_with_user_data(needle, self.user_data.user_data())
+    }
+
+    /// Finds the item closest to the `needle`, if there's one at most `max_distance` away.
+    ///
+    /// This works with distance types that don't implement `Bounded`, and is faster when the distance limit is small.
+    #[inline]
+    pub fn find_nearest_bounded<Q>(&self, needle: &Q, max_distance: Item::Distance) -> Option<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.find_nearest_custom(needle, self.user_data.user_data(), ReturnByIndex::new(max_distance))
+    }
+
+    /// Finds up to `k` items closest to the `needle`. Returns `(index, distance)` pairs sorted by distance, closest first.
+    #[inline]
+    pub fn find_k_nearest<Q>(&self, needle: &Q, k: usize) -> Vec<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.find_nearest_custom(needle, self.user_data.user_data(), KNearest::new(k))
+    }
+
+    /// Like `find_k_nearest`, but only returns items at most `max_distance` away
+    #[inline]
+    pub fn find_k_nearest_bounded<Q>(&self, needle: &Q, k: usize, max_distance: Item::Distance) -> Vec<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.find_nearest_custom(needle, self.user_data.user_data(), KNearest::with_max_distance(k, max_distance))
+    }
+
+    /// Like `find_nearest`, but the distance is measured with `MetricSpaceWithContext::distance_with_context()`
+    #[inline]
+    pub fn find_nearest_with_context<Q, C>(&self, needle: &Q, context: &mut C) -> (usize, Item::Distance) where Q: MetricSpaceWithContext<C, Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        let max = <Item::Distance as Bounded>::max_value();
+        self.find_nearest_custom_with_context(needle, self.user_data.user_data(), context, ReturnByIndex::new(max)).unwrap_or((0, max))
+    }
+
+    /// Like `find_k_nearest`, but the distance is measured with `MetricSpaceWithContext::distance_with_context()`
+    #[inline]
+    pub fn find_k_nearest_with_context<Q, C>(&self, needle: &Q, k: usize, context: &mut C) -> Vec<(usize, Item::Distance)> where Q: MetricSpaceWithContext<C, Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.find_nearest_custom_with_context(needle, self.user_data.user_data(), context, KNearest::new(k))
+    }
+
+    /// Finds all items at most `radius` away from the `needle`. Returns `(index, distance)` pairs sorted by distance, closest first.
+    #[inline]
+    pub fn find_within_radius<Q>(&self, needle: &Q, radius: Item::Distance) -> Vec<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.find_nearest_custom(needle, self.user_data.user_data(), WithinRadius::new(radius))
+    }
+
+    /// Like `find_k_nearest`, but replaces the contents of `results` with the found items, reusing its memory.
+    ///
+    /// Searches don't allocate anything else, so a server that keeps one `Vec` per thread doesn't allocate for every query.
+    pub fn find_k_nearest_into<Q>(&self, needle: &Q, k: usize, results: &mut Vec<(usize, Item::Distance)>) where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        let buffer = core::mem::take(results);
+        *results = self.find_nearest_custom(needle, self.user_data.user_data(), KNearest::with_buffer(k, <Item::Distance as Bounded>::max_value(), buffer));
+    }
+
+    /// Like `find_within_radius`, but replaces the contents of `results` with the found items, reusing its memory.
+    pub fn find_within_radius_into<Q>(&self, needle: &Q, radius: Item::Distance, results: &mut Vec<(usize, Item::Distance)>) where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        let buffer = core::mem::take(results);
+        *results = self.find_nearest_custom(needle, self.user_data.user_data(), WithinRadius::with_buffer(radius, buffer));
     }
 }
 
 impl<Item: MetricSpace<Impl> + Clone, Ownership, Impl> Tree<Item, Impl, Ownership> {
-    fn sort_indexes_by_distance(vantage_point: Item, indexes: &mut [Tmp<Item, Impl>], items: &[Item], user_data: &Item::UserData) {
+    fn sort_indexes_by_distance(vantage_point: Item, indexes: &mut [Tmp<Item, Impl>], items: &[Item], user_data: &Item::UserData) -> Result<(), Error> {
         for i in indexes.iter_mut() {
-            i.distance = vantage_point.distance(&items[i.idx as usize], user_data);
+            let distance = vantage_point.distance(&items[i.idx as usize], user_data);
+            if !is_comparable(&distance) {
+                return Err(Error::InvalidDistance);
+            }
+            i.distance = distance;
         }
-        indexes.sort_unstable_by(|a, b| if a.distance < b.distance {Ordering::Less} else {Ordering::Greater});
+        indexes.sort_unstable_by(|a, b| a.distance.partial_cmp(&b.distance).unwrap_or(Ordering::Equal));
+        Ok(())
     }
 
-    fn create_node(indexes: &mut [Tmp<Item, Impl>], nodes: &mut Vec<Node<Item, Impl>>, items: &[Item], user_data: &Item::UserData) -> u32 {
-        if indexes.len() == 0 {
-            return NO_NODE;
+    fn create_node(indexes: &mut [Tmp<Item, Impl>], nodes: &mut Vec<Node<Item, Impl>>, buckets: &mut Buckets<Item>, items: &[Item], user_data: &Item::UserData) -> Result<u32, Error> {
+        if indexes.is_empty() {
+            return Ok(NO_NODE);
         }
 
-        if indexes.len() == 1 {
-            let node_idx = nodes.len();
+        let node_idx = nodes.len();
+        if let Some(half_idx) = Self::push_node(indexes, nodes, buckets, items, user_data)? {
+            let last = indexes.len()-1;
+            let (near_indexes, far_indexes) = indexes[..last].split_at_mut(half_idx);
+            let near = Self::create_node(near_indexes, nodes, buckets, items, user_data)?;
+            let far = Self::create_node(far_indexes, nodes, buckets, items, user_data)?;
+            nodes[node_idx].near = near;
+            nodes[node_idx].far = far;
+        }
+        Ok(node_idx as u32)
+    }
+
+    /// Adds a node for the (non-empty) `indexes`, without its children.
+    ///
+    /// If the node needs children, returns where `indexes[..len-1]` is split between the near and far child, and they're still `NO_NODE`.
+    fn push_node(indexes: &mut [Tmp<Item, Impl>], nodes: &mut Vec<Node<Item, Impl>>, buckets: &mut Buckets<Item>, items: &[Item], user_data: &Item::UserData) -> Result<Option<usize>, Error> {
+        if indexes.len() <= LEAF_SIZE {
+            let (first, rest) = (&indexes[0], &indexes[1..]);
+            let (near, far) = if rest.is_empty() {
+                (NO_NODE, NO_NODE)
+            } else {
+                let start = buckets.items.len() as u32;
+                buckets.items.extend(rest.iter().map(|i| items[i.idx as usize].clone()));
+                buckets.idx.extend(rest.iter().map(|i| i.idx));
+                (start, BUCKET | rest.len() as u32)
+            };
             nodes.push(Node{
-                near: NO_NODE, far: NO_NODE,
-                vantage_point: items[indexes[0].idx as usize].clone(),
-                idx: indexes[0].idx,
-                radius: <Item::Distance as Bounded>::max_value(),
+                near, far,
+                vantage_point: items[first.idx as usize].clone(),
+                idx: first.idx,
+                // Leaves have no children, so the radius is never used
+                radius: first.distance,
             });
-            return node_idx as u32;
+            return Ok(None);
         }
 
         let last = indexes.len()-1;
@@ -256,17 +844,15 @@ impl<Item: MetricSpace<Impl> + Clone, Ownership, Impl> Tree<Item, Impl, Ownershi
         // Removes the `ref_idx` item from remaining items, because it's included in the current node
         let rest = &mut indexes[..last];
 
-        Self::sort_indexes_by_distance(items[ref_idx as usize].clone(), rest, items, user_data);
+        Self::sort_indexes_by_distance(items[ref_idx as usize].clone(), rest, items, user_data)?;
 
         // Remaining items are split by the median distance
         let half_idx = rest.len()/2;
 
-        let (near_indexes, far_indexes) = rest.split_at_mut(half_idx);
         let vantage_point = items[ref_idx as usize].clone();
-        let radius = far_indexes[0].distance;
+        let radius = rest[half_idx].distance;
 
         // push first to reserve space before its children
-        let node_idx = nodes.len();
         nodes.push(Node{
             vantage_point,
             idx: ref_idx,
@@ -274,12 +860,7 @@ impl<Item: MetricSpace<Impl> + Clone, Ownership, Impl> Tree<Item, Impl, Ownershi
             near: NO_NODE,
             far: NO_NODE,
         });
-
-        let near = Self::create_node(near_indexes, nodes, items, user_data);
-        let far = Self::create_node(far_indexes, nodes, items, user_data);
-        nodes[node_idx].near = near;
-        nodes[node_idx].far = far;
-        node_idx as u32
+        Ok(Some(half_idx))
     }
 }
 
@@ -289,89 +870,294 @@ impl<Item: MetricSpace<Impl> + Clone, Impl> Tree<Item, Impl, Owned<Item::UserDat
      *
      * * `items` —       Array of items that will be searched.
      * * `user_data` —   Reference to any object that is passed down to item.distance()
+     *
+     * Panics if there are too many items, or the distance between them is NaN.
      */
     pub fn new_with_user_data_owned(items: &[Item], user_data: Item::UserData) -> Self {
-        let mut nodes = Vec::with_capacity(items.len());
-        let root = Self::create_root_node(items, &mut nodes, &user_data);
-        Tree {
-            root,
-            nodes,
-            user_data: Owned(user_data),
+        Self::create_root_node(items, &user_data, None).unwrap_or_else(|err| panic!("{}", err)).with_ownership(Owned(user_data))
+    }
+
+    /// Like `new_with_user_data_owned`, but returns an error instead of panicking. It also doesn't accept an empty slice of items.
+    pub fn try_new_with_user_data_owned(items: &[Item], user_data: Item::UserData) -> Result<Self, Error> {
+        if items.is_empty() {
+            return Err(Error::Empty);
         }
+        Ok(Self::create_root_node(items, &user_data, None)?.with_ownership(Owned(user_data)))
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl> Tree<Item, Impl, Shared<Item::UserData>> {
+    /// Like `new_with_user_data_owned`, but the user data can be shared with other trees or the rest of the program.
+    ///
+    /// This is useful for large read-only data, like a codebook or a weight matrix, that would be too expensive to clone.
+    pub fn new_with_user_data_shared(items: &[Item], user_data: Arc<Item::UserData>) -> Self {
+        Self::create_root_node(items, &user_data, None).unwrap_or_else(|err| panic!("{}", err)).with_ownership(Shared(user_data))
+    }
+
+    /// Like `new_with_user_data_shared`, but returns an error instead of panicking. It also doesn't accept an empty slice of items.
+    pub fn try_new_with_user_data_shared(items: &[Item], user_data: Arc<Item::UserData>) -> Result<Self, Error> {
+        if items.is_empty() {
+            return Err(Error::Empty);
+        }
+        Ok(Self::create_root_node(items, &user_data, None)?.with_ownership(Shared(user_data)))
+    }
+
+    /// The user data given to `new_with_user_data_shared`
+    pub fn shared_user_data(&self) -> &Arc<Item::UserData> {
+        &self.user_data.0
     }
 }
 
 impl<Item: MetricSpace<Impl> + Clone, Impl> Tree<Item, Impl, ()> {
     /// The tree doesn't have to own the UserData. You can keep passing it to find_nearest().
     pub fn new_with_user_data_ref(items: &[Item], user_data: &Item::UserData) -> Self {
-        let mut nodes = Vec::with_capacity(items.len());
-        let root = Self::create_root_node(items, &mut nodes, &user_data);
-        Tree {
-            root,
-            nodes,
-            user_data: (),
+        Self::create_root_node(items, user_data, None).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Like `new_with_user_data_ref`, but returns an error instead of panicking. It also doesn't accept an empty slice of items.
+    pub fn try_new_with_user_data_ref(items: &[Item], user_data: &Item::UserData) -> Result<Self, Error> {
+        if items.is_empty() {
+            return Err(Error::Empty);
         }
+        Self::create_root_node(items, user_data, None)
     }
 
     #[inline]
-    pub fn find_nearest(&self, needle: &Item, user_data: &Item::UserData) -> (usize, Item::Distance) {
+    pub fn find_nearest<Q>(&self, needle: &Q, user_data: &Item::UserData) -> (usize, Item::Distance) where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
         self.find_nearest_with_user_data(needle, user_data)
     }
+
+    /// See `find_nearest_bounded` of the owned variant
+    #[inline]
+    pub fn find_nearest_bounded<Q>(&self, needle: &Q, max_distance: Item::Distance, user_data: &Item::UserData) -> Option<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.find_nearest_custom(needle, user_data, ReturnByIndex::new(max_distance))
+    }
+
+    /// See `find_k_nearest` of the owned variant
+    #[inline]
+    pub fn find_k_nearest<Q>(&self, needle: &Q, k: usize, user_data: &Item::UserData) -> Vec<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.find_nearest_custom(needle, user_data, KNearest::new(k))
+    }
+
+    /// See `find_k_nearest_bounded` of the owned variant
+    #[inline]
+    pub fn find_k_nearest_bounded<Q>(&self, needle: &Q, k: usize, max_distance: Item::Distance, user_data: &Item::UserData) -> Vec<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.find_nearest_custom(needle, user_data, KNearest::with_max_distance(k, max_distance))
+    }
+
+    /// See `find_within_radius` of the owned variant
+    #[inline]
+    pub fn find_within_radius<Q>(&self, needle: &Q, radius: Item::Distance, user_data: &Item::UserData) -> Vec<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.find_nearest_custom(needle, user_data, WithinRadius::new(radius))
+    }
 }
 
 impl<Item: MetricSpace<Impl> + Clone, Ownership, Impl> Tree<Item, Impl, Ownership> {
-    fn create_root_node(items: &[Item], nodes: &mut Vec<Node<Item, Impl>>, user_data: &Item::UserData) -> u32 {
-        assert!(items.len() < (u32::max_value()/2) as usize);
+    /// Number of items in the tree
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.nodes.len() + self.buckets.items.len()
+    }
+
+    /// `true` if the tree has been created from an empty slice
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Bytes of memory used by the nodes and items of the tree.
+    ///
+    /// It doesn't count heap memory that the items own (e.g. contents of `Vec`s), nor the user data.
+    /// If the items are large, see `new_with_user_data_ref` for a tree that stores only indexes of the items,
+    /// and `BudgetedTree` for vectors stored in a layout that fits in a budget.
+    pub fn memory_size(&self) -> usize {
+        core::mem::size_of::<Self>()
+            + self.nodes.capacity() * core::mem::size_of::<Node<Item, Impl>>()
+            + self.buckets.items.capacity() * core::mem::size_of::<Item>()
+            + self.buckets.idx.capacity() * core::mem::size_of::<u32>()
+    }
+
+    /// All items with their index, in the order they're stored in the tree
+    fn items(&self) -> impl Iterator<Item = (usize, &Item)> {
+        let vantage_points = self.nodes.iter().map(|node| (node.idx as usize, &node.vantage_point));
+        vantage_points.chain(self.buckets.idx.iter().map(|&idx| idx as usize).zip(&self.buckets.items))
+    }
+
+    /// All items in the order of the slice the tree has been created from
+    fn items_by_index(&self) -> Vec<&Item> {
+        let mut items: Vec<_> = self.items().collect();
+        items.sort_unstable_by_key(|&(idx, _)| idx);
+        items.into_iter().map(|(_, item)| item).collect()
+    }
+
+    /// The tree without the user data. Use `with_ownership` to add it.
+    ///
+    /// With a `seed` the items are shuffled first, so that the shape of the tree doesn't depend on their order.
+    fn create_root_node(items: &[Item], user_data: &Item::UserData, seed: Option<u64>) -> Result<Tree<Item, Impl, ()>, Error> {
+        let mut nodes = Vec::new();
+        let mut buckets = Buckets { items: Vec::new(), idx: Vec::new() };
+        let mut indexes = Self::initial_indexes(items, user_data, seed)?;
+        let root = Self::create_node(&mut indexes[..], &mut nodes, &mut buckets, items, user_data)?;
+        Ok(Tree { nodes, buckets, root, user_data: () })
+    }
 
+    /// All items, to be sorted and split into nodes. With a `seed` they're shuffled.
+    fn initial_indexes(items: &[Item], user_data: &Item::UserData, seed: Option<u64>) -> Result<Vec<Tmp<Item, Impl>>, Error> {
+        if items.len() >= (u32::MAX/2) as usize {
+            return Err(Error::TooManyItems);
+        }
+        let first = match items.first() {
+            Some(first) => first,
+            None => return Ok(Vec::new()),
+        };
+        // The initial distance is overwritten when sorting, so any value will do
+        let placeholder = first.distance(first, user_data);
         let mut indexes: Vec<_> = (0..items.len() as u32).map(|i| Tmp{
-            idx: i, distance: <Item::Distance as Bounded>::max_value(),
+            idx: i, distance: placeholder,
         }).collect();
+        if let Some(seed) = seed {
+            shuffle(&mut indexes, seed);
+        }
+        Ok(indexes)
+    }
 
-        Self::create_node(&mut indexes[..], nodes, items, user_data) as u32
+    fn with_ownership<O>(self, user_data: O) -> Tree<Item, Impl, O> {
+        Tree {
+            nodes: self.nodes,
+            buckets: self.buckets,
+            root: self.root,
+            user_data,
+        }
     }
 
-    fn search_node<B: BestCandidate<Item, Impl>>(node: &Node<Item, Impl>, nodes: &[Node<Item, Impl>], needle: &Item, best_candidate: &mut B, user_data: &Item::UserData) {
-        let distance = needle.distance(&node.vantage_point, user_data);
+    /// Visits nodes in a loop rather than recursively, so the search uses a fixed amount of stack memory and no heap
+    ///
+    /// `root` is the node to start from, so that it can also search only a part of the tree.
+    #[inline]
+    fn search<'tree, B: BestCandidate<'tree, Item, Impl>, D: NeedleDistance<Item, Impl>>(&'tree self, root: u32, needle: &mut D, best_candidate: &mut B, user_data: &Item::UserData) {
+        self.search_subtrees(root, needle, best_candidate, user_data, |_| true, &mut NoExtraPruning);
+    }
 
-        best_candidate.consider(&node.vantage_point, distance, node.idx as usize, user_data);
+    /// Like `search`, but skips the whole subtree of every node for which `subtree_matches` returns `false`,
+    /// and every child that the `rule` rejects.
+    fn search_subtrees<'tree, B: BestCandidate<'tree, Item, Impl>, D: NeedleDistance<Item, Impl>>(&'tree self, root: u32, needle: &mut D, best_candidate: &mut B, user_data: &Item::UserData, mut subtree_matches: impl FnMut(u32) -> bool, rule: &mut impl PruningRule<Item::Distance>) {
+        let nodes = &self.nodes;
+        // Children that may need to be visited after the other child of their parent has been searched
+        let mut pending: [Option<Pending<Item::Distance>>; MAX_DEPTH] = [None; MAX_DEPTH];
+        let mut pending_len = 0;
+        let mut next = root;
 
-        // Recurse towards most likely candidate first to narrow best candidate's distance as soon as possible
-        if distance < node.radius {
+        loop {
             // No-node case uses out-of-bounds index, so this reuses a safe bounds check as the "null" check
-            if let Some(near) = nodes.get(node.near as usize) {
-                Self::search_node(near, nodes, needle, best_candidate, user_data);
-            }
-            // The best node (final answer) may be just ouside the radius, but not farther than
-            // the best distance we know so far. The search_node above should have narrowed
-            // best_candidate.distance, so this path is rarely taken.
-            if let Some(far) = nodes.get(node.far as usize) {
-                if distance + best_candidate.distance() >= node.radius {
-                    Self::search_node(far, nodes, needle, best_candidate, user_data);
+            if let Some(node) = nodes.get(next as usize).filter(|_| subtree_matches(next)) {
+                // Both children are loaded while the distance is computed, before it's known which one comes next
+                if let Some(bucket) = node.bucket() {
+                    prefetch(&self.buckets.items[bucket.start]);
+                } else {
+                    if let Some(near) = nodes.get(node.near as usize) {
+                        prefetch(near);
+                    }
+                    if let Some(far) = nodes.get(node.far as usize) {
+                        prefetch(far);
+                    }
                 }
+
+                let distance = needle.distance_to_indexed(&node.vantage_point, node.idx);
+                debug_assert!(is_comparable(&distance), "distance to the needle is NaN");
+
+                best_candidate.consider(&node.vantage_point, distance, node.idx as usize, user_data);
+
+                if let Some(bucket) = node.bucket() {
+                    let items = &self.buckets.items[bucket.clone()];
+                    let mut distances = [distance; LEAF_SIZE];
+                    let distances = &mut distances[..items.len()];
+                    let idx = &self.buckets.idx[bucket];
+                    needle.distances_to_indexed(items, idx, distances);
+                    for ((item, &idx), &distance) in items.iter().zip(idx).zip(distances.iter()) {
+                        debug_assert!(is_comparable(&distance), "distance to the needle is NaN");
+                        best_candidate.consider(item, distance, idx as usize, user_data);
+                    }
+                    next = NO_NODE;
+                    continue;
+                }
+
+                // Go towards most likely candidate first to narrow best candidate's distance as soon as possible
+                let (first, second, second_is_far) = if distance < node.radius {
+                    (node.near, node.far, true)
+                } else {
+                    (node.far, node.near, false)
+                };
+                if (second as usize) < nodes.len() {
+                    pending[pending_len] = Some(Pending { parent: next, distance, far: second_is_far });
+                    pending_len += 1;
+                }
+                let skip_first = (first as usize) < nodes.len() && !rule.visit_child(distance, node.radius, best_candidate.distance(), !second_is_far);
+                next = if skip_first { NO_NODE } else { first };
+                continue;
             }
-        } else {
-            if let Some(far) = nodes.get(node.far as usize) {
-                Self::search_node(far, nodes, needle, best_candidate, user_data);
-            }
-            if let Some(near) = nodes.get(node.near as usize) {
-                if distance <= node.radius + best_candidate.distance() {
-                    Self::search_node(near, nodes, needle, best_candidate, user_data);
+
+            // The first child has been searched, which should have narrowed best_candidate.distance,
+            // so the other child can often be skipped
+            loop {
+                if pending_len == 0 {
+                    return;
+                }
+                pending_len -= 1;
+                let Pending { parent, distance, far } = pending[pending_len].take().unwrap();
+                let parent = &nodes[parent as usize];
+                let bound = best_candidate.distance();
+                if far {
+                    // The best node (final answer) may be just ouside the radius, but not farther than
+                    // the best distance we know so far.
+                    if distance.saturating_add(bound) >= parent.radius && rule.visit_child(distance, parent.radius, bound, true) {
+                        next = parent.far;
+                        break;
+                    }
+                } else if distance <= parent.radius.saturating_add(bound) && rule.visit_child(distance, parent.radius, bound, false) {
+                    next = parent.near;
+                    break;
                 }
             }
         }
     }
 
     #[inline]
-    fn find_nearest_with_user_data(&self, needle: &Item, user_data: &Item::UserData) -> (usize, Item::Distance) {
-        self.find_nearest_custom(needle, user_data, ReturnByIndex::new())
+    fn find_nearest_with_user_data<Q>(&self, needle: &Q, user_data: &Item::UserData) -> (usize, Item::Distance) where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        let max = <Item::Distance as Bounded>::max_value();
+        self.find_nearest_custom(needle, user_data, ReturnByIndex::new(max)).unwrap_or((0, max))
     }
 
     #[inline]
     /// All the bells and whistles version. For best_candidate implement `BestCandidate<Item, Impl>` trait.
-    pub fn find_nearest_custom<ReturnBy: BestCandidate<Item, Impl>>(&self, needle: &Item, user_data: &Item::UserData, mut best_candidate: ReturnBy) -> ReturnBy::Output {
-        if let Some(root) = self.nodes.get(self.root as usize) {
-            Self::search_node(root, &self.nodes, needle, &mut best_candidate, user_data);
-        }
+    pub fn find_nearest_custom<'tree, ReturnBy: BestCandidate<'tree, Item, Impl>, Q>(&'tree self, needle: &Q, user_data: &Item::UserData, mut best_candidate: ReturnBy) -> ReturnBy::Output where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.search(self.root, &mut Needle { needle, user_data }, &mut best_candidate, user_data);
+
+        best_candidate.result(user_data)
+    }
+
+    /// Like `find_nearest_custom`, but children of nodes that the tree would search are searched only if the `rule` agrees.
+    ///
+    /// This is for prototyping different search algorithms. Rules that skip children that may have better items make the search approximate.
+    ///
+    /// ```rust
+    /// # let tree = vpsearch::Tree::<[f32; 2], vpsearch::metrics::Euclidean>::new(&[[0., 0.], [1., 1.], [5., 5.]]);
+    /// // Skips children that are unlikely to have better items, by pretending the bound is half as large
+    /// let rule = |needle_distance: f32, radius: f32, bound: f32, far: bool| {
+    ///     if far { needle_distance + bound / 2. >= radius } else { needle_distance <= radius + bound / 2. }
+    /// };
+    /// let found = tree.find_nearest_custom_pruned(&[1., 1.2], &(), vpsearch::KNearest::new(1), rule);
+    /// assert_eq!(found[0].0, 1);
+    /// ```
+    pub fn find_nearest_custom_pruned<'tree, ReturnBy: BestCandidate<'tree, Item, Impl>, Q, R: PruningRule<Item::Distance>>(&'tree self, needle: &Q, user_data: &Item::UserData, mut best_candidate: ReturnBy, mut rule: R) -> ReturnBy::Output where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.search_subtrees(self.root, &mut Needle { needle, user_data }, &mut best_candidate, user_data, |_| true, &mut rule);
+        best_candidate.result(user_data)
+    }
+
+    /// Like `find_nearest_custom`, but the distance is measured with `MetricSpaceWithContext`, which gets the `context` too.
+    ///
+    /// The context is only needed for this query, and isn't stored in the tree.
+    pub fn find_nearest_custom_with_context<'tree, ReturnBy: BestCandidate<'tree, Item, Impl>, Q, C>(&'tree self, needle: &Q, user_data: &Item::UserData, context: &mut C, mut best_candidate: ReturnBy) -> ReturnBy::Output where Q: MetricSpaceWithContext<C, Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.search(self.root, &mut NeedleWithContext { needle, user_data, context }, &mut best_candidate, user_data);
 
         best_candidate.result(user_data)
     }
@@ -0,0 +1,178 @@
+//! A map searched by similarity of the keys, for users who don't need indexes or custom searches.
+
+use crate::collectors::{insert_nearest, kth_distance};
+use crate::{BestCandidate, MetricSpace, Tree, TreeWithValues};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+use core::iter::FromIterator;
+use num_traits::Bounded;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A map from keys to values, where lookups find the keys nearest to the needle.
+///
+/// It's immutable once built. Insert all entries into a `VpMapBuilder` (or `collect()` them), and then search.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+/// let mut builder = vpsearch::VpMap::builder();
+/// builder.insert(Point(1.), "one");
+/// builder.insert(Point(5.), "five");
+/// let map = builder.build();
+///
+/// let (key, &value, distance) = map.nearest(&Point(4.)).unwrap();
+/// assert_eq!((key.0, value, distance), (5., "five", 1.));
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "K: Serialize, K::Distance: Serialize, V: Serialize",
+    deserialize = "K: Deserialize<'de>, K::Distance: Deserialize<'de>, V: Deserialize<'de>",
+)))]
+pub struct VpMap<K: MetricSpace<Impl, UserData = ()> + Clone, V, Impl = ()> {
+    tree: TreeWithValues<K, V, Impl>,
+}
+
+/// Collects entries for a `VpMap`. Created with `VpMap::builder()`.
+pub struct VpMapBuilder<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+}
+
+impl<K, V> VpMapBuilder<K, V> {
+    pub fn new() -> Self {
+        VpMapBuilder { keys: Vec::new(), values: Vec::new() }
+    }
+
+    /// Adds an entry. Keys don't have to be unique: equal keys are both kept, and both can be found.
+    pub fn insert(&mut self, key: K, value: V) {
+        self.keys.push(key);
+        self.values.push(value);
+    }
+
+    /// Number of entries inserted so far
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Builds the map from all inserted entries. Panics in the same cases as `Tree::new`.
+    pub fn build<Impl>(self) -> VpMap<K, V, Impl> where K: MetricSpace<Impl, UserData = ()> + Clone {
+        VpMap { tree: Tree::new(&self.keys).with_values(self.values) }
+    }
+}
+
+impl<K, V> Default for VpMapBuilder<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Extend<(K, V)> for VpMapBuilder<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, entries: I) {
+        for (key, value) in entries {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<K: MetricSpace<Impl, UserData = ()> + Clone, V, Impl> FromIterator<(K, V)> for VpMap<K, V, Impl> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(entries: I) -> Self {
+        let mut builder = VpMapBuilder::new();
+        builder.extend(entries);
+        builder.build()
+    }
+}
+
+impl<K: MetricSpace<Impl, UserData = ()> + Clone, V, Impl> VpMap<K, V, Impl> {
+    /// Start here to insert the entries
+    pub fn builder() -> VpMapBuilder<K, V> {
+        VpMapBuilder::new()
+    }
+
+    /// Number of entries
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+
+    /// All entries, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &V)> {
+        let values = self.tree.values();
+        self.tree.tree().items().map(move |(idx, key)| (key, &values[idx]))
+    }
+
+    /// All values, in the order they have been inserted
+    #[inline]
+    pub fn values(&self) -> &[V] {
+        self.tree.values()
+    }
+
+    /// Finds the entry with the key nearest to the `needle`. Returns `None` only if the map is empty.
+    pub fn nearest<Q>(&self, needle: &Q) -> Option<(&K, &V, K::Distance)> where Q: MetricSpace<Impl, UserData = (), Distance = K::Distance> + ?Sized, K: Borrow<Q>, K::Distance: Bounded {
+        self.k_nearest(needle, 1).into_iter().next()
+    }
+
+    /// Finds up to `k` entries with keys nearest to the `needle`, sorted by distance, closest first.
+    pub fn k_nearest<Q>(&self, needle: &Q, k: usize) -> Vec<(&K, &V, K::Distance)> where Q: MetricSpace<Impl, UserData = (), Distance = K::Distance> + ?Sized, K: Borrow<Q>, K::Distance: Bounded {
+        self.search(needle, Entries { limit: k, max_distance: <K::Distance as Bounded>::max_value(), found: Vec::with_capacity(k.min(1024) + 1) })
+    }
+
+    /// Finds all entries with keys at most `radius` away from the `needle`, sorted by distance, closest first.
+    pub fn within_radius<Q>(&self, needle: &Q, radius: K::Distance) -> Vec<(&K, &V, K::Distance)> where Q: MetricSpace<Impl, UserData = (), Distance = K::Distance> + ?Sized, K: Borrow<Q> {
+        self.search(needle, Entries { limit: usize::MAX, max_distance: radius, found: Vec::new() })
+    }
+
+    fn search<'map, Q>(&'map self, needle: &Q, entries: Entries<'map, K, Impl>) -> Vec<(&'map K, &'map V, K::Distance)> where Q: MetricSpace<Impl, UserData = (), Distance = K::Distance> + ?Sized, K: Borrow<Q> {
+        let values = self.tree.values();
+        self.tree.tree().find_nearest_custom(needle, &(), entries).into_iter()
+            .map(|(key, idx, distance)| (key, &values[idx], distance))
+            .collect()
+    }
+}
+
+/// Nearest keys with their indexes. With `usize::MAX` as the `limit` it's a radius search.
+struct Entries<'tree, K: MetricSpace<Impl>, Impl> {
+    limit: usize,
+    max_distance: K::Distance,
+    found: Vec<((&'tree K, usize), K::Distance)>,
+}
+
+impl<'tree, K: MetricSpace<Impl> + Clone, Impl> BestCandidate<'tree, K, Impl> for Entries<'tree, K, Impl> {
+    type Output = Vec<(&'tree K, usize, K::Distance)>;
+
+    #[inline]
+    fn consider(&mut self, key: &'tree K, distance: K::Distance, candidate_index: usize, _: &K::UserData) {
+        if self.limit == usize::MAX {
+            if distance <= self.max_distance {
+                self.found.push(((key, candidate_index), distance));
+            }
+        } else {
+            insert_nearest(&mut self.found, self.limit, self.max_distance, (key, candidate_index), distance);
+        }
+    }
+
+    #[inline]
+    fn distance(&self) -> K::Distance {
+        kth_distance(&self.found, self.limit, self.max_distance)
+    }
+
+    fn result(mut self, _: &K::UserData) -> Self::Output {
+        if self.limit == usize::MAX {
+            self.found.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal).then((a.0).1.cmp(&(b.0).1)));
+        }
+        self.found.into_iter().map(|((key, idx), distance)| (key, idx, distance)).collect()
+    }
+}
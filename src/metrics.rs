@@ -0,0 +1,487 @@
+//! Ready-made distance functions for common types.
+//!
+//! Each metric is selected by a marker type used as the `Impl` type parameter of the `Tree`:
+//!
+//! ```rust
+//! use vpsearch::{Tree, metrics::Euclidean};
+//!
+//! let points = [[2.0f32, 3.0], [0.0, 1.0], [4.0, 5.0]];
+//! let tree: Tree<[f32; 2], Euclidean> = Tree::new(&points);
+//! let (index, _) = tree.find_nearest(&[0.5, 1.5]);
+//! assert_eq!(index, 1);
+//! ```
+
+use crate::MetricSpace;
+use num_traits::{Bounded, Float, One, Zero};
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use core::ops::Add;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "simd")]
+pub mod simd;
+mod levenshtein;
+#[cfg(feature = "color")]
+mod color;
+#[cfg(feature = "std")]
+mod cached;
+mod composite;
+mod map;
+mod matrix;
+mod quantized;
+
+pub use self::levenshtein::{levenshtein, levenshtein_within, Levenshtein, LevenshteinScratch};
+#[cfg(feature = "color")]
+pub use self::color::Lab;
+#[cfg(feature = "std")]
+pub use self::cached::{CachedMetric, DistanceCache};
+pub use self::composite::{CompositeMetric, Weights};
+pub use self::map::{MapMetric, Projection};
+pub use self::matrix::{DistanceMatrix, MatrixItem};
+pub use self::quantized::{Quantized, QuantizedElement, Quantizer};
+
+/// The straight-line distance, `sqrt(Σ(a-b)²)`.
+///
+/// Implemented for `[T; N]`, `[T]` (so also `&[T]` and `Box<[T]>`) and `Vec<T>`, where `T` is `f32` or `f64`.
+/// With the `tuples` feature also for `(T, T)` and `(T, T, T)`, and with the `half` feature for `half::f16` and `bf16` elements.
+/// For vectors stored at a lower precision, see `Quantized`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Euclidean;
+
+/// The taxicab distance, `Σ|a-b|`.
+///
+/// Implemented for `[T; N]`, `[T]` (so also `&[T]` and `Box<[T]>`) and `Vec<T>`, where `T` is `f32` or `f64`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Manhattan;
+
+/// The largest difference in any dimension, `max(|a-b|)`.
+///
+/// Implemented for `[T; N]`, `[T]` (so also `&[T]` and `Box<[T]>`) and `Vec<T>`, where `T` is `f32` or `f64`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Chebyshev;
+
+macro_rules! vector_kernels {
+    ($t:ty, $euclidean:ident, $manhattan:ident, $chebyshev:ident) => {
+        #[inline]
+        fn $euclidean(a: &[$t], b: &[$t]) -> $t {
+            // For short vectors the run-time CPU feature check isn't worth it
+            #[cfg(feature = "simd")]
+            {
+                if a.len() >= 16 {
+                    return simd::$euclidean(a, b);
+                }
+            }
+            a.iter().zip(b)
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum::<$t>()
+                .sqrt()
+        }
+
+        #[inline]
+        fn $manhattan(a: &[$t], b: &[$t]) -> $t {
+            #[cfg(feature = "simd")]
+            {
+                if a.len() >= 16 {
+                    return simd::$manhattan(a, b);
+                }
+            }
+            a.iter().zip(b)
+                .map(|(a, b)| (a - b).abs())
+                .sum::<$t>()
+        }
+
+        #[inline]
+        fn $chebyshev(a: &[$t], b: &[$t]) -> $t {
+            a.iter().zip(b)
+                .map(|(a, b)| (a - b).abs())
+                .fold(0., <$t>::max)
+        }
+    };
+}
+
+vector_kernels!(f32, euclidean_f32, manhattan_f32, chebyshev_f32);
+vector_kernels!(f64, euclidean_f64, manhattan_f64, chebyshev_f64);
+
+macro_rules! impl_vector_metric {
+    ($metric:ty, $t:ty, $kernel:ident) => {
+        impl<const N: usize> MetricSpace<$metric> for [$t; N] {
+            type UserData = ();
+            type Distance = $t;
+
+            #[inline]
+            fn distance(&self, other: &Self, _: &()) -> $t {
+                $kernel(self, other)
+            }
+        }
+
+        impl MetricSpace<$metric> for [$t] {
+            type UserData = ();
+            type Distance = $t;
+
+            #[inline]
+            fn distance(&self, other: &Self, _: &()) -> $t {
+                $kernel(self, other)
+            }
+        }
+
+        impl MetricSpace<$metric> for Vec<$t> {
+            type UserData = ();
+            type Distance = $t;
+
+            #[inline]
+            fn distance(&self, other: &Self, _: &()) -> $t {
+                $kernel(self, other)
+            }
+        }
+    };
+}
+
+impl_vector_metric!(Euclidean, f32, euclidean_f32);
+impl_vector_metric!(Euclidean, f64, euclidean_f64);
+impl_vector_metric!(Manhattan, f32, manhattan_f32);
+impl_vector_metric!(Manhattan, f64, manhattan_f64);
+impl_vector_metric!(Chebyshev, f32, chebyshev_f32);
+impl_vector_metric!(Chebyshev, f64, chebyshev_f64);
+
+/// Euclidean distance for half-precision vectors (`half` feature). It's computed in `f32`, and rounded to the vector's type.
+#[cfg(feature = "half")]
+macro_rules! half_kernel {
+    ($t:ty, $name:ident) => {
+        #[inline]
+        fn $name(a: &[$t], b: &[$t]) -> $t {
+            let sum = a.iter().zip(b)
+                .map(|(a, b)| (a.to_f32() - b.to_f32()) * (a.to_f32() - b.to_f32()))
+                .sum::<f32>();
+            <$t>::from_f32(sum.sqrt())
+        }
+    };
+}
+
+#[cfg(feature = "half")]
+half_kernel!(half::f16, euclidean_f16);
+#[cfg(feature = "half")]
+half_kernel!(half::bf16, euclidean_bf16);
+#[cfg(feature = "half")]
+impl_vector_metric!(Euclidean, half::f16, euclidean_f16);
+#[cfg(feature = "half")]
+impl_vector_metric!(Euclidean, half::bf16, euclidean_bf16);
+
+/// Euclidean distance for 2D and 3D points stored as plain tuples (`tuples` feature).
+#[cfg(feature = "tuples")]
+macro_rules! impl_euclidean_tuple {
+    ($tuple:ty, $t:ty, $($n:tt),+) => {
+        impl MetricSpace<Euclidean> for $tuple {
+            type UserData = ();
+            type Distance = $t;
+
+            #[inline]
+            fn distance(&self, other: &Self, _: &()) -> $t {
+                (0. $(+ (self.$n - other.$n) * (self.$n - other.$n))+).sqrt()
+            }
+        }
+    };
+}
+
+#[cfg(feature = "tuples")]
+impl_euclidean_tuple!((f32, f32), f32, 0, 1);
+#[cfg(feature = "tuples")]
+impl_euclidean_tuple!((f64, f64), f64, 0, 1);
+#[cfg(feature = "tuples")]
+impl_euclidean_tuple!((f32, f32, f32), f32, 0, 1, 2);
+#[cfg(feature = "tuples")]
+impl_euclidean_tuple!((f64, f64, f64), f64, 0, 1, 2);
+
+/// The number of differing bits, for comparing perceptual hashes and other binary fingerprints.
+///
+/// Implemented for `Hash64`, `Hash128` and `[u8; N]`. The distance is `u32`.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Hamming;
+
+/// A 64-bit hash compared with the `Hamming` distance.
+///
+/// Plain integers don't implement the metric, because then integer literals in code with its own `MetricSpace` impls for integers
+/// would no longer have an unambiguous type.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Hash64(pub u64);
+
+/// A 128-bit hash compared with the `Hamming` distance. See `Hash64`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Hash128(pub u128);
+
+macro_rules! impl_hash {
+    ($hash:ident, $int:ty) => {
+        impl From<$int> for $hash {
+            #[inline]
+            fn from(hash: $int) -> Self {
+                $hash(hash)
+            }
+        }
+
+        impl MetricSpace<Hamming> for $hash {
+            type UserData = ();
+            type Distance = u32;
+
+            #[inline]
+            fn distance(&self, other: &Self, _: &()) -> u32 {
+                (self.0 ^ other.0).count_ones()
+            }
+        }
+    };
+}
+
+impl_hash!(Hash64, u64);
+impl_hash!(Hash128, u128);
+
+impl<const N: usize> MetricSpace<Hamming> for [u8; N] {
+    type UserData = ();
+    type Distance = u32;
+
+    #[inline]
+    fn distance(&self, other: &Self, _: &()) -> u32 {
+        #[cfg(feature = "simd")]
+        {
+            simd::hamming(self, other)
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            hamming_bytes(self, other)
+        }
+    }
+}
+
+#[inline(always)]
+pub(crate) fn hamming_bytes(a: &[u8], b: &[u8]) -> u32 {
+    // Comparing 8 bytes at a time is much faster than going byte by byte
+    let a = a.chunks_exact(8);
+    let b = b.chunks_exact(8);
+    let tail = a.remainder().iter().zip(b.remainder())
+        .map(|(a, b)| (a ^ b).count_ones())
+        .sum::<u32>();
+    a.zip(b).map(|(a, b)| {
+        let a = u64::from_ne_bytes(a.try_into().unwrap());
+        let b = u64::from_ne_bytes(b.try_into().unwrap());
+        (a ^ b).count_ones()
+    }).sum::<u32>() + tail
+}
+
+/// Great-circle distance on Earth, in meters, between `(latitude, longitude)` pairs in degrees.
+///
+/// Implemented for `(f64, f64)` and `(f32, f32)`. It uses the haversine formula on a sphere with the mean Earth radius,
+/// so it's a true metric (unlike ellipsoidal distances), with an error of up to 0.5% compared to geodesic distances.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Haversine;
+
+/// Mean Earth radius in meters, as defined by IUGG
+pub const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// See `Haversine`
+#[inline]
+pub fn haversine(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (a.0.to_radians(), a.1.to_radians());
+    let (lat2, lon2) = (b.0.to_radians(), b.1.to_radians());
+    let sin_dlat = ((lat2 - lat1) * 0.5).sin();
+    let sin_dlon = ((lon2 - lon1) * 0.5).sin();
+    let h = sin_dlat * sin_dlat + lat1.cos() * lat2.cos() * sin_dlon * sin_dlon;
+    // rounding errors could push it slightly out of asin's domain
+    2. * EARTH_RADIUS_METERS * h.sqrt().min(1.).asin()
+}
+
+impl MetricSpace<Haversine> for (f64, f64) {
+    type UserData = ();
+    type Distance = f64;
+
+    #[inline]
+    fn distance(&self, other: &Self, _: &()) -> f64 {
+        haversine(*self, *other)
+    }
+}
+
+impl MetricSpace<Haversine> for (f32, f32) {
+    type UserData = ();
+    type Distance = f32;
+
+    /// Computed in `f64`, because `f32` isn't precise enough for short distances
+    #[inline]
+    fn distance(&self, other: &Self, _: &()) -> f32 {
+        haversine((self.0.into(), self.1.into()), (other.0.into(), other.1.into())) as f32
+    }
+}
+
+/// A vector scaled to unit length, compared by the angle between vectors (in radians, from 0 to π).
+///
+/// This is a metric replacement for the cosine "distance" (`1 - cosine similarity`), which doesn't satisfy
+/// the triangle inequality, and can't be used with the tree. Both rank neighbors in the same order.
+///
+/// Vectors are normalized once when the `UnitVector` is created, so comparisons don't need to compute lengths.
+/// The storage can be a `Vec`, `Box<[_]>` or an array of `f32` or `f64`.
+///
+/// ```rust
+/// use vpsearch::metrics::UnitVector;
+///
+/// let items: Vec<_> = vec![[1.0f32, 0.0], [1.0, 1.0], [0.0, 5.0]].into_iter().filter_map(UnitVector::new).collect();
+/// let tree = vpsearch::Tree::new(&items);
+/// let (index, angle) = tree.find_nearest(&UnitVector::new([3.0, 2.0]).unwrap());
+/// assert_eq!(index, 1);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitVector<V>(V);
+
+/// Storage of a `UnitVector`: `Vec<T>`, `Box<[T]>` or `[T; N]`, where `T` is `f32` or `f64`.
+pub trait VectorStorage {
+    type Elem: Float;
+    fn as_slice(&self) -> &[Self::Elem];
+    fn as_mut_slice(&mut self) -> &mut [Self::Elem];
+}
+
+macro_rules! impl_vector_storage {
+    ($t:ty) => {
+        impl VectorStorage for Vec<$t> {
+            type Elem = $t;
+            fn as_slice(&self) -> &[$t] { self }
+            fn as_mut_slice(&mut self) -> &mut [$t] { self }
+        }
+
+        impl VectorStorage for Box<[$t]> {
+            type Elem = $t;
+            fn as_slice(&self) -> &[$t] { self }
+            fn as_mut_slice(&mut self) -> &mut [$t] { self }
+        }
+
+        impl<const N: usize> VectorStorage for [$t; N] {
+            type Elem = $t;
+            fn as_slice(&self) -> &[$t] { self }
+            fn as_mut_slice(&mut self) -> &mut [$t] { self }
+        }
+    };
+}
+
+impl_vector_storage!(f32);
+impl_vector_storage!(f64);
+
+impl<V: VectorStorage> UnitVector<V> {
+    /// Scales the vector to unit length.
+    ///
+    /// Returns `None` if the vector has zero length, or contains NaN or infinity, since it has no direction then.
+    pub fn new(mut vector: V) -> Option<Self> {
+        let len = vector.as_slice().iter().fold(V::Elem::zero(), |sum, &v| sum + v * v).sqrt();
+        if !len.is_normal() {
+            return None;
+        }
+        for v in vector.as_mut_slice() {
+            *v = *v / len;
+        }
+        Some(UnitVector(vector))
+    }
+
+    #[inline]
+    pub fn as_slice(&self) -> &[V::Elem] {
+        self.0.as_slice()
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> V {
+        self.0
+    }
+}
+
+impl<V: VectorStorage> MetricSpace for UnitVector<V> where V::Elem: crate::DistanceAdd {
+    type UserData = ();
+    type Distance = V::Elem;
+
+    /// The angle is `acos(a·b)`, but calculated as `2·atan2(|a-b|, |a+b|)`, which is precise even for tiny angles
+    #[inline]
+    fn distance(&self, other: &Self, _: &()) -> V::Elem {
+        let (diff, sum) = self.as_slice().iter().zip(other.as_slice())
+            .fold((V::Elem::zero(), V::Elem::zero()), |(diff, sum), (&a, &b)| {
+                (diff + (a - b) * (a - b), sum + (a + b) * (a + b))
+            });
+        let two = V::Elem::one() + V::Elem::one();
+        two * diff.sqrt().atan2(sum.sqrt())
+    }
+}
+
+/// Kademlia-style XOR distance between node IDs, interpreted as big-endian integers.
+///
+/// Implemented for `[u8; N]` (e.g. 160-bit `[u8; 20]` or 256-bit `[u8; 32]` IDs). The distance is `XorDistance<N>`.
+/// It satisfies the triangle inequality, because XOR is addition without carrying.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Xor;
+
+/// Result of the `Xor` metric: an `N`-byte big-endian unsigned integer.
+///
+/// Addition saturates at the maximum value instead of overflowing.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct XorDistance<const N: usize>(pub [u8; N]);
+
+impl<const N: usize> XorDistance<N> {
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8; N] {
+        &self.0
+    }
+
+    /// Number of leading zero bits. In Kademlia `N*8 - leading_zeros` is the index of the k-bucket.
+    pub fn leading_zeros(&self) -> u32 {
+        match self.0.iter().position(|&b| b != 0) {
+            Some(pos) => pos as u32 * 8 + self.0[pos].leading_zeros(),
+            None => N as u32 * 8,
+        }
+    }
+}
+
+impl<const N: usize> Bounded for XorDistance<N> {
+    #[inline]
+    fn min_value() -> Self {
+        XorDistance([0; N])
+    }
+
+    #[inline]
+    fn max_value() -> Self {
+        XorDistance([0xFF; N])
+    }
+}
+
+impl<const N: usize> Add for XorDistance<N> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let mut sum = [0; N];
+        let mut carry = 0;
+        for ((s, &a), &b) in sum.iter_mut().zip(&self.0).zip(&other.0).rev() {
+            let res = u16::from(a) + u16::from(b) + carry;
+            *s = res as u8;
+            carry = res >> 8;
+        }
+        if carry != 0 {
+            return Self::max_value();
+        }
+        XorDistance(sum)
+    }
+}
+
+impl<const N: usize> crate::DistanceAdd for XorDistance<N> {
+    #[inline]
+    fn saturating_add(self, other: Self) -> Self {
+        self + other
+    }
+}
+
+impl<const N: usize> MetricSpace<Xor> for [u8; N] {
+    type UserData = ();
+    type Distance = XorDistance<N>;
+
+    #[inline]
+    fn distance(&self, other: &Self, _: &()) -> XorDistance<N> {
+        let mut d = [0; N];
+        for ((d, a), b) in d.iter_mut().zip(self).zip(other) {
+            *d = a ^ b;
+        }
+        XorDistance(d)
+    }
+}
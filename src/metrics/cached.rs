@@ -0,0 +1,168 @@
+use crate::{CacheStats, MetricSpace};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Wraps another `MetricSpace` item, and remembers distances between items, so that each pair is compared only once.
+///
+/// It's for expensive metrics, when the same pairs are compared repeatedly, e.g. when searching for neighbors of every item in the tree.
+/// The distances are keyed by item numbers, which must be unique. `wrap_all()` numbers items by their index.
+/// The `UserData` is a `DistanceCache`, which can also look up distances that have been computed elsewhere, see `DistanceCache::with_lookup`.
+///
+/// ```rust
+/// use vpsearch::metrics::{CachedMetric, DistanceCache};
+/// # #[derive(Clone)] struct Expensive(f32);
+/// # impl vpsearch::MetricSpace for Expensive {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+///
+/// let items = CachedMetric::wrap_all(vec![Expensive(1.), Expensive(5.), Expensive(2.)]);
+/// let tree = vpsearch::Tree::new_with_user_data_owned(&items, DistanceCache::new(()));
+/// for item in &items {
+///     tree.find_k_nearest(item, 2);
+/// }
+/// // Needles that aren't in the tree aren't cached
+/// tree.find_nearest(&CachedMetric::needle(Expensive(3.)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct CachedMetric<T> {
+    key: Option<u32>,
+    item: T,
+}
+
+/// `UserData` of `CachedMetric`: the distances computed so far, and the `UserData` of the wrapped metric.
+#[derive(Debug, Default)]
+pub struct DistanceCache<U, D> {
+    user_data: U,
+    distances: Mutex<HashMap<(u32, u32), D>>,
+    lookup: Option<Lookup<D>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+struct Lookup<D>(Box<dyn Fn(u32, u32) -> Option<D> + Send + Sync>);
+
+impl<D> fmt::Debug for Lookup<D> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Lookup")
+    }
+}
+
+impl<T> CachedMetric<T> {
+    /// The `key` identifies the item in the cache. Items with the same key must be equal.
+    #[inline]
+    pub fn new(key: u32, item: T) -> Self {
+        CachedMetric { key: Some(key), item }
+    }
+
+    /// Wraps the items, using their index as the key
+    pub fn wrap_all<I: IntoIterator<Item = T>>(items: I) -> Vec<Self> {
+        items.into_iter().enumerate().map(|(i, item)| Self::new(i as u32, item)).collect()
+    }
+
+    /// A one-off item, e.g. a needle, that is compared without the cache
+    #[inline]
+    pub fn needle(item: T) -> Self {
+        CachedMetric { key: None, item }
+    }
+
+    /// The wrapped item
+    #[inline]
+    pub fn get(&self) -> &T {
+        &self.item
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.item
+    }
+}
+
+impl<U, D> DistanceCache<U, D> {
+    /// `user_data` is for the wrapped metric
+    pub fn new(user_data: U) -> Self {
+        DistanceCache { user_data, distances: Mutex::new(HashMap::new()), lookup: None, hits: AtomicU64::new(0), misses: AtomicU64::new(0) }
+    }
+
+    /// Like `new`, but distances that aren't cached yet are looked up with `lookup(a, b)` before they're computed,
+    /// e.g. in a database of results of earlier runs. `a` is the smaller of the two item numbers.
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use vpsearch::metrics::{CachedMetric, DistanceCache};
+    /// # #[derive(Clone)] struct Experiment(f32);
+    /// # impl vpsearch::MetricSpace for Experiment {
+    /// #     type UserData = (); type Distance = f32;
+    /// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+    /// # }
+    ///
+    /// let known: HashMap<(u32, u32), f32> = vec![((0, 1), 4.), ((0, 2), 1.)].into_iter().collect();
+    /// let cache = DistanceCache::with_lookup((), move |a, b| known.get(&(a, b)).copied());
+    /// let items = CachedMetric::wrap_all(vec![Experiment(1.), Experiment(5.), Experiment(2.)]);
+    /// let tree = vpsearch::Tree::new_with_user_data_owned(&items, cache);
+    /// println!("{:.0}% of distances were known", tree.user_data().stats().hit_rate() * 100.);
+    /// ```
+    pub fn with_lookup(user_data: U, lookup: impl Fn(u32, u32) -> Option<D> + Send + Sync + 'static) -> Self {
+        DistanceCache { lookup: Some(Lookup(Box::new(lookup))), ..Self::new(user_data) }
+    }
+
+    /// The `UserData` of the wrapped metric
+    #[inline]
+    pub fn user_data(&self) -> &U {
+        &self.user_data
+    }
+
+    /// Number of pairs of items with a cached distance
+    pub fn len(&self) -> usize {
+        self.distances.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of distances that have been cached or looked up (hits), and that had to be computed (misses).
+    /// Distances to needles that aren't cached aren't counted.
+    pub fn stats(&self) -> CacheStats {
+        CacheStats { hits: self.hits.load(Ordering::Relaxed), misses: self.misses.load(Ordering::Relaxed) }
+    }
+
+    /// Forgets all distances and resets the statistics, e.g. to free memory after the tree has been built
+    pub fn clear(&self) {
+        self.distances.lock().unwrap().clear();
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+    }
+}
+
+impl<T: MetricSpace<Impl>, Impl> MetricSpace<Impl> for CachedMetric<T> {
+    type UserData = DistanceCache<T::UserData, T::Distance>;
+    type Distance = T::Distance;
+
+    fn distance(&self, other: &Self, cache: &Self::UserData) -> Self::Distance {
+        // The distance is symmetric, so (a, b) and (b, a) are the same pair
+        let key = match (self.key, other.key) {
+            (Some(a), Some(b)) => (a.min(b), a.max(b)),
+            _ => return self.item.distance(&other.item, &cache.user_data),
+        };
+        if let Some(&distance) = cache.distances.lock().unwrap().get(&key) {
+            cache.hits.fetch_add(1, Ordering::Relaxed);
+            return distance;
+        }
+        // Not holding the lock while looking up or computing, so that other threads can use the cache
+        let distance = match cache.lookup.as_ref().and_then(|lookup| (lookup.0)(key.0, key.1)) {
+            Some(distance) => {
+                cache.hits.fetch_add(1, Ordering::Relaxed);
+                distance
+            },
+            None => {
+                cache.misses.fetch_add(1, Ordering::Relaxed);
+                self.item.distance(&other.item, &cache.user_data)
+            },
+        };
+        cache.distances.lock().unwrap().insert(key, distance);
+        distance
+    }
+}
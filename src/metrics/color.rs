@@ -0,0 +1,100 @@
+use crate::MetricSpace;
+use rgb::RGB8;
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+
+/// A color in the CIE L\*a\*b\* color space (D65 white point), compared using the ΔE\*76 color difference.
+///
+/// ΔE\*76 is the Euclidean distance in Lab, so unlike the later ΔE formulas it's a true metric.
+/// A difference of about 2.3 is just noticeable.
+///
+/// ```rust
+/// use vpsearch::metrics::Lab;
+///
+/// let palette: Vec<Lab> = [[0, 0, 0], [255, 255, 255], [255, 0, 0], [0, 128, 0]].iter().map(|&c| c.into()).collect();
+/// let tree = vpsearch::Tree::new(&palette);
+/// let (index, _) = tree.find_nearest(&Lab::from_srgb(200, 30, 40));
+/// assert_eq!(index, 2);
+/// ```
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct Lab {
+    /// Lightness, 0-100
+    pub l: f32,
+    /// Green-red axis
+    pub a: f32,
+    /// Blue-yellow axis
+    pub b: f32,
+}
+
+impl Lab {
+    #[inline]
+    pub fn new(l: f32, a: f32, b: f32) -> Self {
+        Lab { l, a, b }
+    }
+
+    /// Converts from 8-bit sRGB (gamma-compressed, as used in most image files and CSS)
+    pub fn from_srgb(r: u8, g: u8, b: u8) -> Self {
+        let (r, g, b) = (srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b));
+        // linear sRGB to XYZ, normalized to the D65 white point
+        let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+        let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175 * b;
+        let z = (0.019_333_9 * r + 0.119_192 * g + 0.950_304_1 * b) / 1.088_83;
+        let (fx, fy, fz) = (lab_f(x), lab_f(y), lab_f(z));
+        Lab {
+            l: 116. * fy - 16.,
+            a: 500. * (fx - fy),
+            b: 200. * (fy - fz),
+        }
+    }
+
+    /// ΔE\*76 color difference
+    #[inline]
+    pub fn delta_e(&self, other: &Self) -> f32 {
+        let dl = self.l - other.l;
+        let da = self.a - other.a;
+        let db = self.b - other.b;
+        (dl * dl + da * da + db * db).sqrt()
+    }
+}
+
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = f32::from(c) / 255.;
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn lab_f(t: f32) -> f32 {
+    const DELTA: f32 = 6. / 29.;
+    if t > DELTA * DELTA * DELTA {
+        t.cbrt()
+    } else {
+        t / (3. * DELTA * DELTA) + 4. / 29.
+    }
+}
+
+impl From<RGB8> for Lab {
+    #[inline]
+    fn from(c: RGB8) -> Self {
+        Lab::from_srgb(c.r, c.g, c.b)
+    }
+}
+
+impl From<[u8; 3]> for Lab {
+    #[inline]
+    fn from(c: [u8; 3]) -> Self {
+        Lab::from_srgb(c[0], c[1], c[2])
+    }
+}
+
+impl MetricSpace for Lab {
+    type UserData = ();
+    type Distance = f32;
+
+    #[inline]
+    fn distance(&self, other: &Self, _: &()) -> f32 {
+        self.delta_e(other)
+    }
+}
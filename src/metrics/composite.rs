@@ -0,0 +1,104 @@
+use crate::{DistanceAdd, MetricSpace};
+use core::ops::Mul;
+
+/// Combines two kinds of distances between the same things, e.g. visual and textual similarity of products.
+///
+/// The distance is `w₁·d₁ + w₂·d₂`, where the weights come from the `UserData`, which is `Weights`.
+/// A weighted sum of metrics is a metric too, as long as the weights aren't negative, and at least one isn't zero.
+/// Both metrics must have the same `Distance` type.
+///
+/// The `Impl` of the tree is a tuple of the `Impl`s of the two metrics, `((), ())` for types with the default one.
+///
+/// ```rust
+/// use vpsearch::metrics::{CompositeMetric, Weights};
+/// # #[derive(Clone)] struct Image(f32);
+/// # impl vpsearch::MetricSpace for Image {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+/// # #[derive(Clone)] struct Text(f32);
+/// # impl vpsearch::MetricSpace for Text {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+///
+/// let products = vec![
+///     CompositeMetric::new(Image(1.), Text(10.)),
+///     CompositeMetric::new(Image(5.), Text(2.)),
+/// ];
+/// let tree = vpsearch::Tree::new_with_user_data_owned(&products, Weights::new(0.75, 0.25));
+/// let (index, distance) = tree.find_nearest(&CompositeMetric::new(Image(1.), Text(2.)));
+/// assert_eq!((index, distance), (0, 2.));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompositeMetric<A, B> {
+    first: A,
+    second: B,
+}
+
+/// `UserData` of `CompositeMetric`: weights of the two distances, and the `UserData` of both metrics.
+#[derive(Debug, Clone)]
+pub struct Weights<D, UA = (), UB = ()> {
+    first: D,
+    second: D,
+    user_data: (UA, UB),
+}
+
+impl<A, B> CompositeMetric<A, B> {
+    #[inline]
+    pub fn new(first: A, second: B) -> Self {
+        CompositeMetric { first, second }
+    }
+
+    #[inline]
+    pub fn first(&self) -> &A {
+        &self.first
+    }
+
+    #[inline]
+    pub fn second(&self) -> &B {
+        &self.second
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> (A, B) {
+        (self.first, self.second)
+    }
+}
+
+impl<D> Weights<D> {
+    /// Weights for metrics that don't use `UserData`
+    pub fn new(first: D, second: D) -> Self {
+        Self::with_user_data(first, second, (), ())
+    }
+}
+
+impl<D, UA, UB> Weights<D, UA, UB> {
+    /// `first_user_data` and `second_user_data` are passed to the two metrics
+    pub fn with_user_data(first: D, second: D, first_user_data: UA, second_user_data: UB) -> Self {
+        Weights { first, second, user_data: (first_user_data, second_user_data) }
+    }
+
+    /// The `UserData` of the two metrics
+    #[inline]
+    pub fn user_data(&self) -> &(UA, UB) {
+        &self.user_data
+    }
+}
+
+impl<A, B, ImplA, ImplB> MetricSpace<(ImplA, ImplB)> for CompositeMetric<A, B>
+where
+    A: MetricSpace<ImplA>,
+    B: MetricSpace<ImplB, Distance = A::Distance>,
+    A::Distance: Mul<Output = A::Distance>,
+{
+    type UserData = Weights<A::Distance, A::UserData, B::UserData>;
+    type Distance = A::Distance;
+
+    #[inline]
+    fn distance(&self, other: &Self, weights: &Self::UserData) -> Self::Distance {
+        let first = weights.first * self.first.distance(&other.first, &weights.user_data.0);
+        let second = weights.second * self.second.distance(&other.second, &weights.user_data.1);
+        first.saturating_add(second)
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{MetricSpace, MetricSpaceWithContext};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem;
+
+/// Edit distance between strings: the number of inserted, deleted or substituted characters.
+///
+/// Implemented for `String` and `str` (so also `&str` and `Box<str>`). The distance is `u32`.
+///
+/// Strings are compared by Unicode scalar values (`char`s), and the calculation only explores edits
+/// near the diagonal, so it's fast when strings are similar, and still exact when they're not.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Levenshtein;
+
+/// Reusable memory for computing `Levenshtein` distances without allocating on every comparison.
+///
+/// Pass it as the context to `find_k_nearest_with_context()` and similar methods.
+#[derive(Debug, Default)]
+pub struct LevenshteinScratch {
+    rows: (Vec<usize>, Vec<usize>),
+    chars: (Vec<char>, Vec<char>),
+}
+
+/// Edit distance between two strings. See `Levenshtein`.
+pub fn levenshtein(a: &str, b: &str) -> u32 {
+    levenshtein_with_scratch(a, b, &mut LevenshteinScratch::default())
+}
+
+fn levenshtein_with_scratch(a: &str, b: &str, scratch: &mut LevenshteinScratch) -> u32 {
+    // ASCII strings are compared as bytes to avoid decoding
+    let d = if a.is_ascii() && b.is_ascii() {
+        exact(a.as_bytes(), b.as_bytes(), &mut scratch.rows)
+    } else {
+        let (a_chars, b_chars) = &mut scratch.chars;
+        a_chars.clear();
+        a_chars.extend(a.chars());
+        b_chars.clear();
+        b_chars.extend(b.chars());
+        exact(a_chars, b_chars, &mut scratch.rows)
+    };
+    d as u32
+}
+
+/// Edit distance between two strings, or `None` if it's larger than `max`.
+///
+/// This gives up as soon as the limit is exceeded, so it's much faster than `levenshtein()` for dissimilar strings.
+pub fn levenshtein_within(a: &str, b: &str, max: u32) -> Option<u32> {
+    let max = max as usize;
+    let d = if a.is_ascii() && b.is_ascii() {
+        let (a, b) = trim_common(a.as_bytes(), b.as_bytes());
+        bounded(a, b, max, &mut Default::default())
+    } else {
+        let a: Vec<_> = a.chars().collect();
+        let b: Vec<_> = b.chars().collect();
+        let (a, b) = trim_common(&a, &b);
+        bounded(a, b, max, &mut Default::default())
+    };
+    d.map(|d| d as u32)
+}
+
+/// Common prefix and suffix don't affect the distance
+fn trim_common<'a, T: PartialEq>(a: &'a [T], b: &'a [T]) -> (&'a [T], &'a [T]) {
+    let prefix = a.iter().zip(b).take_while(|(a, b)| a == b).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let suffix = a.iter().rev().zip(b.iter().rev()).take_while(|(a, b)| a == b).count();
+    (&a[..a.len() - suffix], &b[..b.len() - suffix])
+}
+
+/// Tries increasingly wide bands until the distance fits in one, which makes it exact
+fn exact<T: PartialEq>(a: &[T], b: &[T], rows: &mut (Vec<usize>, Vec<usize>)) -> usize {
+    let (a, b) = trim_common(a, b);
+    let longest = a.len().max(b.len());
+    let mut band = (longest - a.len().min(b.len())).max(4);
+    loop {
+        if let Some(d) = bounded(a, b, band, rows) {
+            return d;
+        }
+        if band >= longest {
+            // can't happen, since the distance is never larger than the longer string
+            return longest;
+        }
+        band = (band * 2).min(longest);
+    }
+}
+
+/// Computes only the cells of the edit matrix that are at most `max` away from the diagonal,
+/// and stops early when all cells in a row exceed `max`.
+fn bounded<T: PartialEq>(a: &[T], b: &[T], max: usize, (prev, cur): &mut (Vec<usize>, Vec<usize>)) -> Option<usize> {
+    let (a, b) = if a.len() > b.len() { (b, a) } else { (a, b) };
+    let (n, m) = (a.len(), b.len());
+    if m - n > max {
+        return None;
+    }
+    if n == 0 {
+        return Some(m);
+    }
+
+    let over = max.saturating_add(1);
+    prev.clear();
+    prev.extend((0..=m).map(|j| if j <= max { j } else { over }));
+    cur.clear();
+    cur.resize(m + 1, over);
+    for i in 1..=n {
+        let lo = i.saturating_sub(max).max(1);
+        let hi = i.saturating_add(max).min(m);
+        cur[lo - 1] = if lo == 1 && i <= max { i } else { over };
+        let mut row_min = cur[lo - 1];
+        for j in lo..=hi {
+            let substitution = prev[j - 1] + (a[i - 1] != b[j - 1]) as usize;
+            let d = substitution.min(prev[j] + 1).min(cur[j - 1] + 1).min(over);
+            cur[j] = d;
+            row_min = row_min.min(d);
+        }
+        if hi < m {
+            cur[hi + 1] = over;
+        }
+        if row_min > max {
+            return None;
+        }
+        mem::swap(prev, cur);
+    }
+    Some(prev[m]).filter(|&d| d <= max)
+}
+
+impl MetricSpace<Levenshtein> for String {
+    type UserData = ();
+    type Distance = u32;
+
+    #[inline]
+    fn distance(&self, other: &Self, _: &()) -> u32 {
+        levenshtein(self, other)
+    }
+}
+
+impl MetricSpace<Levenshtein> for str {
+    type UserData = ();
+    type Distance = u32;
+
+    #[inline]
+    fn distance(&self, other: &Self, _: &()) -> u32 {
+        levenshtein(self, other)
+    }
+}
+
+impl MetricSpaceWithContext<LevenshteinScratch, Levenshtein> for String {
+    #[inline]
+    fn distance_with_context(&self, other: &Self, _: &(), scratch: &mut LevenshteinScratch) -> u32 {
+        levenshtein_with_scratch(self, other, scratch)
+    }
+}
+
+impl MetricSpaceWithContext<LevenshteinScratch, Levenshtein> for str {
+    #[inline]
+    fn distance_with_context(&self, other: &Self, _: &(), scratch: &mut LevenshteinScratch) -> u32 {
+        levenshtein_with_scratch(self, other, scratch)
+    }
+}
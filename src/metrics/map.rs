@@ -0,0 +1,98 @@
+use crate::MetricSpace;
+use alloc::vec::Vec;
+use core::fmt;
+use core::marker::PhantomData;
+
+/// Measures distance between items using only a part of them, e.g. an embedding field of a larger record.
+///
+/// The part is taken by a function from the `UserData`, which is a `Projection`, and compared with the part's own `MetricSpace`.
+/// The `Impl` of the tree is the `Impl` of the part's metric. The needle is wrapped the same way as the items.
+///
+/// ```rust
+/// use vpsearch::metrics::{Euclidean, MapMetric, Projection};
+///
+/// #[derive(Clone)]
+/// struct Record { name: &'static str, embedding: [f32; 2] }
+///
+/// let records = MapMetric::wrap_all(vec![
+///     Record { name: "a", embedding: [1., 2.] },
+///     Record { name: "b", embedding: [5., 5.] },
+/// ]);
+/// let tree: vpsearch::Tree<_, Euclidean, _> = vpsearch::Tree::new_with_user_data_owned(&records, Projection::new(|r: &Record| &r.embedding));
+/// let (index, _) = tree.find_nearest(&MapMetric::new(Record { name: "needle", embedding: [4., 4.] }));
+/// assert_eq!(records[index].get().name, "b");
+/// ```
+pub struct MapMetric<T, P: ?Sized> {
+    item: T,
+    part: PhantomData<fn(&T) -> &P>,
+}
+
+/// `UserData` of `MapMetric`: the function that takes the part of the item to compare, and the `UserData` of the part's metric.
+pub struct Projection<T, P: ?Sized, U = ()> {
+    project: fn(&T) -> &P,
+    user_data: U,
+}
+
+impl<T, P: ?Sized> MapMetric<T, P> {
+    #[inline]
+    pub fn new(item: T) -> Self {
+        MapMetric { item, part: PhantomData }
+    }
+
+    pub fn wrap_all<I: IntoIterator<Item = T>>(items: I) -> Vec<Self> {
+        items.into_iter().map(Self::new).collect()
+    }
+
+    /// The whole item
+    #[inline]
+    pub fn get(&self) -> &T {
+        &self.item
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.item
+    }
+}
+
+impl<T: Clone, P: ?Sized> Clone for MapMetric<T, P> {
+    fn clone(&self) -> Self {
+        Self::new(self.item.clone())
+    }
+}
+
+impl<T: fmt::Debug, P: ?Sized> fmt::Debug for MapMetric<T, P> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("MapMetric").field(&self.item).finish()
+    }
+}
+
+impl<T, P: ?Sized> Projection<T, P> {
+    /// `project` returns the part of the item to compare. A closure works, as long as it doesn't capture anything.
+    pub fn new(project: fn(&T) -> &P) -> Self {
+        Self::with_user_data(project, ())
+    }
+}
+
+impl<T, P: ?Sized, U> Projection<T, P, U> {
+    /// Like `new`, but `user_data` is passed to the metric of the part
+    pub fn with_user_data(project: fn(&T) -> &P, user_data: U) -> Self {
+        Projection { project, user_data }
+    }
+
+    /// The `UserData` of the part's metric
+    #[inline]
+    pub fn user_data(&self) -> &U {
+        &self.user_data
+    }
+}
+
+impl<T, P: MetricSpace<Impl> + ?Sized, Impl> MetricSpace<Impl> for MapMetric<T, P> {
+    type UserData = Projection<T, P, P::UserData>;
+    type Distance = P::Distance;
+
+    #[inline]
+    fn distance(&self, other: &Self, projection: &Self::UserData) -> Self::Distance {
+        (projection.project)(&self.item).distance((projection.project)(&other.item), &projection.user_data)
+    }
+}
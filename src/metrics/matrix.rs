@@ -0,0 +1,93 @@
+use crate::{DistanceAdd, MetricSpace, Owned, Tree};
+use alloc::vec::Vec;
+use num_traits::Zero;
+
+/// Distances between all pairs of items, computed elsewhere, e.g. by another program or by SciPy's `pdist`.
+///
+/// It's used as both the `Impl` and the `UserData` of a tree of `MatrixItem` numbers. Create the tree with `Tree::from_distance_matrix`.
+/// The items themselves aren't needed: needles are item numbers too, and distances are looked up in the matrix.
+///
+/// ```rust
+/// use vpsearch::metrics::{DistanceMatrix, MatrixItem};
+///
+/// // Distances between 4 items: 0-1, 0-2, 0-3, 1-2, 1-3, 2-3
+/// let matrix = DistanceMatrix::new(4, vec![1., 5., 6., 4., 5., 2.]).unwrap();
+/// let tree = vpsearch::Tree::from_distance_matrix(matrix);
+/// assert_eq!(tree.find_k_nearest(&MatrixItem(2), 2), [(2, 0.), (3, 2.)]);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistanceMatrix<D> {
+    len: usize,
+    distances: Vec<D>,
+}
+
+impl<D: Copy + Zero> DistanceMatrix<D> {
+    /// Distances between `len` items in the condensed form: the upper triangle of the matrix, row by row,
+    /// `(0, 1), (0, 2) … (0, len-1), (1, 2) … (len-2, len-1)`.
+    ///
+    /// Returns `None` if there aren't exactly `len × (len - 1) / 2` distances.
+    pub fn new(len: usize, distances: Vec<D>) -> Option<Self> {
+        if distances.len() != len.checked_mul(len.saturating_sub(1))? / 2 {
+            return None;
+        }
+        Some(DistanceMatrix { len, distances })
+    }
+
+    /// Number of items
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Distance between items `a` and `b`. It's zero if they're the same item.
+    ///
+    /// Panics if either is out of bounds.
+    #[inline]
+    pub fn get(&self, a: usize, b: usize) -> D {
+        let (a, b) = (a.min(b), a.max(b));
+        assert!(b < self.len, "item {} is out of bounds of the distance matrix of {} items", b, self.len);
+        if a == b {
+            return D::zero();
+        }
+        self.distances[a * (2 * self.len - a - 1) / 2 + (b - a - 1)]
+    }
+}
+
+/// Number of an item in a `DistanceMatrix`, which is the item of its tree.
+///
+/// It's not a plain `u32`, because then integer literals in code with its own `MetricSpace` impls for integers
+/// would no longer have an unambiguous type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MatrixItem(pub u32);
+
+impl From<u32> for MatrixItem {
+    #[inline]
+    fn from(item: u32) -> Self {
+        MatrixItem(item)
+    }
+}
+
+impl<D: Copy + PartialOrd + DistanceAdd + Zero> MetricSpace<DistanceMatrix<D>> for MatrixItem {
+    type UserData = DistanceMatrix<D>;
+    type Distance = D;
+
+    #[inline]
+    fn distance(&self, other: &Self, matrix: &DistanceMatrix<D>) -> D {
+        matrix.get(self.0 as usize, other.0 as usize)
+    }
+}
+
+impl<D: Copy + PartialOrd + DistanceAdd + Zero> Tree<MatrixItem, DistanceMatrix<D>, Owned<DistanceMatrix<D>>> {
+    /// Creates a tree of items numbered from `0` to `matrix.len() - 1`. Search it with item numbers as needles.
+    ///
+    /// Panics in the same cases as `new`.
+    pub fn from_distance_matrix(matrix: DistanceMatrix<D>) -> Self {
+        let items: Vec<MatrixItem> = (0..matrix.len() as u32).map(MatrixItem).collect();
+        Self::new_with_user_data_owned(&items, matrix)
+    }
+}
@@ -0,0 +1,145 @@
+use super::Euclidean;
+use crate::MetricSpace;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+
+/// A vector stored with fewer bits per element than `f32`, e.g. `u8`, to fit more items in memory.
+///
+/// Elements are converted back to `f32` by the `Quantizer`, which is the tree's `UserData`, and the `Euclidean` distance is computed in `f32`.
+/// The tree is built and searched with the same `Quantizer`, so needles have to be quantized by `Tree::user_data()`.
+/// The distances are distances between the quantized vectors, so searches are only as exact as the quantization.
+///
+/// ```rust
+/// use vpsearch::metrics::{Euclidean, Quantizer};
+///
+/// let embeddings = vec![vec![0.1f32, 0.9, 0.4], vec![0.8, 0.2, 0.5], vec![0.3, 0.3, 0.3]];
+/// let quantizer = Quantizer::fit(&embeddings);
+/// // 1 byte per element instead of 4
+/// let items: Vec<_> = embeddings.iter().map(|e| quantizer.quantize::<u8>(e)).collect();
+/// let tree: vpsearch::Tree<_, Euclidean, _> = vpsearch::Tree::new_with_user_data_owned(&items, quantizer);
+///
+/// let needle = tree.user_data().quantize(&[0.75, 0.25, 0.5]);
+/// assert_eq!(tree.find_nearest(&needle).0, 1);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Quantized<S> {
+    elements: Box<[S]>,
+}
+
+/// Conversion between `f32` and the elements of `Quantized` vectors: `value = min + element × scale`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quantizer {
+    min: f32,
+    scale: f32,
+}
+
+/// Element type of `Quantized` vectors.
+///
+/// It's implemented for `u8` (256 steps between the `Quantizer`'s min and max), `f32` (no quantization, for comparing the results),
+/// and with the `half` feature for `half::f16` and `bf16` (which don't use the `Quantizer`'s range).
+pub trait QuantizedElement: Copy {
+    /// The element closest to the `value`
+    fn encode(value: f32, quantizer: &Quantizer) -> Self;
+    fn decode(self, quantizer: &Quantizer) -> f32;
+}
+
+impl QuantizedElement for u8 {
+    #[inline]
+    fn encode(value: f32, quantizer: &Quantizer) -> Self {
+        ((value - quantizer.min) / quantizer.scale).round().clamp(0., 255.) as u8
+    }
+
+    #[inline(always)]
+    fn decode(self, quantizer: &Quantizer) -> f32 {
+        quantizer.min + f32::from(self) * quantizer.scale
+    }
+}
+
+impl QuantizedElement for f32 {
+    #[inline(always)]
+    fn encode(value: f32, _: &Quantizer) -> Self {
+        value
+    }
+
+    #[inline(always)]
+    fn decode(self, _: &Quantizer) -> f32 {
+        self
+    }
+}
+
+#[cfg(feature = "half")]
+macro_rules! impl_half_element {
+    ($t:ty) => {
+        impl QuantizedElement for $t {
+            #[inline(always)]
+            fn encode(value: f32, _: &Quantizer) -> Self {
+                <$t>::from_f32(value)
+            }
+
+            #[inline(always)]
+            fn decode(self, _: &Quantizer) -> f32 {
+                self.to_f32()
+            }
+        }
+    };
+}
+
+#[cfg(feature = "half")]
+impl_half_element!(half::f16);
+#[cfg(feature = "half")]
+impl_half_element!(half::bf16);
+
+impl Quantizer {
+    /// Elements of `u8` vectors will cover values from `min` to `max`. Values outside of the range are clamped.
+    pub fn new(min: f32, max: f32) -> Self {
+        let scale = (max - min) / 255.;
+        // All values are the same, and any non-zero scale keeps them exact
+        Self { min, scale: if scale > 0. { scale } else { 1. } }
+    }
+
+    /// Covers the range of all elements of the `vectors`
+    pub fn fit<V: AsRef<[f32]>>(vectors: &[V]) -> Self {
+        let (min, max) = vectors.iter().flat_map(|v| v.as_ref().iter().copied())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), value| (min.min(value), max.max(value)));
+        if min > max {
+            return Self::new(0., 1.);
+        }
+        Self::new(min, max)
+    }
+
+    pub fn quantize<S: QuantizedElement>(&self, vector: &[f32]) -> Quantized<S> {
+        Quantized { elements: vector.iter().map(|&value| S::encode(value, self)).collect() }
+    }
+
+    /// The `f32` values the `vector` stands for
+    pub fn dequantize<S: QuantizedElement>(&self, vector: &Quantized<S>) -> Vec<f32> {
+        vector.elements.iter().map(|&element| element.decode(self)).collect()
+    }
+}
+
+impl<S> Quantized<S> {
+    /// The stored elements. Use `Quantizer::dequantize` to get their values.
+    #[inline]
+    pub fn as_slice(&self) -> &[S] {
+        &self.elements
+    }
+}
+
+impl<S: QuantizedElement> MetricSpace<Euclidean> for Quantized<S> {
+    type UserData = Quantizer;
+    type Distance = f32;
+
+    #[inline]
+    fn distance(&self, other: &Self, quantizer: &Quantizer) -> f32 {
+        debug_assert_eq!(self.elements.len(), other.elements.len());
+        self.elements.iter().zip(other.elements.iter())
+            .map(|(&a, &b)| {
+                let d = a.decode(quantizer) - b.decode(quantizer);
+                d * d
+            })
+            .sum::<f32>()
+            .sqrt()
+    }
+}
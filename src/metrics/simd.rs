@@ -0,0 +1,219 @@
+//! Explicitly vectorized distance kernels for slices (`simd` feature).
+//!
+//! On x86-64 they use AVX2/FMA or `popcnt` instructions when the CPU supports them (checked at run time),
+//! and fall back to portable code that is friendly to auto-vectorization otherwise.
+//!
+//! The built-in metrics for long arrays use these automatically when the feature is enabled.
+//! Slices of different lengths are compared only up to the length of the shorter one.
+
+/// `sqrt(Σ(a-b)²)`
+#[inline]
+pub fn euclidean_f32(a: &[f32], b: &[f32]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            return unsafe { x86::squared_euclidean_f32(a, b) }.sqrt();
+        }
+    }
+    portable::squared_euclidean_f32(a, b).sqrt()
+}
+
+/// `sqrt(Σ(a-b)²)`
+#[inline]
+pub fn euclidean_f64(a: &[f64], b: &[f64]) -> f64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            return unsafe { x86::squared_euclidean_f64(a, b) }.sqrt();
+        }
+    }
+    portable::squared_euclidean_f64(a, b).sqrt()
+}
+
+/// `Σ|a-b|`
+#[inline]
+pub fn manhattan_f32(a: &[f32], b: &[f32]) -> f32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { x86::manhattan_f32(a, b) };
+        }
+    }
+    portable::manhattan_f32(a, b)
+}
+
+/// `Σ|a-b|`
+#[inline]
+pub fn manhattan_f64(a: &[f64], b: &[f64]) -> f64 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx2") {
+            return unsafe { x86::manhattan_f64(a, b) };
+        }
+    }
+    portable::manhattan_f64(a, b)
+}
+
+/// Number of differing bits
+#[inline]
+pub fn hamming(a: &[u8], b: &[u8]) -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("popcnt") {
+            return unsafe { x86::hamming(a, b) };
+        }
+    }
+    portable::hamming(a, b)
+}
+
+pub(crate) mod portable {
+    // Independent accumulators let the compiler use vector registers without reordering float additions itself
+    macro_rules! lanes_sum {
+        ($name:ident, $t:ty, $op:expr) => {
+            #[inline(always)]
+            pub fn $name(a: &[$t], b: &[$t]) -> $t {
+                let n = a.len().min(b.len());
+                let (a, b) = (&a[..n], &b[..n]);
+                let mut acc = [0 as $t; 8];
+                let a_chunks = a.chunks_exact(8);
+                let b_chunks = b.chunks_exact(8);
+                let tail = a_chunks.remainder().iter().zip(b_chunks.remainder()).map(|(&a, &b)| $op(a - b)).sum::<$t>();
+                for (a, b) in a_chunks.zip(b_chunks) {
+                    for i in 0..8 {
+                        acc[i] += $op(a[i] - b[i]);
+                    }
+                }
+                acc.iter().sum::<$t>() + tail
+            }
+        };
+    }
+
+    lanes_sum!(squared_euclidean_f32, f32, |d: f32| d * d);
+    lanes_sum!(squared_euclidean_f64, f64, |d: f64| d * d);
+    lanes_sum!(manhattan_f32, f32, f32::abs);
+    lanes_sum!(manhattan_f64, f64, f64::abs);
+
+    pub(crate) use crate::metrics::hamming_bytes as hamming;
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn sum_ps(v: __m256) -> f32 {
+        let v = _mm_add_ps(_mm256_castps256_ps128(v), _mm256_extractf128_ps(v, 1));
+        let v = _mm_add_ps(v, _mm_movehl_ps(v, v));
+        let v = _mm_add_ss(v, _mm_shuffle_ps(v, v, 1));
+        _mm_cvtss_f32(v)
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn sum_pd(v: __m256d) -> f64 {
+        let v = _mm_add_pd(_mm256_castpd256_pd128(v), _mm256_extractf128_pd(v, 1));
+        let v = _mm_add_sd(v, _mm_unpackhi_pd(v, v));
+        _mm_cvtsd_f64(v)
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn squared_euclidean_f32(a: &[f32], b: &[f32]) -> f32 {
+        let n = a.len().min(b.len());
+        let (a, b) = (a.as_ptr(), b.as_ptr());
+        let mut acc0 = _mm256_setzero_ps();
+        let mut acc1 = _mm256_setzero_ps();
+        let mut i = 0;
+        while i + 16 <= n {
+            let d0 = _mm256_sub_ps(_mm256_loadu_ps(a.add(i)), _mm256_loadu_ps(b.add(i)));
+            let d1 = _mm256_sub_ps(_mm256_loadu_ps(a.add(i + 8)), _mm256_loadu_ps(b.add(i + 8)));
+            acc0 = _mm256_fmadd_ps(d0, d0, acc0);
+            acc1 = _mm256_fmadd_ps(d1, d1, acc1);
+            i += 16;
+        }
+        if i + 8 <= n {
+            let d = _mm256_sub_ps(_mm256_loadu_ps(a.add(i)), _mm256_loadu_ps(b.add(i)));
+            acc0 = _mm256_fmadd_ps(d, d, acc0);
+            i += 8;
+        }
+        let mut sum = sum_ps(_mm256_add_ps(acc0, acc1));
+        while i < n {
+            let d = *a.add(i) - *b.add(i);
+            sum += d * d;
+            i += 1;
+        }
+        sum
+    }
+
+    #[target_feature(enable = "avx2,fma")]
+    pub unsafe fn squared_euclidean_f64(a: &[f64], b: &[f64]) -> f64 {
+        let n = a.len().min(b.len());
+        let (a, b) = (a.as_ptr(), b.as_ptr());
+        let mut acc0 = _mm256_setzero_pd();
+        let mut acc1 = _mm256_setzero_pd();
+        let mut i = 0;
+        while i + 8 <= n {
+            let d0 = _mm256_sub_pd(_mm256_loadu_pd(a.add(i)), _mm256_loadu_pd(b.add(i)));
+            let d1 = _mm256_sub_pd(_mm256_loadu_pd(a.add(i + 4)), _mm256_loadu_pd(b.add(i + 4)));
+            acc0 = _mm256_fmadd_pd(d0, d0, acc0);
+            acc1 = _mm256_fmadd_pd(d1, d1, acc1);
+            i += 8;
+        }
+        if i + 4 <= n {
+            let d = _mm256_sub_pd(_mm256_loadu_pd(a.add(i)), _mm256_loadu_pd(b.add(i)));
+            acc0 = _mm256_fmadd_pd(d, d, acc0);
+            i += 4;
+        }
+        let mut sum = sum_pd(_mm256_add_pd(acc0, acc1));
+        while i < n {
+            let d = *a.add(i) - *b.add(i);
+            sum += d * d;
+            i += 1;
+        }
+        sum
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn manhattan_f32(a: &[f32], b: &[f32]) -> f32 {
+        let n = a.len().min(b.len());
+        let (a, b) = (a.as_ptr(), b.as_ptr());
+        let sign = _mm256_set1_ps(-0.);
+        let mut acc = _mm256_setzero_ps();
+        let mut i = 0;
+        while i + 8 <= n {
+            let d = _mm256_sub_ps(_mm256_loadu_ps(a.add(i)), _mm256_loadu_ps(b.add(i)));
+            acc = _mm256_add_ps(acc, _mm256_andnot_ps(sign, d));
+            i += 8;
+        }
+        let mut sum = sum_ps(acc);
+        while i < n {
+            sum += (*a.add(i) - *b.add(i)).abs();
+            i += 1;
+        }
+        sum
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub unsafe fn manhattan_f64(a: &[f64], b: &[f64]) -> f64 {
+        let n = a.len().min(b.len());
+        let (a, b) = (a.as_ptr(), b.as_ptr());
+        let sign = _mm256_set1_pd(-0.);
+        let mut acc = _mm256_setzero_pd();
+        let mut i = 0;
+        while i + 4 <= n {
+            let d = _mm256_sub_pd(_mm256_loadu_pd(a.add(i)), _mm256_loadu_pd(b.add(i)));
+            acc = _mm256_add_pd(acc, _mm256_andnot_pd(sign, d));
+            i += 4;
+        }
+        let mut sum = sum_pd(acc);
+        while i < n {
+            sum += (*a.add(i) - *b.add(i)).abs();
+            i += 1;
+        }
+        sum
+    }
+
+    /// Same as the portable version, but `count_ones` compiles to a single instruction
+    #[target_feature(enable = "popcnt")]
+    pub unsafe fn hamming(a: &[u8], b: &[u8]) -> u32 {
+        super::portable::hamming(a, b)
+    }
+}
@@ -0,0 +1,88 @@
+//! Minimum spanning tree of the items, using Borůvka's algorithm.
+//!
+//! In every round each group of connected items looks for the shortest edge to an item outside of the group,
+//! which is a nearest neighbor search that skips items of the same group. Every round at least halves the number of groups.
+
+use crate::{Filter, HoldsUserData, MetricSpace, ReturnByIndex, Tree};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use num_traits::Bounded;
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Finds edges that connect all items with the smallest total distance.
+    ///
+    /// Returns `len() - 1` edges as `(index, index, distance)`, sorted by distance, shortest first.
+    /// Removing the longest edges splits the items into clusters (it's single-linkage clustering, the basis of HDBSCAN).
+    pub fn minimum_spanning_tree(&self) -> Vec<(usize, usize, Item::Distance)> where Item::Distance: Bounded {
+        let user_data = self.user_data.user_data();
+        let len = self.len();
+        let mut groups = DisjointSets::new(len);
+        let mut group_of = vec![0; len];
+        let mut edges = Vec::with_capacity(len.saturating_sub(1));
+
+        while edges.len() + 1 < len {
+            for (idx, group) in group_of.iter_mut().enumerate() {
+                *group = groups.find(idx);
+            }
+
+            // Indexed by the group
+            let mut shortest: Vec<Option<(usize, usize, Item::Distance)>> = vec![None; len];
+            for (idx, item) in self.items() {
+                let group = group_of[idx];
+                // Only edges shorter than what the group already has are interesting, which prunes the search
+                let max_distance = shortest[group].map_or(<Item::Distance as Bounded>::max_value(), |edge| edge.2);
+                let outside = Filter::new(ReturnByIndex::new(max_distance), |other| group_of[other] != group);
+                if let Some((other, distance)) = self.find_nearest_custom(item, user_data, outside) {
+                    if shortest[group].map_or(true, |edge| distance < edge.2) {
+                        shortest[group] = Some((idx, other, distance));
+                    }
+                }
+            }
+
+            // When edges are equally long, groups may pick edges that would form a cycle
+            for edge in shortest.into_iter().flatten() {
+                if groups.union(edge.0, edge.1) {
+                    edges.push(edge);
+                }
+            }
+        }
+
+        edges.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal));
+        edges
+    }
+}
+
+/// Union-find with path halving and union by size
+struct DisjointSets {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        DisjointSets { parent: (0..len).collect(), size: vec![1; len] }
+    }
+
+    fn find(&mut self, mut idx: usize) -> usize {
+        while self.parent[idx] != idx {
+            self.parent[idx] = self.parent[self.parent[idx]];
+            idx = self.parent[idx];
+        }
+        idx
+    }
+
+    /// Returns `false` if they were already in the same set
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let (mut a, mut b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        if self.size[a] < self.size[b] {
+            core::mem::swap(&mut a, &mut b);
+        }
+        self.parent[b] = a;
+        self.size[a] += self.size[b];
+        true
+    }
+}
@@ -0,0 +1,43 @@
+//! `MetricSpace` for `nalgebra` points and vectors (`nalgebra` feature).
+//!
+//! `Point<f32, D>` (`Point2`, `Point3`, …) and `SVector<f32, D>` (`Vector2`, `Vector3`, …), and their `f64` versions,
+//! can be put in the tree directly. The distance is Euclidean.
+//!
+//! ```rust
+//! use nalgebra::Point3;
+//!
+//! let points = vec![Point3::new(2.0f32, 3.0, 0.0), Point3::new(0.0, 1.0, 0.0), Point3::new(4.0, 5.0, 1.0)];
+//! let tree = vpsearch::Tree::new(&points);
+//! let (index, _) = tree.find_nearest(&Point3::new(0.5, 1.5, 0.0));
+//! assert_eq!(index, 1);
+//! ```
+
+use crate::MetricSpace;
+use nalgebra::{Point, SVector};
+
+macro_rules! impl_euclidean {
+    ($t:ty) => {
+        impl<const D: usize> MetricSpace for Point<$t, D> {
+            type UserData = ();
+            type Distance = $t;
+
+            #[inline]
+            fn distance(&self, other: &Self, _: &()) -> $t {
+                nalgebra::distance(self, other)
+            }
+        }
+
+        impl<const D: usize> MetricSpace for SVector<$t, D> {
+            type UserData = ();
+            type Distance = $t;
+
+            #[inline]
+            fn distance(&self, other: &Self, _: &()) -> $t {
+                (self - other).norm()
+            }
+        }
+    };
+}
+
+impl_euclidean!(f32);
+impl_euclidean!(f64);
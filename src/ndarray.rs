@@ -0,0 +1,104 @@
+//! Integration with the `ndarray` crate (`ndarray` feature).
+//!
+//! Rows of an `Array2<f32>` are indexed as points, and the distance between them is computed by a `RowMetric`.
+//!
+//! ```rust
+//! use ndarray::array;
+//! use vpsearch::ndarray::Euclidean;
+//!
+//! let points = array![[2.0, 3.0], [0.0, 1.0], [4.0, 5.0]];
+//! let tree = vpsearch::Tree::from_array2(&points, Euclidean);
+//! let (indices, distances) = tree.find_nearest_rows(&array![[0.5, 1.5], [5.0, 5.0]]);
+//! assert_eq!(indices.to_vec(), vec![1, 2]);
+//! ```
+
+use crate::{KNearest, MetricSpace, Owned, Tree};
+use ndarray::{Array1, Array2, ArrayBase, ArrayView1, Data, Ix2};
+
+/// Distance between two rows. It must be a metric (e.g. use `sqrt()` for the Euclidean distance).
+///
+/// It's implemented for closures `Fn(ArrayView1<f32>, ArrayView1<f32>) -> f32`.
+pub trait RowMetric {
+    fn distance(&self, a: ArrayView1<'_, f32>, b: ArrayView1<'_, f32>) -> f32;
+}
+
+impl<F: Fn(ArrayView1<'_, f32>, ArrayView1<'_, f32>) -> f32> RowMetric for F {
+    #[inline]
+    fn distance(&self, a: ArrayView1<'_, f32>, b: ArrayView1<'_, f32>) -> f32 {
+        self(a, b)
+    }
+}
+
+pub use crate::metrics::Euclidean;
+
+impl RowMetric for Euclidean {
+    #[inline]
+    fn distance(&self, a: ArrayView1<'_, f32>, b: ArrayView1<'_, f32>) -> f32 {
+        a.iter().zip(b.iter())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f32>()
+            .sqrt()
+    }
+}
+
+/// A row copied out of an `Array2`. The tree stores these.
+#[derive(Debug, Clone)]
+pub struct ArrayRow(pub Array1<f32>);
+
+impl<M: RowMetric> MetricSpace<M> for ArrayRow {
+    type UserData = M;
+    type Distance = f32;
+
+    #[inline]
+    fn distance(&self, other: &Self, metric: &M) -> f32 {
+        metric.distance(self.0.view(), other.0.view())
+    }
+}
+
+impl<M: RowMetric> Tree<ArrayRow, M, Owned<M>> {
+    /// Creates a tree where every row of the 2D array is a point. Indices returned from searches are row numbers.
+    pub fn from_array2<S: Data<Elem = f32>>(data: &ArrayBase<S, Ix2>, metric: M) -> Self {
+        let rows: Vec<_> = data.outer_iter().map(|row| ArrayRow(row.to_owned())).collect();
+        Self::new_with_user_data_owned(&rows, metric)
+    }
+
+    /// Finds the nearest point for every row of `needles`.
+    ///
+    /// Returns row indices of the found points, and their distances. If the tree is empty, distances are `f32::MAX`.
+    ///
+    /// Panics if the needles have a different number of columns than the indexed array.
+    pub fn find_nearest_rows<S: Data<Elem = f32>>(&self, needles: &ArrayBase<S, Ix2>) -> (Array1<usize>, Array1<f32>) {
+        self.check_columns(needles);
+        let (indices, distances): (Vec<_>, Vec<_>) = needles.outer_iter()
+            .map(|needle| self.find_nearest(&ArrayRow(needle.to_owned())))
+            .unzip();
+        (Array1::from(indices), Array1::from(distances))
+    }
+
+    /// Finds up to `k` nearest points for every row of `needles`.
+    ///
+    /// Returns 2D arrays of row indices and distances, with one row per needle, closest points first.
+    /// If the tree has fewer than `k` points, the results have fewer columns.
+    ///
+    /// Panics if the needles have a different number of columns than the indexed array.
+    pub fn find_k_nearest_rows<S: Data<Elem = f32>>(&self, needles: &ArrayBase<S, Ix2>, k: usize) -> (Array2<usize>, Array2<f32>) {
+        self.check_columns(needles);
+        let k = k.min(self.len());
+        let mut indices = Array2::zeros((needles.nrows(), k));
+        let mut distances = Array2::zeros((needles.nrows(), k));
+        for (i, needle) in needles.outer_iter().enumerate() {
+            let found = self.find_nearest_custom(&ArrayRow(needle.to_owned()), self.user_data(), KNearest::new(k));
+            for (j, (idx, dist)) in found.into_iter().enumerate() {
+                indices[(i, j)] = idx;
+                distances[(i, j)] = dist;
+            }
+        }
+        (indices, distances)
+    }
+
+    fn check_columns<S: Data<Elem = f32>>(&self, needles: &ArrayBase<S, Ix2>) {
+        if let Some(node) = self.nodes.first() {
+            assert_eq!(node.vantage_point.0.len(), needles.ncols(), "needles must have the same number of columns as the indexed array");
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Walking the structure of the tree, e.g. to visualize it or export it to another tool.
+
+use crate::{MetricSpace, Tree, NO_NODE};
+use alloc::vec;
+use core::iter;
+
+/// An item in the tree, and its place in the structure. Returned by `Tree::nodes()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NodeInfo<Distance> {
+    /// Index of the item in the slice the tree has been created from
+    pub index: usize,
+    /// Items within this distance from this one are in the near subtree, others in the far subtree. `None` if the item has no children.
+    pub radius: Option<Distance>,
+    /// `0` for the root
+    pub depth: usize,
+    /// Index of the item that is the parent of this one. `None` for the root.
+    pub parent: Option<usize>,
+    /// `true` if the item is in the far subtree of its parent.
+    ///
+    /// Leaves store up to 8 items in a bucket. They have the leaf as the parent, no radius, and `far` is `false`.
+    pub far: bool,
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// All items, with their position in the tree.
+    ///
+    /// The order is depth-first, starting from the root: each node comes before its near subtree, which comes before its far subtree.
+    /// Items in a leaf's bucket come right after the leaf. The order is the same every time, but may change in future versions of this crate.
+    pub fn nodes(&self) -> impl Iterator<Item = NodeInfo<Item::Distance>> + '_ {
+        let mut stack = vec![(self.root, 0, None, false)];
+        let mut bucket = [].iter();
+        let mut leaf = None;
+        iter::from_fn(move || {
+            if let Some(&idx) = bucket.next() {
+                let (index, depth) = leaf?;
+                return Some(NodeInfo { index: idx as usize, radius: None, depth: depth + 1, parent: Some(index), far: false });
+            }
+            loop {
+                let (next, depth, parent, far) = stack.pop()?;
+                let node = match self.nodes.get(next as usize) {
+                    Some(node) => node,
+                    None => continue,
+                };
+                let index = node.idx as usize;
+                let radius = match node.bucket() {
+                    Some(range) => {
+                        bucket = self.buckets.idx[range].iter();
+                        leaf = Some((index, depth));
+                        None
+                    },
+                    None if node.near == NO_NODE && node.far == NO_NODE => None,
+                    None => {
+                        stack.push((node.far, depth + 1, Some(index), true));
+                        stack.push((node.near, depth + 1, Some(index), false));
+                        Some(node.radius)
+                    },
+                };
+                return Some(NodeInfo { index, radius, depth, parent, far });
+            }
+        })
+    }
+}
@@ -0,0 +1,121 @@
+//! Splitting search results into pages, e.g. for a web API that lists neighbors a page at a time.
+//!
+//! Results are ordered by distance, and then by index, so that every item has a unique place in the order.
+//! The cursor is the place of the last item of a page, and the next page is the nearest items after it.
+//! Each page is a new search, so nothing is kept between requests, and the pages are the same every time.
+
+use crate::{BestCandidate, DistanceAdd, HoldsUserData, MetricSpace, Tree};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::cmp::Ordering;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Where a page of results ends. Returned in `Page::next`, and passed to `Tree::search_page` to get the next page.
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PageCursor<Distance> {
+    /// Distance of the last item of the page
+    pub distance: Distance,
+    /// Index of the last item of the page
+    pub index: usize,
+}
+
+/// Results of `Tree::search_page`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<Distance> {
+    /// `(index, distance)` of the items, closest first
+    pub items: Vec<(usize, Distance)>,
+    /// Cursor for the next page, or `None` if this is the last page
+    pub next: Option<PageCursor<Distance>>,
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Returns up to `page_size` items at most `radius` away from the needle, which come after the `cursor`,
+    /// or the nearest items if the `cursor` is `None`. For pages of `find_k_nearest` results, use the largest distance as the radius.
+    ///
+    /// The tree has to be the same for all pages, but it can be a copy, e.g. loaded in another process.
+    /// Later pages take longer, because the search has to skip over the items of the earlier pages.
+    ///
+    /// Panics if `page_size` is `0`.
+    ///
+    /// ```rust
+    /// # #[derive(Clone)] struct Point(f32);
+    /// # impl vpsearch::MetricSpace for Point {
+    /// #     type UserData = (); type Distance = f32;
+    /// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+    /// # }
+    /// let points: Vec<_> = (0..1000).map(|i| Point(i as f32)).collect();
+    /// let tree = vpsearch::Tree::new(&points);
+    /// let first = tree.search_page(&Point(500.), 100., None, 50);
+    /// // The cursor would be sent to the client, and come back with the request for the next page
+    /// let second = tree.search_page(&Point(500.), 100., first.next.as_ref(), 50);
+    /// assert_eq!(second.items.len(), 50);
+    /// ```
+    pub fn search_page<Q>(&self, needle: &Q, radius: Item::Distance, cursor: Option<&PageCursor<Item::Distance>>, page_size: usize) -> Page<Item::Distance>
+    where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        assert!(page_size > 0, "pages must have room for at least one item");
+        let cursor = cursor.copied();
+        // One more item than fits on the page tells whether there's a next page
+        let collector = PageCollector { after: cursor, radius, k: page_size + 1, found: Vec::with_capacity(page_size.min(1024) + 1) };
+        // The near subtree is skipped if even its farthest item would be before the cursor
+        let rule = |needle_distance: Item::Distance, node_radius: Item::Distance, _, far| {
+            far || cursor.map_or(true, |cursor| needle_distance.saturating_add(node_radius) >= cursor.distance)
+        };
+        let mut items = self.find_nearest_custom_pruned(needle, self.user_data.user_data(), collector, rule);
+        let next = if items.len() > page_size {
+            items.truncate(page_size);
+            items.last().map(|&(index, distance)| PageCursor { distance, index })
+        } else {
+            None
+        };
+        Page { items, next }
+    }
+}
+
+/// Like `KNearest`, but only for items after the cursor, and ties are broken by the index, so that pages don't overlap
+struct PageCollector<D> {
+    after: Option<PageCursor<D>>,
+    radius: D,
+    k: usize,
+    found: Vec<(usize, D)>,
+}
+
+/// `true` if `(a_distance, a_index)` comes before `(b_distance, b_index)`
+#[inline]
+fn is_before<D: PartialOrd>(a: (usize, D), b: (usize, D)) -> bool {
+    a.1 < b.1 || (a.1 == b.1 && a.0 < b.0)
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<'tree, Item, Impl> for PageCollector<Item::Distance> {
+    type Output = Vec<(usize, Item::Distance)>;
+
+    fn consider(&mut self, _: &Item, distance: Item::Distance, candidate_index: usize, _: &Item::UserData) {
+        let candidate = (candidate_index, distance);
+        // NaN isn't within any distance
+        if !matches!(distance.partial_cmp(&self.radius), Some(Ordering::Less | Ordering::Equal)) || self.after.is_some_and(|after| !is_before((after.index, after.distance), candidate)) {
+            return;
+        }
+        if self.found.len() >= self.k {
+            match self.found.last() {
+                Some(&worst) if is_before(candidate, worst) => {},
+                _ => return,
+            }
+            self.found.pop();
+        }
+        let pos = self.found.iter().position(|&found| is_before(candidate, found)).unwrap_or(self.found.len());
+        self.found.insert(pos, candidate);
+    }
+
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        if self.found.len() < self.k {
+            return self.radius;
+        }
+        self.found.last().map_or(self.radius, |&(_, distance)| distance)
+    }
+
+    fn result(self, _: &Item::UserData) -> Self::Output {
+        self.found
+    }
+}
@@ -0,0 +1,28 @@
+//! Searches with metrics that may panic, for services that have to report an error instead of crashing.
+
+use crate::{Error, MetricSpace, Tree};
+use std::panic::{self, AssertUnwindSafe};
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// Runs the `search`, and returns `Error::MetricPanicked` if it panics, e.g. because the metric panics on some needles.
+    ///
+    /// Searches don't modify the tree, so the tree can still be used after a panic. Anything else the `search`
+    /// modifies may be left half-done, e.g. results collected into a `Vec` with `find_k_nearest_into`.
+    /// The panic is still reported by the panic hook, which prints it by default.
+    ///
+    /// ```rust
+    /// # #[derive(Clone)] struct Point(f32);
+    /// # impl vpsearch::MetricSpace for Point {
+    /// #     type UserData = (); type Distance = f32;
+    /// #     fn distance(&self, other: &Self, _: &()) -> f32 {
+    /// #         assert!(self.0.is_finite() && other.0.is_finite()); (self.0 - other.0).abs()
+    /// #     }
+    /// # }
+    /// let tree = vpsearch::Tree::new(&[Point(1.), Point(2.)]);
+    /// assert!(tree.catch(|tree| tree.find_nearest(&Point(f32::NAN))).is_err());
+    /// assert_eq!(tree.catch(|tree| tree.find_nearest(&Point(1.5))), Ok((0, 0.5)));
+    /// ```
+    pub fn catch<R>(&self, search: impl FnOnce(&Self) -> R) -> Result<R, Error> {
+        panic::catch_unwind(AssertUnwindSafe(|| search(self))).map_err(|_| Error::MetricPanicked)
+    }
+}
@@ -0,0 +1,288 @@
+//! Searching a single needle with multiple threads.
+//!
+//! The top of the tree is searched first, and then its subtrees are searched by a pool of scoped threads.
+//! The threads share the distance of the `k`th nearest item found so far, so that they can skip subtrees
+//! that another thread has already ruled out.
+
+use crate::{BestCandidate, Filter, HoldsUserData, KNearest, MetricSpace, Needle, Tree, WithinRadius, NO_NODE};
+use num_traits::Bounded;
+use std::borrow::Borrow;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+/// Each thread gets a few subtrees on average, so that threads that finish early can take over the remaining ones
+const SUBTREES_PER_THREAD: usize = 4;
+
+/// Number of items searched by a thread at a time in the `self_*` searches of all items
+const ITEMS_PER_CHUNK: usize = 1024;
+
+/// `(index, distance)` of the nearest items, for every item
+type NeighborLists<D> = Vec<Vec<(usize, D)>>;
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Like `find_k_nearest`, but the search is split across all available CPU cores.
+    ///
+    /// This lowers latency of a single search in huge trees or with expensive `distance()`. Otherwise the overhead of starting threads
+    /// is larger than the work, and searching different needles on different threads is more efficient.
+    ///
+    /// When several items are at the same distance, it may pick different ones than `find_k_nearest`.
+    pub fn find_k_nearest_parallel<Q>(&self, needle: &Q, k: usize) -> Vec<(usize, Item::Distance)>
+    where
+        Self: Sync,
+        Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + Sync + ?Sized,
+        Item: Borrow<Q>,
+        Item::UserData: Sync,
+        Item::Distance: Bounded + Send,
+    {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        let user_data = self.user_data.user_data();
+
+        // The top of the tree is searched without pruning, because it's where the subtrees for the threads come from
+        let mut top = KNearest::new(k);
+        let mut subtrees = vec![self.root];
+        while subtrees.len() < threads * SUBTREES_PER_THREAD {
+            let mut next_level = Vec::with_capacity(subtrees.len() * 2);
+            let mut split = false;
+            for &idx in &subtrees {
+                let node = match self.nodes.get(idx as usize) {
+                    Some(node) => node,
+                    None => continue,
+                };
+                if node.bucket().is_some() || node.far == NO_NODE {
+                    // Leaves are searched by the threads as they are
+                    next_level.push(idx);
+                    continue;
+                }
+                top.consider(&node.vantage_point, needle.distance(node.vantage_point.borrow(), user_data), node.idx as usize, user_data);
+                next_level.extend([node.near, node.far].iter().copied().filter(|&child| child != NO_NODE));
+                split = true;
+            }
+            subtrees = next_level;
+            if !split {
+                break;
+            }
+        }
+
+        let bound = Mutex::new(top.distance());
+        let next_subtree = AtomicUsize::new(0);
+        let mut found = top.result(user_data);
+        thread::scope(|s| {
+            let workers: Vec<_> = (0..threads.min(subtrees.len())).map(|_| s.spawn(|| {
+                let mut best_candidate = SharedBound::new(k, &bound);
+                while let Some(&root) = subtrees.get(next_subtree.fetch_add(1, Ordering::Relaxed)) {
+                    best_candidate.refresh();
+                    self.search(root, &mut Needle { needle, user_data }, &mut best_candidate, user_data);
+                }
+                best_candidate.nearest.result(user_data)
+            })).collect();
+            for worker in workers {
+                found.extend(worker.join().unwrap());
+            }
+        });
+
+        found.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
+        found.truncate(k);
+        found
+    }
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership>
+where
+    Self: Sync,
+    Item: Sync,
+    Item::UserData: Sync,
+{
+    /// Finds up to `k` nearest other items for every item in the tree, using all available CPU cores.
+    ///
+    /// Returns a list of `(index, distance)` for each item, indexed like the items the tree has been created from.
+    /// An item doesn't count as its own neighbor, but its duplicates do.
+    ///
+    /// `on_progress` is called every now and then with the number of items done so far and the total.
+    /// It can return `false` to cancel the work, and then this returns `None`. It's called on the calling thread.
+    pub fn self_knn<F>(&self, k: usize, mut on_progress: F) -> Option<NeighborLists<Item::Distance>>
+    where
+        Item::Distance: Bounded + Send,
+        F: FnMut(usize, usize) -> bool,
+    {
+        let total = self.len();
+        let mut neighbors = vec![Vec::new(); total];
+        let mut done = 0;
+        let finished = self.for_each_item_parallel(|idx, item, user_data| self.k_nearest_others(idx, item, k, user_data), |chunk| {
+            done += chunk.len();
+            for (idx, found) in chunk {
+                neighbors[idx] = found;
+            }
+            on_progress(done, total)
+        });
+        if finished { Some(neighbors) } else { None }
+    }
+
+    /// Like `self_knn`, but instead of collecting all the results, gives them to `on_result` as soon as they're found.
+    ///
+    /// `on_result` gets the index of an item and its nearest other items. The items come in no particular order.
+    /// It's called on the calling thread, and it can return `false` to stop the work. Returns `false` if it has been stopped.
+    ///
+    /// Searches wait while `on_result` is busy, so results never pile up in memory.
+    pub fn self_knn_each<F>(&self, k: usize, mut on_result: F) -> bool
+    where
+        Item::Distance: Bounded + Send,
+        F: FnMut(usize, Vec<(usize, Item::Distance)>) -> bool,
+    {
+        self.for_each_item_parallel(|idx, item, user_data| self.k_nearest_others(idx, item, k, user_data), |chunk| {
+            chunk.into_iter().all(|(idx, found)| on_result(idx, found))
+        })
+    }
+
+    /// Finds all pairs of items at most `radius` apart, using all available CPU cores, and gives them to `on_pair` as soon as they're found.
+    ///
+    /// `on_pair` gets `(index, index, distance)`. Each pair is given once, with the smaller index first, in no particular order.
+    /// It's called on the calling thread, and it can return `false` to stop the work. Returns `false` if it has been stopped.
+    ///
+    /// The pairs aren't collected anywhere, so there can be more of them than would fit in memory.
+    pub fn self_join_each<F>(&self, radius: Item::Distance, mut on_pair: F) -> bool
+    where
+        Item::Distance: Send + Sync,
+        F: FnMut(usize, usize, Item::Distance) -> bool,
+    {
+        self.for_each_item_parallel(|idx, item, user_data| {
+            let later = Filter::new(WithinRadius::new(radius), |other| other > idx);
+            self.find_nearest_custom(item, user_data, later)
+        }, |chunk| {
+            chunk.into_iter().all(|(idx, found)| found.into_iter().all(|(other, distance)| on_pair(idx, other, distance)))
+        })
+    }
+
+    /// Finds the nearest item for each of the `needles`, using all available CPU cores. Returns the indexes of the nearest items, in the order of the needles.
+    ///
+    /// This is for trees of centroids, e.g. to map pixels to the nearest colors of a palette, or vectors to codes of a quantization codebook.
+    ///
+    /// Panics if the tree is empty.
+    pub fn assign<Q>(&self, needles: &[Q]) -> Vec<usize>
+    where
+        Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + Sync,
+        Item: Borrow<Q>,
+        Item::Distance: Bounded,
+    {
+        assert!(!self.is_empty(), "there must be at least one item to assign to");
+        let user_data = self.user_data.user_data();
+        let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(needles.len().div_ceil(ITEMS_PER_CHUNK)).max(1);
+        let chunk_len = needles.len().div_ceil(threads).max(1);
+
+        let mut nearest = vec![0; needles.len()];
+        thread::scope(|s| {
+            for (needles, nearest) in needles.chunks(chunk_len).zip(nearest.chunks_mut(chunk_len)) {
+                s.spawn(move || {
+                    for (needle, nearest) in needles.iter().zip(nearest) {
+                        *nearest = self.find_nearest_with_user_data(needle, user_data).0;
+                    }
+                });
+            }
+        });
+        nearest
+    }
+
+    fn k_nearest_others(&self, idx: usize, item: &Item, k: usize, user_data: &Item::UserData) -> Vec<(usize, Item::Distance)> where Item::Distance: Bounded {
+        self.find_nearest_custom(item, user_data, Filter::new(KNearest::new(k), |other| other != idx))
+    }
+
+    /// Runs `search` for every item on a pool of threads, and gives the results in chunks to `on_chunk` on the calling thread.
+    ///
+    /// Returns `false` if `on_chunk` has returned `false` to stop the work.
+    fn for_each_item_parallel<T: Send>(&self, search: impl Fn(usize, &Item, &Item::UserData) -> T + Sync, mut on_chunk: impl FnMut(Vec<(usize, T)>) -> bool) -> bool {
+        let user_data = self.user_data.user_data();
+        // In the order of the tree, so that each thread searches for items that are near each other
+        let items: Vec<_> = self.items().collect();
+        let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(items.len().div_ceil(ITEMS_PER_CHUNK)).max(1);
+
+        let next_chunk = AtomicUsize::new(0);
+        let stopped = AtomicBool::new(false);
+        // Bounded, so that the threads wait for `on_chunk` instead of filling the memory with results
+        let (sender, receiver) = mpsc::sync_channel(threads);
+        thread::scope(|s| {
+            for _ in 0..threads {
+                let sender = sender.clone();
+                let (items, next_chunk, stopped, search) = (&items, &next_chunk, &stopped, &search);
+                s.spawn(move || {
+                    while !stopped.load(Ordering::Relaxed) {
+                        let start = next_chunk.fetch_add(1, Ordering::Relaxed) * ITEMS_PER_CHUNK;
+                        if start >= items.len() {
+                            break;
+                        }
+                        let chunk = items[start..(start + ITEMS_PER_CHUNK).min(items.len())].iter()
+                            .map(|&(idx, item)| (idx, search(idx, item, user_data)))
+                            .collect();
+                        // Fails only when the receiver has stopped
+                        if sender.send(chunk).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            // Otherwise the receiver would wait for it forever
+            drop(sender);
+
+            for chunk in receiver.iter() {
+                if !on_chunk(chunk) {
+                    stopped.store(true, Ordering::Relaxed);
+                    // Unblocks threads waiting to send
+                    drop(receiver);
+                    break;
+                }
+            }
+        });
+        !stopped.into_inner()
+    }
+}
+
+/// `KNearest` of one thread that also prunes the search using the best distance found by all threads
+struct SharedBound<'a, Item: MetricSpace<Impl>, Impl> {
+    nearest: KNearest<Item, Impl>,
+    bound: &'a Mutex<Item::Distance>,
+    /// Copy of the shared `bound`, so that the search doesn't lock it for every node. Refreshed for every subtree.
+    local_bound: Item::Distance,
+}
+
+impl<'a, Item: MetricSpace<Impl>, Impl> SharedBound<'a, Item, Impl> where Item::Distance: Bounded {
+    fn new(k: usize, bound: &'a Mutex<Item::Distance>) -> Self {
+        let local_bound = *bound.lock().unwrap();
+        Self { nearest: KNearest::new(k), bound, local_bound }
+    }
+
+    /// Picks up the bounds found by other threads in the meantime
+    fn refresh(&mut self) {
+        let shared = *self.bound.lock().unwrap();
+        if shared < self.local_bound {
+            self.local_bound = shared;
+        }
+    }
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<'tree, Item, Impl> for SharedBound<'_, Item, Impl> {
+    type Output = Vec<(usize, Item::Distance)>;
+
+    #[inline]
+    fn consider(&mut self, item: &'tree Item, distance: Item::Distance, candidate_index: usize, user_data: &Item::UserData) {
+        self.nearest.consider(item, distance, candidate_index, user_data);
+        // Any thread that found k items within a distance proves that the final k nearest are within it too
+        let own = self.nearest.distance();
+        if own < self.local_bound {
+            // Locks only when this thread's own bound improves, which happens a few times per subtree
+            let mut bound = self.bound.lock().unwrap();
+            if own < *bound {
+                *bound = own;
+            }
+            self.local_bound = *bound;
+        }
+    }
+
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        let own = self.nearest.distance();
+        if self.local_bound < own { self.local_bound } else { own }
+    }
+
+    fn result(self, user_data: &Item::UserData) -> Self::Output {
+        self.nearest.result(user_data)
+    }
+}
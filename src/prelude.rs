@@ -0,0 +1,16 @@
+//! The traits and types most programs need, in one import.
+//!
+//! ```rust
+//! use vpsearch::prelude::*;
+//!
+//! let points = vec![[1f32, 2.], [3., 4.], [5., 6.]];
+//! let tree: Tree<[f32; 2], Euclidean> = Tree::new(&points);
+//! let nearest = tree.find_nearest_custom(&[3.5, 4.], &(), KNearest::new(2));
+//! assert_eq!(nearest[0].0, 1);
+//! ```
+
+pub use crate::{AsyncMetricSpace, BestCandidate, DistanceAdd, MetricSpace, MetricSpaceWithContext};
+pub use crate::{Filter, KNearest, KNearestArray, KNearestDistinct, KNearestItems, KNearestScored, WithinRadius};
+pub use crate::{Tree, VpMap, VpMapBuilder};
+#[cfg(any(feature = "std", feature = "libm"))]
+pub use crate::metrics::{Chebyshev, Euclidean, Hamming, Hash128, Hash64, Haversine, Levenshtein, Manhattan, Xor};
@@ -0,0 +1,154 @@
+//! Remembering results of recent searches, for workloads where the same needles are searched for over and over.
+//!
+//! Results are keyed by the needle (or a key chosen by the caller) and `k`. When the cache is full, the least recently used result is dropped.
+
+use crate::{HoldsUserData, MetricSpace, Owned, Tree};
+use core::borrow::Borrow;
+use core::hash::Hash;
+use num_traits::Bounded;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// A tree with a cache of `find_k_nearest` results. Created with `Tree::with_query_cache`.
+///
+/// The cache is behind a lock, so the tree can be searched from many threads.
+///
+/// ```rust
+/// # #[derive(Clone, PartialEq, Eq, Hash)] struct Point(i32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = u32;
+/// #     fn distance(&self, other: &Self, _: &()) -> u32 { (self.0 - other.0).unsigned_abs() }
+/// # }
+/// let tree = vpsearch::Tree::new(&[Point(1), Point(5), Point(9)]).with_query_cache(1000);
+/// tree.find_k_nearest(&Point(4), 2);
+/// tree.find_k_nearest(&Point(4), 2);
+/// assert_eq!(tree.stats().hits, 1);
+/// ```
+pub struct QueryCache<Item: MetricSpace<Impl> + Clone, Key = Item, Impl = (), Ownership = Owned<()>> {
+    tree: Tree<Item, Impl, Ownership>,
+    capacity: usize,
+    lru: Mutex<Lru<Key, Item::Distance>>,
+}
+
+/// Number of searches answered from the cache. Returned by `QueryCache::stats()`.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// Fraction of searches answered from the cache, from `0` to `1`
+    #[must_use]
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 { 0. } else { self.hits as f64 / total as f64 }
+    }
+}
+
+struct Lru<Key, D> {
+    results: HashMap<(Key, usize), Cached<D>>,
+    /// Keys of results by when they were last used, oldest first
+    by_use: BTreeMap<u64, (Key, usize)>,
+    clock: u64,
+    stats: CacheStats,
+}
+
+struct Cached<D> {
+    result: Vec<(usize, D)>,
+    last_used: u64,
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// Adds a cache of up to `capacity` recent search results.
+    ///
+    /// The needles are the keys of the cache, so they need to implement `Hash` and `Eq`.
+    /// Use `QueryCache::find_k_nearest_by_key` to choose other keys, e.g. ids of the needles.
+    pub fn with_query_cache<Key: Hash + Eq + Clone>(self, capacity: usize) -> QueryCache<Item, Key, Impl, Ownership> {
+        QueryCache {
+            tree: self,
+            capacity,
+            lru: Mutex::new(Lru { results: HashMap::new(), by_use: BTreeMap::new(), clock: 0, stats: CacheStats::default() }),
+        }
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Key: Hash + Eq + Clone, Impl, Ownership> QueryCache<Item, Key, Impl, Ownership> {
+    /// The tree for searches that aren't cached
+    #[inline]
+    pub fn tree(&self) -> &Tree<Item, Impl, Ownership> {
+        &self.tree
+    }
+
+    /// Numbers of cache hits and misses so far
+    pub fn stats(&self) -> CacheStats {
+        self.lru.lock().unwrap().stats
+    }
+
+    /// Number of cached results
+    pub fn len(&self) -> usize {
+        self.lru.lock().unwrap().results.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets all results and resets the statistics
+    pub fn clear(&self) {
+        let mut lru = self.lru.lock().unwrap();
+        lru.results.clear();
+        lru.by_use.clear();
+        lru.stats = CacheStats::default();
+    }
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Key: Hash + Eq + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> QueryCache<Item, Key, Impl, Ownership> {
+    /// Like `Tree::find_k_nearest`, but the result is cached under the `key`. Needles with the same key must be equal.
+    pub fn find_k_nearest_by_key<Q>(&self, key: Key, needle: &Q, k: usize) -> Vec<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        let key = (key, k);
+        {
+            let mut lru = self.lru.lock().unwrap();
+            lru.clock += 1;
+            let now = lru.clock;
+            if let Some(cached) = lru.results.get_mut(&key) {
+                let result = cached.result.clone();
+                let previous = core::mem::replace(&mut cached.last_used, now);
+                lru.by_use.remove(&previous);
+                lru.by_use.insert(now, key);
+                lru.stats.hits += 1;
+                return result;
+            }
+            lru.stats.misses += 1;
+        }
+
+        // Not holding the lock while searching, so that other threads can use the cache
+        let result = self.tree.find_k_nearest(needle, k);
+        if self.capacity == 0 {
+            return result;
+        }
+        let mut lru = self.lru.lock().unwrap();
+        lru.clock += 1;
+        let now = lru.clock;
+        if let Some(previous) = lru.results.insert(key.clone(), Cached { result: result.clone(), last_used: now }) {
+            // Another thread has searched for the same key in the meantime
+            lru.by_use.remove(&previous.last_used);
+        }
+        lru.by_use.insert(now, key);
+        while lru.results.len() > self.capacity {
+            let oldest = match lru.by_use.pop_first() {
+                Some((_, oldest)) => oldest,
+                None => break,
+            };
+            lru.results.remove(&oldest);
+        }
+        result
+    }
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone + Hash + Eq, Ownership: HoldsUserData<UserData = Item::UserData>> QueryCache<Item, Item, Impl, Ownership> {
+    /// Like `Tree::find_k_nearest`, but the result is cached, with the needle as the key
+    pub fn find_k_nearest(&self, needle: &Item, k: usize) -> Vec<(usize, Item::Distance)> where Item::Distance: Bounded {
+        self.find_k_nearest_by_key(needle.clone(), needle, k)
+    }
+}
@@ -0,0 +1,139 @@
+//! Recording searches, so that they can be replayed later against differently built trees, or with different search limits.
+//!
+//! Replays report how long the searches took, and their recall compared to the exact search of the same tree.
+
+use crate::{HoldsUserData, MetricSpace, Tree};
+use core::borrow::Borrow;
+use num_traits::Bounded;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// A list of searches to replay with `QueryLog::replay()`.
+///
+/// Logging is up to the caller: only the needles passed to `record()` are stored. They're stored as they are,
+/// so for sensitive data, record a stand-in that has similar distances, e.g. a needle with some noise added.
+/// With the `serde` feature the log can be saved to a file in any `serde` format.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+/// let points: Vec<_> = (0..1000).map(|i| Point(i as f32)).collect();
+/// let mut log = vpsearch::QueryLog::new();
+/// log.record(Point(12.5), 10, None);
+/// log.record(Point(800.), 3, Some(50));
+///
+/// let report = log.replay(&vpsearch::Tree::new_shuffled(&points, 1));
+/// assert_eq!(report.queries, 2);
+/// println!("{:?} per search, recall {}", report.mean_time(), report.recall);
+/// ```
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct QueryLog<Q> {
+    entries: Vec<LoggedQuery<Q>>,
+}
+
+/// One search in the `QueryLog`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LoggedQuery<Q> {
+    pub needle: Q,
+    /// Number of nearest items searched for
+    pub k: usize,
+    /// Limit of `Tree::find_k_nearest_budgeted`, or `None` for the exact `find_k_nearest`
+    pub max_distance_evaluations: Option<usize>,
+}
+
+/// Results of `QueryLog::replay()`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReplayReport {
+    /// Number of searches replayed
+    pub queries: usize,
+    /// Time of all searches. Exact searches that check recall aren't included.
+    pub total_time: Duration,
+    /// Time of the slowest search
+    pub max_time: Duration,
+    /// Mean fraction of the exact `k` nearest items that have been found, from `0` to `1`. It's `1` when all searches are exact.
+    pub recall: f64,
+}
+
+impl ReplayReport {
+    /// Average time of a search
+    #[must_use]
+    pub fn mean_time(&self) -> Duration {
+        self.total_time.checked_div(self.queries as u32).unwrap_or_default()
+    }
+}
+
+impl<Q> QueryLog<Q> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self { entries: Vec::new() }
+    }
+
+    /// Adds a search for the `k` nearest items, optionally with a limit of `find_k_nearest_budgeted`
+    pub fn record(&mut self, needle: Q, k: usize, max_distance_evaluations: Option<usize>) {
+        self.entries.push(LoggedQuery { needle, k, max_distance_evaluations });
+    }
+
+    /// The searches in the order they've been recorded
+    #[inline]
+    pub fn entries(&self) -> &[LoggedQuery<Q>] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Runs all the searches on the `tree`, with the limits they've been recorded with
+    pub fn replay<Item, Impl, Ownership>(&self, tree: &Tree<Item, Impl, Ownership>) -> ReplayReport
+    where Item: MetricSpace<Impl> + Clone + Borrow<Q>, Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance>, Item::Distance: Bounded, Ownership: HoldsUserData<UserData = Item::UserData> {
+        self.replay_inner(tree, |entry| entry.max_distance_evaluations)
+    }
+
+    /// Runs all the searches on the `tree`, all with the same limit of `find_k_nearest_budgeted`, e.g. to find the lowest limit with good enough recall
+    pub fn replay_with_budget<Item, Impl, Ownership>(&self, tree: &Tree<Item, Impl, Ownership>, max_distance_evaluations: usize) -> ReplayReport
+    where Item: MetricSpace<Impl> + Clone + Borrow<Q>, Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance>, Item::Distance: Bounded, Ownership: HoldsUserData<UserData = Item::UserData> {
+        self.replay_inner(tree, |_| Some(max_distance_evaluations))
+    }
+
+    fn replay_inner<Item, Impl, Ownership>(&self, tree: &Tree<Item, Impl, Ownership>, budget: impl Fn(&LoggedQuery<Q>) -> Option<usize>) -> ReplayReport
+    where Item: MetricSpace<Impl> + Clone + Borrow<Q>, Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance>, Item::Distance: Bounded, Ownership: HoldsUserData<UserData = Item::UserData> {
+        let mut report = ReplayReport { queries: 0, total_time: Duration::ZERO, max_time: Duration::ZERO, recall: 1. };
+        let mut recall_sum = 0.;
+        for entry in &self.entries {
+            let start = Instant::now();
+            let (found, exact) = match budget(entry) {
+                Some(max) => tree.find_k_nearest_budgeted(&entry.needle, entry.k, max),
+                None => (tree.find_k_nearest(&entry.needle, entry.k), true),
+            };
+            let elapsed = start.elapsed();
+            report.queries += 1;
+            report.total_time += elapsed;
+            report.max_time = report.max_time.max(elapsed);
+
+            if exact {
+                recall_sum += 1.;
+                continue;
+            }
+            // Items tied with the farthest of the exact results are as good as the ones that have been found
+            let expected = tree.find_k_nearest(&entry.needle, entry.k);
+            recall_sum += match expected.last() {
+                Some(&(_, farthest)) => found.iter().filter(|&&(_, distance)| distance <= farthest).count().min(expected.len()) as f64 / expected.len() as f64,
+                None => 1.,
+            };
+        }
+        if report.queries > 0 {
+            report.recall = recall_sum / report.queries as f64;
+        }
+        report
+    }
+}
@@ -0,0 +1,173 @@
+//! What happened while a tree was built, for logging and monitoring builds of large trees.
+//!
+//! The report is collected during the normal build from the distances it computes anyway, so it costs almost nothing.
+
+use crate::{Buckets, Error, MetricSpace, Node, Owned, Tmp, Tree, NO_NODE};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use std::time::{Duration, Instant};
+
+/// Statistics and warnings about a build. Returned by `Tree::try_new_with_report`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildReport {
+    /// Number of items in the tree
+    pub items: usize,
+    /// Time of preparing the list of items
+    pub prepare_time: Duration,
+    /// Time of computing distances to vantage points and splitting items into nodes, which is most of the build
+    pub partition_time: Duration,
+    /// Number of distances computed
+    pub distance_evaluations: usize,
+    /// Number of leaves at each depth, where the root has depth `0`
+    pub depth_histogram: Vec<usize>,
+    /// Number of items that are at zero distance from a vantage point, i.e. duplicates of another item.
+    ///
+    /// Items in the same leaf aren't compared with each other, so there may be more.
+    pub duplicates: usize,
+    /// Nodes whose median distance is shared by items on both sides of the split
+    pub tied_splits: usize,
+    /// Problems with the data that make searches slower
+    pub warnings: Vec<BuildWarning>,
+}
+
+/// Something about the items or the metric that is likely to make searches slower than they could be
+#[derive(Debug, Copy, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum BuildWarning {
+    /// Over 10% of the items are duplicates. They're all visited whenever one of them could be the nearest.
+    ManyDuplicates { duplicates: usize, items: usize },
+    /// Over half of the nodes have ties at the median distance. The metric has few distinct values
+    /// (e.g. a Hamming distance of short strings), so searches often have to visit both children of a node.
+    ManyTiedSplits { tied_splits: usize, nodes: usize },
+}
+
+impl fmt::Display for BuildWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            BuildWarning::ManyDuplicates { duplicates, items } => write!(f, "{} of {} items are duplicates; consider removing them and storing their indexes in a map", duplicates, items),
+            BuildWarning::ManyTiedSplits { tied_splits, nodes } => write!(f, "{} of {} nodes have ties at the median distance; the metric may have too few distinct values for the tree to prune well", tied_splits, nodes),
+        }
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl> Tree<Item, Impl, Owned<Item::UserData>> {
+    /// Like `try_new_with_user_data_owned`, but also returns a report of the build: how long it took, how deep the tree is,
+    /// and warnings about the data, e.g. to log them after a long offline build.
+    ///
+    /// The tree is the same as the one created by `try_new_with_user_data_owned`.
+    ///
+    /// ```rust
+    /// # #[derive(Clone)] struct Point(f32);
+    /// # impl vpsearch::MetricSpace for Point {
+    /// #     type UserData = (); type Distance = f32;
+    /// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+    /// # }
+    /// let points: Vec<_> = (0..1000).map(|i| Point((i % 100) as f32)).collect();
+    /// let (tree, report) = vpsearch::Tree::try_new_with_report(&points, ()).unwrap();
+    /// for warning in &report.warnings {
+    ///     eprintln!("warning: {}", warning);
+    /// }
+    /// assert!(report.duplicates > 0);
+    /// ```
+    pub fn try_new_with_report(items: &[Item], user_data: Item::UserData) -> Result<(Self, BuildReport), Error> {
+        if items.is_empty() {
+            return Err(Error::Empty);
+        }
+        let start = Instant::now();
+        let mut indexes = Self::initial_indexes(items, &user_data, None)?;
+        let prepare_time = start.elapsed();
+
+        let start = Instant::now();
+        let mut report = BuildReport {
+            items: items.len(),
+            prepare_time,
+            partition_time: Duration::ZERO,
+            // The distance used for the initial indexes, and the `zero`
+            distance_evaluations: 2,
+            depth_histogram: Vec::new(),
+            duplicates: 0,
+            tied_splits: 0,
+            warnings: Vec::new(),
+        };
+        let mut builder = ReportingBuilder {
+            items,
+            user_data: &user_data,
+            nodes: Vec::new(),
+            buckets: Buckets { items: Vec::new(), idx: Vec::new() },
+            zero: items[0].distance(&items[0], &user_data),
+            is_duplicate: vec![false; items.len()],
+            splits: 0,
+            report: &mut report,
+        };
+        let root = builder.create_node(&mut indexes[..], 0)?;
+        let (nodes, buckets, splits) = (builder.nodes, builder.buckets, builder.splits);
+        report.duplicates = builder.is_duplicate.iter().filter(|&&d| d).count();
+        report.partition_time = start.elapsed();
+
+        if report.duplicates > report.items / 10 {
+            report.warnings.push(BuildWarning::ManyDuplicates { duplicates: report.duplicates, items: report.items });
+        }
+        if splits > 0 && report.tied_splits > splits / 2 {
+            report.warnings.push(BuildWarning::ManyTiedSplits { tied_splits: report.tied_splits, nodes: splits });
+        }
+
+        let tree = Tree { nodes, buckets, root, user_data: () }.with_ownership(Owned(user_data));
+        Ok((tree, report))
+    }
+}
+
+/// Like `Tree::create_node`, but looks at the sorted distances of every node before they're overwritten by its children
+struct ReportingBuilder<'a, Item: MetricSpace<Impl> + Clone, Impl> {
+    items: &'a [Item],
+    user_data: &'a Item::UserData,
+    nodes: Vec<Node<Item, Impl>>,
+    buckets: Buckets<Item>,
+    /// Distance of an item to itself
+    zero: Item::Distance,
+    /// Indexed by the item
+    is_duplicate: Vec<bool>,
+    /// Number of nodes that have been split into children
+    splits: usize,
+    report: &'a mut BuildReport,
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl> ReportingBuilder<'_, Item, Impl> {
+    fn create_node(&mut self, indexes: &mut [Tmp<Item, Impl>], depth: usize) -> Result<u32, Error> {
+        if indexes.is_empty() {
+            return Ok(NO_NODE);
+        }
+
+        let node_idx = self.nodes.len();
+        let half_idx = match Tree::<Item, Impl, ()>::push_node(indexes, &mut self.nodes, &mut self.buckets, self.items, self.user_data)? {
+            Some(half_idx) => half_idx,
+            None => {
+                if self.report.depth_histogram.len() <= depth {
+                    self.report.depth_histogram.resize(depth + 1, 0);
+                }
+                self.report.depth_histogram[depth] += 1;
+                return Ok(node_idx as u32);
+            },
+        };
+
+        let last = indexes.len() - 1;
+        let rest = &indexes[..last];
+        self.report.distance_evaluations += rest.len();
+        self.splits += 1;
+        if half_idx > 0 && rest[half_idx - 1].distance == rest[half_idx].distance {
+            self.report.tied_splits += 1;
+        }
+        // Sorted by distance, so items at zero distance are first
+        let zero = self.zero;
+        for tmp in rest.iter().take_while(|tmp| tmp.distance <= zero) {
+            self.is_duplicate[tmp.idx as usize] = true;
+        }
+
+        let (near_indexes, far_indexes) = indexes[..last].split_at_mut(half_idx);
+        let near = self.create_node(near_indexes, depth + 1)?;
+        let far = self.create_node(far_indexes, depth + 1)?;
+        self.nodes[node_idx].near = near;
+        self.nodes[node_idx].far = far;
+        Ok(node_idx as u32)
+    }
+}
@@ -0,0 +1,29 @@
+//! Randomized functions that get their randomness from a `rand_core::RngCore`, e.g. to use the same seeded RNG for a whole simulation.
+//!
+//! Each of them takes one number from the RNG, and uses it as the `seed` of the function it's a variant of.
+//! The same state of the RNG gives the same results on all platforms.
+
+use crate::{check_metric, CountedTree, HoldsUserData, MetricCheck, MetricSpace, Owned, Tree};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use num_traits::ToPrimitive;
+use rand_core::RngCore;
+
+impl<Item: MetricSpace<Impl, UserData = ()> + Clone, Impl> Tree<Item, Impl, Owned<()>> {
+    /// Like `new_shuffled`, with the order of the items chosen by the `rng`
+    pub fn new_shuffled_with_rng<R: RngCore + ?Sized>(items: &[Item], rng: &mut R) -> Self {
+        Self::new_shuffled(items, rng.next_u64())
+    }
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> CountedTree<Item, Impl, Ownership> {
+    /// Like `sample_within_radius`, with the items picked by the `rng`
+    pub fn sample_within_radius_with_rng<Q, R: RngCore + ?Sized>(&self, needle: &Q, radius: Item::Distance, size: usize, rng: &mut R) -> Vec<usize> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.sample_within_radius(needle, radius, size, rng.next_u64())
+    }
+}
+
+/// Like `check_metric`, with the sample picked by the `rng`
+pub fn check_metric_with_rng<Item: MetricSpace<Impl>, Impl, R: RngCore + ?Sized>(items: &[Item], user_data: &Item::UserData, sample_size: usize, rng: &mut R) -> MetricCheck where Item::Distance: ToPrimitive {
+    check_metric(items, user_data, sample_size, rng.next_u64())
+}
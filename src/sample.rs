@@ -0,0 +1,106 @@
+//! Choosing a subset of items that covers the whole space, using farthest-first traversal.
+//!
+//! Each next item is the one farthest from all items chosen so far, which gives at most twice the optimal covering radius (the k-center problem).
+//! After an item is chosen, only items that got closer to the sample need updating, and they're found with a radius search around it.
+//! The farthest item is taken from a heap, in which updated items are added again, and their old entries are skipped when they come up.
+
+use crate::{BestCandidate, HoldsUserData, MetricSpace, Tree};
+use alloc::collections::BinaryHeap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use num_traits::Bounded;
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Chooses `size` items that are spread evenly over the space the items cover, e.g. for a smaller dataset for evaluation.
+    ///
+    /// Returns indexes of the chosen items. They're in the order of choosing, so the first `n` of them are a good sample of size `n` too.
+    /// Outliers are likely to be chosen, because they're far from everything else.
+    /// It's deterministic, and starts from an arbitrary item. Returns all items if `size >= len()`.
+    ///
+    /// Each chosen item costs a radius search around it, which gets smaller as the sample grows, and picking the next item doesn't scan all items.
+    pub fn representative_sample(&self, size: usize) -> Vec<usize> where Item::Distance: Bounded {
+        let user_data = self.user_data.user_data();
+        let items = self.items_by_index();
+
+        // Distance from each item to the nearest chosen one. `None` if it's been chosen.
+        let max = <Item::Distance as Bounded>::max_value();
+        let mut coverage = vec![Some(max); items.len()];
+        let mut farthest: BinaryHeap<_> = (0..items.len()).map(|idx| Farthest { distance: max, idx }).collect();
+        let mut sample = Vec::with_capacity(size.min(items.len()));
+        let mut next = match self.nodes.get(self.root as usize) {
+            Some(root) => root.idx as usize,
+            None => return sample,
+        };
+        while sample.len() < size {
+            let radius = coverage[next].take();
+            sample.push(next);
+            if let Some(radius) = radius {
+                self.find_nearest_custom(items[next], user_data, Cover { radius, coverage: &mut coverage, farthest: &mut farthest });
+            }
+            // Entries of chosen items, and of items that have got closer since, are out of date
+            next = loop {
+                match farthest.pop() {
+                    Some(Farthest { distance, idx }) if coverage[idx] == Some(distance) => break idx,
+                    Some(_) => {},
+                    None => return sample,
+                }
+            };
+        }
+        sample
+    }
+}
+
+/// Lowers the `coverage` distances of items that are closer to the needle. Items farther than `radius` can't be.
+struct Cover<'a, D> {
+    radius: D,
+    coverage: &'a mut [Option<D>],
+    farthest: &'a mut BinaryHeap<Farthest<D>>,
+}
+
+/// Item's distance to the sample, ordered so that the heap gives the farthest item first, and the lowest index of equally far ones, to be deterministic
+struct Farthest<D> {
+    distance: D,
+    idx: usize,
+}
+
+impl<D: PartialOrd> PartialEq for Farthest<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<D: PartialOrd> Eq for Farthest<D> {}
+
+impl<D: PartialOrd> PartialOrd for Farthest<D> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<D: PartialOrd> Ord for Farthest<D> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance.partial_cmp(&other.distance).unwrap_or(Ordering::Equal).then(other.idx.cmp(&self.idx))
+    }
+}
+
+impl<'tree, Item: MetricSpace<Impl> + Clone, Impl> BestCandidate<'tree, Item, Impl> for Cover<'_, Item::Distance> {
+    type Output = ();
+
+    #[inline]
+    fn consider(&mut self, _: &Item, distance: Item::Distance, candidate_index: usize, _: &Item::UserData) {
+        if let Some(covered) = &mut self.coverage[candidate_index] {
+            if distance < *covered {
+                *covered = distance;
+                self.farthest.push(Farthest { distance, idx: candidate_index });
+            }
+        }
+    }
+
+    #[inline]
+    fn distance(&self) -> Item::Distance {
+        self.radius
+    }
+
+    fn result(self, _: &Item::UserData) {}
+}
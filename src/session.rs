@@ -0,0 +1,122 @@
+//! Several searches for the same needle, which compute the distance from the needle to each item at most once.
+//!
+//! Searches for the same needle visit the same vantage points at the top of the tree, and often the same buckets,
+//! so for metrics that are expensive to compute, most of the distances of the later searches are already known.
+
+use crate::{BestCandidate, HoldsUserData, KNearest, MetricSpace, Needle, NeedleDistance, Owned, ReturnByIndex, Tree, WithinRadius};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use num_traits::Bounded;
+
+/// Searches for one needle, which remember the distances they've computed. Created with `Tree::session`.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+/// let points: Vec<_> = (0..1000).map(|i| Point(i as f32)).collect();
+/// let tree = vpsearch::Tree::new(&points);
+/// let mut session = tree.session(&Point(12.3));
+/// let nearest = session.find_k_nearest(3);
+/// // Looking for more items reuses the distances of the first search
+/// let more = session.find_k_nearest(10);
+/// assert_eq!(more[..3], nearest[..]);
+/// // Everything the last search needs is known already
+/// let computed = session.distance_evaluations();
+/// session.find_within_radius(2.);
+/// assert_eq!(computed, session.distance_evaluations());
+/// ```
+pub struct QuerySession<'tree, Item: MetricSpace<Impl> + Clone, Q: ?Sized, Impl = (), Ownership = Owned<()>> {
+    tree: &'tree Tree<Item, Impl, Ownership>,
+    needle: &'tree Q,
+    /// Distances from the needle, by the index of the item
+    distances: BTreeMap<u32, Item::Distance>,
+    evaluations: usize,
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Starts a series of searches for the `needle`, e.g. a search for more results after the first ones weren't enough,
+    /// or searches with different collectors.
+    ///
+    /// The session keeps every distance it has computed, so it uses memory proportional to the number of items visited.
+    pub fn session<'tree, Q>(&'tree self, needle: &'tree Q) -> QuerySession<'tree, Item, Q, Impl, Ownership> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        QuerySession { tree: self, needle, distances: BTreeMap::new(), evaluations: 0 }
+    }
+}
+
+impl<'tree, Impl, Item: MetricSpace<Impl> + Clone, Q, Ownership: HoldsUserData<UserData = Item::UserData>> QuerySession<'tree, Item, Q, Impl, Ownership>
+where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+    /// Like `Tree::find_nearest`
+    pub fn find_nearest(&mut self) -> (usize, Item::Distance) where Item::Distance: Bounded {
+        let max = <Item::Distance as Bounded>::max_value();
+        self.find_nearest_custom(ReturnByIndex::new(max)).unwrap_or((0, max))
+    }
+
+    /// Like `Tree::find_k_nearest`
+    pub fn find_k_nearest(&mut self, k: usize) -> Vec<(usize, Item::Distance)> where Item::Distance: Bounded {
+        self.find_nearest_custom(KNearest::new(k))
+    }
+
+    /// Like `Tree::find_within_radius`
+    pub fn find_within_radius(&mut self, radius: Item::Distance) -> Vec<(usize, Item::Distance)> {
+        self.find_nearest_custom(WithinRadius::new(radius))
+    }
+
+    /// Like `Tree::find_nearest_custom`, with the tree's user data
+    pub fn find_nearest_custom<ReturnBy: BestCandidate<'tree, Item, Impl>>(&mut self, mut best_candidate: ReturnBy) -> ReturnBy::Output {
+        let tree = self.tree;
+        let user_data = tree.user_data.user_data();
+        let mut needle = CachedNeedle {
+            needle: Needle { needle: self.needle, user_data },
+            distances: &mut self.distances,
+            evaluations: &mut self.evaluations,
+        };
+        tree.search(tree.root, &mut needle, &mut best_candidate, user_data);
+        best_candidate.result(user_data)
+    }
+
+    /// Number of distances computed by all searches of this session so far
+    #[inline]
+    pub fn distance_evaluations(&self) -> usize {
+        self.evaluations
+    }
+}
+
+struct CachedNeedle<'a, Q: ?Sized, U, D> {
+    needle: Needle<'a, Q, U>,
+    distances: &'a mut BTreeMap<u32, D>,
+    evaluations: &'a mut usize,
+}
+
+impl<'a, Q, Item, Impl> NeedleDistance<Item, Impl> for CachedNeedle<'a, Q, Item::UserData, Item::Distance> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: MetricSpace<Impl> + Borrow<Q> {
+    #[inline]
+    fn distance_to(&mut self, item: &Item) -> Item::Distance {
+        *self.evaluations += 1;
+        NeedleDistance::<Item, Impl>::distance_to(&mut self.needle, item)
+    }
+
+    fn distance_to_indexed(&mut self, item: &Item, idx: u32) -> Item::Distance {
+        if let Some(&distance) = self.distances.get(&idx) {
+            return distance;
+        }
+        let distance = self.distance_to(item);
+        self.distances.insert(idx, distance);
+        distance
+    }
+
+    fn distances_to_indexed(&mut self, items: &[Item], idx: &[u32], out: &mut [Item::Distance]) {
+        // Buckets are searched all at once, so their distances are either all known or all new
+        if idx.iter().all(|idx| self.distances.contains_key(idx)) {
+            for (idx, out) in idx.iter().zip(out) {
+                *out = self.distances[idx];
+            }
+            return;
+        }
+        *self.evaluations += items.len();
+        NeedleDistance::<Item, Impl>::distances_to(&mut self.needle, items, out);
+        self.distances.extend(idx.iter().copied().zip(out.iter().copied()));
+    }
+}
@@ -0,0 +1,71 @@
+//! Data that trees like `TreeWithValues` or `WeightedTree` keep next to the `Tree`, for each item or for each node.
+
+#[cfg(feature = "serde")]
+use crate::Error;
+use crate::{MetricSpace, Tree, NO_NODE};
+use alloc::vec::Vec;
+use core::ops::Deref;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// One value for each item, in the order of the items the tree has been created from
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub(crate) struct PerItem<T>(Vec<T>);
+
+impl<T> PerItem<T> {
+    /// Panics if the number of values is different than the number of items in the `tree`. `what` is the name of the values for the message.
+    pub fn new<Item: MetricSpace<Impl> + Clone, Impl, Ownership>(tree: &Tree<Item, Impl, Ownership>, values: Vec<T>, what: &str) -> Self {
+        assert_eq!(tree.len(), values.len(), "there must be one {} for each item", what);
+        Self(values)
+    }
+
+    #[inline]
+    pub fn into_vec(self) -> Vec<T> {
+        self.0
+    }
+
+    /// For deserialized data, which may not have been made for this `tree`
+    #[cfg(feature = "serde")]
+    pub fn check_len<Item: MetricSpace<Impl> + Clone, Impl, Ownership>(&self, tree: &Tree<Item, Impl, Ownership>) -> Result<(), Error> {
+        if self.0.len() == tree.len() { Ok(()) } else { Err(Error::InvalidTree) }
+    }
+}
+
+impl<T> Deref for PerItem<T> {
+    type Target = [T];
+
+    #[inline]
+    fn deref(&self) -> &[T] {
+        &self.0
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// For every node, combines the values of all items in its subtree, e.g. to count them. Indexed like `nodes`.
+    ///
+    /// `of_item(index)` is the value of one item, and `combine` merges two values in any order.
+    pub(crate) fn aggregate_nodes<T: Copy>(&self, mut of_item: impl FnMut(usize) -> T, mut combine: impl FnMut(T, T) -> T) -> Vec<T> {
+        let mut aggregates = Vec::with_capacity(self.nodes.len());
+        // Children are always after their parent, so in reverse order they're done before the parent needs them
+        for node in self.nodes.iter().rev() {
+            let own = of_item(node.idx as usize);
+            let aggregate = match node.bucket() {
+                Some(bucket) => self.buckets.idx[bucket].iter().fold(own, |sum, &idx| combine(sum, of_item(idx as usize))),
+                None => [node.near, node.far].iter()
+                    .filter(|&&child| child != NO_NODE)
+                    // Collected in reverse, so the children are counted from the end
+                    .fold(own, |sum, &child| combine(sum, aggregates[self.nodes.len() - 1 - child as usize])),
+            };
+            aggregates.push(aggregate);
+        }
+        aggregates.reverse();
+        aggregates
+    }
+
+    /// For deserialized data of every node, which may not have been made for this tree
+    #[cfg(feature = "serde")]
+    pub(crate) fn check_per_node_len<T>(&self, per_node: &[T]) -> Result<(), Error> {
+        if per_node.len() == self.nodes.len() { Ok(()) } else { Err(Error::InvalidTree) }
+    }
+}
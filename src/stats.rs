@@ -0,0 +1,110 @@
+//! Distances between the items and their nearest neighbors, which tell how well the tree can work with the data.
+
+use crate::{Filter, HoldsUserData, KNearest, MetricSpace, Tree};
+use alloc::vec;
+use alloc::vec::Vec;
+use num_traits::{Bounded, ToPrimitive};
+#[cfg(not(feature = "std"))]
+use num_traits::Float;
+
+/// Number of items whose neighbors are searched for by `suggest_epsilon()`
+const SAMPLE_SIZE: usize = 256;
+
+/// Summary of the distances from each item to its nearest neighbor. Returned by `Tree::neighbor_stats()`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct NeighborStats {
+    /// The smallest distance to the nearest neighbor. It's `0` if there are duplicate items.
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// Estimate of how many dimensions the data really spans, regardless of how many coordinates the items have.
+    ///
+    /// VP trees skip most of the items when it's low. When it's more than 10-20, most searches have to check most of the items,
+    /// and an approximate search may be a better choice. It's `NaN` if there are fewer than 3 distinct items.
+    pub intrinsic_dimension: f64,
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Distance from each item to the nearest other item, indexed like the items the tree has been created from.
+    ///
+    /// Duplicate items have `0` distance to each other. If the tree has only one item, the distance is `max_value()`.
+    pub fn nearest_neighbor_distances(&self) -> Vec<Item::Distance> where Item::Distance: Bounded {
+        self.two_nearest_neighbors().into_iter().map(|(first, _)| first).collect()
+    }
+
+    /// Statistics of `nearest_neighbor_distances()`, and an estimate of the intrinsic dimension of the data.
+    ///
+    /// It searches for neighbors of every item, so it takes as long as `len()` searches.
+    /// Returns `None` if there are fewer than 2 items.
+    pub fn neighbor_stats(&self) -> Option<NeighborStats> where Item::Distance: Bounded + ToPrimitive {
+        if self.len() < 2 {
+            return None;
+        }
+        // With 2 items there's no second neighbor, and its `max_value()` distance isn't a real one
+        let max = <Item::Distance as Bounded>::max_value();
+        let to_f64 = |distance: Item::Distance| if distance < max { distance.to_f64().unwrap_or(f64::NAN) } else { f64::NAN };
+        let neighbors: Vec<(f64, f64)> = self.two_nearest_neighbors().into_iter()
+            .map(|(first, second)| (to_f64(first), to_f64(second)))
+            .collect();
+
+        let mut nearest: Vec<f64> = neighbors.iter().map(|&(first, _)| first).collect();
+        nearest.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+        let len = nearest.len();
+        let median = (nearest[(len - 1) / 2] + nearest[len / 2]) / 2.;
+
+        // TwoNN estimator (Facco et al. 2017): the ratio of distances to the 2nd and 1st neighbor depends only on the dimension
+        let (count, log_ratios) = neighbors.iter()
+            .filter(|&&(first, second)| first > 0. && second.is_finite())
+            .fold((0usize, 0f64), |(count, sum), &(first, second)| (count + 1, sum + (second / first).ln()));
+
+        Some(NeighborStats {
+            min: nearest[0],
+            max: nearest[len - 1],
+            mean: nearest.iter().sum::<f64>() / len as f64,
+            median,
+            intrinsic_dimension: if log_ratios > 0. { count as f64 / log_ratios } else { f64::NAN },
+        })
+    }
+
+    /// A radius for `find_within_radius` that finds `target_avg_neighbors` other items on average, when the needles are like the items in the tree.
+    ///
+    /// It searches for neighbors of up to 256 items spread over the tree, and picks the smallest radius that has enough neighbors on average.
+    /// Returns `None` if there are fewer than 2 items or the target is `0`. If the target is larger than the number of other items, the radius covers them all.
+    pub fn suggest_epsilon(&self, target_avg_neighbors: usize) -> Option<Item::Distance> where Item::Distance: Bounded {
+        let len = self.len();
+        if len < 2 || target_avg_neighbors == 0 {
+            return None;
+        }
+        let user_data = self.user_data.user_data();
+        // Counts up to twice the target are enough to tell where the average crosses it
+        let k = target_avg_neighbors.saturating_mul(2).min(len - 1);
+        let sample: Vec<Vec<Item::Distance>> = self.items().step_by(len.div_ceil(SAMPLE_SIZE)).map(|(idx, item)| {
+            self.find_nearest_custom(item, user_data, Filter::new(KNearest::new(k), |other| other != idx))
+                .into_iter().map(|(_, distance)| distance).collect()
+        }).collect();
+
+        let mut candidates: Vec<Item::Distance> = sample.iter().flatten().copied().collect();
+        candidates.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+        let neighbors_within = |radius: Item::Distance| -> usize {
+            sample.iter().map(|distances| distances.partition_point(|&distance| distance <= radius)).sum()
+        };
+        let target_total = target_avg_neighbors.saturating_mul(sample.len());
+        let first_enough = candidates.partition_point(|&radius| neighbors_within(radius) < target_total);
+        candidates.get(first_enough).or_else(|| candidates.last()).copied()
+    }
+
+    /// Distances to the nearest and the second-nearest other item, for every item
+    fn two_nearest_neighbors(&self) -> Vec<(Item::Distance, Item::Distance)> where Item::Distance: Bounded {
+        let user_data = self.user_data.user_data();
+        let max = <Item::Distance as Bounded>::max_value();
+        let mut neighbors = vec![(max, max); self.len()];
+        // In the order of the tree, so that consecutive searches visit mostly the same nodes
+        for (idx, item) in self.items() {
+            let found = self.find_nearest_custom(item, user_data, Filter::new(KNearest::new(2), |other| other != idx));
+            let mut found = found.into_iter().map(|(_, distance)| distance);
+            neighbors[idx] = (found.next().unwrap_or(max), found.next().unwrap_or(max));
+        }
+        neighbors
+    }
+}
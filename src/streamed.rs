@@ -0,0 +1,124 @@
+//! Building a tree from items that can be iterated only once.
+//!
+//! The first items are a sample, from which the top levels of the tree are built. The vantage points of these levels are chosen
+//! and their radii are set by the sample alone. The rest of the items are routed down these levels as they arrive, each
+//! compared only with the vantage points on its path, and every group of items that ends up below the top levels is built like a regular tree.
+
+use crate::{Buckets, Error, MetricSpace, Node, Owned, Tmp, Tree, MAX_DEPTH, NO_NODE};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// Sample items are split further only if there are more of them than this, so that the radii are estimated from enough items
+const MIN_SPLIT: usize = 32;
+
+/// Top levels of the tree, built from the sample
+enum Pivot<D> {
+    Split { idx: u32, radius: D, near: Box<Pivot<D>>, far: Box<Pivot<D>> },
+    /// Index into the groups of items that are built into regular subtrees
+    Group(usize),
+}
+
+impl<Item: MetricSpace<Impl, UserData = ()> + Clone, Impl> Tree<Item, Impl, Owned<()>> {
+    /// Creates a tree from an iterator, which is read only once, e.g. when reading items from a file or a network stream.
+    ///
+    /// The first `sample_size` items decide the shape of the top of the tree, so they should be representative of all items.
+    /// Larger samples make the tree more balanced, and smaller samples make the build faster. Items are indexed in the order of the iterator.
+    /// The tree finds the same results as one made by `new`, but it may need more distance computations to search if the sample is small.
+    ///
+    /// Panics in the same cases as `new`.
+    pub fn new_streamed<I: IntoIterator<Item = Item>>(items: I, sample_size: usize) -> Self {
+        Self::create_streamed(items, sample_size).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn create_streamed<I: IntoIterator<Item = Item>>(items: I, sample_size: usize) -> Result<Self, Error> {
+        let mut items = items.into_iter();
+        let mut all: Vec<Item> = items.by_ref().take(sample_size.max(1)).collect();
+        if all.is_empty() {
+            return Ok(Self::new(&[]));
+        }
+        let placeholder = all[0].distance(&all[0], &());
+        let sample: Vec<_> = (0..all.len() as u32).map(|idx| Tmp { idx, distance: placeholder }).collect();
+        let mut groups = Vec::new();
+        let pivots = Self::split_sample(sample, &all, &mut groups, 0)?;
+
+        for item in items {
+            if all.len() >= (u32::MAX/2) as usize {
+                return Err(Error::TooManyItems);
+            }
+            let mut pivot = &pivots;
+            let group = loop {
+                match pivot {
+                    Pivot::Split { idx, radius, near, far } => {
+                        let distance = all[*idx as usize].distance(&item, &());
+                        pivot = match distance.partial_cmp(radius) {
+                            Some(Ordering::Greater) => far,
+                            Some(_) => near,
+                            None => return Err(Error::InvalidDistance),
+                        };
+                    },
+                    Pivot::Group(group) => break *group,
+                }
+            };
+            groups[group].push(Tmp { idx: all.len() as u32, distance: placeholder });
+            all.push(item);
+        }
+
+        // An unrepresentative sample can put too many items in one group, and make the tree too deep to search
+        let pivot_depth = Self::pivot_depth(&pivots);
+        let largest = groups.iter().map(|group| group.len()).max().unwrap_or(0);
+        if pivot_depth + (usize::BITS - largest.leading_zeros()) as usize > MAX_DEPTH {
+            return Ok(Self::create_root_node(&all, &(), None)?.with_ownership(Owned(())));
+        }
+
+        let mut nodes = Vec::with_capacity(all.len() / 2);
+        let mut buckets = Buckets { items: Vec::new(), idx: Vec::new() };
+        let root = Self::create_pivot_node(&pivots, &mut groups, &mut nodes, &mut buckets, &all)?;
+        Ok(Tree { nodes, buckets, root, user_data: Owned(()) })
+    }
+
+    fn split_sample(mut sample: Vec<Tmp<Item, Impl>>, items: &[Item], groups: &mut Vec<Vec<Tmp<Item, Impl>>>, depth: usize) -> Result<Pivot<Item::Distance>, Error> {
+        // Half of the depth is left for the groups
+        if sample.len() <= MIN_SPLIT || depth >= MAX_DEPTH / 2 {
+            groups.push(sample);
+            return Ok(Pivot::Group(groups.len() - 1));
+        }
+        let vantage = sample.pop().unwrap();
+        Self::sort_indexes_by_distance(items[vantage.idx as usize].clone(), &mut sample, items, &())?;
+        let far = sample.split_off(sample.len() / 2);
+        let radius = far[0].distance;
+        // Items at exactly the radius may be on either side, and the streamed ones go to the near side
+        let near = Self::split_sample(sample, items, groups, depth + 1)?;
+        let far = Self::split_sample(far, items, groups, depth + 1)?;
+        Ok(Pivot::Split { idx: vantage.idx, radius, near: Box::new(near), far: Box::new(far) })
+    }
+
+    fn pivot_depth(pivot: &Pivot<Item::Distance>) -> usize {
+        match pivot {
+            Pivot::Split { near, far, .. } => 1 + Self::pivot_depth(near).max(Self::pivot_depth(far)),
+            Pivot::Group(_) => 0,
+        }
+    }
+
+    /// Adds nodes in the same order as `create_node`, so the tree has the same layout as other trees
+    fn create_pivot_node(pivot: &Pivot<Item::Distance>, groups: &mut [Vec<Tmp<Item, Impl>>], nodes: &mut Vec<Node<Item, Impl>>, buckets: &mut Buckets<Item>, items: &[Item]) -> Result<u32, Error> {
+        match pivot {
+            Pivot::Split { idx, radius, near, far } => {
+                let node_idx = nodes.len();
+                nodes.push(Node {
+                    vantage_point: items[*idx as usize].clone(),
+                    idx: *idx,
+                    radius: *radius,
+                    near: NO_NODE,
+                    far: NO_NODE,
+                });
+                let near = Self::create_pivot_node(near, groups, nodes, buckets, items)?;
+                let far = Self::create_pivot_node(far, groups, nodes, buckets, items)?;
+                nodes[node_idx].near = near;
+                nodes[node_idx].far = far;
+                Ok(node_idx as u32)
+            },
+            Pivot::Group(group) => Self::create_node(&mut groups[*group], nodes, buckets, items, &()),
+        }
+    }
+}
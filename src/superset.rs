@@ -0,0 +1,72 @@
+//! Radius searches that may return a few extra items, but compute fewer distances.
+//!
+//! All items in the near subtree of a node are at most the node's radius away from its vantage point.
+//! When the whole near subtree fits in the search radius (plus the allowed slack), its items are taken
+//! without computing their distances, because the triangle inequality already proves they're close enough.
+
+use crate::{DistanceAdd, HoldsUserData, MetricSpace, Tree, LEAF_SIZE, NO_NODE};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Finds indexes of all items at most `radius` away from the `needle`, and possibly some items up to `radius + slack` away.
+    ///
+    /// It never misses an item within the `radius`. Larger `slack` lets it skip more distance computations,
+    /// so it's faster when checking the extra items afterwards is cheaper than the search. With `slack` of `0` there are no extra items.
+    ///
+    /// The indexes are in no particular order. Distances aren't returned, because most of them haven't been computed.
+    pub fn find_within_radius_superset<Q>(&self, needle: &Q, radius: Item::Distance, slack: Item::Distance) -> Vec<usize> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        let user_data = self.user_data.user_data();
+        let max_distance = radius.saturating_add(slack);
+        let mut found = Vec::new();
+        let mut stack = vec![self.root];
+        while let Some(next) = stack.pop() {
+            let node = match self.nodes.get(next as usize) {
+                Some(node) => node,
+                None => continue,
+            };
+            let distance = needle.distance(node.vantage_point.borrow(), user_data);
+            if distance <= radius {
+                found.push(node.idx as usize);
+            }
+
+            if let Some(bucket) = node.bucket() {
+                let items = &self.buckets.items[bucket.clone()];
+                let mut distances = [distance; LEAF_SIZE];
+                let distances = &mut distances[..items.len()];
+                needle.distance_to_many(items, distances, user_data);
+                found.extend(self.buckets.idx[bucket].iter().zip(distances.iter())
+                    .filter(|&(_, &distance)| distance <= radius)
+                    .map(|(&idx, _)| idx as usize));
+                continue;
+            }
+
+            if distance.saturating_add(node.radius) <= max_distance {
+                self.subtree_items(node.near, &mut found);
+            } else if distance <= node.radius.saturating_add(radius) {
+                stack.push(node.near);
+            }
+            if distance.saturating_add(radius) >= node.radius {
+                stack.push(node.far);
+            }
+        }
+        found
+    }
+
+    /// Adds indexes of all items in the subtree to `found`
+    fn subtree_items(&self, root: u32, found: &mut Vec<usize>) {
+        let mut stack = vec![root];
+        while let Some(next) = stack.pop() {
+            let node = match self.nodes.get(next as usize) {
+                Some(node) => node,
+                None => continue,
+            };
+            found.push(node.idx as usize);
+            match node.bucket() {
+                Some(bucket) => found.extend(self.buckets.idx[bucket].iter().map(|&idx| idx as usize)),
+                None => stack.extend([node.near, node.far].iter().copied().filter(|&child| child != NO_NODE)),
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Trees with a tag for each item, e.g. a kind of the item, that searches can be limited to.
+
+use crate::side_data::PerItem;
+#[cfg(feature = "serde")]
+use crate::Error;
+use crate::{Filter, HoldsUserData, KNearest, MetricSpace, Owned, ReturnByIndex, Tree, WithinRadius};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+#[cfg(feature = "serde")]
+use core::convert::TryFrom;
+use num_traits::Bounded;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A tree that has a tag for each item, so that searches can skip items with tags they don't want. Created with `Tree::with_tags`.
+///
+/// Tags are meant to be small, like a fieldless `enum` or a `u8`, because they're kept in a separate array, and
+/// checked for every item the search looks at. It's cheaper than having a separate tree for every kind of items.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+/// #[derive(Copy, Clone, PartialEq)]
+/// enum Kind { Image, Video }
+///
+/// let tree = vpsearch::Tree::new(&[Point(1.), Point(2.), Point(5.)]).with_tags(vec![Kind::Image, Kind::Video, Kind::Image]);
+/// let (index, _) = tree.find_nearest_tagged(&Point(2.), |kind| kind == Kind::Image).unwrap();
+/// assert_eq!(index, 0);
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "TaggedTreeData<Item, Tag, Impl, Ownership>"))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "Item: Serialize, Item::Distance: Serialize, Ownership: Serialize, Tag: Serialize",
+    deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>, Ownership: Deserialize<'de>, Tag: Deserialize<'de>",
+)))]
+pub struct TaggedTree<Item: MetricSpace<Impl> + Clone, Tag, Impl = (), Ownership = Owned<()>> {
+    tree: Tree<Item, Impl, Ownership>,
+    tags: PerItem<Tag>,
+}
+
+/// Same fields as `TaggedTree`, which is checked after they're deserialized
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "TaggedTree")]
+#[serde(bound(deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>, Ownership: Deserialize<'de>, Tag: Deserialize<'de>"))]
+struct TaggedTreeData<Item: MetricSpace<Impl> + Clone, Tag, Impl, Ownership> {
+    tree: Tree<Item, Impl, Ownership>,
+    tags: PerItem<Tag>,
+}
+
+#[cfg(feature = "serde")]
+impl<Item: MetricSpace<Impl> + Clone, Tag, Impl, Ownership> TryFrom<TaggedTreeData<Item, Tag, Impl, Ownership>> for TaggedTree<Item, Tag, Impl, Ownership> {
+    type Error = Error;
+
+    fn try_from(TaggedTreeData { tree, tags }: TaggedTreeData<Item, Tag, Impl, Ownership>) -> Result<Self, Error> {
+        tags.check_len(&tree)?;
+        Ok(Self { tree, tags })
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// Adds a tag for each item, in the same order as the items the tree has been created from.
+    ///
+    /// Panics if the number of tags is different than the number of items.
+    pub fn with_tags<Tag: Copy>(self, tags: Vec<Tag>) -> TaggedTree<Item, Tag, Impl, Ownership> {
+        let tags = PerItem::new(&self, tags, "tag");
+        TaggedTree { tree: self, tags }
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Tag: Copy, Impl, Ownership> TaggedTree<Item, Tag, Impl, Ownership> {
+    /// The tree for searches without tags
+    #[inline]
+    pub fn tree(&self) -> &Tree<Item, Impl, Ownership> {
+        &self.tree
+    }
+
+    /// Tag of the item at `index`
+    #[inline]
+    pub fn tag(&self, index: usize) -> Tag {
+        self.tags[index]
+    }
+
+    /// All tags, in the order of the items the tree has been created from
+    #[inline]
+    pub fn tags(&self) -> &[Tag] {
+        &self.tags
+    }
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Tag: Copy, Ownership: HoldsUserData<UserData = Item::UserData>> TaggedTree<Item, Tag, Impl, Ownership> {
+    /// Finds the item closest to the `needle`, out of the items with a tag for which `filter` returns `true`.
+    ///
+    /// Returns `None` if no items have a matching tag.
+    pub fn find_nearest_tagged<Q, F: FnMut(Tag) -> bool>(&self, needle: &Q, filter: F) -> Option<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.find_nearest_tagged_bounded(needle, <Item::Distance as Bounded>::max_value(), filter)
+    }
+
+    /// Like `find_nearest_tagged`, but only finds an item at most `max_distance` away
+    pub fn find_nearest_tagged_bounded<Q, F: FnMut(Tag) -> bool>(&self, needle: &Q, max_distance: Item::Distance, filter: F) -> Option<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.tree.find_nearest_custom(needle, self.tree.user_data(), Filter::new(ReturnByIndex::new(max_distance), self.tag_filter(filter)))
+    }
+
+    /// Finds up to `k` items closest to the `needle`, out of the items with a tag for which `filter` returns `true`
+    pub fn find_k_nearest_tagged<Q, F: FnMut(Tag) -> bool>(&self, needle: &Q, k: usize, filter: F) -> Vec<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.tree.find_nearest_custom(needle, self.tree.user_data(), Filter::new(KNearest::new(k), self.tag_filter(filter)))
+    }
+
+    /// Finds all items at most `radius` away from the `needle`, out of the items with a tag for which `filter` returns `true`
+    pub fn find_within_radius_tagged<Q, F: FnMut(Tag) -> bool>(&self, needle: &Q, radius: Item::Distance, filter: F) -> Vec<(usize, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.tree.find_nearest_custom(needle, self.tree.user_data(), Filter::new(WithinRadius::new(radius), self.tag_filter(filter)))
+    }
+
+    #[inline]
+    fn tag_filter<'a, F: FnMut(Tag) -> bool + 'a>(&'a self, mut filter: F) -> impl FnMut(usize) -> bool + 'a {
+        move |index| filter(self.tags[index])
+    }
+}
@@ -2,6 +2,7 @@
 use super::*;
 
 #[test]
+#[allow(clippy::cast_abs_to_unsigned)]
 fn test_impl_orphan() {
     struct MyImpl;
     impl MetricSpace<MyImpl> for i32 {
@@ -46,6 +47,64 @@ fn test_without_user_data() {
 }
 
 #[test]
+fn test_empty_tree() {
+    let empty = Tree::<Point2>::new(&[]);
+    assert_eq!(empty.len(), 0);
+    assert!(empty.is_empty());
+    let needle = Point2(1., 2.);
+    assert_eq!(empty.find_nearest(&needle), (0, f32::MAX));
+    assert_eq!(empty.find_nearest_bounded(&needle, 10.), None);
+    assert!(empty.find_k_nearest(&needle, 3).is_empty());
+    assert!(empty.find_within_radius(&needle, 100.).is_empty());
+
+    let by_ref = Tree::<Point2, (), ()>::new_with_user_data_ref(&[], &());
+    assert!(by_ref.is_empty());
+    assert_eq!(by_ref.find_nearest_bounded(&needle, 10., &()), None);
+
+    let one = Tree::new(&[needle]);
+    assert_eq!((one.len(), one.is_empty()), (1, false));
+}
+
+#[test]
+#[should_panic(expected = "NaN")]
+fn test_nan_build() {
+    let mut points = random_points(50, 43);
+    points[3] = Point2(1., f32::NAN);
+    Tree::new(&points);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "NaN")]
+fn test_nan_needle() {
+    Tree::new(&random_points(50, 44)).find_nearest(&Point2(f32::NAN, 1.));
+}
+
+#[test]
+fn test_nan_not_collected() {
+    let mut k = KNearest::<Point2, ()>::new(3);
+    k.consider(&Point2(0., 0.), f32::NAN, 0, &());
+    k.consider(&Point2(0., 0.), 1., 1, &());
+    assert_eq!(k.result(&()), vec![(1, 1.)]);
+}
+
+#[test]
+fn test_try_new() {
+    let points = random_points(50, 42);
+    let vp = Tree::try_new(&points).unwrap();
+    assert_eq!(vp.find_nearest(&points[7]), (7, 0.));
+
+    assert_eq!(Tree::<Point2>::try_new(&[]).err(), Some(Error::Empty));
+    assert_eq!(Tree::try_new_with_user_data_ref(&[] as &[Point2], &()).err(), Some(Error::Empty));
+
+    let mut with_nan = points.clone();
+    with_nan[20] = Point2(f32::NAN, 1.);
+    assert_eq!(Tree::try_new(&with_nan).err(), Some(Error::InvalidDistance));
+    assert_eq!(Tree::try_new_with_user_data_owned(&with_nan, ()).err().map(|e| e.to_string()), Some(Error::InvalidDistance.to_string()));
+}
+
+#[test]
+#[allow(clippy::cast_abs_to_unsigned)]
 fn test_with_user_data() {
     #[derive(Copy, Clone)]
     struct Bar(i32);
@@ -72,3 +131,2599 @@ fn test_with_user_data() {
     assert_eq!((0, 1), vp.find_nearest(&Bar(9), &magic));
     assert_eq!((0, 1), vp.find_nearest_with_user_data(&Bar(9), &magic));
 }
+
+#[test]
+fn test_instrumented_user_data() {
+    use std::cell::Cell;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone)]
+    struct Counted(Point2);
+    impl MetricSpace for Counted {
+        type UserData = AtomicUsize;
+        type Distance = f32;
+        fn distance(&self, other: &Self, calls: &AtomicUsize) -> f32 {
+            calls.fetch_add(1, Ordering::Relaxed);
+            self.0.distance(&other.0, &())
+        }
+    }
+
+    let points: Vec<_> = random_points(500, 36).into_iter().map(Counted).collect();
+    let vp = Tree::new_with_user_data_owned(&points, AtomicUsize::new(0));
+    let built = vp.user_data().swap(0, Ordering::Relaxed);
+    assert!(built > 0);
+
+    let needles = random_points(40, 37);
+    std::thread::scope(|s| {
+        for chunk in needles.chunks(10) {
+            let vp = &vp;
+            s.spawn(move || for &needle in chunk {
+                vp.find_k_nearest(&Counted(needle), 3);
+            });
+        }
+    });
+    let searched = vp.user_data().load(Ordering::Relaxed);
+    assert!(searched >= needles.len() && searched < needles.len() * points.len() / 2);
+
+    #[derive(Clone)]
+    struct SingleThreaded(Point2);
+    impl MetricSpace for SingleThreaded {
+        type UserData = Cell<usize>;
+        type Distance = f32;
+        fn distance(&self, other: &Self, calls: &Cell<usize>) -> f32 {
+            calls.set(calls.get() + 1);
+            self.0.distance(&other.0, &())
+        }
+    }
+
+    let points: Vec<_> = random_points(100, 38).into_iter().map(SingleThreaded).collect();
+    let calls = Cell::new(0);
+    let vp = Tree::new_with_user_data_ref(&points, &calls);
+    calls.set(0);
+    vp.find_nearest(&SingleThreaded(Point2(1., 2.)), &calls);
+    assert!(calls.get() > 0);
+}
+
+#[test]
+fn test_distance_to_many() {
+    use std::borrow::Borrow;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone)]
+    struct Batched(Point2);
+    impl MetricSpace for Batched {
+        type UserData = AtomicUsize;
+        type Distance = f32;
+        fn distance(&self, other: &Self, _: &AtomicUsize) -> f32 {
+            self.0.distance(&other.0, &())
+        }
+        fn distance_to_many<O: Borrow<Self>>(&self, others: &[O], out: &mut [f32], batches: &AtomicUsize) {
+            assert_eq!(others.len(), out.len());
+            batches.fetch_add(1, Ordering::Relaxed);
+            for (other, out) in others.iter().zip(out) {
+                *out = self.0.distance(&other.borrow().0, &());
+            }
+        }
+    }
+
+    let points = random_points(300, 40);
+    let batched: Vec<_> = points.iter().copied().map(Batched).collect();
+    let vp = Tree::new_with_user_data_owned(&batched, AtomicUsize::new(0));
+    assert_eq!(vp.len(), points.len());
+    for needle in random_points(30, 41) {
+        let expected = brute_force(&points, &needle);
+        let found = vp.find_k_nearest(&Batched(needle), 5);
+        let found_dist: Vec<_> = found.iter().map(|&(_, d)| d).collect();
+        let expected_dist: Vec<_> = expected.iter().take(5).map(|&(_, d)| d).collect();
+        assert_eq!(found_dist, expected_dist);
+    }
+    assert!(vp.user_data().load(Ordering::Relaxed) > 0);
+}
+
+#[test]
+fn test_type_aliases() {
+    use crate::metrics::Euclidean;
+    use std::sync::Arc;
+
+    let points = random_points(50, 39);
+    let simple: SimpleTree<Point2> = Tree::new(&points);
+    let with_data: TreeWithData<Point2, ()> = Tree::new_with_user_data_owned(&points, ());
+    let shared: TreeWithSharedData<Point2, ()> = Tree::new_with_user_data_shared(&points, Arc::new(()));
+    let by_ref: TreeWithDataRef<Point2> = Tree::new_with_user_data_ref(&points, &());
+    let needle = Point2(1., 2.);
+    assert_eq!(simple.find_nearest(&needle), with_data.find_nearest(&needle));
+    assert_eq!(simple.find_nearest(&needle), shared.find_nearest(&needle));
+    assert_eq!(simple.find_nearest(&needle), by_ref.find_nearest(&needle, &()));
+
+    let arrays: SimpleTree<[f32; 2], Euclidean> = Tree::new(&[[0., 0.], [3., 4.]]);
+    assert_eq!((1, 5.), arrays.find_nearest(&[6., 8.]));
+}
+
+fn assert_send_sync<T: Send + Sync>() {}
+
+#[test]
+fn test_send_sync() {
+    use crate::metrics::{Euclidean, Levenshtein};
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::Arc;
+
+    assert_send_sync::<SimpleTree<Point2>>();
+    assert_send_sync::<TreeWithData<Point2, AtomicUsize>>();
+    assert_send_sync::<TreeWithSharedData<Point2, Vec<f32>>>();
+    assert_send_sync::<TreeWithDataRef<Point2>>();
+    assert_send_sync::<SimpleTree<String, Levenshtein>>();
+    assert_send_sync::<SimpleTree<Arc<[f32]>, Euclidean>>();
+
+    let points = random_points(200, 47);
+    let vp = Arc::new(Tree::new_with_user_data_shared(&points, Arc::new(())));
+    let threads: Vec<_> = random_points(4, 48).into_iter().map(|needle| {
+        let vp = Arc::clone(&vp);
+        std::thread::spawn(move || vp.find_nearest(&needle))
+    }).collect();
+    for (thread, needle) in threads.into_iter().zip(random_points(4, 48)) {
+        assert_eq!(thread.join().unwrap(), brute_force(&points, &needle)[0]);
+    }
+}
+
+#[test]
+fn test_shared_user_data() {
+    use std::sync::Arc;
+
+    #[derive(Clone, Copy)]
+    struct Id(usize);
+    impl MetricSpace for Id {
+        type UserData = Vec<Point2>;
+        type Distance = f32;
+        fn distance(&self, other: &Self, points: &Vec<Point2>) -> f32 {
+            points[self.0].distance(&points[other.0], &())
+        }
+    }
+
+    let points = Arc::new(random_points(200, 35));
+    let evens: Vec<_> = (0..200).step_by(2).map(Id).collect();
+    let odds: Vec<_> = (1..200).step_by(2).map(Id).collect();
+    let even_tree = Tree::new_with_user_data_shared(&evens, Arc::clone(&points));
+    let odd_tree = Tree::new_with_user_data_shared(&odds, Arc::clone(&points));
+    assert_eq!(3, Arc::strong_count(even_tree.shared_user_data()));
+
+    let (even, _) = even_tree.find_nearest(&Id(7));
+    let (odd, dist) = odd_tree.find_nearest(&Id(7));
+    assert_eq!((odds[odd].0, 0.), (7, dist));
+    assert_eq!(0, evens[even].0 % 2);
+    assert_eq!(3, odd_tree.find_k_nearest(&Id(7), 3).len());
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Point2(f32, f32);
+
+impl MetricSpace for Point2 {
+    type UserData = ();
+    type Distance = f32;
+    fn distance(&self, other: &Self, _: &()) -> f32 {
+        let dx = self.0 - other.0;
+        let dy = self.1 - other.1;
+        (dx*dx + dy*dy).sqrt()
+    }
+}
+
+/// Deterministic pseudo-random points, so that tests don't need a rand dependency
+fn random_points(n: usize, mut seed: u32) -> Vec<Point2> {
+    let mut next = move || {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        (seed >> 8) as f32 / (1 << 24) as f32 * 100.
+    };
+    (0..n).map(|_| Point2(next(), next())).collect()
+}
+
+fn brute_force(points: &[Point2], needle: &Point2) -> Vec<(usize, f32)> {
+    let mut all: Vec<_> = points.iter().map(|p| p.distance(needle, &())).enumerate().collect();
+    all.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then(a.0.cmp(&b.0)));
+    all
+}
+
+#[test]
+fn test_k_nearest() {
+    let points = random_points(500, 1);
+    let vp = Tree::new(&points);
+    for needle in random_points(50, 2) {
+        let expected = brute_force(&points, &needle);
+        for &k in &[0, 1, 7, 600] {
+            let found = vp.find_k_nearest(&needle, k);
+            assert_eq!(found.len(), k.min(points.len()));
+            let found_dist: Vec<_> = found.iter().map(|&(_, d)| d).collect();
+            let expected_dist: Vec<_> = expected.iter().take(k).map(|&(_, d)| d).collect();
+            assert_eq!(found_dist, expected_dist);
+        }
+    }
+    assert!(Tree::<Point2>::new(&[]).find_k_nearest(&Point2(0., 0.), 3).is_empty());
+}
+
+#[test]
+fn test_k_nearest_parallel() {
+    let points = random_points(3000, 51);
+    let vp = Tree::new(&points);
+    for needle in random_points(10, 52) {
+        for &k in &[0, 1, 10, 5000] {
+            let found: Vec<_> = vp.find_k_nearest_parallel(&needle, k).into_iter().map(|(_, d)| d).collect();
+            let expected: Vec<_> = vp.find_k_nearest(&needle, k).into_iter().map(|(_, d)| d).collect();
+            assert_eq!(found, expected);
+        }
+    }
+    assert_eq!(Tree::new(&points[..5]).find_k_nearest_parallel(&points[0], 2), Tree::new(&points[..5]).find_k_nearest(&points[0], 2));
+    assert!(Tree::<Point2>::new(&[]).find_k_nearest_parallel(&Point2(0., 0.), 3).is_empty());
+}
+
+#[test]
+fn test_k_nearest_items() {
+    let points = random_points(300, 49);
+    let vp = Tree::new(&points);
+    for needle in random_points(20, 50) {
+        let items = vp.find_nearest_custom(&needle, &(), KNearestItems::new(5));
+        let expected: Vec<_> = vp.find_k_nearest(&needle, 5).into_iter().map(|(idx, d)| (&points[idx], d)).collect();
+        assert_eq!(items, expected);
+    }
+
+    let words: Vec<String> = ["apple", "apply", "ample", "maple"].iter().map(|s| s.to_string()).collect();
+    let vp = Tree::<_, crate::metrics::Levenshtein>::new(&words);
+    let nearest: &String = vp.find_nearest_custom("appl", &(), KNearestItems::with_max_distance(1, 1))[0].0;
+    assert!(nearest.starts_with("appl"));
+}
+
+#[test]
+fn test_k_nearest_array() {
+    let points = random_points(500, 45);
+    let vp = Tree::new(&points);
+    for needle in random_points(50, 46) {
+        let expected = vp.find_k_nearest(&needle, 7);
+        let found = vp.find_nearest_custom(&needle, &(), KNearestArray::<_, _, 7>::new());
+        assert_eq!(found.iter().flatten().copied().collect::<Vec<_>>(), expected);
+
+        let bounded = vp.find_nearest_custom(&needle, &(), KNearestArray::<_, _, 3>::with_max_distance(5.));
+        assert_eq!(bounded.iter().flatten().copied().collect::<Vec<_>>(), vp.find_k_nearest_bounded(&needle, 3, 5.));
+        assert_eq!(vp.find_nearest_custom(&needle, &(), KNearestArray::<_, _, 0>::new()), []);
+    }
+    let few = vp.find_nearest_custom(&points[0], &(), KNearestArray::<_, _, 600>::new());
+    assert!(few[..500].iter().all(Option::is_some) && few[500..].iter().all(Option::is_none));
+}
+
+#[test]
+fn test_within_radius() {
+    let points = random_points(500, 3);
+    let vp = Tree::new_with_user_data_ref(&points, &());
+    for needle in random_points(50, 4) {
+        let expected: Vec<_> = brute_force(&points, &needle).into_iter().filter(|&(_, d)| d <= 10.).collect();
+        assert_eq!(expected, vp.find_within_radius(&needle, 10., &()));
+    }
+    assert_eq!(vec![(7, 0.)], vp.find_within_radius(&points[7], 0., &()));
+}
+
+#[cfg(feature = "ordered-float")]
+#[test]
+fn test_ordered_float_distance() {
+    use ordered_float::{NotNan, OrderedFloat};
+
+    #[derive(Clone)]
+    struct Checked(Point2);
+    impl MetricSpace for Checked {
+        type UserData = ();
+        type Distance = NotNan<f32>;
+        fn distance(&self, other: &Self, _: &()) -> NotNan<f32> {
+            NotNan::new(self.0.distance(&other.0, &())).unwrap()
+        }
+    }
+
+    #[derive(Clone)]
+    struct Ordered(Point2);
+    impl MetricSpace for Ordered {
+        type UserData = ();
+        type Distance = OrderedFloat<f64>;
+        fn distance(&self, other: &Self, _: &()) -> OrderedFloat<f64> {
+            OrderedFloat(self.0.distance(&other.0, &()).into())
+        }
+    }
+
+    let points = random_points(300, 23);
+    let reference = Tree::new(&points);
+    let checked = Tree::new(&points.iter().copied().map(Checked).collect::<Vec<_>>());
+    let ordered = Tree::new(&points.iter().copied().map(Ordered).collect::<Vec<_>>());
+    for needle in random_points(20, 24) {
+        let expected = reference.find_k_nearest(&needle, 5);
+        let found = checked.find_k_nearest(&Checked(needle), 5);
+        assert_eq!(expected, found.iter().map(|&(i, d)| (i, d.into_inner())).collect::<Vec<_>>());
+        let found = ordered.find_within_radius(&Ordered(needle), OrderedFloat(10.));
+        assert_eq!(reference.find_within_radius(&needle, 10.).len(), found.len());
+    }
+}
+
+#[cfg(feature = "half")]
+#[test]
+fn test_half_distance() {
+    use crate::metrics::Euclidean;
+    use half::{bf16, f16};
+
+    let points = random_points(300, 25);
+    let halves: Vec<[f16; 2]> = points.iter().map(|p| [f16::from_f32(p.0), f16::from_f32(p.1)]).collect();
+    let vp: Tree<[f16; 2], Euclidean> = Tree::new(&halves);
+    for needle in random_points(20, 26) {
+        let needle = [f16::from_f32(needle.0), f16::from_f32(needle.1)];
+        let expected = halves.iter().map(|h| h.distance(&needle, &())).fold(f16::MAX, f16::min);
+        assert_eq!(expected, vp.find_nearest(&needle).1);
+    }
+
+    let vp: Tree<Vec<bf16>, Euclidean> = Tree::new(&[vec![bf16::ZERO; 3], vec![bf16::ONE, bf16::from_f32(2.), bf16::from_f32(2.)]]);
+    assert_eq!((1, bf16::from_f32(3.)), vp.find_nearest(&vec![bf16::ONE, bf16::from_f32(2.), bf16::from_f32(5.)]));
+    assert_eq!(2, vp.find_k_nearest(&vec![bf16::ZERO; 3], 5).len());
+}
+
+#[cfg(feature = "fixed")]
+#[test]
+fn test_fixed_distance() {
+    use fixed::types::U16F16;
+
+    #[derive(Clone, Copy)]
+    struct Pos(U16F16);
+    impl MetricSpace for Pos {
+        type UserData = ();
+        type Distance = U16F16;
+        fn distance(&self, other: &Self, _: &()) -> U16F16 {
+            self.0.dist(other.0)
+        }
+    }
+
+    // large values make sums of distances overflow
+    let mut items: Vec<_> = random_points(200, 27).iter().map(|p| Pos(U16F16::from_num(p.0 * 600.))).collect();
+    items.push(Pos(U16F16::MAX));
+    items.push(Pos(U16F16::ZERO));
+    let vp = Tree::new(&items);
+    for needle in random_points(30, 28).iter().map(|p| Pos(U16F16::from_num(p.1 * 655.))) {
+        let expected = items.iter().map(|i| i.distance(&needle, &())).min().unwrap();
+        assert_eq!(expected, vp.find_nearest(&needle).1);
+        assert_eq!(3, vp.find_k_nearest(&needle, 3).len());
+    }
+}
+
+#[test]
+fn test_unbounded_distance() {
+    // Doesn't implement Bounded
+    #[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+    struct Meters(f64);
+    impl DistanceAdd for Meters {
+        fn saturating_add(self, other: Self) -> Self {
+            Meters(self.0 + other.0)
+        }
+    }
+
+    #[derive(Clone)]
+    struct Place(Point2);
+    impl MetricSpace for Place {
+        type UserData = ();
+        type Distance = Meters;
+        fn distance(&self, other: &Self, _: &()) -> Meters {
+            Meters(self.0.distance(&other.0, &()).into())
+        }
+    }
+
+    let points = random_points(300, 29);
+    let reference = Tree::new(&points);
+    let vp = Tree::new(&points.iter().copied().map(Place).collect::<Vec<_>>());
+    for needle in random_points(20, 30) {
+        let (idx, dist) = reference.find_nearest(&needle);
+        assert_eq!(Some((idx, Meters(dist.into()))), vp.find_nearest_bounded(&Place(needle), Meters(1000.)));
+        assert_eq!(None, vp.find_nearest_bounded(&Place(needle), Meters(dist as f64 * 0.99)));
+
+        let expected: Vec<_> = reference.find_k_nearest(&needle, 10).into_iter().filter(|&(_, d)| d <= 5.).map(|(i, _)| i).collect();
+        let found: Vec<_> = vp.find_k_nearest_bounded(&Place(needle), 10, Meters(5.)).into_iter().map(|(i, _)| i).collect();
+        assert_eq!(expected, found);
+    }
+    assert_eq!(None, Tree::<Place>::new(&[]).find_nearest_bounded(&Place(Point2(0., 0.)), Meters(1.)));
+}
+
+#[test]
+fn test_integer_distance_overflow() {
+    #[derive(Clone, Copy)]
+    struct Narrow(u8);
+    impl MetricSpace for Narrow {
+        type UserData = ();
+        type Distance = u8;
+        fn distance(&self, other: &Self, _: &()) -> u8 {
+            self.0.abs_diff(other.0)
+        }
+    }
+
+    // Distances close to u8::MAX would overflow when added during pruning
+    let items: Vec<Narrow> = (0..=255u8).step_by(3).chain([1, 254, 255]).map(Narrow).collect();
+    let vp = Tree::new(&items);
+    for needle in (0..=255u8).map(Narrow) {
+        let mut expected: Vec<_> = items.iter().map(|i| i.distance(&needle, &())).collect();
+        expected.sort_unstable();
+        assert_eq!(expected[0], vp.find_nearest(&needle).1);
+        let found: Vec<_> = vp.find_k_nearest(&needle, 4).into_iter().map(|(_, d)| d).collect();
+        assert_eq!(&expected[..4], &found[..]);
+        assert_eq!(expected.len(), vp.find_within_radius(&needle, u8::MAX).len());
+        assert_eq!(expected.len(), vp.find_k_nearest(&needle, 1000).len());
+    }
+}
+
+#[test]
+fn test_lexicographic_distance() {
+    #[derive(Clone, Copy)]
+    struct Photo {
+        hash: u64,
+        timestamp: u32,
+    }
+    impl MetricSpace for Photo {
+        type UserData = ();
+        // Ties in the Hamming distance are broken by the difference in time
+        type Distance = (u32, u32);
+        fn distance(&self, other: &Self, _: &()) -> (u32, u32) {
+            ((self.hash ^ other.hash).count_ones(), self.timestamp.abs_diff(other.timestamp))
+        }
+    }
+
+    let photos: Vec<_> = (0..400u64).map(|i| Photo {
+        // few distinct hashes, so there are lots of ties
+        hash: (i % 13).wrapping_mul(0x9E3779B97F4A7C15),
+        timestamp: (i as u32).wrapping_mul(2654435761) >> 12,
+    }).collect();
+    let vp = Tree::new(&photos);
+    for i in (0..400).step_by(17) {
+        let needle = Photo { hash: photos[i].hash ^ 0b101, timestamp: photos[i].timestamp + 1000 };
+        let mut expected: Vec<_> = photos.iter().map(|p| p.distance(&needle, &())).collect();
+        expected.sort_unstable();
+        assert_eq!(expected[0], vp.find_nearest(&needle).1);
+        let found: Vec<_> = vp.find_k_nearest(&needle, 5).into_iter().map(|(_, d)| d).collect();
+        assert_eq!(&expected[..5], &found[..]);
+    }
+    assert_eq!((u32::MAX, 3), (u32::MAX, 1).saturating_add((5, 2)));
+}
+
+#[test]
+fn test_levenshtein_scratch() {
+    use crate::metrics::{Levenshtein, LevenshteinScratch};
+
+    let words: Vec<String> = ["kitten", "sitting", "saturday", "sunday", "łódź", "lodz", "日本語", "日本"].iter().map(|&w| w.to_owned()).collect();
+    let vp: Tree<String, Levenshtein> = Tree::new(&words);
+    let mut scratch = LevenshteinScratch::default();
+    for needle in ["monday", "sittin", "łodź", "日本人", ""] {
+        assert_eq!(vp.find_k_nearest(needle, 3), vp.find_k_nearest_with_context(needle, 3, &mut scratch));
+    }
+}
+
+#[test]
+fn test_borrowed_needle() {
+    use crate::metrics::{Euclidean, Levenshtein};
+    use std::sync::Arc;
+
+    let words: Vec<String> = ["kitten", "sitting", "saturday", "sunday"].iter().map(|&w| w.to_owned()).collect();
+    let vp: Tree<String, Levenshtein> = Tree::new(&words);
+    assert_eq!((3, 2), vp.find_nearest("monday"));
+    assert_eq!(vec![(0, 0), (1, 3)], vp.find_k_nearest("kitten", 2));
+
+    let vectors: Vec<Vec<f32>> = random_points(100, 31).iter().map(|p| vec![p.0, p.1]).collect();
+    let vp: Tree<Vec<f32>, Euclidean> = Tree::new(&vectors);
+    let needle = [50f32, 50.];
+    assert_eq!(vp.find_nearest(&needle.to_vec()), vp.find_nearest(&needle[..]));
+
+    let points = random_points(100, 32);
+    let arcs: Vec<_> = points.iter().copied().map(Arc::new).collect();
+    let vp = Tree::new_with_user_data_ref(&arcs, &());
+    assert_eq!(Tree::new(&points).find_nearest(&Point2(50., 50.)), vp.find_nearest(&Point2(50., 50.), &()));
+}
+
+#[test]
+fn test_query_context() {
+    struct Stats {
+        calls: usize,
+    }
+    impl MetricSpaceWithContext<Stats> for Point2 {
+        fn distance_with_context(&self, other: &Self, user_data: &(), stats: &mut Stats) -> f32 {
+            stats.calls += 1;
+            self.distance(other, user_data)
+        }
+    }
+
+    let points = random_points(1000, 33);
+    let vp = Tree::new(&points);
+    for needle in random_points(20, 34) {
+        let mut stats = Stats { calls: 0 };
+        assert_eq!(vp.find_k_nearest(&needle, 3), vp.find_k_nearest_with_context(&needle, 3, &mut stats));
+        assert!(stats.calls > 0 && stats.calls < points.len() / 2);
+
+        let mut stats = Stats { calls: 0 };
+        assert_eq!(vp.find_nearest(&needle), vp.find_nearest_with_context(&needle, &mut stats));
+        let found = vp.find_nearest_custom_with_context(&needle, &(), &mut stats, WithinRadius::new(5.));
+        assert_eq!(vp.find_within_radius(&needle, 5.), found);
+    }
+}
+
+#[test]
+fn test_pointers() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+
+    let points = random_points(100, 20);
+    let needle = Point2(50., 50.);
+    let expected = Tree::new(&points).find_nearest(&needle);
+
+    let arcs: Vec<_> = points.iter().copied().map(Arc::new).collect();
+    assert_eq!(expected, Tree::new(&arcs).find_nearest(&Arc::new(needle)));
+    let rcs: Vec<_> = points.iter().copied().map(Rc::new).collect();
+    assert_eq!(expected, Tree::new(&rcs).find_nearest(&Rc::new(needle)));
+    let boxes: Vec<_> = points.iter().copied().map(Box::new).collect();
+    assert_eq!(expected, Tree::new(&boxes).find_nearest(&Box::new(needle)));
+    let refs: Vec<&Point2> = points.iter().collect();
+    assert_eq!(expected, Tree::new(&refs).find_nearest(&&needle));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_roundtrip() {
+    let points = random_points(100, 5);
+    let vp = Tree::new(&points);
+    let serialized = serde_json::to_string(&vp).unwrap();
+    let vp2: Tree<Point2> = serde_json::from_str(&serialized).unwrap();
+    for needle in random_points(20, 6) {
+        assert_eq!(vp.find_k_nearest(&needle, 5), vp2.find_k_nearest(&needle, 5));
+    }
+
+    let mut corrupted: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+    corrupted["nodes"][0]["far"] = 1000.into();
+    let err = serde_json::from_value::<Tree<Point2>>(corrupted).err().unwrap();
+    assert!(err.to_string().contains(&crate::Error::InvalidTree.to_string()));
+
+    let mut corrupted: serde_json::Value = serde_json::from_str(&serialized).unwrap();
+    corrupted["buckets"]["idx"][0] = 100.into();
+    assert!(serde_json::from_value::<Tree<Point2>>(corrupted).is_err());
+
+    // A chain of nodes deeper than any tree of 2^31 items
+    let chain = |len: u32| {
+        let nodes: Vec<_> = (0..len).map(|i| serde_json::json!({
+            "near": if i + 1 < len { i + 1 } else { u32::MAX }, "far": u32::MAX, "vantage_point": [i as f32, 0.], "radius": 1., "idx": i,
+        })).collect();
+        serde_json::json!({ "nodes": nodes, "buckets": { "items": [], "idx": [] }, "root": 0, "user_data": null })
+    };
+    assert!(serde_json::from_value::<Tree<Point2>>(chain(20)).is_ok());
+    assert!(serde_json::from_value::<Tree<Point2>>(chain(100)).is_err());
+}
+
+/// Serializes `data`, lets `corrupt` change one of its fields, and checks that loading it back fails
+#[cfg(feature = "serde")]
+fn assert_corrupted_fails<T: serde::Serialize + serde::de::DeserializeOwned>(data: &T, corrupt: impl FnOnce(&mut serde_json::Value)) {
+    let mut json = serde_json::to_value(data).unwrap();
+    assert!(serde_json::from_value::<T>(json.clone()).is_ok());
+    corrupt(&mut json);
+    let err = serde_json::from_value::<T>(json).err().unwrap();
+    assert!(err.to_string().contains(&crate::Error::InvalidTree.to_string()), "{}", err);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_serde_side_data() {
+    let points = random_points(100, 7);
+    let values = Tree::new(&points).with_values((0..100).map(|i| i * 2).collect::<Vec<u32>>());
+    assert_corrupted_fails(&values, |json| { json["values"].as_array_mut().unwrap().truncate(1); });
+    let remapped = Tree::new_filtered(&points, |i, _| i % 2 == 0);
+    assert_corrupted_fails(&remapped, |json| { json["values"].as_array_mut().unwrap().pop(); });
+
+    let counted = Tree::new(&points).with_counts();
+    assert_corrupted_fails(&counted, |json| { json["counts"].as_array_mut().unwrap().pop(); });
+    assert_corrupted_fails(&counted, |json| { json["counts"][0] = 1000.into(); });
+
+    let weighted = Tree::new(&points).with_weights(vec![1u32; 100]);
+    assert_corrupted_fails(&weighted, |json| { json["weights"].as_array_mut().unwrap().pop(); });
+    assert_corrupted_fails(&weighted, |json| { json["node_weights"].as_array_mut().unwrap().truncate(3); });
+
+    let attributes = Tree::new(&points).with_attribute((0..100).collect::<Vec<u32>>());
+    assert_corrupted_fails(&attributes, |json| { json["attributes"].as_array_mut().unwrap().pop(); });
+    assert_corrupted_fails(&attributes, |json| { json["node_ranges"].as_array_mut().unwrap().pop(); });
+    let fuzzy: crate::FuzzyStringIndex = vec!["apple", "apply", "maple", "banana"].into_iter().collect();
+    assert_corrupted_fails(&fuzzy, |json| { json["tree"]["attributes"].as_array_mut().unwrap().clear(); });
+
+    let tagged = Tree::new(&points).with_tags((0..100).map(|i| i % 3 == 0).collect());
+    assert_corrupted_fails(&tagged, |json| { json["tags"].as_array_mut().unwrap().truncate(50); });
+}
+
+#[cfg(feature = "arrow")]
+impl From<Vec<f32>> for Point2 {
+    fn from(v: Vec<f32>) -> Self {
+        Point2(v[0], v[1])
+    }
+}
+
+#[cfg(feature = "arrow")]
+fn points_record_batch(points: &[Point2]) -> arrow_array::RecordBatch {
+    use arrow_array::types::Float32Type;
+    use arrow_array::{ArrayRef, FixedSizeListArray, Int32Array, RecordBatch};
+    use std::sync::Arc;
+
+    let list = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(points.iter().map(|p| Some(vec![Some(p.0), Some(p.1)])), 2);
+    let ids = Int32Array::from_iter_values(0..points.len() as i32);
+    RecordBatch::try_from_iter([("id", Arc::new(ids) as ArrayRef), ("point", Arc::new(list) as ArrayRef)]).unwrap()
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+fn test_from_record_batch() {
+    let points = random_points(200, 7);
+    let batch = points_record_batch(&points);
+
+    let vp = Tree::<Point2>::from_record_batch::<f32>(&batch, "point").unwrap();
+    for needle in random_points(20, 8) {
+        assert_eq!(Tree::new(&points).find_nearest(&needle), vp.find_nearest(&needle));
+    }
+    assert!(Tree::<Point2>::from_record_batch::<f32>(&batch, "nope").is_err());
+    assert!(Tree::<Point2>::from_record_batch::<f32>(&batch, "id").is_err());
+    assert!(crate::arrow::vectors_from_record_batch::<f64>(&batch, "point").is_err());
+
+    let mut with_nan = points.clone();
+    with_nan[50].0 = f32::NAN;
+    let err = Tree::<Point2>::from_record_batch::<f32>(&points_record_batch(&with_nan), "point").err().unwrap();
+    assert!(matches!(err, arrow_schema::ArrowError::ComputeError(_)), "{}", err);
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_from_parquet() {
+    let points = random_points(200, 9);
+    let batch = points_record_batch(&points);
+
+    let path = std::env::temp_dir().join(format!("vpsearch-test-{}.parquet", std::process::id()));
+    let file = std::fs::File::create(&path).unwrap();
+    let mut writer = parquet::arrow::ArrowWriter::try_new(file, batch.schema(), None).unwrap();
+    writer.write(&batch).unwrap();
+    writer.close().unwrap();
+
+    let vp = Tree::<Point2>::from_parquet::<f32>(&path, "point");
+    std::fs::remove_file(&path).unwrap();
+    let vp = vp.unwrap();
+    for needle in random_points(20, 10) {
+        assert_eq!(Tree::new(&points).find_nearest(&needle), vp.find_nearest(&needle));
+    }
+}
+
+#[cfg(feature = "wasm")]
+#[test]
+fn test_wasm_tree() {
+    let tree = crate::wasm::VpTree::new(&[0., 0., 1., 1., 5., 5.], 2).unwrap_or_else(|_| unreachable!());
+    assert_eq!(3, tree.length());
+    assert_eq!(Some(1), tree.find_nearest(&[0.9, 0.8]).unwrap_or_else(|_| unreachable!()));
+    let found = tree.find_k_nearest(&[0.9, 0.8], 2).unwrap_or_else(|_| unreachable!());
+    assert_eq!(vec![1, 0], found.indices());
+    let found = tree.find_within_radius(&[5., 4.], 1.).unwrap_or_else(|_| unreachable!());
+    assert_eq!(vec![2], found.indices());
+    assert_eq!(vec![1.], found.distances());
+}
+
+#[cfg(feature = "ndarray")]
+#[test]
+fn test_ndarray() {
+    use ::ndarray::{Array2, ArrayView1};
+
+    let points = random_points(300, 11);
+    let data = Array2::from_shape_fn((points.len(), 2), |(r, c)| if c == 0 { points[r].0 } else { points[r].1 });
+    let needles = random_points(30, 12);
+    let needle_data = Array2::from_shape_fn((needles.len(), 2), |(r, c)| if c == 0 { needles[r].0 } else { needles[r].1 });
+
+    let vp = Tree::from_array2(&data, crate::ndarray::Euclidean);
+    let reference = Tree::new(&points);
+    let (indices, distances) = vp.find_nearest_rows(&needle_data);
+    let (k_indices, k_distances) = vp.find_k_nearest_rows(&needle_data.view(), 4);
+    assert_eq!((30, 4), k_indices.dim());
+    for (i, needle) in needles.iter().enumerate() {
+        assert_eq!(reference.find_nearest(needle), (indices[i], distances[i]));
+        let expected = reference.find_k_nearest(needle, 4);
+        assert_eq!(expected.iter().map(|&(_, d)| d).collect::<Vec<_>>(), k_distances.row(i).to_vec());
+    }
+
+    let manhattan = |a: ArrayView1<'_, f32>, b: ArrayView1<'_, f32>| (&a - &b).mapv(f32::abs).sum();
+    let vp = Tree::from_array2(&data, manhattan);
+    assert_eq!((1, 2), vp.find_k_nearest_rows(&data.slice(::ndarray::s![..1, ..]), 2).0.dim());
+    assert_eq!(0., vp.find_nearest_rows(&data).1.sum());
+}
+
+#[cfg(feature = "nalgebra")]
+#[test]
+fn test_nalgebra() {
+    use ::nalgebra::{Point2, Vector3};
+
+    let points = random_points(200, 13);
+    let na_points: Vec<_> = points.iter().map(|p| Point2::new(p.0, p.1)).collect();
+    let vp = Tree::new(&na_points);
+    let reference = Tree::new(&points);
+    for needle in random_points(20, 14) {
+        assert_eq!(reference.find_nearest(&needle), vp.find_nearest(&Point2::new(needle.0, needle.1)));
+    }
+
+    let vectors = [Vector3::new(0., 0., 0.), Vector3::new(1., 2., 2.), Vector3::new(10., 0., 0.)];
+    let vp = Tree::new(&vectors);
+    assert_eq!((1, 3.0f64), vp.find_nearest(&Vector3::new(1., 2., 5.)));
+}
+
+#[test]
+fn test_euclidean_arrays() {
+    use crate::metrics::Euclidean;
+
+    let points = random_points(200, 15);
+    let arrays: Vec<[f32; 2]> = points.iter().map(|p| [p.0, p.1]).collect();
+    let vp: Tree<[f32; 2], Euclidean> = Tree::new(&arrays);
+    let reference = Tree::new(&points);
+    for needle in random_points(20, 16) {
+        assert_eq!(reference.find_nearest(&needle), vp.find_nearest(&[needle.0, needle.1]));
+    }
+
+    let vp: Tree<[f64; 3], Euclidean> = Tree::new(&[[0., 0., 0.], [1., 2., 2.], [10., 0., 0.]]);
+    assert_eq!((1, 3.), vp.find_nearest(&[1., 2., 5.]));
+}
+
+#[cfg(feature = "tuples")]
+#[test]
+fn test_tuples() {
+    use crate::metrics::Euclidean;
+
+    let points = random_points(200, 21);
+    let tuples: Vec<(f32, f32)> = points.iter().map(|p| (p.0, p.1)).collect();
+    let vp: Tree<(f32, f32), Euclidean> = Tree::new(&tuples);
+    let reference = Tree::new(&points);
+    for needle in random_points(20, 22) {
+        assert_eq!(reference.find_nearest(&needle), vp.find_nearest(&(needle.0, needle.1)));
+    }
+
+    let vp: Tree<(f64, f64, f64), Euclidean> = Tree::new(&[(0., 0., 0.), (1., 2., 2.), (10., 0., 0.)]);
+    assert_eq!((1, 3.), vp.find_nearest(&(1., 2., 5.)));
+}
+
+#[test]
+fn test_manhattan_chebyshev() {
+    use crate::metrics::{Chebyshev, Manhattan};
+
+    let points: Vec<Vec<f64>> = random_points(300, 17).iter().map(|p| vec![p.0 as f64, p.1 as f64]).collect();
+    let manhattan: Tree<Vec<f64>, Manhattan> = Tree::new(&points);
+    let chebyshev: Tree<Vec<f64>, Chebyshev> = Tree::new(&points);
+    for needle in random_points(20, 18) {
+        let needle = vec![needle.0 as f64, needle.1 as f64];
+        let l1 = |p: &Vec<f64>| (p[0] - needle[0]).abs() + (p[1] - needle[1]).abs();
+        let linf = |p: &Vec<f64>| (p[0] - needle[0]).abs().max((p[1] - needle[1]).abs());
+        assert_eq!(points.iter().map(l1).fold(f64::MAX, f64::min), manhattan.find_nearest(&needle).1);
+        assert_eq!(points.iter().map(linf).fold(f64::MAX, f64::min), chebyshev.find_nearest(&needle).1);
+    }
+
+    let arrays = [[0f32, 0., 0.], [1., 2., 2.], [3., 0., 0.]];
+    let vp: Tree<[f32; 3], Manhattan> = Tree::new(&arrays);
+    assert_eq!((1, 3.), vp.find_nearest(&[1., 2., 5.]));
+    let vp: Tree<[f32; 3], Chebyshev> = Tree::new(&arrays);
+    assert_eq!((2, 1.), vp.find_nearest(&[2., 0., 1.]));
+
+    let slices: Vec<&[f32]> = arrays.iter().map(|a| &a[..]).collect();
+    let vp: Tree<&[f32], Manhattan> = Tree::new(&slices);
+    assert_eq!((2, 2.), vp.find_nearest(&&[2f32, 0., 1.][..]));
+}
+
+#[test]
+fn test_hamming() {
+    use crate::metrics::{Hamming, Hash128, Hash64};
+
+    let hashes: Vec<u64> = (0..500u64).map(|i| i.wrapping_mul(0x9E3779B97F4A7C15)).collect();
+    let vp: Tree<Hash64, Hamming> = Tree::new(&hashes.iter().copied().map(Hash64).collect::<Vec<_>>());
+    for &h in hashes.iter().step_by(37) {
+        let needle = h ^ 0b1001;
+        let expected = hashes.iter().map(|x| (x ^ needle).count_ones()).min().unwrap();
+        assert_eq!(expected, vp.find_nearest(&Hash64(needle)).1);
+        assert!(vp.find_within_radius(&Hash64(needle), 2).iter().any(|&(_, d)| d == 2));
+        assert_eq!(expected, vp.find_k_nearest(&Hash64(needle), 3)[0].1);
+    }
+
+    let wide: Vec<Hash128> = hashes.iter().map(|&h| Hash128((h as u128) << 64 | h.rotate_left(7) as u128)).collect();
+    let vp: Tree<Hash128, Hamming> = Tree::new(&wide);
+    assert_eq!((10, 1), vp.find_nearest(&Hash128(wide[10].0 ^ 1 << 100)));
+
+    let bytes: Vec<[u8; 13]> = hashes.iter().map(|&h| {
+        let mut b = [0; 13];
+        b[..8].copy_from_slice(&h.to_le_bytes());
+        b[8..].copy_from_slice(&h.to_be_bytes()[..5]);
+        b
+    }).collect();
+    let vp: Tree<[u8; 13], Hamming> = Tree::new(&bytes);
+    let mut needle = bytes[42];
+    needle[0] ^= 0xF0;
+    needle[12] ^= 1;
+    assert_eq!((42, 5), vp.find_nearest(&needle));
+    assert_eq!(13 * 8, MetricSpace::<Hamming>::distance(&[0u8; 13], &[255u8; 13], &()));
+}
+
+#[test]
+fn test_hash_index() {
+    use crate::{Hash64, HashIndex};
+
+    let hashes: Vec<u64> = (0..300u64).map(|i| (i / 3).wrapping_mul(0x9E3779B97F4A7C15) ^ (i % 3) << (i % 64)).collect();
+    let brute_force = |threshold: u32| {
+        let mut pairs = Vec::new();
+        for (a, ha) in hashes.iter().enumerate() {
+            for (b, hb) in hashes.iter().enumerate().skip(a + 1) {
+                let distance = (ha ^ hb).count_ones();
+                if distance <= threshold {
+                    pairs.push((a, b, distance));
+                }
+            }
+        }
+        pairs
+    };
+
+    let mut added = HashIndex::new();
+    for (i, &hash) in hashes.iter().enumerate() {
+        assert_eq!(i, added.add(Hash64(hash)));
+    }
+    assert_eq!(hashes.len(), added.len());
+    // some of them are still pending
+    assert!(added.tree().len() < hashes.len());
+    let collected: HashIndex = hashes.iter().copied().map(Hash64).collect();
+
+    for index in [&added, &collected].iter() {
+        for threshold in [0, 2, 10].iter().copied() {
+            assert_eq!(brute_force(threshold), index.find_duplicates(threshold));
+        }
+        for &needle in hashes.iter().step_by(7) {
+            let needle = needle ^ 0b110;
+            let expected = hashes.iter().map(|h| (h ^ needle).count_ones()).min().unwrap();
+            let (idx, distance) = index.nearest(&Hash64(needle)).unwrap();
+            assert_eq!(expected, distance);
+            assert_eq!(distance, (hashes[idx] ^ needle).count_ones());
+            let similar = index.find_similar(&Hash64(needle), expected + 1);
+            assert_eq!(hashes.iter().filter(|h| (*h ^ needle).count_ones() <= expected + 1).count(), similar.len());
+        }
+    }
+
+    let mut wide = HashIndex::<[u8; 32]>::default();
+    assert_eq!(None, wide.nearest(&[0; 32]));
+    wide.add([0; 32]);
+    wide.add([0xFF; 32]);
+    let mut needle = [0xFF; 32];
+    needle[31] = 0;
+    assert_eq!(Some((1, 8)), wide.nearest(&needle));
+}
+
+#[cfg(feature = "simd")]
+#[test]
+fn test_simd_kernels() {
+    use crate::metrics::simd;
+
+    let values: Vec<f64> = random_points(100, 17).iter().flat_map(|p| vec![p.0 as f64 - 50., p.1 as f64]).collect();
+    let values32: Vec<f32> = values.iter().map(|&v| v as f32).collect();
+    for len in (0..40).chain(Some(199)) {
+        let (a, b) = (&values[..len], &values[1..=len]);
+        let (a32, b32) = (&values32[..len], &values32[1..=len]);
+        let euclidean = a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum::<f64>().sqrt();
+        let manhattan = a.iter().zip(b).map(|(a, b)| (a - b).abs()).sum::<f64>();
+        assert!((simd::euclidean_f64(a, b) - euclidean).abs() < 1e-9);
+        assert!((simd::portable::squared_euclidean_f64(a, b).sqrt() - euclidean).abs() < 1e-9);
+        assert!((simd::portable::manhattan_f32(a32, b32) as f64 - manhattan).abs() < 1e-1);
+        assert!((simd::manhattan_f64(a, b) - manhattan).abs() < 1e-9);
+        assert!((simd::euclidean_f32(a32, b32) as f64 - euclidean).abs() < 1e-2);
+        assert!((simd::manhattan_f32(a32, b32) as f64 - manhattan).abs() < 1e-1);
+
+        let bytes_a: Vec<u8> = a32.iter().map(|&v| v as u8).collect();
+        let bytes_b: Vec<u8> = b32.iter().map(|&v| v as u8).collect();
+        let expected = bytes_a.iter().zip(&bytes_b).map(|(a, b)| (a ^ b).count_ones()).sum::<u32>();
+        assert_eq!(expected, simd::hamming(&bytes_a, &bytes_b));
+    }
+
+    let long: Vec<[f32; 32]> = (0..100).map(|i| {
+        let mut a = [0.; 32];
+        a.copy_from_slice(&values32[i..i + 32]);
+        a
+    }).collect();
+    let vp: Tree<[f32; 32], crate::metrics::Euclidean> = Tree::new(&long);
+    assert_eq!(33, vp.find_nearest(&long[33]).0);
+}
+
+#[test]
+fn test_levenshtein() {
+    use crate::metrics::{levenshtein, levenshtein_within, Levenshtein};
+
+    fn naive(a: &str, b: &str) -> u32 {
+        let b: Vec<char> = b.chars().collect();
+        let mut prev: Vec<u32> = (0..=b.len() as u32).collect();
+        for (i, ca) in a.chars().enumerate() {
+            let mut cur = vec![i as u32 + 1];
+            for (j, &cb) in b.iter().enumerate() {
+                cur.push((prev[j] + (ca != cb) as u32).min(prev[j + 1] + 1).min(cur[j] + 1));
+            }
+            prev = cur;
+        }
+        prev[b.len()]
+    }
+
+    let words = ["", "a", "kitten", "sitting", "saturday", "sunday", "rosettacode", "raisethysword",
+        "abcdefghijklmnopqrstuvwxyz", "zyxwvutsrqponmlkjihgfedcba", "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaab",
+        "łódź", "lodz", "żółć", "日本語", "日本", "flaw", "lawn", "intention", "execution"];
+    for a in words.iter() {
+        for b in words.iter() {
+            let expected = naive(a, b);
+            assert_eq!(expected, levenshtein(a, b), "{} {}", a, b);
+            for max in 0..8 {
+                assert_eq!(Some(expected).filter(|&d| d <= max), levenshtein_within(a, b, max), "{} {} {}", a, b, max);
+            }
+        }
+    }
+
+    let owned: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+    let vp: Tree<String, Levenshtein> = Tree::new(&owned);
+    assert_eq!(3, vp.find_nearest(&"sittin".to_string()).0);
+    let vp: Tree<&str, Levenshtein> = Tree::new(&words);
+    assert_eq!((5, 2), vp.find_nearest(&"monday"));
+    assert_eq!(vec![(12, 0), (11, 3), (17, 3)], vp.find_within_radius(&"lodz", 3));
+}
+
+#[test]
+fn test_fuzzy_string_index() {
+    use crate::metrics::levenshtein;
+    use crate::FuzzyStringIndex;
+
+    let mut seed = 1u32;
+    let words: Vec<String> = (0..2000).map(|_| {
+        seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+        let len = 2 + (seed >> 28) as usize;
+        (0..len).map(|i| (b'a' + ((seed >> (i * 3 % 24)) & 7) as u8) as char).collect()
+    }).collect();
+    let index = FuzzyStringIndex::new(words.iter().map(String::as_str));
+    assert_eq!(words.len(), index.len());
+
+    for query in ["abc", "hgfedcba", "a", "", "bbbbbbbbbbbbbbbbbbbbbb", "ceha"].iter() {
+        for max_edits in 0..4 {
+            let mut expected: Vec<(usize, u32)> = words.iter().enumerate()
+                .map(|(idx, word)| (idx, levenshtein(query, word)))
+                .filter(|&(_, edits)| edits <= max_edits)
+                .collect();
+            expected.sort_by_key(|&(idx, edits)| (edits, idx));
+            let mut found = index.suggest_indexes(query, max_edits, usize::MAX);
+            found.sort_by_key(|&(idx, edits)| (edits, idx));
+            assert_eq!(expected, found, "{} {}", query, max_edits);
+
+            let suggestions = index.suggest(query, max_edits, 5);
+            assert_eq!(expected.len().min(5), suggestions.len());
+            for (&(word, edits), &(_, expected_edits)) in suggestions.iter().zip(&expected) {
+                assert_eq!(expected_edits, edits);
+                assert_eq!(edits, levenshtein(query, word));
+            }
+        }
+    }
+    assert!(FuzzyStringIndex::new(Vec::<String>::new()).suggest("a", 1, 1).is_empty());
+}
+
+#[test]
+fn test_haversine() {
+    use crate::metrics::{haversine, Haversine};
+
+    let london = (51.5074, -0.1278);
+    let paris = (48.8566, 2.3522);
+    let new_york = (40.7128, -74.0060);
+    let sydney = (-33.8688, 151.2093);
+    assert!((haversine(london, paris) - 343_900.).abs() < 1000.);
+    assert!((haversine(london, new_york) - 5_570_000.).abs() < 10_000.);
+    assert_eq!(0., haversine(sydney, sydney));
+    // antipodes
+    assert!((haversine((0., 0.), (0., 180.)) - std::f64::consts::PI * crate::metrics::EARTH_RADIUS_METERS).abs() < 1e-6);
+
+    let cities = [london, paris, new_york, sydney];
+    let vp: Tree<(f64, f64), Haversine> = Tree::new(&cities);
+    assert_eq!(1, vp.find_nearest(&(50.85, 4.35)).0); // Brussels
+    // across the antimeridian
+    let vp: Tree<(f32, f32), Haversine> = Tree::new(&[(0., 179.9), (0., 170.), (0., -175.)]);
+    assert_eq!(0, vp.find_nearest(&(0., -179.9)).0);
+}
+
+#[test]
+#[cfg(feature = "geo")]
+fn test_geo() {
+    use crate::geo::{lat_lon, point_from_lat_lon};
+    use crate::metrics::{haversine, Haversine};
+    use geo_types::{coord, Coord, Point};
+
+    let london = Point::new(-0.1278, 51.5074);
+    let paris = Point::new(2.3522, 48.8566);
+    assert_eq!((51.5074, -0.1278), lat_lon(london));
+    assert_eq!(london, point_from_lat_lon(lat_lon(london)));
+    assert_eq!(haversine(lat_lon(london), lat_lon(paris)), MetricSpace::<Haversine>::distance(&london, &paris, &()));
+
+    let vp: Tree<Point, Haversine> = Tree::new(&[london, paris, Point::new(-74.0060, 40.7128)]);
+    assert_eq!(1, vp.find_nearest(&Point::new(4.35, 50.85)).0); // Brussels
+    // across the antimeridian
+    let vp: Tree<Coord<f32>, Haversine> = Tree::new(&[coord! { x: 179.9, y: 0. }, coord! { x: 170., y: 0. }, coord! { x: -175., y: 0. }]);
+    assert_eq!(0, vp.find_nearest(&coord! { x: -179.9, y: 0. }).0);
+
+    let vp: Tree<Point<f32>> = Tree::new(&[Point::new(0., 0.), Point::new(3., 4.), Point::new(10., 0.)]);
+    assert_eq!((1, 1.), vp.find_nearest(&Point::new(3., 5.)));
+    assert_eq!(5., MetricSpace::<()>::distance(&coord! { x: 0f64, y: 0. }, &coord! { x: 3., y: 4. }, &()));
+}
+
+#[test]
+fn test_angular() {
+    use crate::metrics::UnitVector;
+    use std::f64::consts::PI;
+
+    assert!(UnitVector::new(vec![0f32, 0.]).is_none());
+    assert!(UnitVector::new([1f64, f64::NAN]).is_none());
+
+    let a = UnitVector::new(vec![3f64, 0.]).unwrap();
+    assert_eq!(&[1., 0.], a.as_slice());
+    let b = UnitVector::new(vec![0f64, 0.5]).unwrap();
+    let c = UnitVector::new(vec![-2f64, 0.]).unwrap();
+    assert!((a.distance(&b, &()) - PI / 2.).abs() < 1e-12);
+    assert!((a.distance(&c, &()) - PI).abs() < 1e-12);
+    assert_eq!(0., a.distance(&a, &()));
+    // precise for nearly parallel vectors, where acos(dot) would round to 0
+    let d = UnitVector::new(vec![1f32, 1e-6]).unwrap();
+    let e = UnitVector::new(vec![1f32, 0.]).unwrap();
+    assert!((d.distance(&e, &()) - 1e-6).abs() < 1e-9);
+
+    let points = random_points(300, 19);
+    let vectors: Vec<_> = points.iter().filter_map(|p| UnitVector::new(Box::<[f32]>::from([p.0 - 50., p.1 - 50.]))).collect();
+    let vp = Tree::new(&vectors);
+    for needle in random_points(20, 20) {
+        let needle = UnitVector::new(Box::<[f32]>::from([needle.0 - 50., needle.1 - 50.])).unwrap();
+        let best = vectors.iter().map(|v| v.distance(&needle, &())).fold(f32::MAX, f32::min);
+        assert_eq!(best, vp.find_nearest(&needle).1);
+    }
+}
+
+#[cfg(feature = "color")]
+#[test]
+fn test_lab_color() {
+    use crate::metrics::Lab;
+
+    let close = |a: Lab, b: Lab| a.delta_e(&b) < 0.01;
+    assert!(close(Lab::new(100., 0., 0.), Lab::from_srgb(255, 255, 255)));
+    assert!(close(Lab::new(0., 0., 0.), Lab::from_srgb(0, 0, 0)));
+    assert!(close(Lab::new(53.2408, 80.0925, 67.2032), Lab::from_srgb(255, 0, 0)));
+    assert!(close(Lab::new(32.2970, 79.1875, -107.8602), rgb::RGB8::new(0, 0, 255).into()));
+    assert!(close(Lab::new(53.5850, 0., 0.), [128, 128, 128].into()));
+
+    let palette: Vec<Lab> = (0..=255u8).step_by(51).flat_map(|r| (0..=255u8).step_by(51).map(move |g| Lab::from_srgb(r, g, 100))).collect();
+    let vp = Tree::new(&palette);
+    for needle in [[10, 200, 90], [250, 5, 130], [128, 128, 128]].iter() {
+        let needle = Lab::from(*needle);
+        let best = palette.iter().map(|c| c.delta_e(&needle)).fold(f32::MAX, f32::min);
+        assert_eq!(best, vp.find_nearest(&needle).1);
+    }
+}
+
+#[test]
+fn test_xor() {
+    use crate::metrics::{Xor, XorDistance};
+
+    let ids: Vec<[u8; 20]> = (0..300u32).map(|i| {
+        let mut id = [0; 20];
+        for (j, b) in id.iter_mut().enumerate() {
+            *b = (i.wrapping_mul(2654435761).rotate_left(j as u32) >> 5) as u8;
+        }
+        id
+    }).collect();
+    let vp: Tree<[u8; 20], Xor> = Tree::new(&ids);
+    for needle in ids.iter().step_by(17) {
+        let mut needle = *needle;
+        needle[19] ^= 0x55;
+        needle[3] ^= 1;
+        let best = ids.iter().map(|id| MetricSpace::<Xor>::distance(id, &needle, &())).min().unwrap();
+        assert_eq!(best, vp.find_nearest(&needle).1);
+        let expected: Vec<_> = {
+            let mut all: Vec<_> = ids.iter().map(|id| MetricSpace::<Xor>::distance(id, &needle, &())).collect();
+            all.sort();
+            all.truncate(5);
+            all
+        };
+        assert_eq!(expected, vp.find_k_nearest(&needle, 5).into_iter().map(|(_, d)| d).collect::<Vec<_>>());
+    }
+
+    let max = XorDistance([0xFF; 4]);
+    assert_eq!(max, max + XorDistance([0, 0, 0, 1]));
+    assert_eq!(XorDistance([1, 0, 0, 0]), XorDistance([0, 0xFF, 0xFF, 0xFF]) + XorDistance([0, 0, 0, 1]));
+    assert_eq!(7, XorDistance([0, 1, 0]).leading_zeros() - 8);
+    assert_eq!(24, XorDistance([0, 0, 0]).leading_zeros());
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_derive() {
+    #[derive(Clone, crate::MetricSpace)]
+    struct Weighted {
+        x: f32,
+        #[vpsearch(weight = 4.0)]
+        y: u8,
+        #[vpsearch(skip)]
+        _name: &'static str,
+    }
+
+    #[derive(Clone, crate::MetricSpace)]
+    struct Tuple(f64, i32);
+
+    assert_eq!(5., Weighted { x: 0., y: 0, _name: "a" }.distance(&Weighted { x: 3., y: 2, _name: "b" }, &()));
+    assert_eq!(5f64, Tuple(1., 1).distance(&Tuple(4., 5), &()));
+
+    let points: Vec<_> = random_points(200, 19).iter().map(|p| Tuple(p.0 as f64, p.1 as i32)).collect();
+    let vp = Tree::new(&points);
+    let (idx, dist) = vp.find_nearest(&Tuple(50., 50));
+    assert_eq!(dist, points.iter().map(|p| p.distance(&Tuple(50., 50), &())).fold(f64::MAX, f64::min));
+    assert_eq!(dist, points[idx].distance(&Tuple(50., 50), &()));
+}
+
+#[cfg(feature = "derive")]
+#[test]
+fn test_derive_distance() {
+    #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, crate::Distance)]
+    struct Meters(f32);
+
+    #[derive(Debug, Copy, Clone, PartialEq, PartialOrd, crate::Distance)]
+    struct Hops<T> { count: T }
+
+    #[derive(Clone)]
+    struct Located(Point2);
+    impl MetricSpace for Located {
+        type UserData = ();
+        type Distance = Meters;
+        fn distance(&self, other: &Self, _: &()) -> Meters {
+            Meters(self.0.distance(&other.0, &()))
+        }
+    }
+
+    assert_eq!(Meters(f32::MAX), <Meters as num_traits::Bounded>::max_value());
+    assert_eq!(Hops { count: u8::MAX }, DistanceAdd::saturating_add(Hops { count: 200u8 }, Hops { count: 100 }));
+    assert_eq!(Some(3.), num_traits::ToPrimitive::to_f64(&Hops { count: 3u32 }));
+
+    let points = random_points(300, 42);
+    let located: Vec<_> = points.iter().copied().map(Located).collect();
+    let vp = Tree::new(&located);
+    let plain = Tree::new(&points);
+    for needle in random_points(10, 43) {
+        let found = vp.find_k_nearest(&Located(needle), 3);
+        let expected = plain.find_k_nearest(&needle, 3);
+        assert_eq!(expected, found.iter().map(|&(idx, Meters(d))| (idx, d)).collect::<Vec<_>>());
+    }
+    assert!(vp.neighbor_stats().is_some());
+}
+
+#[test]
+fn test_cached_metric() {
+    use crate::metrics::{CachedMetric, DistanceCache};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone)]
+    struct Counted(Point2);
+    impl MetricSpace for Counted {
+        type UserData = AtomicUsize;
+        type Distance = f32;
+        fn distance(&self, other: &Self, calls: &AtomicUsize) -> f32 {
+            calls.fetch_add(1, Ordering::Relaxed);
+            self.0.distance(&other.0, &())
+        }
+    }
+
+    let points = random_points(200, 53);
+    let items = CachedMetric::wrap_all(points.iter().copied().map(Counted));
+    let vp = Tree::new_with_user_data_owned(&items, DistanceCache::new(AtomicUsize::new(0)));
+    assert_eq!(vp.user_data().len(), vp.user_data().user_data().load(Ordering::Relaxed));
+    assert_eq!(vp.user_data().len() as u64, vp.user_data().stats().misses);
+
+    let self_join = |vp: &Tree<CachedMetric<Counted>, (), Owned<DistanceCache<AtomicUsize, f32>>>| {
+        items.iter().map(|item| vp.find_k_nearest(item, 3)).collect::<Vec<_>>()
+    };
+    let first = self_join(&vp);
+    let calls = vp.user_data().user_data().load(Ordering::Relaxed);
+    assert_eq!(self_join(&vp), first);
+    assert_eq!(calls, vp.user_data().user_data().load(Ordering::Relaxed));
+    for (found, point) in first.iter().zip(&points) {
+        assert_eq!(found[0].1, 0.);
+        assert_eq!(found[1].1, brute_force(&points, point)[1].1);
+    }
+
+    vp.find_nearest(&CachedMetric::needle(Counted(Point2(1., 2.))));
+    assert!(vp.user_data().user_data().load(Ordering::Relaxed) > calls);
+    vp.user_data().clear();
+    assert!(vp.user_data().is_empty());
+    assert_eq!(0, vp.user_data().stats().hits);
+
+    // Distances computed by the first tree are looked up by the second one, instead of computing them
+    let known: std::collections::HashMap<_, _> = (0..items.len() as u32)
+        .flat_map(|a| (a + 1..items.len() as u32).map(move |b| (a, b)))
+        .filter(|&(a, b)| (a + b) % 2 == 0)
+        .map(|(a, b)| ((a, b), points[a as usize].distance(&points[b as usize], &())))
+        .collect();
+    let vp2 = Tree::new_with_user_data_owned(&items, DistanceCache::with_lookup(AtomicUsize::new(0), move |a, b| known.get(&(a, b)).copied()));
+    let stats = vp2.user_data().stats();
+    assert!(stats.hits > 0 && stats.misses > 0);
+    assert_eq!(stats.misses, vp2.user_data().user_data().load(Ordering::Relaxed) as u64);
+    assert_eq!(self_join(&vp2), first);
+}
+
+#[test]
+fn test_k_nearest_batch() {
+    let points = random_points(1000, 54);
+    let vp = Tree::new(&points);
+    // Needles close to each other, like a point moving between frames
+    let needles: Vec<_> = random_points(20, 55).into_iter().map(|p| Point2(50. + p.0 / 20., 50. + p.1 / 20.)).collect();
+    let batch = vp.find_k_nearest_batch(&needles, 5);
+    assert_eq!(batch.len(), needles.len());
+    for (found, needle) in batch.iter().zip(&needles) {
+        assert_eq!(found, &vp.find_k_nearest(needle, 5));
+    }
+
+    let radius = vp.find_nearest_custom_batch(&needles[..3], &(), (0..3).map(|_| WithinRadius::new(3.)).collect());
+    for (found, needle) in radius.iter().zip(&needles) {
+        assert_eq!(found, &vp.find_within_radius(needle, 3.));
+    }
+    assert!(vp.find_k_nearest_batch(&[] as &[Point2], 5).is_empty());
+}
+
+#[test]
+fn test_tree_with_values() {
+    let points = random_points(200, 56);
+    let names: Vec<String> = (0..points.len()).map(|i| format!("point {}", i)).collect();
+    let vp = Tree::new_with_values(points.iter().copied().zip(names.iter().cloned()));
+    assert_eq!(vp.len(), points.len());
+    for needle in random_points(20, 57) {
+        let (idx, distance) = vp.tree().find_nearest(&needle);
+        assert_eq!(vp.find_nearest(&needle), Some((&names[idx], distance)));
+        let expected: Vec<_> = vp.tree().find_k_nearest(&needle, 4).into_iter().map(|(idx, d)| (&names[idx], d)).collect();
+        assert_eq!(vp.find_k_nearest(&needle, 4), expected);
+        assert_eq!(vp.find_within_radius(&needle, 5.).len(), vp.tree().find_within_radius(&needle, 5.).len());
+    }
+
+    let empty = Tree::<Point2>::new_with_values(Vec::<(Point2, u8)>::new());
+    assert_eq!(empty.find_nearest(&Point2(0., 0.)), None);
+
+    let owned = Tree::new_with_user_data_owned(&points[..3], ()).with_values(vec!['a', 'b', 'c']);
+    assert_eq!(owned.find_nearest_bounded(&points[1], 0.), Some((&'b', 0.)));
+}
+
+#[test]
+fn test_vp_map() {
+    let points = random_points(300, 58);
+    let map: VpMap<Point2, usize> = points.iter().copied().zip(0..).collect();
+    assert_eq!(map.len(), points.len());
+    let mut seen: Vec<_> = map.iter().map(|(key, &value)| {
+        assert_eq!(key, &points[value]);
+        value
+    }).collect();
+    seen.sort_unstable();
+    assert!(seen.iter().copied().eq(0..points.len()));
+
+    for needle in random_points(20, 59) {
+        let expected = brute_force(&points, &needle);
+        let (key, &value, distance) = map.nearest(&needle).unwrap();
+        assert_eq!((key, distance), (&points[value], expected[0].1));
+        let k: Vec<_> = map.k_nearest(&needle, 5).into_iter().map(|(_, _, d)| d).collect();
+        assert_eq!(k, expected[..5].iter().map(|e| e.1).collect::<Vec<_>>());
+        let radius = map.within_radius(&needle, 8.);
+        assert_eq!(radius.len(), expected.iter().filter(|e| e.1 <= 8.).count());
+        assert!(radius.windows(2).all(|w| w[0].2 <= w[1].2));
+    }
+
+    let mut builder = VpMap::builder();
+    builder.insert(Point2(1., 1.), "a");
+    builder.insert(Point2(1., 1.), "b");
+    let map: VpMap<_, _> = builder.build();
+    assert_eq!(map.k_nearest(&Point2(0., 0.), 5).len(), 2);
+    assert!(VpMap::<Point2, ()>::builder().build::<()>().nearest(&Point2(0., 0.)).is_none());
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&map).unwrap();
+        let map2: VpMap<Point2, String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(map2.values(), ["a", "b"]);
+        // A value is missing, so `nearest()` couldn't find it
+        assert_corrupted_fails(&map2, |json| { json["tree"]["values"].as_array_mut().unwrap().pop(); });
+    }
+}
+
+#[test]
+fn test_tagged_tree() {
+    let points = random_points(400, 60);
+    let tags: Vec<u8> = (0..points.len()).map(|i| (i % 3) as u8).collect();
+    let vp = Tree::new(&points).with_tags(tags.clone());
+    for needle in random_points(20, 61) {
+        let expected: Vec<_> = brute_force(&points, &needle).into_iter().filter(|&(i, _)| tags[i] == 2).collect();
+        assert_eq!(vp.find_nearest_tagged(&needle, |tag| tag == 2).map(|f| f.1), Some(expected[0].1));
+        let k = vp.find_k_nearest_tagged(&needle, 6, |tag| tag == 2);
+        assert!(k.iter().all(|&(i, _)| vp.tag(i) == 2));
+        assert_eq!(k.iter().map(|f| f.1).collect::<Vec<_>>(), expected[..6].iter().map(|f| f.1).collect::<Vec<_>>());
+        let radius = vp.find_within_radius_tagged(&needle, 10., |tag| tag != 2);
+        assert_eq!(radius.len(), brute_force(&points, &needle).iter().filter(|&&(i, d)| tags[i] != 2 && d <= 10.).count());
+    }
+    assert_eq!(vp.find_nearest_tagged(&points[0], |tag| tag > 2), None);
+}
+
+#[test]
+fn test_composite_metric() {
+    use crate::metrics::{CompositeMetric, Euclidean, Manhattan, Weights};
+
+    let items: Vec<_> = random_points(100, 62).into_iter().enumerate()
+        .map(|(i, p)| CompositeMetric::new([p.0, p.1], [(i % 7) as f32]))
+        .collect();
+    let weights = Weights::new(1., 1000.);
+    let vp: Tree<_, (Euclidean, Manhattan), _> = Tree::new_with_user_data_owned(&items, weights);
+    let needle = CompositeMetric::new([20., 30.], [3.]);
+    let (idx, distance) = vp.find_nearest(&needle);
+    assert_eq!(items[idx].second(), &[3.]);
+    let expected = items.iter().map(|item| MetricSpace::<(Euclidean, Manhattan)>::distance(item, &needle, vp.user_data())).fold(f32::MAX, f32::min);
+    assert_eq!(distance, expected);
+}
+
+#[test]
+fn test_map_metric() {
+    use crate::metrics::{Euclidean, MapMetric, Projection};
+
+    #[derive(Clone)]
+    struct Record {
+        id: usize,
+        position: [f32; 2],
+    }
+
+    let points = random_points(200, 63);
+    let records = MapMetric::wrap_all(points.iter().enumerate().map(|(id, p)| Record { id, position: [p.0, p.1] }));
+    let vp: Tree<_, Euclidean, _> = Tree::new_with_user_data_owned(&records, Projection::new(|r: &Record| &r.position[..]));
+    for needle in random_points(10, 64) {
+        let (idx, distance) = vp.find_nearest(&MapMetric::new(Record { id: 0, position: [needle.0, needle.1] }));
+        let expected = brute_force(&points, &needle)[0];
+        assert_eq!((records[idx].get().id, distance), expected);
+    }
+}
+
+#[test]
+fn test_minimum_spanning_tree() {
+    let points = random_points(300, 65);
+    let vp = Tree::new(&points);
+    let edges = vp.minimum_spanning_tree();
+    assert_eq!(edges.len(), points.len() - 1);
+    assert!(edges.windows(2).all(|w| w[0].2 <= w[1].2));
+    for &(a, b, distance) in &edges {
+        assert_eq!(distance, points[a].distance(&points[b], &()));
+    }
+
+    // Prim's algorithm
+    let mut in_tree = vec![false; points.len()];
+    let mut nearest = vec![f32::MAX; points.len()];
+    nearest[0] = 0.;
+    let mut expected = 0.;
+    for _ in 0..points.len() {
+        let next = (0..points.len()).filter(|&i| !in_tree[i]).min_by(|&a, &b| nearest[a].partial_cmp(&nearest[b]).unwrap()).unwrap();
+        in_tree[next] = true;
+        expected += nearest[next];
+        for i in 0..points.len() {
+            nearest[i] = nearest[i].min(points[next].distance(&points[i], &()));
+        }
+    }
+    let total: f32 = edges.iter().map(|e| e.2).sum();
+    assert!((total - expected).abs() < 0.01, "{} {}", total, expected);
+
+    assert!(Tree::new(&[Point2(1., 1.)]).minimum_spanning_tree().is_empty());
+    assert!(Tree::<Point2>::new(&[]).minimum_spanning_tree().is_empty());
+}
+
+#[test]
+fn test_representative_sample() {
+    let points = random_points(500, 66);
+    let vp = Tree::new(&points);
+    let sample = vp.representative_sample(20);
+    assert_eq!(sample.len(), 20);
+    let mut unique = sample.clone();
+    unique.sort_unstable();
+    unique.dedup();
+    assert_eq!(unique.len(), 20);
+
+    // Farthest-first is within 2x of the optimal covering radius, so it's much better than the first 20 random points
+    let covering_radius = |chosen: &[usize]| points.iter()
+        .map(|p| chosen.iter().map(|&c| p.distance(&points[c], &())).fold(f32::MAX, f32::min))
+        .fold(0., f32::max);
+    let arbitrary: Vec<_> = (0..20).collect();
+    assert!(covering_radius(&sample) < covering_radius(&arbitrary));
+
+    // Same choices as the simple farthest-first traversal that checks all items every time
+    let mut naive = vec![sample[0]];
+    while naive.len() < 20 {
+        let coverage = |i: usize| naive.iter().map(|&c| points[i].distance(&points[c], &())).fold(f32::MAX, f32::min);
+        let next = (0..points.len()).filter(|i| !naive.contains(i))
+            .fold(None, |best: Option<(usize, f32)>, i| if best.map_or(true, |(_, d)| coverage(i) > d) { Some((i, coverage(i))) } else { best });
+        naive.push(next.unwrap().0);
+    }
+    assert_eq!(naive, sample);
+
+    assert_eq!(vp.representative_sample(600).len(), 500);
+    assert!(Tree::<Point2>::new(&[]).representative_sample(3).is_empty());
+}
+
+#[test]
+fn test_neighbor_stats() {
+    let points = random_points(2000, 67);
+    let vp = Tree::new(&points);
+    let distances = vp.nearest_neighbor_distances();
+    for (i, p) in points.iter().enumerate().step_by(97) {
+        assert_eq!(distances[i], brute_force(&points, p)[1].1);
+    }
+
+    let stats = vp.neighbor_stats().unwrap();
+    assert!(stats.min <= stats.median && stats.median <= stats.max);
+    assert!((stats.mean - distances.iter().map(|&d| d as f64).sum::<f64>() / 2000.).abs() < 1e-6);
+    // The points are on a plane
+    assert!(stats.intrinsic_dimension > 1.5 && stats.intrinsic_dimension < 2.5, "{:?}", stats);
+
+    let line: Vec<_> = random_points(500, 68).into_iter().map(|p| Point2(p.0, p.0 * 2.)).collect();
+    let dimension = Tree::new(&line).neighbor_stats().unwrap().intrinsic_dimension;
+    assert!(dimension < 1.5, "{}", dimension);
+    assert_eq!(Tree::new(&[Point2(0., 0.)]).neighbor_stats(), None);
+}
+
+#[test]
+fn test_attribute_tree() {
+    let points = random_points(1000, 69);
+    let timestamps: Vec<u32> = (0..points.len() as u32).map(|i| (i * 7919) % 1000).collect();
+    let vp = Tree::new(&points).with_attribute(timestamps.clone());
+    for needle in random_points(20, 70) {
+        let all = brute_force(&points, &needle);
+        let expected: Vec<_> = all.iter().copied().filter(|&(i, _)| (100..200).contains(&timestamps[i])).collect();
+        assert_eq!(vp.find_nearest_in_range(&needle, 100..200), Some(expected[0]));
+        let k: Vec<_> = vp.find_k_nearest_in_range(&needle, 5, 100..200).into_iter().map(|f| f.1).collect();
+        assert_eq!(k, expected[..5].iter().map(|f| f.1).collect::<Vec<_>>());
+        let radius = vp.find_within_radius_in_range(&needle, 20., 900..);
+        assert_eq!(radius.len(), all.iter().filter(|&&(i, d)| timestamps[i] >= 900 && d <= 20.).count());
+        assert!(radius.iter().all(|&(i, _)| vp.attribute(i) >= 900));
+    }
+    assert_eq!(vp.find_nearest_in_range(&points[0], 1000..), None);
+}
+
+#[test]
+fn test_self_knn() {
+    let points = random_points(3000, 71);
+    let vp = Tree::new(&points);
+    let mut reports = Vec::new();
+    let neighbors = vp.self_knn(3, |done, total| {
+        reports.push((done, total));
+        true
+    }).unwrap();
+    assert_eq!(neighbors.len(), points.len());
+    for (i, found) in neighbors.iter().enumerate().step_by(101) {
+        assert!(found.iter().all(|&(j, _)| j != i));
+        let expected: Vec<_> = brute_force(&points, &points[i])[1..4].iter().map(|f| f.1).collect();
+        assert_eq!(found.iter().map(|f| f.1).collect::<Vec<_>>(), expected);
+    }
+    assert_eq!(reports.iter().map(|r| r.0).max(), Some(3000));
+    assert!(reports.iter().all(|r| r.1 == 3000));
+
+    assert_eq!(vp.self_knn(3, |_, _| false), None);
+}
+
+#[test]
+fn test_self_join_each() {
+    let points = random_points(2500, 72);
+    let vp = Tree::new(&points);
+    let mut pairs = Vec::new();
+    assert!(vp.self_join_each(1.5, |a, b, distance| {
+        pairs.push((a, b, distance));
+        true
+    }));
+    pairs.sort_by_key(|&(a, b, _)| (a, b));
+    let mut expected = Vec::new();
+    for (a, p) in points.iter().enumerate() {
+        for (b, q) in points.iter().enumerate().skip(a + 1) {
+            let distance = p.distance(q, &());
+            if distance <= 1.5 {
+                expected.push((a, b, distance));
+            }
+        }
+    }
+    assert_eq!(pairs, expected);
+
+    let mut seen = 0;
+    assert!(!vp.self_join_each(1.5, |_, _, _| {
+        seen += 1;
+        seen < 10
+    }));
+    assert_eq!(seen, 10);
+
+    let all = vp.self_knn(2, |_, _| true).unwrap();
+    let mut count = 0;
+    assert!(!vp.self_knn_each(2, |idx, found| {
+        assert_eq!(found, all[idx]);
+        count += 1;
+        count < 5
+    }));
+}
+
+#[test]
+fn test_assign() {
+    let centroids = random_points(50, 3);
+    let vp = Tree::new(&centroids);
+    let points = random_points(5000, 4);
+    let assigned = vp.assign(&points);
+    assert_eq!(points.len(), assigned.len());
+    for (point, &centroid) in points.iter().zip(&assigned) {
+        assert_eq!(vp.find_nearest(point).1, point.distance(&centroids[centroid], &()));
+    }
+    assert!(vp.assign(&[] as &[Point2]).is_empty());
+}
+
+#[test]
+fn test_tree_diff() {
+    let points = random_points(300, 73);
+    let vp = Tree::new(&points);
+    assert!(vp.diff(&Tree::new(&points)).is_identical());
+
+    let mut changed = points.clone();
+    changed[10] = Point2(1., 1.);
+    changed.push(Point2(2., 2.));
+    let diff = vp.diff(&Tree::new(&changed));
+    assert_eq!(diff.added, [300]);
+    assert!(diff.removed.is_empty());
+    assert_eq!(diff.changed, [10]);
+    assert!(diff.structure_drift > 0. && diff.structure_drift <= 1.);
+
+    let diff = Tree::new(&changed).diff(&vp);
+    assert_eq!((diff.added.len(), diff.removed.len()), (0, 1));
+    assert!(Tree::<Point2>::new(&[]).diff(&Tree::new(&[])).is_identical());
+}
+
+#[test]
+fn test_new_shuffled() {
+    let mut points = random_points(1000, 5);
+    points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+    let vp = Tree::new_shuffled(&points, 1);
+    assert_eq!(points.len(), vp.len());
+    for (idx, point) in points.iter().enumerate().step_by(13) {
+        assert_eq!((idx, 0.), vp.find_nearest(point));
+        let needle = Point2(point.0 + 0.3, point.1 - 0.2);
+        let expected = points.iter().map(|p| p.distance(&needle, &())).fold(f32::MAX, f32::min);
+        assert_eq!(expected, vp.find_nearest(&needle).1);
+    }
+    assert!(vp.diff(&Tree::new_shuffled(&points, 1)).is_identical());
+    assert!(vp.diff(&Tree::new_shuffled(&points, 2)).structure_drift > 0.);
+    assert!(Tree::<Point2>::new_shuffled(&[], 1).is_empty());
+}
+
+#[test]
+fn test_pruning_rule() {
+    let points = random_points(3000, 8);
+    let vp = Tree::new(&points);
+    let needle = Point2(0.5, 0.5);
+    let exact = vp.find_k_nearest(&needle, 10);
+
+    let mut calls = 0;
+    let all = vp.find_nearest_custom_pruned(&needle, &(), KNearest::new(10), |_: f32, _: f32, _: f32, _: bool| {
+        calls += 1;
+        true
+    });
+    assert_eq!(exact, all);
+    assert!(calls > 0);
+
+    // Only the root is checked
+    let none = vp.find_nearest_custom_pruned(&needle, &(), KNearest::new(10), |_: f32, _: f32, _: f32, _: bool| false);
+    assert_eq!(1, none.len());
+
+    // Ignoring the bound makes it a greedy descent to a single leaf
+    let greedy = vp.find_nearest_custom_pruned(&needle, &(), KNearest::new(10), |distance: f32, radius: f32, _: f32, far: bool| (distance < radius) != far);
+    assert!(greedy.len() < 20);
+    assert!(greedy[0].1 >= exact[0].1);
+}
+
+#[test]
+fn test_budgeted_search() {
+    use std::cell::Cell;
+
+    #[derive(Clone)]
+    struct Counted(f32, f32);
+    impl MetricSpace for Counted {
+        type UserData = Cell<usize>;
+        type Distance = f32;
+        fn distance(&self, other: &Self, calls: &Cell<usize>) -> f32 {
+            calls.set(calls.get() + 1);
+            Point2(self.0, self.1).distance(&Point2(other.0, other.1), &())
+        }
+    }
+
+    let points: Vec<_> = random_points(2000, 9).into_iter().map(|p| Counted(p.0, p.1)).collect();
+    let vp = Tree::new_with_user_data_owned(&points, Cell::new(0));
+    let needle = Counted(0.25, 0.75);
+    vp.user_data().set(0);
+    let exact = vp.find_k_nearest(&needle, 5);
+    let full_cost = vp.user_data().get();
+
+    for budget in [0, 1, 10, 50, full_cost - 1, full_cost, full_cost * 2].iter().copied() {
+        vp.user_data().set(0);
+        let (found, is_exact) = vp.find_k_nearest_budgeted(&needle, 5, budget);
+        assert!(vp.user_data().get() <= budget);
+        assert_eq!(is_exact, budget >= full_cost, "{}", budget);
+        if is_exact {
+            assert_eq!(exact, found);
+        } else if let (Some(best), Some(approx)) = (exact.first(), found.first()) {
+            assert!(approx.1 >= best.1);
+        }
+    }
+    assert!(vp.find_k_nearest_budgeted(&needle, 5, 0).0.is_empty());
+}
+
+/// Polls the future in a loop, for async functions that don't need a runtime. Returns the output and the number of polls.
+fn block_on<F: std::future::Future>(future: F) -> (F::Output, usize) {
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+
+    struct NoopWaker;
+    impl Wake for NoopWaker {
+        fn wake(self: Arc<Self>) {}
+    }
+    let waker = Waker::from(Arc::new(NoopWaker));
+    let mut cx = Context::from_waker(&waker);
+    let mut future = Box::pin(future);
+    let mut polls = 0;
+    loop {
+        polls += 1;
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return (output, polls);
+        }
+    }
+}
+
+#[test]
+fn test_async_search() {
+    use crate::AsyncMetricSpace;
+    use std::cell::Cell;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// Pending on the first poll, like a real async computation
+    struct Later(Option<f32>, bool);
+    impl Future for Later {
+        type Output = f32;
+        fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<f32> {
+            if !self.1 {
+                self.1 = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Poll::Ready(self.0.take().unwrap())
+        }
+    }
+
+    #[derive(Clone)]
+    struct Remote(f32, f32);
+    impl MetricSpace for Remote {
+        type UserData = Cell<usize>;
+        type Distance = f32;
+        fn distance(&self, other: &Self, calls: &Cell<usize>) -> f32 {
+            calls.set(calls.get() + 1);
+            Point2(self.0, self.1).distance(&Point2(other.0, other.1), &())
+        }
+    }
+    impl AsyncMetricSpace for Remote {
+        fn distance_async(&self, other: &Self, calls: &Cell<usize>) -> impl Future<Output = f32> {
+            Later(Some(self.distance(other, calls)), false)
+        }
+    }
+
+    let points: Vec<_> = random_points(2000, 11).into_iter().map(|p| Remote(p.0, p.1)).collect();
+    let vp = Tree::new_with_user_data_owned(&points, Cell::new(0));
+    for needle in [Remote(0.1, 0.2), Remote(5., 5.), Remote(-3., 1.)].iter() {
+        vp.user_data().set(0);
+        let expected = vp.find_k_nearest(needle, 7);
+        let sync_calls = vp.user_data().get();
+        for &max_in_flight in [0, 1, 4, 64].iter() {
+            vp.user_data().set(0);
+            assert_eq!(expected, block_on(vp.find_k_nearest_async(needle, 7, max_in_flight)).0);
+            if max_in_flight <= 1 {
+                assert_eq!(sync_calls, vp.user_data().get());
+            }
+        }
+    }
+    let empty = Tree::new_with_user_data_owned(&[] as &[Remote], Cell::new(0));
+    assert!(block_on(empty.find_k_nearest_async(&Remote(0., 0.), 3, 8)).0.is_empty());
+}
+
+#[test]
+fn test_memory_size() {
+    let points = random_points(1000, 74);
+    let vp = Tree::new(&points);
+    let size = vp.memory_size();
+    assert!(size >= 1000 * std::mem::size_of::<Point2>());
+    assert!(size < 1000 * 4 * std::mem::size_of::<Point2>(), "{}", size);
+    assert!(Tree::new(&points[..10]).memory_size() < size);
+}
+
+#[test]
+fn test_trees_share_items() {
+    use crate::metrics::{Euclidean, Manhattan};
+    use std::sync::Arc;
+
+    let items: Vec<Arc<[f32; 2]>> = random_points(200, 75).into_iter().map(|p| Arc::new([p.0, p.1])).collect();
+    let euclidean: Tree<_, Euclidean> = Tree::new(&items);
+    let manhattan: Tree<_, Manhattan> = Tree::new(&items[..100]);
+    // Each tree holds a reference, not a copy
+    assert_eq!(Arc::strong_count(&items[0]), 3);
+    assert_eq!(Arc::strong_count(&items[150]), 2);
+
+    let needle = [20f32, 30.];
+    let (idx, _) = euclidean.find_nearest(&needle);
+    let expected = brute_force(&items.iter().map(|p| Point2(p[0], p[1])).collect::<Vec<_>>(), &Point2(20., 30.))[0].0;
+    assert_eq!(idx, expected);
+    assert!(manhattan.find_nearest(&needle).0 < 100);
+}
+
+#[test]
+fn test_k_nearest_distinct() {
+    let points = random_points(600, 76);
+    // Every point is a chunk of one of 40 documents
+    let document = |idx: usize| idx % 40;
+    let vp = Tree::new(&points);
+    for needle in random_points(20, 77) {
+        let mut expected = Vec::new();
+        for (idx, distance) in brute_force(&points, &needle) {
+            if !expected.iter().any(|&(i, _)| document(i) == document(idx)) {
+                expected.push((idx, distance));
+            }
+        }
+        let found = vp.find_nearest_custom(&needle, &(), KNearestDistinct::new(8, document));
+        assert_eq!(found.iter().map(|f| f.1).collect::<Vec<_>>(), expected[..8].iter().map(|f| f.1).collect::<Vec<_>>());
+        let mut documents: Vec<_> = found.iter().map(|&(i, _)| document(i)).collect();
+        documents.sort_unstable();
+        documents.dedup();
+        assert_eq!(documents.len(), 8);
+    }
+    assert_eq!(vp.find_nearest_custom(&points[0], &(), KNearestDistinct::new(100, |_| ())).len(), 1);
+}
+
+#[test]
+fn test_k_nearest_scored() {
+    let points = random_points(3000, 12);
+    // Older items are less relevant
+    let age = |idx: usize| (idx % 17) as f32 * 0.05;
+    let vp = Tree::new(&points);
+    let needle = Point2(1., -1.);
+
+    let mut expected: Vec<(usize, f32)> = points.iter().enumerate().map(|(idx, p)| (idx, p.distance(&needle, &()) + age(idx))).collect();
+    expected.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    expected.truncate(10);
+
+    let mut scored = 0;
+    let found = vp.find_nearest_custom(&needle, &(), KNearestScored::new(10, |idx, _: &Point2, distance| {
+        scored += 1;
+        distance + age(idx)
+    }));
+    assert_eq!(expected, found);
+    // It still prunes using the distance
+    assert!(scored < points.len() / 2);
+
+    let within = vp.find_nearest_custom(&needle, &(), KNearestScored::with_max_score(100, expected[3].1, |idx, _: &Point2, distance| distance + age(idx)));
+    assert_eq!(&expected[..4], &within[..]);
+}
+
+#[test]
+fn test_search_into() {
+    let points = random_points(1000, 13);
+    let vp = Tree::new(&points);
+    let mut results = Vec::new();
+    for needle in points.iter().step_by(50) {
+        vp.find_k_nearest_into(needle, 5, &mut results);
+        assert_eq!(vp.find_k_nearest(needle, 5), results);
+        let capacity = results.capacity();
+        vp.find_within_radius_into(needle, 0.5, &mut results);
+        assert_eq!(vp.find_within_radius(needle, 0.5), results);
+        vp.find_k_nearest_into(needle, 3, &mut results);
+        assert_eq!(3, results.len());
+        assert!(results.capacity() >= capacity);
+    }
+
+    let mut context = vp.query_context();
+    for needle in points.iter().step_by(50) {
+        assert_eq!(vp.find_k_nearest(needle, 5), context.find_k_nearest(&vp, needle, 5));
+        assert_eq!(vp.find_within_radius(needle, 3.), context.find_within_radius(&vp, needle, 3.));
+    }
+}
+
+#[test]
+fn test_within_radius_superset() {
+    use std::cell::Cell;
+
+    #[derive(Clone)]
+    struct Counted(f32, f32);
+    impl MetricSpace for Counted {
+        type UserData = Cell<usize>;
+        type Distance = f32;
+        fn distance(&self, other: &Self, calls: &Cell<usize>) -> f32 {
+            calls.set(calls.get() + 1);
+            Point2(self.0, self.1).distance(&Point2(other.0, other.1), &())
+        }
+    }
+
+    let points: Vec<_> = random_points(3000, 14).into_iter().map(|p| Counted(p.0, p.1)).collect();
+    let vp = Tree::new_with_user_data_owned(&points, Cell::new(0));
+    let distance = |a: &Counted, b: &Counted| Point2(a.0, a.1).distance(&Point2(b.0, b.1), &());
+    for needle in [Counted(50., 50.), Counted(20., 70.), Counted(90., 10.)].iter() {
+        vp.user_data().set(0);
+        let exact: Vec<usize> = vp.find_within_radius(needle, 15.).into_iter().map(|(idx, _)| idx).collect();
+        let exact_calls = vp.user_data().get();
+
+        let mut tight = vp.find_within_radius_superset(needle, 15., 0.);
+        tight.sort_unstable();
+        let mut expected = exact.clone();
+        expected.sort_unstable();
+        assert_eq!(expected, tight);
+
+        vp.user_data().set(0);
+        let loose = vp.find_within_radius_superset(needle, 15., 10.);
+        assert!(vp.user_data().get() < exact_calls);
+        assert!(exact.iter().all(|idx| loose.contains(idx)));
+        assert!(loose.iter().all(|&idx| distance(needle, &points[idx]) <= 25.));
+    }
+}
+
+#[test]
+fn test_counted_tree() {
+    let points = random_points(3000, 15);
+    let vp = Tree::new(&points).with_counts();
+    assert!(Tree::<Point2>::new(&[]).with_counts().sample_within_radius(&Point2(0., 0.), 1., 5, 0).is_empty());
+
+    for (needle, radius) in [(Point2(50., 50.), 20.), (Point2(10., 90.), 5.), (Point2(50., 50.), 200.), (Point2(-50., 0.), 10.)].iter() {
+        let within: Vec<usize> = vp.tree().find_within_radius(needle, *radius).into_iter().map(|(idx, _)| idx).collect();
+        assert_eq!(within.len(), vp.count_within_radius(needle, *radius));
+
+        for &size in [0, 1, 10, within.len(), within.len() + 5].iter() {
+            let mut sample = vp.sample_within_radius(needle, *radius, size, 7);
+            assert_eq!(sample, vp.sample_within_radius(needle, *radius, size, 7));
+            assert_eq!(size.min(within.len()), sample.len());
+            assert!(sample.iter().all(|idx| within.contains(idx)));
+            sample.sort_unstable();
+            sample.dedup();
+            assert_eq!(size.min(within.len()), sample.len());
+        }
+    }
+
+    // Every item is about equally likely to be picked
+    let needle = Point2(50., 50.);
+    let within = vp.tree().find_within_radius(&needle, 10.);
+    let mut picked = vec![0u32; points.len()];
+    for seed in 0..2000 {
+        for idx in vp.sample_within_radius(&needle, 10., 5, seed) {
+            picked[idx] += 1;
+        }
+    }
+    let expected = 2000. * 5. / within.len() as f32;
+    for &(idx, _) in &within {
+        assert!((picked[idx] as f32 - expected).abs() < expected * 0.5, "{} {}", picked[idx], expected);
+    }
+}
+
+#[test]
+fn test_weighted_tree() {
+    let points = random_points(3000, 16);
+    let weights: Vec<f64> = (0..points.len()).map(|i| (i % 7) as f64 + 0.5).collect();
+    let vp = Tree::new(&points).with_weights(weights.clone());
+    assert_eq!(weights.iter().sum::<f64>(), vp.total_weight());
+    assert_eq!(2.5, vp.weight(2));
+
+    for (needle, radius) in [(Point2(50., 50.), 20.), (Point2(10., 90.), 5.), (Point2(50., 50.), 200.), (Point2(-50., 0.), 10.)].iter() {
+        let expected: f64 = points.iter().zip(&weights).filter(|&(p, _)| p.distance(needle, &()) <= *radius).map(|(_, w)| w).sum();
+        assert!((expected - vp.weight_within_radius(needle, *radius)).abs() < 1e-9);
+    }
+    assert_eq!(0u32, Tree::<Point2>::new(&[]).with_weights(Vec::<u32>::new()).weight_within_radius(&Point2(0., 0.), 1.));
+}
+
+#[test]
+fn test_check_metric() {
+    let points = random_points(200, 17);
+    let check = crate::check_metric(&points, &(), 40, 1);
+    assert_eq!(40 * 39 * 38, check.triples);
+    assert!(check.is_ok());
+    assert_eq!(None, check.worst);
+    assert_eq!(1., check.score());
+
+    #[derive(Clone)]
+    struct Squared(f32);
+    impl MetricSpace for Squared {
+        type UserData = ();
+        type Distance = f32;
+        fn distance(&self, other: &Self, _: &()) -> f32 {
+            (self.0 - other.0) * (self.0 - other.0)
+        }
+    }
+    let items: Vec<_> = (0..100).map(|i| Squared(i as f32)).collect();
+    let check = crate::check_metric(&items, &(), 1000, 2);
+    assert_eq!(100 * 99 * 98, check.triples);
+    assert!(check.violations > 0);
+    assert!(check.score() < 1.);
+    // The worst detours go through the midpoint: (2x)² / (x² + x²) = 2
+    let (a, b, c) = check.worst.unwrap();
+    assert_eq!(a + c, 2 * b);
+    assert_eq!(2., check.worst_ratio);
+
+    let check = crate::check_metric::<Squared, ()>(&[], &(), 10, 0);
+    assert_eq!(0, check.triples);
+    assert_eq!(1., check.score());
+}
+
+#[test]
+fn test_reorder_by_access() {
+    let points = random_points(2000, 18);
+    let queries = random_points(50, 19);
+    // The queries are all in one corner, so that some subtrees are visited much more often
+    let queries: Vec<_> = queries.iter().map(|p| Point2(p.0 / 5., p.1 / 5.)).collect();
+    let expected: Vec<_> = queries.iter().map(|q| Tree::new(&points).find_k_nearest(q, 5)).collect();
+
+    let vp = Tree::new(&points).reorder_by_access(&queries, 5);
+    vp.warm();
+    assert_eq!(points.len(), vp.len());
+    for (node_idx, node) in vp.nodes.iter().enumerate() {
+        if node.bucket().is_none() {
+            assert!(node.near == NO_NODE || node.near as usize > node_idx);
+            assert!(node.far == NO_NODE || node.far as usize > node_idx);
+        }
+    }
+    for (q, expected) in queries.iter().zip(&expected) {
+        assert_eq!(expected, &vp.find_k_nearest(q, 5));
+    }
+    let other = Point2(90., 90.);
+    assert_eq!(Tree::new(&points).find_k_nearest(&other, 10), vp.find_k_nearest(&other, 10));
+    assert_eq!(points.len(), vp.with_counts().count_within_radius(&Point2(50., 50.), 1000.));
+
+    let empty = Tree::<Point2>::new(&[]).reorder_by_access(&queries, 1);
+    empty.warm();
+    assert!(empty.find_k_nearest(&other, 1).is_empty());
+}
+
+#[test]
+fn test_query_cache() {
+    let points = random_points(500, 20);
+    let queries = random_points(3, 21);
+    let vp = Tree::new(&points).with_query_cache(2);
+    assert!(vp.is_empty());
+
+    let first = vp.find_k_nearest_by_key(0, &queries[0], 3);
+    assert_eq!(vp.tree().find_k_nearest(&queries[0], 3), first);
+    assert_eq!(first, vp.find_k_nearest_by_key(0, &queries[0], 3));
+    assert_eq!(crate::CacheStats { hits: 1, misses: 1 }, vp.stats());
+    // A different k is a different result
+    assert_eq!(5, vp.find_k_nearest_by_key(0, &queries[0], 5).len());
+    assert_eq!(2, vp.stats().misses);
+
+    // Key 0 with k = 3 is the least recently used, so it's dropped
+    vp.find_k_nearest_by_key(1, &queries[1], 3);
+    assert_eq!(2, vp.len());
+    vp.find_k_nearest_by_key(0, &queries[0], 5);
+    vp.find_k_nearest_by_key(0, &queries[0], 3);
+    assert_eq!(crate::CacheStats { hits: 2, misses: 4 }, vp.stats());
+    assert!((vp.stats().hit_rate() - 1. / 3.).abs() < 1e-9);
+
+    vp.clear();
+    assert!(vp.is_empty());
+    assert_eq!(0., vp.stats().hit_rate());
+
+    let uncached = Tree::new(&points).with_query_cache(0);
+    assert_eq!(first, uncached.find_k_nearest_by_key("a", &queries[0], 3));
+    uncached.find_k_nearest_by_key("a", &queries[0], 3);
+    assert_eq!(0, uncached.stats().hits);
+}
+
+#[test]
+fn test_query_log() {
+    let points = random_points(3000, 22);
+    let mut log = crate::QueryLog::new();
+    assert!(log.is_empty());
+    assert_eq!(1., log.replay(&Tree::new(&points)).recall);
+    for needle in random_points(20, 23) {
+        log.record(needle, 10, None);
+    }
+    assert_eq!(20, log.len());
+    assert_eq!(10, log.entries()[0].k);
+
+    let shuffled = Tree::new_shuffled(&points, 5);
+    let report = log.replay(&shuffled);
+    assert_eq!(20, report.queries);
+    assert_eq!(1., report.recall);
+    assert!(report.max_time <= report.total_time);
+    assert!(report.mean_time() <= report.max_time);
+
+    let limited = log.replay_with_budget(&shuffled, 30);
+    assert!(limited.recall < 1.);
+    assert!(limited.recall > 0.);
+    assert_eq!(1., log.replay_with_budget(&shuffled, points.len() + 100).recall);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&log).unwrap();
+        let loaded: crate::QueryLog<Point2> = serde_json::from_str(&json).unwrap();
+        assert_eq!(log.entries()[3].needle, loaded.entries()[3].needle);
+        assert_eq!(20, loaded.replay(&shuffled).queries);
+    }
+}
+
+#[test]
+fn test_incremental_build() {
+    fn assert_same_tree(a: &Tree<Point2>, b: &Tree<Point2>) {
+        assert_eq!(a.root, b.root);
+        assert_eq!(a.nodes.len(), b.nodes.len());
+        for (a, b) in a.nodes.iter().zip(&b.nodes) {
+            assert_eq!((a.idx, a.near, a.far, a.radius), (b.idx, b.near, b.far, b.radius));
+        }
+        assert_eq!(a.buckets.idx, b.buckets.idx);
+    }
+
+    let points = random_points(5000, 24);
+    let expected = Tree::new(&points);
+
+    let mut build = Tree::build_incrementally(&points);
+    assert_eq!(0., build.progress());
+    let mut steps = 0;
+    let mut progress = 0.;
+    while !build.step(2000).unwrap() {
+        steps += 1;
+        assert!(build.progress() > progress);
+        progress = build.progress();
+    }
+    assert!(steps > 10);
+    assert_eq!(1., build.progress());
+    assert_same_tree(&expected, &build.finish().unwrap());
+
+    let mut build = Tree::build_incrementally(&points);
+    build.step(0).unwrap();
+    assert!(build.progress() > 0.);
+    assert_same_tree(&expected, &build.finish().unwrap());
+
+    let (tree, polls) = block_on(Tree::build_incrementally(&points).build_async(1000));
+    assert!(polls > 10);
+    assert_same_tree(&expected, &tree.unwrap());
+
+    let mut empty = Tree::<Point2>::build_incrementally(&[]);
+    assert!(empty.step(10).unwrap());
+    assert_eq!(1., empty.progress());
+    assert_eq!(0, empty.finish().unwrap().len());
+
+    let nan = [Point2(1., 2.), Point2(f32::NAN, 0.)].repeat(10);
+    assert_eq!(Err(crate::Error::InvalidDistance), Tree::build_incrementally(&nan).finish().map(|_| ()));
+}
+
+#[test]
+fn test_nodes() {
+    let points = random_points(1000, 25);
+    let vp = Tree::new(&points);
+    let nodes: Vec<_> = vp.nodes().collect();
+    assert_eq!(points.len(), nodes.len());
+    let mut indexes: Vec<_> = nodes.iter().map(|n| n.index).collect();
+    indexes.sort_unstable();
+    assert!(indexes.iter().enumerate().all(|(i, &idx)| i == idx));
+
+    assert_eq!((0, None), (nodes[0].depth, nodes[0].parent));
+    for (pos, node) in nodes.iter().enumerate().skip(1) {
+        // The parent is earlier in the order, one level up, and the radius tells which subtree the item is in
+        let parent = nodes[..pos].iter().rev().find(|n| Some(n.index) == node.parent).unwrap();
+        assert_eq!(parent.depth + 1, node.depth);
+        if let Some(radius) = parent.radius {
+            let distance = points[node.index].distance(&points[parent.index], &());
+            if node.far { assert!(distance >= radius) } else { assert!(distance <= radius) }
+        }
+    }
+    assert!(nodes.iter().any(|n| n.radius.is_none() && n.depth > 0));
+    assert_eq!(0, Tree::<Point2>::new(&[]).nodes().count());
+    assert_eq!(1, Tree::new(&[Point2(1., 1.)]).nodes().count());
+}
+
+#[test]
+fn test_suggest_epsilon() {
+    let points = random_points(5000, 26);
+    let vp = Tree::new(&points);
+    for &target in [1, 10, 50].iter() {
+        let epsilon = vp.suggest_epsilon(target).unwrap();
+        // Checked on different needles than the sample
+        let found: usize = points.iter().step_by(7).map(|p| vp.find_within_radius(p, epsilon).len() - 1).sum();
+        let average = found as f64 / points.iter().step_by(7).count() as f64;
+        assert!(average > target as f64 * 0.8 && average < target as f64 * 1.25, "{} {}", target, average);
+    }
+    assert!(vp.suggest_epsilon(10).unwrap() < vp.suggest_epsilon(50).unwrap());
+
+    let few = Tree::new(&points[..5]);
+    let all = few.suggest_epsilon(100).unwrap();
+    assert!(points[..5].iter().all(|p| few.find_within_radius(p, all).len() == 5));
+    assert_eq!(None, few.suggest_epsilon(0));
+    assert_eq!(None, Tree::new(&points[..1]).suggest_epsilon(1));
+}
+
+#[test]
+fn test_new_streamed() {
+    let points = random_points(5000, 27);
+    let regular = Tree::new(&points);
+    for &sample_size in [0, 10, 500, 10000].iter() {
+        let vp = Tree::new_streamed(points.iter().copied(), sample_size);
+        assert_eq!(points.len(), vp.len());
+        for needle in random_points(20, 28).iter() {
+            assert_eq!(regular.find_k_nearest(needle, 5), vp.find_k_nearest(needle, 5));
+            assert_eq!(regular.find_within_radius(needle, 3.).len(), vp.find_within_radius(needle, 3.).len());
+        }
+        for (node_idx, node) in vp.nodes.iter().enumerate() {
+            if node.bucket().is_none() {
+                assert!(node.near == NO_NODE || node.near as usize > node_idx);
+                assert!(node.far == NO_NODE || node.far as usize > node_idx);
+            }
+        }
+    }
+    assert!(Tree::<Point2>::new_streamed(std::iter::empty(), 100).is_empty());
+    assert_eq!(1, Tree::new_streamed(std::iter::once(Point2(1., 1.)), 100).len());
+}
+
+#[test]
+fn test_session() {
+    use std::cell::Cell;
+
+    #[derive(Clone)]
+    struct Counted(Point2);
+    impl MetricSpace for Counted {
+        type UserData = Cell<usize>;
+        type Distance = f32;
+        fn distance(&self, other: &Self, calls: &Cell<usize>) -> f32 {
+            calls.set(calls.get() + 1);
+            self.0.distance(&other.0, &())
+        }
+    }
+
+    let points: Vec<_> = random_points(2000, 29).into_iter().map(Counted).collect();
+    let vp = Tree::new_with_user_data_owned(&points, Cell::new(0));
+    for needle in random_points(10, 30) {
+        let needle = Counted(needle);
+        vp.user_data().set(0);
+        let mut session = vp.session(&needle);
+        assert_eq!(vp.find_nearest(&needle), session.find_nearest());
+        assert_eq!(vp.find_k_nearest(&needle, 10), session.find_k_nearest(10));
+        assert_eq!(vp.find_within_radius(&needle, 5.), session.find_within_radius(5.));
+        let first = session.distance_evaluations();
+        assert_eq!(vp.find_k_nearest(&needle, 20), session.find_k_nearest(20));
+        // The second search only computes distances to items the first ones haven't visited
+        let alone = vp.user_data().replace(0);
+        vp.find_k_nearest(&needle, 20);
+        assert!(session.distance_evaluations() - first < vp.user_data().get());
+        assert!(session.distance_evaluations() < alone);
+
+        vp.user_data().set(0);
+        session.find_k_nearest(20);
+        assert_eq!(0, vp.user_data().get());
+    }
+}
+
+#[test]
+fn test_catch_panic() {
+    use std::cell::Cell;
+
+    /// Panics once, at the given call
+    #[derive(Clone)]
+    struct Fragile(Point2);
+    impl MetricSpace for Fragile {
+        type UserData = Cell<usize>;
+        type Distance = f32;
+        fn distance(&self, other: &Self, countdown: &Cell<usize>) -> f32 {
+            countdown.set(countdown.get().wrapping_sub(1));
+            assert_ne!(0, countdown.get(), "metric failed");
+            self.0.distance(&other.0, &())
+        }
+    }
+
+    let points: Vec<_> = random_points(500, 31).into_iter().map(Fragile).collect();
+    let mut build = crate::TreeBuild::new_with_user_data_owned(&points, Cell::new(2000));
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| while !build.step(100).unwrap() {})).is_err());
+    let vp = build.finish().unwrap();
+    assert_eq!(points.len(), vp.nodes().count());
+
+    let needle = Fragile(Point2(5., 5.));
+    vp.user_data().set(10);
+    assert_eq!(Err(Error::MetricPanicked), vp.catch(|vp| vp.find_k_nearest(&needle, 5)));
+    vp.user_data().set(usize::MAX);
+    let found = vp.catch(|vp| vp.find_k_nearest(&needle, 5)).unwrap();
+    let expected = brute_force(&points.iter().map(|p| p.0).collect::<Vec<_>>(), &needle.0);
+    assert_eq!(found.iter().map(|&(idx, _)| idx).collect::<Vec<_>>(), expected.iter().take(5).map(|&(idx, _)| idx).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_find_k_nearest_checked() {
+    #[derive(Clone)]
+    struct Squared(Point2);
+    impl MetricSpace for Squared {
+        type UserData = ();
+        type Distance = f32;
+        fn distance(&self, other: &Self, _: &()) -> f32 {
+            self.0.distance(&other.0, &()).powi(2)
+        }
+    }
+
+    let points = random_points(1000, 32);
+    let needles = random_points(100, 33);
+    let vp = Tree::new(&points);
+    for needle in &needles {
+        assert_eq!(Ok(vp.find_k_nearest(needle, 5)), vp.find_k_nearest_checked(needle, 5, usize::MAX));
+    }
+
+    let squared: Vec<_> = points.iter().copied().map(Squared).collect();
+    let vp = Tree::new(&squared);
+    let mistakes: Vec<_> = needles.iter().filter_map(|&needle| vp.find_k_nearest_checked(&Squared(needle), 5, usize::MAX).err()).collect();
+    assert!(!mistakes.is_empty());
+    for mistake in &mistakes {
+        assert!(mistake.missed_distance < mistake.bound);
+        assert_eq!(mistake.vantage_distance, squared[mistake.vantage_point].distance(&squared[mistake.missed], &()));
+        assert!(!mistake.to_string().is_empty());
+    }
+    // Subtrees that are too large aren't checked
+    assert!(needles.iter().all(|&needle| vp.find_k_nearest_checked(&Squared(needle), 5, 0).is_ok()));
+}
+
+#[test]
+fn test_distance_matrix() {
+    use crate::metrics::{DistanceMatrix, MatrixItem};
+
+    let points = random_points(300, 34);
+    let mut condensed = Vec::new();
+    for (i, a) in points.iter().enumerate() {
+        condensed.extend(points[i + 1..].iter().map(|b| a.distance(b, &())));
+    }
+    assert!(DistanceMatrix::new(points.len() + 1, condensed.clone()).is_none());
+    let matrix = DistanceMatrix::new(points.len(), condensed).unwrap();
+    assert_eq!(matrix.get(7, 3), points[3].distance(&points[7], &()));
+    assert_eq!(0., matrix.get(5, 5));
+
+    let vp = Tree::from_distance_matrix(matrix);
+    assert_eq!(points.len(), vp.len());
+    for i in (0..points.len() as u32).step_by(7) {
+        let expected = brute_force(&points, &points[i as usize]);
+        let found = vp.find_k_nearest(&MatrixItem(i), 4);
+        assert_eq!(expected[..4], found[..]);
+    }
+    assert!(Tree::from_distance_matrix(DistanceMatrix::<f32>::new(0, vec![]).unwrap()).is_empty());
+}
+
+#[test]
+fn test_self_join_blocks() {
+    let points = random_points(800, 35);
+    let vp = Tree::new(&points);
+    let mut expected = Vec::new();
+    assert!(vp.self_join_each(2., |a, b, d| { expected.push((a, b, d)); true }));
+    expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    assert!(!expected.is_empty());
+
+    for &block_size in [1, 7, 1000, 100000].iter() {
+        let mut blocks = 0;
+        let mut found = Vec::new();
+        assert!(vp.self_join_blocks(2., block_size, |block| {
+            assert!(!block.is_empty() && block.len() <= block_size);
+            blocks += 1;
+            found.extend_from_slice(block);
+            true
+        }));
+        assert_eq!(blocks, expected.len().div_ceil(block_size));
+        found.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(expected, found);
+    }
+
+    let mut blocks = 0;
+    assert!(!vp.self_join_blocks(2., 3, |_| { blocks += 1; blocks < 5 }));
+    assert_eq!(5, blocks);
+}
+
+#[test]
+fn test_search_page() {
+    #[derive(Clone)]
+    struct Grid(i32, i32);
+    impl MetricSpace for Grid {
+        type UserData = ();
+        type Distance = u32;
+        fn distance(&self, other: &Self, _: &()) -> u32 {
+            (self.0 - other.0).unsigned_abs() + (self.1 - other.1).unsigned_abs()
+        }
+    }
+
+    // Lots of items at the same distance, so pages often end in the middle of a tie
+    let grid: Vec<_> = (0..40).flat_map(|x| (0..40).map(move |y| Grid(x, y))).collect();
+    let vp = Tree::new(&grid);
+    let needle = Grid(13, 21);
+    let mut expected: Vec<_> = grid.iter().map(|item| needle.distance(item, &())).enumerate().filter(|&(_, d)| d <= 15).collect();
+    expected.sort_by_key(|&(idx, d)| (d, idx));
+
+    for &page_size in [1, 7, 50, 10000].iter() {
+        let mut all = Vec::new();
+        let mut cursor = None;
+        loop {
+            let page = vp.search_page(&needle, 15, cursor.as_ref(), page_size);
+            assert!(page.items.len() <= page_size);
+            all.extend_from_slice(&page.items);
+            cursor = page.next;
+            if cursor.is_none() {
+                break;
+            }
+            assert_eq!(page.items.len(), page_size);
+        }
+        assert_eq!(expected, all);
+    }
+
+    let page = vp.search_page(&needle, u32::MAX, None, 5);
+    assert_eq!(vp.find_k_nearest(&needle, 5).iter().map(|&(_, d)| d).collect::<Vec<_>>(), page.items.iter().map(|&(_, d)| d).collect::<Vec<_>>());
+}
+
+#[test]
+#[cfg(feature = "rand_core")]
+fn test_with_rng() {
+    struct Counter(u64);
+    impl rand_core::RngCore for Counter {
+        fn next_u32(&mut self) -> u32 {
+            self.next_u64() as u32
+        }
+        fn next_u64(&mut self) -> u64 {
+            self.0 += 1;
+            self.0
+        }
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            rand_core::impls::fill_bytes_via_next(self, dest)
+        }
+    }
+
+    let points = random_points(300, 39);
+    let vp = Tree::new_shuffled_with_rng(&points, &mut Counter(0));
+    assert_eq!(vp.find_k_nearest(&Point2(3., 3.), 5), Tree::new_shuffled(&points, 1).find_k_nearest(&Point2(3., 3.), 5));
+
+    let counted = vp.with_counts();
+    let mut rng = Counter(10);
+    let first = counted.sample_within_radius_with_rng(&Point2(3., 3.), 5., 10, &mut rng);
+    assert_eq!(first, counted.sample_within_radius(&Point2(3., 3.), 5., 10, 11));
+    assert_eq!(counted.sample_within_radius(&Point2(3., 3.), 5., 10, 12), counted.sample_within_radius_with_rng(&Point2(3., 3.), 5., 10, &mut rng));
+
+    let rng: &mut dyn rand_core::RngCore = &mut Counter(5);
+    assert_eq!(crate::check_metric_with_rng(&points, &(), 20, rng), crate::check_metric(&points, &(), 20, 6));
+}
+
+#[test]
+fn test_cancellable() {
+    use std::ops::ControlFlow;
+    use std::sync::atomic::AtomicBool;
+
+    let points = random_points(2000, 44);
+    let vp = Tree::new(&points);
+    let needle = Point2(20., 30.);
+    assert_eq!(Ok(vp.find_k_nearest(&needle, 5)), vp.find_k_nearest_cancellable(&needle, 5, &AtomicBool::new(false)));
+    assert_eq!(Err(Error::Cancelled), vp.find_k_nearest_cancellable(&needle, 5, &AtomicBool::new(true)));
+
+    // Stops after a few nodes, like after a deadline
+    let mut checks = 0;
+    let result = vp.find_nearest_custom_cancellable(&needle, &(), KNearest::new(5), || {
+        checks += 1;
+        if checks > 3 { ControlFlow::Break(()) } else { ControlFlow::Continue(()) }
+    });
+    assert_eq!(Err(Error::Cancelled), result);
+    assert_eq!(4, checks);
+}
+
+#[test]
+fn test_nearest_with_confidence() {
+    let points = random_points(5000, 45);
+    let vp = Tree::new(&points);
+    let needle = Point2(20., 30.);
+    let exact = vp.find_k_nearest(&needle, 3);
+    assert_eq!((exact.clone(), 1.), vp.find_k_nearest_with_confidence(&needle, 3, usize::MAX));
+
+    let mut last_confidence = 0.;
+    let mut wrong_and_confident = 0;
+    for budget in (10..300).step_by(10) {
+        let (found, confidence) = vp.find_k_nearest_with_confidence(&needle, 3, budget);
+        let (budgeted, is_exact) = vp.find_k_nearest_budgeted(&needle, 3, budget);
+        assert_eq!(budgeted, found);
+        assert!((0. ..=1.).contains(&confidence));
+        assert!(!is_exact || confidence == 1.);
+        if found != exact && confidence > 0.9 {
+            wrong_and_confident += 1;
+        }
+        last_confidence = confidence;
+    }
+    assert!(last_confidence > 0.5);
+    assert!(wrong_and_confident < 3);
+
+    // Nothing searched, nothing known
+    assert_eq!((vec![], 0.), vp.find_k_nearest_with_confidence(&needle, 1, 0));
+}
+
+#[test]
+fn test_nth_pair_distance() {
+    let points = random_points(300, 46);
+    let vp = Tree::new(&points);
+    let mut all = Vec::new();
+    for (i, a) in points.iter().enumerate() {
+        for b in &points[i + 1..] {
+            all.push(a.distance(b, &()));
+        }
+    }
+    all.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    for &n in &[0, 1, 7, 100, 5000, all.len() - 1] {
+        let (a, b, distance) = vp.nth_pair_distance(n).unwrap();
+        assert!(a < b);
+        assert_eq!(all[n], distance);
+        assert_eq!(distance, points[a].distance(&points[b], &()));
+    }
+    assert_eq!(None, vp.nth_pair_distance(all.len()));
+    assert_eq!(None, Tree::new(&points[..1]).nth_pair_distance(0));
+}
+
+#[test]
+fn test_tracker() {
+    let points = random_points(3000, 47);
+    let vp = Tree::new(&points);
+    let mut tracker = vp.tracker();
+    let mut position = Point2(10., 10.);
+    for step in 0..50 {
+        position = Point2(position.0 + 0.7, position.1 + (step % 5) as f32 * 0.3);
+        let (idx, distance) = tracker.find_nearest(&position);
+        assert_eq!(distance, vp.find_nearest(&position).1);
+        assert_eq!(distance, points[idx].distance(&position, &()));
+    }
+    // Any prior item works, even a far one
+    let expected = vp.find_nearest(&Point2(5., 5.));
+    for prior in [0, 1234, 2999] {
+        assert_eq!(expected.1, tracker.find_nearest_seeded(&Point2(5., 5.), prior).1);
+    }
+
+    let empty = Tree::<Point2>::new(&[]);
+    assert_eq!(f32::MAX, empty.tracker().find_nearest(&position).1);
+}
+
+#[test]
+fn test_forest() {
+    use crate::metrics::Euclidean;
+
+    // Trees prune poorly in many dimensions, so a budget is needed, and several trees make up for each other's bad splits
+    let vectors = |n: usize, seed| -> Vec<[f32; 16]> {
+        let coords = random_points(n * 8, seed);
+        coords.chunks(8).map(|c| core::array::from_fn(|i| if i % 2 == 0 { c[i / 2].0 } else { c[i / 2].1 })).collect()
+    };
+    let items = vectors(3000, 48);
+    let forest: Vec<Tree<_, Euclidean>> = (0..4).map(|seed| Tree::new_shuffled(&items, seed)).collect();
+    let (mut forest_found, mut single_found) = (0, 0);
+    for needle in &vectors(50, 49) {
+        let exact = forest[0].find_k_nearest(needle, 5);
+        assert_eq!((exact.clone(), true), crate::find_k_nearest_in_forest(&forest, needle, 5, usize::MAX));
+
+        let (found, _) = crate::find_k_nearest_in_forest(&forest, needle, 5, 300);
+        forest_found += found.iter().filter(|item| exact.contains(item)).count();
+        let (found, _) = forest[0].find_k_nearest_budgeted(needle, 5, 300);
+        single_found += found.iter().filter(|item| exact.contains(item)).count();
+    }
+    assert!(forest_found > single_found, "{} {}", forest_found, single_found);
+
+    assert_eq!((vec![], true), crate::find_k_nearest_in_forest::<Point2, (), Owned<()>, _>(&[], &Point2(0., 0.), 5, 100));
+}
+
+#[test]
+fn test_build_report() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[derive(Clone)]
+    struct Counted(Point2);
+    impl MetricSpace for Counted {
+        type UserData = AtomicUsize;
+        type Distance = f32;
+        fn distance(&self, other: &Self, calls: &AtomicUsize) -> f32 {
+            calls.fetch_add(1, Ordering::Relaxed);
+            self.0.distance(&other.0, &())
+        }
+    }
+
+    let points: Vec<_> = random_points(2000, 50).into_iter().map(Counted).collect();
+    let (vp, report) = Tree::try_new_with_report(&points, AtomicUsize::new(0)).unwrap();
+    assert_eq!(vp.user_data().load(Ordering::Relaxed), report.distance_evaluations);
+    assert_eq!(2000, report.items);
+    assert_eq!(0, report.duplicates);
+    assert!(report.warnings.is_empty());
+    let leaves: usize = report.depth_histogram.iter().sum();
+    assert!(leaves > 2000 / (LEAF_SIZE + 1) && report.depth_histogram.len() < 12);
+    let plain = Tree::new_with_user_data_owned(&points, AtomicUsize::new(0));
+    for needle in random_points(20, 51) {
+        assert_eq!(plain.find_k_nearest(&Counted(needle), 5), vp.find_k_nearest(&Counted(needle), 5));
+    }
+
+    // Few distinct points, all far apart
+    let grid: Vec<_> = (0..1000).map(|i| Point2((i % 4) as f32 * 10., (i / 4 % 3) as f32)).collect();
+    let (_, report) = Tree::try_new_with_report(&grid, ()).unwrap();
+    assert!(report.duplicates > 900);
+    assert!(matches!(report.warnings[..], [BuildWarning::ManyDuplicates { items: 1000, .. }, BuildWarning::ManyTiedSplits { .. }]));
+    assert!(report.warnings[0].to_string().contains("duplicates"));
+
+    assert_eq!(Some(Error::Empty), Tree::<Point2>::try_new_with_report(&[], ()).err());
+}
+
+#[test]
+fn test_replace_user_data() {
+    #[derive(Clone)]
+    struct Scaled(Point2);
+    impl MetricSpace for Scaled {
+        type UserData = Vec<f32>;
+        type Distance = f32;
+        fn distance(&self, other: &Self, scale: &Vec<f32>) -> f32 {
+            self.0.distance(&other.0, &()) * scale[0]
+        }
+    }
+
+    let points: Vec<_> = random_points(500, 52).into_iter().map(Scaled).collect();
+    let mut vp = Tree::new_with_user_data_owned(&points, vec![1.]);
+    let needle = Scaled(Point2(30., 40.));
+    let expected = vp.find_k_nearest(&needle, 5);
+
+    assert_eq!(vec![1.], vp.replace_user_data(vec![1., 0.]));
+    assert_eq!(Err(Error::DistancesChanged), vp.replace_user_data_checked(vec![2.], 10, 0));
+    assert_eq!(&[1., 0.], &vp.user_data()[..]);
+    assert_eq!(Ok(vec![1., 0.]), vp.replace_user_data_checked(vec![1.], 10, 0));
+    let vp = vp.map_user_data(|mut scale| { scale.push(3.); scale });
+    assert_eq!(&[1., 3.], &vp.user_data()[..]);
+    assert_eq!(expected, vp.find_k_nearest(&needle, 5));
+}
+
+#[test]
+fn test_quantized() {
+    use crate::metrics::{Euclidean, Quantized, Quantizer};
+
+    let points = random_points(1000, 53);
+    let vectors: Vec<[f32; 2]> = points.iter().map(|p| [p.0, p.1]).collect();
+    let quantizer = Quantizer::fit(&vectors);
+    let items: Vec<Quantized<u8>> = vectors.iter().map(|v| quantizer.quantize(v)).collect();
+    for (v, q) in vectors.iter().zip(&items) {
+        for (a, b) in v.iter().zip(quantizer.dequantize(q)) {
+            assert!((a - b).abs() <= 100. / 255. / 2. + 0.001);
+        }
+    }
+    let vp: Tree<_, Euclidean, _> = Tree::new_with_user_data_owned(&items, quantizer);
+    let exact: Tree<_, Euclidean, _> = Tree::new_with_user_data_owned(&vectors.iter().map(|v| quantizer.quantize::<f32>(v)).collect::<Vec<_>>(), quantizer);
+    for needle in random_points(50, 54) {
+        let needle = [needle.0, needle.1];
+        let (found, distance) = vp.find_nearest(&vp.user_data().quantize(&needle));
+        let expected = exact.find_nearest(&quantizer.quantize(&needle)).1;
+        // Quantization moves each point by at most half a step in each dimension
+        assert!((distance - expected).abs() < 0.6);
+        assert!((vectors[found][0] - needle[0]).hypot(vectors[found][1] - needle[1]) < expected + 0.6);
+    }
+
+    // All the same values
+    let flat = Quantizer::fit(&[[5f32, 5.]]);
+    assert_eq!(vec![5., 5.], flat.dequantize(&flat.quantize::<u8>(&[5., 5.])));
+}
+
+#[test]
+fn test_budgeted_tree() {
+    let vectors: Vec<Vec<f32>> = random_points(2000, 57).iter().map(|p| vec![p.0, p.1, p.0 - p.1, 1.]).collect();
+    let needles: Vec<Vec<f32>> = random_points(20, 58).iter().map(|p| vec![p.0, p.1, p.0 - p.1, 1.]).collect();
+    let exact = |needle: &[f32], idx: usize| vectors[idx].iter().zip(needle).map(|(a, b)| (a - b) * (a - b)).sum::<f32>().sqrt();
+
+    let (tree, report) = BudgetedTree::new(&vectors, 1 << 20).unwrap();
+    assert_eq!(StorageLayout::F32, report.layout);
+    assert_eq!(tree.layout(), report.layout);
+    assert!(report.bytes <= 1 << 20);
+    for needle in &needles {
+        let (idx, distance) = tree.find_k_nearest(needle, 1)[0];
+        assert!((0..vectors.len()).all(|other| exact(needle, other) >= distance));
+        assert_eq!(distance, exact(needle, idx));
+    }
+
+    // Enough for the u8 vectors, but not for f32
+    let (tree, report) = BudgetedTree::new(&vectors, report.bytes - 2000 * 4 * 3).unwrap();
+    assert_eq!(StorageLayout::U8, report.layout);
+    assert!(report.bytes <= report.max_bytes);
+    assert_eq!(2000, tree.len());
+    for needle in &needles {
+        let (idx, _) = tree.find_k_nearest(needle, 1)[0];
+        let nearest = (0..vectors.len()).map(|other| exact(needle, other)).fold(f32::INFINITY, f32::min);
+        assert!(exact(needle, idx) < nearest + 1.);
+    }
+
+    assert_eq!(Some(Error::OverBudget), BudgetedTree::new(&vectors, 10000).err());
+}
+
+#[test]
+fn test_remapped_tree() {
+    let points = random_points(1000, 55);
+    let vp = Tree::new_filtered(&points, |index, point| index % 3 != 0 && point.0 < 80.);
+    let kept: Vec<usize> = (0..points.len()).filter(|&i| i % 3 != 0 && points[i].0 < 80.).collect();
+    assert_eq!(&kept[..], vp.values());
+
+    for needle in random_points(20, 56) {
+        let expected: Vec<_> = brute_force(&points, &needle).into_iter().filter(|&(i, _)| kept.contains(&i)).collect();
+        assert_eq!(Some((&expected[0].0, expected[0].1)), vp.find_nearest(&needle));
+        assert_eq!(expected[..5].iter().map(|e| e.1).collect::<Vec<_>>(), vp.find_k_nearest(&needle, 5).iter().map(|f| f.1).collect::<Vec<_>>());
+        for (&index, distance) in vp.find_within_radius(&needle, 10.) {
+            assert!(kept.contains(&index));
+            assert_eq!(distance, points[index].distance(&needle, &()));
+        }
+    }
+
+    assert_eq!(None, Tree::new_filtered(&points, |_, _| false).find_nearest(&points[0]));
+}
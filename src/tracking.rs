@@ -0,0 +1,79 @@
+//! Searches for a stream of needles that move a little between queries, e.g. objects tracked from frame to frame.
+//!
+//! The previous answer is usually near the new one, so its distance to the new needle is a tight bound from the start,
+//! and the search can skip most of the tree right away, instead of narrowing the bound down from the root.
+
+use crate::{BestCandidate, HoldsUserData, MetricSpace, Needle, Owned, ReturnByIndex, Tree};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use num_traits::Bounded;
+
+/// Nearest-neighbor searches that start from a previous answer. Created with `Tree::tracker`.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32, f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).hypot(self.1 - other.1) }
+/// # }
+/// let points: Vec<_> = (0..1000).map(|i| Point((i % 40) as f32, (i / 40) as f32)).collect();
+/// let tree = vpsearch::Tree::new(&points);
+/// let mut tracker = tree.tracker();
+/// for frame in 0..10 {
+///     let position = Point(3. + frame as f32 * 0.2, 5.1);
+///     // The first search has nothing to start from, and later ones start from the previous answer
+///     let (index, _) = tracker.find_nearest(&position);
+///     assert_eq!(tree.find_nearest(&position).0, index);
+/// }
+/// ```
+pub struct Tracker<'tree, Item: MetricSpace<Impl> + Clone, Impl = (), Ownership = Owned<()>> {
+    tree: &'tree Tree<Item, Impl, Ownership>,
+    /// The tree's items in the order of their indexes
+    items: Vec<&'tree Item>,
+    last: Option<usize>,
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Starts a series of searches for needles that are close to each other, usually one after another.
+    ///
+    /// The tracker looks up the items by their indexes, so it keeps a reference to every item.
+    pub fn tracker(&self) -> Tracker<'_, Item, Impl, Ownership> {
+        Tracker { tree: self, items: self.items_by_index(), last: None }
+    }
+}
+
+impl<'tree, Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tracker<'tree, Item, Impl, Ownership> {
+    /// Like `Tree::find_nearest`, but starts with the distance to the item at index `prior_idx` as the bound,
+    /// so only items nearer than it are searched for. The closer the prior item is to the needle, the faster the search.
+    ///
+    /// The result is exact. If several items are equally near, it may be another one of them than `Tree::find_nearest` would return.
+    ///
+    /// Panics if `prior_idx` is out of bounds.
+    pub fn find_nearest_seeded<Q>(&mut self, needle: &Q, prior_idx: usize) -> (usize, Item::Distance)
+    where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        let tree = self.tree;
+        let user_data = tree.user_data.user_data();
+        let prior = self.items[prior_idx];
+        let max = <Item::Distance as Bounded>::max_value();
+        let mut best_candidate = ReturnByIndex::new(max);
+        best_candidate.consider(prior, needle.distance(prior.borrow(), user_data), prior_idx, user_data);
+        tree.search(tree.root, &mut Needle { needle, user_data }, &mut best_candidate, user_data);
+        let nearest = best_candidate.result(user_data).unwrap_or((prior_idx, max));
+        self.last = Some(nearest.0);
+        nearest
+    }
+
+    /// Like `find_nearest_seeded` with the result of the previous search of this tracker, or like `Tree::find_nearest` for the first search.
+    pub fn find_nearest<Q>(&mut self, needle: &Q) -> (usize, Item::Distance)
+    where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        match self.last {
+            Some(prior_idx) => self.find_nearest_seeded(needle, prior_idx),
+            None => {
+                let nearest = self.tree.find_nearest_with_user_data(needle, self.tree.user_data.user_data());
+                // An empty tree has no index to start from
+                self.last = Some(nearest.0).filter(|&idx| idx < self.items.len());
+                nearest
+            },
+        }
+    }
+}
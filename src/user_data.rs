@@ -0,0 +1,59 @@
+//! Replacing the user data of a tree that owns it, without rebuilding the tree.
+//!
+//! The tree's structure depends on distances computed with the user data, so the new user data must give the same distances,
+//! e.g. a reloaded copy of the same lookup table. Data that changes distances makes searches return wrong results.
+
+use crate::{Error, MetricSpace, Owned, SplitMix64, Tree};
+use core::mem;
+
+impl<Item: MetricSpace<Impl> + Clone, Impl> Tree<Item, Impl, Owned<Item::UserData>> {
+    /// Puts the `user_data` in the tree, and returns the previous one.
+    ///
+    /// Distances with the new user data must be the same as with the old one. See `replace_user_data_checked`.
+    pub fn replace_user_data(&mut self, user_data: Item::UserData) -> Item::UserData {
+        mem::replace(&mut self.user_data.0, user_data)
+    }
+
+    /// Changes the user data with a function, e.g. to move it to a new allocation or to reset its counters.
+    ///
+    /// Distances with the new user data must be the same as with the old one.
+    ///
+    /// ```rust
+    /// # use std::collections::HashMap;
+    /// #[derive(Clone)] struct Word(&'static str);
+    /// impl vpsearch::MetricSpace for Word {
+    ///     type UserData = HashMap<&'static str, f32>; type Distance = f32;
+    ///     fn distance(&self, other: &Self, table: &Self::UserData) -> f32 { (table[self.0] - table[other.0]).abs() }
+    /// }
+    /// let table: HashMap<_, _> = vec![("a", 1.), ("b", 2.), ("c", 4.)].into_iter().collect();
+    /// let tree = vpsearch::Tree::new_with_user_data_owned(&[Word("a"), Word("b"), Word("c")], table);
+    /// // More words can be looked up, and the distances between the old ones stay the same
+    /// let tree = tree.map_user_data(|mut table| { table.insert("d", 8.); table });
+    /// assert_eq!(tree.find_nearest(&Word("d")), (2, 4.));
+    /// ```
+    pub fn map_user_data<F>(mut self, f: F) -> Self where F: FnOnce(Item::UserData) -> Item::UserData {
+        self.user_data = Owned(f(self.user_data.0));
+        self
+    }
+
+    /// Like `replace_user_data`, but first compares distances between `sample_size` pairs of items (vantage points chosen by the `seed`)
+    /// computed with the old and the new user data. If any of them differ, the new user data is dropped, the tree keeps the old one,
+    /// and it returns `Error::DistancesChanged`.
+    ///
+    /// It can't prove that the distances are the same, but it catches user data that has been loaded from a wrong file or version.
+    pub fn replace_user_data_checked(&mut self, user_data: Item::UserData, sample_size: usize, seed: u64) -> Result<Item::UserData, Error> {
+        if !self.nodes.is_empty() {
+            let mut rng = SplitMix64(seed);
+            for _ in 0..sample_size {
+                let a = &self.nodes[rng.below(self.nodes.len())].vantage_point;
+                let b = &self.nodes[rng.below(self.nodes.len())].vantage_point;
+                let (old, new) = (a.distance(b, &self.user_data.0), a.distance(b, &user_data));
+                // NaN is different from everything, but NaN in the old data would have made the tree fail to build
+                if old != new {
+                    return Err(Error::DistancesChanged);
+                }
+            }
+        }
+        Ok(self.replace_user_data(user_data))
+    }
+}
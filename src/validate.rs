@@ -0,0 +1,80 @@
+//! Checks of the tree's structure, for trees that haven't been built by this crate, e.g. deserialized from an untrusted file.
+//!
+//! Searches index the nodes and buckets without checking them, and keep pending nodes in a fixed-size array,
+//! so a corrupted tree would make them panic. It's checked once when loading instead.
+
+use crate::{Buckets, Error, MetricSpace, Node, Tree, LEAF_SIZE, MAX_DEPTH, NO_NODE};
+use alloc::vec;
+use alloc::vec::Vec;
+use serde::{Deserialize, Deserializer};
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// Returns `Error::InvalidTree` if any index in the tree is out of bounds, or the tree is too deep to search
+    pub(crate) fn check_structure(&self) -> Result<(), Error> {
+        let (nodes, buckets) = (&self.nodes, &self.buckets);
+        if buckets.items.len() != buckets.idx.len() || (nodes.is_empty() && !buckets.items.is_empty()) {
+            return Err(Error::InvalidTree);
+        }
+        if nodes.is_empty() {
+            return Ok(());
+        }
+        let len = self.len();
+        if self.root as usize >= nodes.len() || buckets.idx.iter().any(|&idx| idx as usize >= len) {
+            return Err(Error::InvalidTree);
+        }
+
+        // Children are after their parent, so the depth of the parent is known before its children are checked
+        let mut depths = vec![0u8; nodes.len()];
+        for (node_idx, node) in nodes.iter().enumerate() {
+            if node.idx as usize >= len {
+                return Err(Error::InvalidTree);
+            }
+            if let Some(bucket) = node.bucket() {
+                if bucket.len() > LEAF_SIZE || bucket.end > buckets.items.len() {
+                    return Err(Error::InvalidTree);
+                }
+                continue;
+            }
+            for &child in &[node.near, node.far] {
+                if child == NO_NODE {
+                    continue;
+                }
+                if child as usize <= node_idx || child as usize >= nodes.len() {
+                    return Err(Error::InvalidTree);
+                }
+                let depth = depths[node_idx] + 1;
+                if depth as usize >= MAX_DEPTH {
+                    return Err(Error::InvalidTree);
+                }
+                depths[child as usize] = depths[child as usize].max(depth);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Same fields as `Tree`, which is checked after they're deserialized
+#[derive(Deserialize)]
+#[serde(rename = "Tree")]
+#[serde(bound(deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>, Ownership: Deserialize<'de>"))]
+struct TreeData<Item: MetricSpace<Impl> + Clone, Impl, Ownership> {
+    nodes: Vec<Node<Item, Impl>>,
+    buckets: Buckets<Item>,
+    root: u32,
+    user_data: Ownership,
+}
+
+impl<'de, Item: MetricSpace<Impl> + Clone, Impl, Ownership> Deserialize<'de> for Tree<Item, Impl, Ownership>
+where
+    Item: Deserialize<'de>,
+    Item::Distance: Deserialize<'de>,
+    Ownership: Deserialize<'de>,
+{
+    /// Fails with `Error::InvalidTree` if the data isn't a tree that can be searched
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let TreeData { nodes, buckets, root, user_data } = TreeData::deserialize(deserializer)?;
+        let tree = Tree { nodes, buckets, root, user_data };
+        tree.check_structure().map_err(serde::de::Error::custom)?;
+        Ok(tree)
+    }
+}
@@ -0,0 +1,162 @@
+//! Trees that return values associated with the items, instead of their indexes.
+
+use crate::side_data::PerItem;
+use crate::{HoldsUserData, KNearest, MetricSpace, Owned, ReturnByIndex, Tree, WithinRadius};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use num_traits::Bounded;
+#[cfg(feature = "serde")]
+use crate::Error;
+#[cfg(feature = "serde")]
+use core::convert::TryFrom;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A tree that has a value for each item, like a map. Created with `Tree::new_with_values` or `Tree::with_values`.
+///
+/// Searches return references to the values, instead of indexes of the items.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+/// let tree = vpsearch::Tree::new_with_values(vec![(Point(1.), "one"), (Point(5.), "five")]);
+/// let (&name, distance) = tree.find_nearest(&Point(4.)).unwrap();
+/// assert_eq!((name, distance), ("five", 1.));
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "TreeWithValuesData<Item, Value, Impl, Ownership>"))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "Item: Serialize, Item::Distance: Serialize, Ownership: Serialize, Value: Serialize",
+    deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>, Ownership: Deserialize<'de>, Value: Deserialize<'de>",
+)))]
+pub struct TreeWithValues<Item: MetricSpace<Impl> + Clone, Value, Impl = (), Ownership = Owned<()>> {
+    tree: Tree<Item, Impl, Ownership>,
+    values: PerItem<Value>,
+}
+
+/// Same fields as `TreeWithValues`, which is checked after they're deserialized
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "TreeWithValues")]
+#[serde(bound(deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>, Ownership: Deserialize<'de>, Value: Deserialize<'de>"))]
+struct TreeWithValuesData<Item: MetricSpace<Impl> + Clone, Value, Impl, Ownership> {
+    tree: Tree<Item, Impl, Ownership>,
+    values: PerItem<Value>,
+}
+
+#[cfg(feature = "serde")]
+impl<Item: MetricSpace<Impl> + Clone, Value, Impl, Ownership> TryFrom<TreeWithValuesData<Item, Value, Impl, Ownership>> for TreeWithValues<Item, Value, Impl, Ownership> {
+    type Error = Error;
+
+    fn try_from(TreeWithValuesData { tree, values }: TreeWithValuesData<Item, Value, Impl, Ownership>) -> Result<Self, Error> {
+        values.check_len(&tree)?;
+        Ok(Self { tree, values })
+    }
+}
+
+/// A tree built from some of the items of a larger list, e.g. only the ones that aren't deleted,
+/// whose values are the items' indexes in the larger list. Created with `Tree::new_filtered`.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+/// let points = [Point(1.), Point(2.), Point(5.), Point(6.)];
+/// // Only points at odd indexes
+/// let tree = vpsearch::Tree::new_filtered(&points, |index, _| index % 2 == 1);
+/// assert_eq!(tree.find_nearest(&Point(5.)), Some((&3, 1.)));
+/// assert_eq!(tree.tree().find_nearest(&Point(5.)), (1, 1.));
+/// ```
+pub type RemappedTree<Item, Impl = (), Ownership = Owned<()>> = TreeWithValues<Item, usize, Impl, Ownership>;
+
+impl<Item: MetricSpace<Impl, UserData = ()> + Clone, Impl> Tree<Item, Impl, Owned<()>> {
+    /// Creates a tree from `(item, value)` pairs. Searches of this tree return the values.
+    ///
+    /// Panics in the same cases as `new`.
+    pub fn new_with_values<Value, I: IntoIterator<Item = (Item, Value)>>(pairs: I) -> TreeWithValues<Item, Value, Impl> {
+        let (items, values): (Vec<_>, Vec<_>) = pairs.into_iter().unzip();
+        Self::new(&items).with_values(values)
+    }
+
+    /// Creates a tree of the items for which `filter(index, item)` returns `true`. Searches of the tree return indexes in `items`.
+    ///
+    /// Panics in the same cases as `new`.
+    pub fn new_filtered<F: FnMut(usize, &Item) -> bool>(items: &[Item], mut filter: F) -> RemappedTree<Item, Impl> {
+        Self::new_with_values(items.iter().enumerate()
+            .filter(|&(index, item)| filter(index, item))
+            .map(|(index, item)| (item.clone(), index)))
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// Adds a value for each item, in the same order as the items the tree has been created from.
+    ///
+    /// Panics if the number of values is different than the number of items.
+    pub fn with_values<Value>(self, values: Vec<Value>) -> TreeWithValues<Item, Value, Impl, Ownership> {
+        let values = PerItem::new(&self, values, "value");
+        TreeWithValues { tree: self, values }
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Value, Impl, Ownership> TreeWithValues<Item, Value, Impl, Ownership> {
+    /// The tree for searches by index
+    #[inline]
+    pub fn tree(&self) -> &Tree<Item, Impl, Ownership> {
+        &self.tree
+    }
+
+    /// All values, in the order of the items the tree has been created from
+    #[inline]
+    pub fn values(&self) -> &[Value] {
+        &self.values
+    }
+
+    /// The tree and the values, separately
+    pub fn into_parts(self) -> (Tree<Item, Impl, Ownership>, Vec<Value>) {
+        (self.tree, self.values.into_vec())
+    }
+
+    /// Number of items (and values) in the tree
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn with_value(&self, (idx, distance): (usize, Item::Distance)) -> (&Value, Item::Distance) {
+        (&self.values[idx], distance)
+    }
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Value, Ownership: HoldsUserData<UserData = Item::UserData>> TreeWithValues<Item, Value, Impl, Ownership> {
+    /// Finds the value of the item closest to the `needle`. Returns `None` only if the tree is empty.
+    #[inline]
+    pub fn find_nearest<Q>(&self, needle: &Q) -> Option<(&Value, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.find_nearest_bounded(needle, <Item::Distance as Bounded>::max_value())
+    }
+
+    /// Finds the value of the item closest to the `needle`, if there's one at most `max_distance` away
+    #[inline]
+    pub fn find_nearest_bounded<Q>(&self, needle: &Q, max_distance: Item::Distance) -> Option<(&Value, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.tree.find_nearest_custom(needle, self.tree.user_data(), ReturnByIndex::new(max_distance)).map(|found| self.with_value(found))
+    }
+
+    /// Finds values of up to `k` items closest to the `needle`. Returns `(value, distance)` pairs sorted by distance, closest first.
+    pub fn find_k_nearest<Q>(&self, needle: &Q, k: usize) -> Vec<(&Value, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q>, Item::Distance: Bounded {
+        self.tree.find_nearest_custom(needle, self.tree.user_data(), KNearest::new(k)).into_iter().map(|found| self.with_value(found)).collect()
+    }
+
+    /// Finds values of all items at most `radius` away from the `needle`, sorted by distance, closest first.
+    pub fn find_within_radius<Q>(&self, needle: &Q, radius: Item::Distance) -> Vec<(&Value, Item::Distance)> where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        self.tree.find_nearest_custom(needle, self.tree.user_data(), WithinRadius::new(radius)).into_iter().map(|found| self.with_value(found)).collect()
+    }
+}
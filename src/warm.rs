@@ -0,0 +1,96 @@
+//! Loading the tree into memory before the first searches, and laying out nodes in the order searches use them.
+//!
+//! Nodes are stored in depth-first order, so reading them in order of their indexes follows the order of searches.
+//! Reordering keeps every parent before its children, but puts the more often searched child right after its parent.
+
+use crate::{HoldsUserData, KNearest, MetricSpace, Needle, NoExtraPruning, Tree, NO_NODE};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use core::mem::{self, MaybeUninit};
+use core::ptr;
+use num_traits::Bounded;
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// Reads all of the tree's memory — the nodes with their vantage points, and the items and indexes of the leaves —
+    /// so that the first searches after loading a large tree (e.g. from a swapped-out or freshly mapped file)
+    /// don't wait for the memory to be paged in.
+    ///
+    /// The memory is read from start to end, which is the order of a depth-first search, and the fastest order to page it in.
+    /// Data that the items point to, like contents of `Vec`s, isn't read.
+    pub fn warm(&self) {
+        touch(&self.nodes);
+        touch(&self.buckets.items);
+        touch(&self.buckets.idx);
+    }
+}
+
+/// Reads a byte of every cache line of the slice's memory
+fn touch<T>(slice: &[T]) {
+    const LINE: usize = 64;
+    let start = slice.as_ptr().cast::<MaybeUninit<u8>>();
+    let len = mem::size_of_val(slice);
+    for offset in (0..len).step_by(LINE) {
+        // SAFETY: the offset is within the slice, and `MaybeUninit` can be read from padding bytes too.
+        // Volatile reads aren't optimized out, even though the value isn't used.
+        unsafe { ptr::read_volatile(start.add(offset)); }
+    }
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, Ownership: HoldsUserData<UserData = Item::UserData>> Tree<Item, Impl, Ownership> {
+    /// Reorders nodes, so that the ones that `find_k_nearest` visits most often for the sample `queries` are next to each other in memory.
+    ///
+    /// Search results don't change. It only helps when the sample is representative of the real queries.
+    pub fn reorder_by_access<Q>(mut self, queries: &[Q], k: usize) -> Self where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance>, Item: Borrow<Q>, Item::Distance: Bounded {
+        let mut visits = vec![0u32; self.nodes.len()];
+        let user_data = self.user_data.user_data();
+        for needle in queries {
+            self.search_subtrees(self.root, &mut Needle { needle, user_data }, &mut KNearest::new(k), user_data, |node| {
+                visits[node as usize] += 1;
+                true
+            }, &mut NoExtraPruning);
+        }
+
+        // Depth-first, like the original order, but the more visited child goes first
+        let mut order = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![self.root];
+        while let Some(next) = stack.pop() {
+            let node = match self.nodes.get(next as usize) {
+                Some(node) => node,
+                None => continue,
+            };
+            order.push(next);
+            if node.bucket().is_none() {
+                let near_visits = visits.get(node.near as usize).copied().unwrap_or(0);
+                let far_visits = visits.get(node.far as usize).copied().unwrap_or(0);
+                // The last one pushed is taken first
+                if near_visits > far_visits {
+                    stack.extend([node.far, node.near].iter().copied());
+                } else {
+                    stack.extend([node.near, node.far].iter().copied());
+                }
+            }
+        }
+
+        let mut new_index = vec![NO_NODE; self.nodes.len()];
+        for (new, &old) in order.iter().enumerate() {
+            new_index[old as usize] = new as u32;
+        }
+        let mut nodes: Vec<_> = self.nodes.into_iter().map(Some).collect();
+        self.nodes = order.iter().map(|&old| {
+            let mut node = nodes[old as usize].take().unwrap();
+            if node.bucket().is_none() {
+                for child in [&mut node.near, &mut node.far] {
+                    if *child != NO_NODE {
+                        *child = new_index[*child as usize];
+                    }
+                }
+            }
+            node
+        }).collect();
+        if let Some(&root) = new_index.get(self.root as usize) {
+            self.root = root;
+        }
+        self
+    }
+}
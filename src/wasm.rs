@@ -0,0 +1,124 @@
+//! JavaScript bindings via `wasm-bindgen` (`wasm` feature).
+//!
+//! Exposes a `VpTree` class that indexes vectors of `Float32Array`s using Euclidean distance.
+//!
+//! ```js
+//! const tree = new VpTree(new Float32Array([0,0, 1,1, 5,5]), 2);
+//! const nearest = tree.findKNearest(new Float32Array([0.9, 0.8]), 2);
+//! console.log(nearest.indices, nearest.distances);
+//! ```
+//!
+//! To build it, compile the library as a `cdylib` and process it with the `wasm-bindgen` CLI:
+//!
+//! ```sh
+//! cargo rustc --release --lib --target wasm32-unknown-unknown --features wasm --crate-type cdylib
+//! wasm-bindgen --target web target/wasm32-unknown-unknown/release/vpsearch.wasm --out-dir pkg
+//! ```
+
+use crate::{MetricSpace, Tree};
+use wasm_bindgen::prelude::*;
+
+#[derive(Clone)]
+struct Vector(Box<[f32]>);
+
+impl MetricSpace for Vector {
+    type UserData = ();
+    type Distance = f32;
+
+    fn distance(&self, other: &Self, _: &Self::UserData) -> Self::Distance {
+        self.0.iter().zip(other.0.iter())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f32>()
+            .sqrt()
+    }
+}
+
+/// Vantage Point tree of fixed-length float vectors
+#[wasm_bindgen]
+pub struct VpTree {
+    tree: Tree<Vector>,
+    dimensions: usize,
+    len: usize,
+}
+
+/// Search results: indices of the found vectors, and their distances from the needle, closest first
+#[wasm_bindgen]
+pub struct Neighbors {
+    indices: Vec<u32>,
+    distances: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl Neighbors {
+    /// Positions of the found vectors in the data the tree has been created from
+    #[wasm_bindgen(getter)]
+    pub fn indices(&self) -> Vec<u32> {
+        self.indices.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn distances(&self) -> Vec<f32> {
+        self.distances.clone()
+    }
+}
+
+impl From<Vec<(usize, f32)>> for Neighbors {
+    fn from(found: Vec<(usize, f32)>) -> Self {
+        let (indices, distances) = found.into_iter().map(|(idx, dist)| (idx as u32, dist)).unzip();
+        Neighbors { indices, distances }
+    }
+}
+
+#[wasm_bindgen]
+impl VpTree {
+    /// `data` contains all vectors concatenated, each `dimensions` long
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: &[f32], dimensions: usize) -> Result<VpTree, JsError> {
+        if dimensions == 0 || data.len() % dimensions != 0 {
+            return Err(JsError::new("data length must be a multiple of the number of dimensions"));
+        }
+        if data.iter().any(|v| !v.is_finite()) {
+            return Err(JsError::new("vectors must not contain NaN or infinite values"));
+        }
+        let items: Vec<_> = data.chunks_exact(dimensions).map(|v| Vector(v.into())).collect();
+        Ok(VpTree {
+            tree: Tree::new(&items),
+            dimensions,
+            len: items.len(),
+        })
+    }
+
+    /// Number of vectors in the tree
+    #[wasm_bindgen(getter)]
+    pub fn length(&self) -> usize {
+        self.len
+    }
+
+    /// Index of the nearest vector, or `undefined` if the tree is empty
+    #[wasm_bindgen(js_name = findNearest)]
+    pub fn find_nearest(&self, needle: &[f32]) -> Result<Option<u32>, JsError> {
+        let needle = self.needle(needle)?;
+        Ok(self.tree.find_k_nearest(&needle, 1).first().map(|&(idx, _)| idx as u32))
+    }
+
+    /// Up to `k` nearest vectors
+    #[wasm_bindgen(js_name = findKNearest)]
+    pub fn find_k_nearest(&self, needle: &[f32], k: usize) -> Result<Neighbors, JsError> {
+        let needle = self.needle(needle)?;
+        Ok(self.tree.find_k_nearest(&needle, k).into())
+    }
+
+    /// All vectors at most `radius` away from the needle
+    #[wasm_bindgen(js_name = findWithinRadius)]
+    pub fn find_within_radius(&self, needle: &[f32], radius: f32) -> Result<Neighbors, JsError> {
+        let needle = self.needle(needle)?;
+        Ok(self.tree.find_within_radius(&needle, radius).into())
+    }
+
+    fn needle(&self, needle: &[f32]) -> Result<Vector, JsError> {
+        if needle.len() != self.dimensions {
+            return Err(JsError::new("needle has a different number of dimensions than the indexed vectors"));
+        }
+        Ok(Vector(needle.into()))
+    }
+}
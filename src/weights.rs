@@ -0,0 +1,107 @@
+//! Summing weights of items within a radius, e.g. for density estimates of points that stand for many events.
+//!
+//! Every node knows the total weight of its subtree, so subtrees entirely within the radius are added up without visiting them.
+
+use crate::counts::Found;
+use crate::side_data::PerItem;
+use crate::{HoldsUserData, MetricSpace, Owned, Tree};
+use alloc::vec::Vec;
+use core::borrow::Borrow;
+use num_traits::Zero;
+#[cfg(feature = "serde")]
+use crate::Error;
+#[cfg(feature = "serde")]
+use core::convert::TryFrom;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A tree that has a weight for each item, and can sum weights of items within a radius. Created with `Tree::with_weights`.
+///
+/// ```rust
+/// # #[derive(Clone)] struct Point(f32);
+/// # impl vpsearch::MetricSpace for Point {
+/// #     type UserData = (); type Distance = f32;
+/// #     fn distance(&self, other: &Self, _: &()) -> f32 { (self.0 - other.0).abs() }
+/// # }
+/// let events_at_point = vec![3, 1, 10];
+/// let tree = vpsearch::Tree::new(&[Point(1.), Point(2.), Point(5.)]).with_weights(events_at_point);
+/// assert_eq!(tree.weight_within_radius(&Point(1.5), 1.), 4);
+/// ```
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "WeightedTreeData<Item, W, Impl, Ownership>"))]
+#[cfg_attr(feature = "serde", serde(bound(
+    serialize = "Item: Serialize, Item::Distance: Serialize, Ownership: Serialize, W: Serialize",
+    deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>, Ownership: Deserialize<'de>, W: Deserialize<'de>",
+)))]
+pub struct WeightedTree<Item: MetricSpace<Impl> + Clone, W, Impl = (), Ownership = Owned<()>> {
+    tree: Tree<Item, Impl, Ownership>,
+    weights: PerItem<W>,
+    /// Sum of weights in the subtree of each node, including the node
+    node_weights: Vec<W>,
+}
+
+/// Same fields as `WeightedTree`, which is checked after they're deserialized
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename = "WeightedTree")]
+#[serde(bound(deserialize = "Item: Deserialize<'de>, Item::Distance: Deserialize<'de>, Ownership: Deserialize<'de>, W: Deserialize<'de>"))]
+struct WeightedTreeData<Item: MetricSpace<Impl> + Clone, W, Impl, Ownership> {
+    tree: Tree<Item, Impl, Ownership>,
+    weights: PerItem<W>,
+    node_weights: Vec<W>,
+}
+
+#[cfg(feature = "serde")]
+impl<Item: MetricSpace<Impl> + Clone, W, Impl, Ownership> TryFrom<WeightedTreeData<Item, W, Impl, Ownership>> for WeightedTree<Item, W, Impl, Ownership> {
+    type Error = Error;
+
+    fn try_from(WeightedTreeData { tree, weights, node_weights }: WeightedTreeData<Item, W, Impl, Ownership>) -> Result<Self, Error> {
+        weights.check_len(&tree)?;
+        tree.check_per_node_len(&node_weights)?;
+        Ok(Self { tree, weights, node_weights })
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, Impl, Ownership> Tree<Item, Impl, Ownership> {
+    /// Adds a weight for each item, in the same order as the items the tree has been created from.
+    ///
+    /// Panics if the number of weights is different than the number of items.
+    pub fn with_weights<W: Zero + Copy>(self, weights: Vec<W>) -> WeightedTree<Item, W, Impl, Ownership> {
+        let weights = PerItem::new(&self, weights, "weight");
+        let node_weights = self.aggregate_nodes(|idx| weights[idx], |a, b| a + b);
+        WeightedTree { tree: self, weights, node_weights }
+    }
+}
+
+impl<Item: MetricSpace<Impl> + Clone, W: Zero + Copy, Impl, Ownership> WeightedTree<Item, W, Impl, Ownership> {
+    /// The tree for other searches
+    #[inline]
+    pub fn tree(&self) -> &Tree<Item, Impl, Ownership> {
+        &self.tree
+    }
+
+    /// Weight of the item at `index`
+    #[inline]
+    pub fn weight(&self, index: usize) -> W {
+        self.weights[index]
+    }
+
+    /// Sum of weights of all items
+    pub fn total_weight(&self) -> W {
+        self.node_weights.get(self.tree.root as usize).copied().unwrap_or_else(W::zero)
+    }
+}
+
+impl<Impl, Item: MetricSpace<Impl> + Clone, W: Zero + Copy, Ownership: HoldsUserData<UserData = Item::UserData>> WeightedTree<Item, W, Impl, Ownership> {
+    /// Sum of weights of items at most `radius` away from the `needle`
+    pub fn weight_within_radius<Q>(&self, needle: &Q, radius: Item::Distance) -> W where Q: MetricSpace<Impl, UserData = Item::UserData, Distance = Item::Distance> + ?Sized, Item: Borrow<Q> {
+        let mut sum = W::zero();
+        self.tree.visit_within_radius(needle, radius, |found| {
+            sum = sum + match found {
+                Found::Item(idx) => self.weights[idx],
+                Found::Subtree(node) => self.node_weights[node as usize],
+            };
+        });
+        sum
+    }
+}
@@ -0,0 +1,175 @@
+//! `#[derive(MetricSpace)]` and `#[derive(Distance)]` for [vpsearch](https://lib.rs/crates/vpsearch). Use them via the `derive` feature of `vpsearch`.
+//!
+//! `MetricSpace` generates the Euclidean distance over all numeric fields of a struct, `sqrt(Σ weight·(a-b)²)`.
+//!
+//! * `#[vpsearch(distance = f64)]` on the struct sets the `Distance` type. It's `f64` if any field is `f64`, `f32` otherwise.
+//! * `#[vpsearch(weight = 2.0)]` on a field multiplies its squared difference. Weights must not be negative.
+//! * `#[vpsearch(skip)]` on a field excludes it from the distance.
+//!
+//! `Distance` is for newtypes of numbers, like `struct Meters(f64)`, used as `MetricSpace::Distance`.
+//! It implements `DistanceAdd`, `Bounded` and `ToPrimitive` by forwarding to the field.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, parse_quote, Attribute, Data, DeriveInput, Error, Expr, ExprUnary, Generics, Index, Member, Type, UnOp};
+
+#[proc_macro_derive(MetricSpace, attributes(vpsearch))]
+pub fn derive_metric_space(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let mut distance: Option<Type> = None;
+    for attr in vpsearch_attrs(&input.attrs) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("distance") {
+                distance = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `distance = f32` or `distance = f64`"))
+            }
+        })?;
+    }
+
+    let fields = match &input.data {
+        Data::Struct(s) => &s.fields,
+        _ => return Err(Error::new_spanned(&input.ident, "MetricSpace can only be derived for structs")),
+    };
+
+    let mut has_f64 = false;
+    let mut terms = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let mut skip = false;
+        let mut weight: Option<Expr> = None;
+        for attr in vpsearch_attrs(&field.attrs) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                    Ok(())
+                } else if meta.path.is_ident("weight") {
+                    weight = Some(meta.value()?.parse()?);
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `skip` or `weight = …`"))
+                }
+            })?;
+        }
+        if skip {
+            continue;
+        }
+        if let Some(w @ Expr::Unary(ExprUnary { op: UnOp::Neg(_), .. })) = &weight {
+            // a negative weight would make the distance not a metric
+            return Err(Error::new_spanned(w, "weight must not be negative"));
+        }
+        has_f64 |= matches!(&field.ty, Type::Path(t) if t.path.is_ident("f64"));
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(Index::from(i)),
+        };
+        terms.push((member, weight));
+    }
+    if terms.is_empty() {
+        return Err(Error::new_spanned(&input.ident, "MetricSpace needs at least one numeric field to compare"));
+    }
+
+    let distance = distance.unwrap_or_else(|| if has_f64 { parse_quote!(f64) } else { parse_quote!(f32) });
+    let squares = terms.iter().map(|(member, weight)| {
+        let diff = quote!((self.#member as #distance - other.#member as #distance));
+        match weight {
+            Some(weight) => quote!((#weight) as #distance * #diff * #diff),
+            None => quote!(#diff * #diff),
+        }
+    });
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        #[allow(clippy::unnecessary_cast)]
+        impl #impl_generics ::vpsearch::MetricSpace for #name #ty_generics #where_clause {
+            type UserData = ();
+            type Distance = #distance;
+
+            #[inline]
+            fn distance(&self, other: &Self, _: &()) -> #distance {
+                let sum: #distance = 0. #(+ #squares)*;
+                sum.sqrt() // sqrt is required
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(Distance)]
+pub fn derive_distance(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_distance(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand_distance(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let field = match &input.data {
+        Data::Struct(s) if s.fields.len() == 1 => s.fields.iter().next().unwrap(),
+        _ => return Err(Error::new_spanned(&input.ident, "Distance can only be derived for structs with one field, like `struct Meters(f64);`")),
+    };
+    let member = match &field.ident {
+        Some(ident) => Member::Named(ident.clone()),
+        None => Member::Unnamed(Index::from(0)),
+    };
+    let ty = &field.ty;
+    let name = &input.ident;
+
+    // Generic fields need the same traits as the impls forward to
+    let with_bound = |bound: TokenStream2| -> Generics {
+        let mut generics = input.generics.clone();
+        generics.make_where_clause().predicates.push(parse_quote!(#ty: #bound));
+        generics
+    };
+    let add_generics = with_bound(quote!(::vpsearch::DistanceAdd));
+    let bounded_generics = with_bound(quote!(::vpsearch::__num_traits::Bounded));
+    let primitive_generics = with_bound(quote!(::vpsearch::__num_traits::ToPrimitive));
+    let (add_impl, ty_generics, add_where) = add_generics.split_for_impl();
+    let (bounded_impl, _, bounded_where) = bounded_generics.split_for_impl();
+    let (primitive_impl, _, primitive_where) = primitive_generics.split_for_impl();
+
+    Ok(quote! {
+        impl #add_impl ::vpsearch::DistanceAdd for #name #ty_generics #add_where {
+            #[inline]
+            fn saturating_add(self, other: Self) -> Self {
+                #name { #member: ::vpsearch::DistanceAdd::saturating_add(self.#member, other.#member) }
+            }
+        }
+
+        impl #bounded_impl ::vpsearch::__num_traits::Bounded for #name #ty_generics #bounded_where {
+            #[inline]
+            fn min_value() -> Self {
+                #name { #member: <#ty as ::vpsearch::__num_traits::Bounded>::min_value() }
+            }
+
+            #[inline]
+            fn max_value() -> Self {
+                #name { #member: <#ty as ::vpsearch::__num_traits::Bounded>::max_value() }
+            }
+        }
+
+        impl #primitive_impl ::vpsearch::__num_traits::ToPrimitive for #name #ty_generics #primitive_where {
+            #[inline]
+            fn to_i64(&self) -> Option<i64> {
+                ::vpsearch::__num_traits::ToPrimitive::to_i64(&self.#member)
+            }
+
+            #[inline]
+            fn to_u64(&self) -> Option<u64> {
+                ::vpsearch::__num_traits::ToPrimitive::to_u64(&self.#member)
+            }
+
+            #[inline]
+            fn to_f64(&self) -> Option<f64> {
+                ::vpsearch::__num_traits::ToPrimitive::to_f64(&self.#member)
+            }
+        }
+    })
+}
+
+fn vpsearch_attrs(attrs: &[Attribute]) -> impl Iterator<Item = &Attribute> {
+    attrs.iter().filter(|a| a.path().is_ident("vpsearch"))
+}